@@ -1,5 +1,5 @@
 // pixie/src/cli.rs
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -8,6 +8,15 @@ pub enum Algorithm {
     Bilinear,
     Bicubic,
     Lanczos3,
+    Box,
+    Triangle,
+    CatmullRom,
+    Mitchell,
+    /// Lanczos with the window set by --lanczos-window
+    Lanczos,
+    /// Content-aware: removes or inserts low-detail seams to change the aspect ratio
+    /// (needs both width and height)
+    SeamCarve,
 }
 
 impl From<Algorithm> for crate::ResizeAlgorithm {
@@ -17,6 +26,317 @@ impl From<Algorithm> for crate::ResizeAlgorithm {
             Algorithm::Bilinear => crate::ResizeAlgorithm::Bilinear,
             Algorithm::Bicubic => crate::ResizeAlgorithm::Bicubic,
             Algorithm::Lanczos3 => crate::ResizeAlgorithm::Lanczos3,
+            Algorithm::Box => crate::ResizeAlgorithm::Box,
+            Algorithm::Triangle => crate::ResizeAlgorithm::Triangle,
+            Algorithm::CatmullRom => crate::ResizeAlgorithm::CatmullRom,
+            Algorithm::Mitchell => crate::ResizeAlgorithm::Mitchell,
+            Algorithm::Lanczos => crate::ResizeAlgorithm::Lanczos(3),
+            Algorithm::SeamCarve => crate::ResizeAlgorithm::SeamCarve,
+        }
+    }
+}
+
+/// External program that enlargements are handed to
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum UpscalerKind {
+    /// Any program, run from the --upscaler-cmd template
+    Command,
+    /// realesrgan-ncnn-vulkan
+    RealesrganNcnn,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Gravity {
+    #[default]
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl From<Gravity> for crate::CropGravity {
+    fn from(value: Gravity) -> Self {
+        match value {
+            Gravity::Center => crate::CropGravity::Center,
+            Gravity::North => crate::CropGravity::North,
+            Gravity::South => crate::CropGravity::South,
+            Gravity::East => crate::CropGravity::East,
+            Gravity::West => crate::CropGravity::West,
+            Gravity::NorthEast => crate::CropGravity::NorthEast,
+            Gravity::NorthWest => crate::CropGravity::NorthWest,
+            Gravity::SouthEast => crate::CropGravity::SouthEast,
+            Gravity::SouthWest => crate::CropGravity::SouthWest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ToneMap {
+    /// Soft roll-off that never clips
+    #[default]
+    Reinhard,
+    /// Filmic curve with more contrast
+    Aces,
+    /// Clip values above 1.0
+    Clamp,
+}
+
+impl From<ToneMap> for crate::ToneMapOperator {
+    fn from(value: ToneMap) -> Self {
+        match value {
+            ToneMap::Reinhard => crate::ToneMapOperator::Reinhard,
+            ToneMap::Aces => crate::ToneMapOperator::Aces,
+            ToneMap::Clamp => crate::ToneMapOperator::Clamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum DenoiseFilter {
+    /// Removes speckles and salt-and-pepper noise
+    Median,
+    /// Edge-preserving blur
+    #[default]
+    Bilateral,
+    /// Non-local means; slowest, keeps the most texture
+    NlMeans,
+}
+
+impl From<DenoiseFilter> for crate::DenoiseMethod {
+    fn from(value: DenoiseFilter) -> Self {
+        match value {
+            DenoiseFilter::Median => crate::DenoiseMethod::Median,
+            DenoiseFilter::Bilateral => crate::DenoiseMethod::Bilateral,
+            DenoiseFilter::NlMeans => crate::DenoiseMethod::NlMeans,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PreserveTimes {
+    /// The input's modification time
+    Mtime,
+    /// The EXIF capture date, or the input's modification time without one
+    Exif,
+}
+
+impl From<PreserveTimes> for crate::TimeSource {
+    fn from(value: PreserveTimes) -> Self {
+        match value {
+            PreserveTimes::Mtime => crate::TimeSource::Modified,
+            PreserveTimes::Exif => crate::TimeSource::Captured,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Salvage {
+    /// Keep the full size and fill the missing area with gray
+    Pad,
+    /// Crop the missing area off the bottom
+    Crop,
+}
+
+impl From<Salvage> for crate::SalvageMode {
+    fn from(value: Salvage) -> Self {
+        match value {
+            Salvage::Pad => crate::SalvageMode::Pad,
+            Salvage::Crop => crate::SalvageMode::Crop,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Mask {
+    Circle,
+    Ellipse,
+}
+
+impl From<Mask> for crate::MaskShape {
+    fn from(value: Mask) -> Self {
+        match value {
+            Mask::Circle => crate::MaskShape::Circle,
+            Mask::Ellipse => crate::MaskShape::Ellipse,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FaceStyle {
+    Blur,
+    Pixelate,
+    Black,
+}
+
+impl From<FaceStyle> for crate::RedactStyle {
+    fn from(value: FaceStyle) -> Self {
+        match value {
+            FaceStyle::Blur => crate::RedactStyle::Blur,
+            FaceStyle::Pixelate => crate::RedactStyle::Pixelate,
+            FaceStyle::Black => crate::RedactStyle::Black,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OnConflict {
+    /// Replace the existing file
+    Overwrite,
+    /// Keep the existing file and leave the input alone
+    Skip,
+    /// Write NAME_1.EXT, NAME_2.EXT, ... instead
+    Rename,
+    /// Fail the input
+    Error,
+}
+
+impl From<OnConflict> for crate::ConflictPolicy {
+    fn from(value: OnConflict) -> Self {
+        match value {
+            OnConflict::Overwrite => crate::ConflictPolicy::Overwrite,
+            OnConflict::Skip => crate::ConflictPolicy::Skip,
+            OnConflict::Rename => crate::ConflictPolicy::Rename,
+            OnConflict::Error => crate::ConflictPolicy::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MetadataSidecar {
+    /// Every EXIF field, plus the raw XMP packet
+    Json,
+    /// An XMP sidecar, as read by Lightroom, darktable and exiftool
+    Xmp,
+}
+
+impl From<MetadataSidecar> for crate::SidecarFormat {
+    fn from(value: MetadataSidecar) -> Self {
+        match value {
+            MetadataSidecar::Json => crate::SidecarFormat::Json,
+            MetadataSidecar::Xmp => crate::SidecarFormat::Xmp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum CompareLayout {
+    /// Original and processed next to each other
+    #[default]
+    SideBySide,
+    /// Original left of a divider, processed right of it
+    Split,
+}
+
+impl From<CompareLayout> for crate::ComparisonLayout {
+    fn from(value: CompareLayout) -> Self {
+        match value {
+            CompareLayout::SideBySide => crate::ComparisonLayout::SideBySide,
+            CompareLayout::Split => crate::ComparisonLayout::Split,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum PyramidKind {
+    /// Deep Zoom (.dzi), as read by OpenSeadragon
+    #[default]
+    Dzi,
+    /// Static IIIF Image API 3.0 tiles with info.json
+    Iiif,
+}
+
+impl From<PyramidKind> for crate::PyramidLayout {
+    fn from(value: PyramidKind) -> Self {
+        match value {
+            PyramidKind::Dzi => crate::PyramidLayout::Dzi,
+            PyramidKind::Iiif => crate::PyramidLayout::Iiif,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Direction {
+    /// Side by side
+    #[value(name = "h", alias = "horizontal")]
+    Horizontal,
+    /// Stacked
+    #[value(name = "v", alias = "vertical")]
+    Vertical,
+}
+
+impl From<Direction> for crate::JoinDirection {
+    fn from(value: Direction) -> Self {
+        match value {
+            Direction::Horizontal => crate::JoinDirection::Horizontal,
+            Direction::Vertical => crate::JoinDirection::Vertical,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Align {
+    /// Top for horizontal strips, left for vertical ones
+    #[value(alias = "top", alias = "left")]
+    Start,
+    #[default]
+    Center,
+    /// Bottom for horizontal strips, right for vertical ones
+    #[value(alias = "bottom", alias = "right")]
+    End,
+}
+
+impl From<Align> for crate::JoinAlign {
+    fn from(value: Align) -> Self {
+        match value {
+            Align::Start => crate::JoinAlign::Start,
+            Align::Center => crate::JoinAlign::Center,
+            Align::End => crate::JoinAlign::End,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ChannelName {
+    #[value(name = "r", alias = "red")]
+    Red,
+    #[value(name = "g", alias = "green")]
+    Green,
+    #[value(name = "b", alias = "blue")]
+    Blue,
+    #[value(name = "a", alias = "alpha")]
+    Alpha,
+}
+
+impl From<ChannelName> for crate::Channel {
+    fn from(value: ChannelName) -> Self {
+        match value {
+            ChannelName::Red => crate::Channel::Red,
+            ChannelName::Green => crate::Channel::Green,
+            ChannelName::Blue => crate::Channel::Blue,
+            ChannelName::Alpha => crate::Channel::Alpha,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ThumbnailMode {
+    /// Rebuild the embedded EXIF thumbnail from the output pixels
+    #[default]
+    Regenerate,
+    /// Drop the embedded EXIF thumbnail
+    Strip,
+}
+
+impl From<ThumbnailMode> for crate::ExifThumbnail {
+    fn from(value: ThumbnailMode) -> Self {
+        match value {
+            ThumbnailMode::Regenerate => crate::ExifThumbnail::Regenerate,
+            ThumbnailMode::Strip => crate::ExifThumbnail::Strip,
         }
     }
 }
@@ -25,7 +345,15 @@ impl From<Algorithm> for crate::ResizeAlgorithm {
 pub enum OutputFormat {
     Jpeg,
     Png,
+    #[value(name = "webp")]
     WebP,
+    Avif,
+    Tga,
+    #[value(alias = "ppm", alias = "pgm")]
+    Pnm,
+    Qoi,
+    #[value(alias = "tif")]
+    Tiff,
     Same,
 }
 
@@ -35,32 +363,433 @@ impl From<OutputFormat> for crate::OutputFormat {
             OutputFormat::Jpeg => crate::OutputFormat::Jpeg,
             OutputFormat::Png => crate::OutputFormat::Png,
             OutputFormat::WebP => crate::OutputFormat::WebP,
+            OutputFormat::Avif => crate::OutputFormat::Avif,
+            OutputFormat::Tga => crate::OutputFormat::Tga,
+            OutputFormat::Pnm => crate::OutputFormat::Pnm,
+            OutputFormat::Qoi => crate::OutputFormat::Qoi,
+            OutputFormat::Tiff => crate::OutputFormat::Tiff,
             OutputFormat::Same => crate::OutputFormat::SameAsInput,
         }
     }
 }
 
+#[derive(Debug, Clone, ValueEnum)]
+pub enum TextureFormat {
+    Dds,
+    Ktx2,
+}
+
+impl From<TextureFormat> for crate::TextureContainer {
+    fn from(value: TextureFormat) -> Self {
+        match value {
+            TextureFormat::Dds => crate::TextureContainer::Dds,
+            TextureFormat::Ktx2 => crate::TextureContainer::Ktx2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum BlockCompression {
+    /// Uncompressed RGBA8
+    None,
+    /// BC1/DXT1, opaque RGB
+    Bc1,
+    /// BC3/DXT5, RGB with alpha
+    Bc3,
+}
+
+impl From<BlockCompression> for crate::TextureCompression {
+    fn from(value: BlockCompression) -> Self {
+        match value {
+            BlockCompression::None => crate::TextureCompression::None,
+            BlockCompression::Bc1 => crate::TextureCompression::Bc1,
+            BlockCompression::Bc3 => crate::TextureCompression::Bc3,
+        }
+    }
+}
+
+/// Options shared by every command that writes processed images
+#[derive(Debug, Clone, Default, Args)]
+pub struct ProcessingArgs {
+    /// Physical resolution to record in the output (dots per inch)
+    #[arg(long, value_name = "DPI")]
+    pub dpi: Option<u32>,
+
+    /// Never enlarge images; smaller sources are only re-encoded
+    #[arg(long)]
+    pub no_upscale: bool,
+
+    /// Resize transparent images without premultiplying alpha (may leave dark fringes)
+    #[arg(long)]
+    pub no_premultiply: bool,
+
+    /// Resample in linear light; keeps dark detail and thin lines intact when shrinking a lot
+    #[arg(long)]
+    pub linear: bool,
+
+    /// Number of lobes for `--algorithm lanczos`
+    #[arg(long, default_value_t = 3, value_name = "LOBES")]
+    pub lanczos_window: u8,
+
+    /// Limit the longest side to N pixels, keeping aspect (overrides width/height)
+    #[arg(long, value_name = "PIXELS")]
+    pub max_dimension: Option<u32>,
+
+    /// Crop to this aspect ratio (e.g. 16:9) before resizing
+    #[arg(long, value_name = "W:H")]
+    pub aspect: Option<crate::AspectRatio>,
+
+    /// Which part of the image to keep when cropping
+    #[arg(long, value_enum, default_value_t = Gravity::Center)]
+    pub gravity: Gravity,
+
+    /// What to do with the embedded EXIF thumbnail when metadata is kept
+    #[arg(long, value_enum, default_value_t = ThumbnailMode::Regenerate)]
+    pub exif_thumbnail: ThumbnailMode,
+
+    /// Keep the pixels as stored instead of turning them upright per the EXIF Orientation tag
+    #[arg(long)]
+    pub no_auto_orient: bool,
+
+    /// Re-open each written file and fail if it is corrupt or has the wrong size
+    #[arg(long)]
+    pub verify: bool,
+
+    /// AVIF encoder speed: 1 compresses hardest (archival), 10 is fastest (default: 6)
+    #[arg(long, visible_alias = "effort", value_name = "1-10")]
+    pub speed: Option<u8>,
+
+    /// Tone-mapping curve for HDR/EXR inputs
+    #[arg(long, value_enum, default_value_t = ToneMap::Reinhard)]
+    pub tone_map: ToneMap,
+
+    /// Exposure adjustment for HDR/EXR inputs, in stops
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true, value_name = "EV")]
+    pub exposure: f32,
+
+    /// Bits per sample in the output (default: keep 16-bit sources where the format allows)
+    #[arg(long, value_name = "8|16")]
+    pub bit_depth: Option<u8>,
+
+    /// Write CMYK TIFF for print (requires TIFF output)
+    #[arg(long)]
+    pub cmyk: bool,
+
+    /// CMYK ICC profile to convert with and embed
+    #[arg(long, requires = "cmyk", value_name = "ICC")]
+    pub cmyk_profile: Option<PathBuf>,
+
+    /// Delegate enlargements (scale above 1.0x) to an external super-resolution tool
+    #[arg(long, value_enum, value_name = "KIND")]
+    pub upscaler: Option<UpscalerKind>,
+
+    /// Command template for `--upscaler command` ("tool {input} {output} {scale}"),
+    /// or the binary path for realesrgan-ncnn (default: realesrgan-ncnn-vulkan)
+    #[arg(long, requires = "upscaler", value_name = "CMD")]
+    pub upscaler_cmd: Option<String>,
+
+    /// Denoise before encoding, 1 (light) to 10 (heavy); helps high-ISO photos compress
+    #[arg(long, value_name = "LEVEL")]
+    pub denoise: Option<u8>,
+
+    /// Denoising filter used by --denoise
+    #[arg(long, value_enum, default_value_t = DenoiseFilter::Bilateral)]
+    pub denoise_method: DenoiseFilter,
+
+    /// Stretch each color channel's histogram to the full range (fixes dull scans and casts)
+    #[arg(long)]
+    pub auto_level: bool,
+
+    /// Stretch contrast with one curve for all channels, keeping colors
+    #[arg(long, conflicts_with = "auto_level")]
+    pub auto_contrast: bool,
+
+    /// Percentage of darkest and brightest samples ignored by --auto-level/--auto-contrast
+    #[arg(long, default_value_t = 0.5, value_name = "PERCENT")]
+    pub level_clip: f32,
+
+    /// Color-grade with a 3D LUT (.cube)
+    #[arg(long, value_name = "FILE")]
+    pub lut: Option<PathBuf>,
+
+    /// Reduce each color channel to N tones (2-255)
+    #[arg(long, value_name = "N")]
+    pub posterize: Option<u8>,
+
+    /// Convert to black and white, splitting at this brightness (0-255); for stencils
+    #[arg(long, value_name = "VALUE")]
+    pub threshold: Option<u8>,
+
+    /// Recolor along a gradient from DARK (shadows) to LIGHT (highlights), e.g. '#1e3264,#f573a0'
+    #[arg(long, value_name = "DARK,LIGHT")]
+    pub duotone: Option<crate::Duotone>,
+
+    /// Reduce to a palette of at most N colors (2-256); PNG output is written indexed
+    #[arg(long, value_name = "N")]
+    pub quantize: Option<u16>,
+
+    /// Reorder channels, e.g. bgr or argb (letters name the source of each output channel)
+    #[arg(long, value_name = "ORDER")]
+    pub swap_channels: Option<crate::ChannelOrder>,
+
+    /// Output a single channel as a grayscale image
+    #[arg(long, value_enum, value_name = "CHANNEL")]
+    pub channel: Option<ChannelName>,
+
+    /// Invert the colors (after --channel, so masks can be inverted)
+    #[arg(long)]
+    pub invert: bool,
+
+    /// Add an alpha channel from a mask image (white = opaque) or a color key such as '#ffffff'
+    #[arg(long, value_name = "MASK|COLOR", conflicts_with = "remove_alpha")]
+    pub add_alpha: Option<crate::AlphaSource>,
+
+    /// Make pixels of this color transparent, e.g. '#ffffff' to drop a logo's white background
+    #[arg(long, value_name = "COLOR", conflicts_with_all = ["add_alpha", "remove_alpha"])]
+    pub transparent_color: Option<crate::Color>,
+
+    /// Blank out a region of the input by pixelating (default), blurring or blacking it out; repeatable
+    #[arg(long, value_name = "X,Y,W,H[:STYLE]")]
+    pub redact: Vec<crate::Redaction>,
+
+    /// Find faces with an external detector and blur (default), pixelate or black out each one
+    #[arg(long, value_enum, value_name = "STYLE", num_args = 0..=1, default_missing_value = "blur")]
+    pub blur_faces: Option<FaceStyle>,
+
+    /// Face detector command template; {input} is the image path, and it prints X Y W H per face
+    /// (default: facedetect {input})
+    #[arg(long, requires = "blur_faces", value_name = "CMD")]
+    pub face_detector: Option<String>,
+
+    /// Crop away uniform borders (scanner margins, letterboxing) before resizing
+    #[arg(long)]
+    pub trim: bool,
+
+    /// How far colors may be from --transparent-color or the --trim border and still match (e.g. 10%)
+    #[arg(long, default_value = "0", value_name = "PERCENT", value_parser = parse_percent)]
+    pub fuzz: f32,
+
+    /// Flatten transparency onto a background color (default white) and drop the alpha channel
+    #[arg(long, value_name = "COLOR", num_args = 0..=1, default_missing_value = "#ffffff")]
+    pub remove_alpha: Option<crate::Color>,
+
+    /// Round the output's corners to this radius, transparent where the format allows
+    #[arg(long, value_name = "PIXELS")]
+    pub corner_radius: Option<u32>,
+
+    /// Cut the output to a circle (from a centered square, see --gravity) or an ellipse
+    #[arg(long, value_enum, value_name = "SHAPE", conflicts_with_all = ["corner_radius", "aspect"])]
+    pub mask: Option<Mask>,
+
+    /// Fill for rounded corners, the outside of --mask and the --shadow canvas in formats without transparency such as JPEG (default white)
+    #[arg(long, value_name = "COLOR")]
+    pub mask_background: Option<crate::Color>,
+
+    /// Drop a soft shadow under the output, e.g. 8,0,4 or 8,0,4:#333333 (blur sigma, x and y offset, color)
+    #[arg(long, value_name = "BLUR,DX,DY[:COLOR]", allow_hyphen_values = true)]
+    pub shadow: Option<crate::Shadow>,
+
+    /// Transparent canvas margin around the output on every side, outside --width/--height (default: room for --shadow)
+    #[arg(long, value_name = "PIXELS")]
+    pub padding: Option<u32>,
+
+    /// Give outputs the input's modification time (default) or EXIF capture date, so libraries keep their order
+    #[arg(long, value_enum, value_name = "SOURCE", num_args = 0..=1, default_missing_value = "mtime")]
+    pub preserve_times: Option<PreserveTimes>,
+
+    /// Give outputs the input's permission bits, and its owner, group and xattrs where allowed
+    #[arg(long)]
+    pub preserve_attrs: bool,
+
+    /// Fail instead of creating missing directories in the output path (batch still mirrors its input tree)
+    #[arg(long)]
+    pub no_create_dirs: bool,
+
+    /// When an output already exists (default: rename generated names, overwrite paths given with -o and batch outputs)
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub on_conflict: Option<OnConflict>,
+
+    /// Recover what is readable from truncated JPEGs instead of failing, padding (default) or cropping the missing area
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "pad")]
+    pub salvage: Option<Salvage>,
+
+    /// Save each input's EXIF and XMP next to its output (as NAME.json or NAME.xmp) before any stripping
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub export_metadata: Option<MetadataSidecar>,
+
+    /// Draw text on the output; {Tag} inserts the input's EXIF value, e.g. "{Artist} — {DateTimeOriginal}"
+    #[arg(long, value_name = "TEXT")]
+    pub caption: Option<String>,
+
+    /// TrueType font for --caption (default: a system sans-serif font)
+    #[arg(long, value_name = "FILE", requires = "caption")]
+    pub caption_font: Option<PathBuf>,
+
+    /// Caption height in pixels (default: 1/25 of the shorter side)
+    #[arg(long, value_name = "PIXELS", requires = "caption")]
+    pub caption_size: Option<f32>,
+
+    /// Where to place the caption
+    #[arg(long, value_enum, default_value_t = Gravity::SouthEast, requires = "caption")]
+    pub caption_position: Gravity,
+
+    /// Write the original next to the encoded result for review (a directory for batch)
+    #[arg(long, value_name = "PATH")]
+    pub save_comparison: Option<PathBuf>,
+
+    /// Score each output against the image it was encoded from (SSIM and PSNR)
+    #[arg(long)]
+    pub measure_quality: bool,
+
+    /// Layout of the --save-comparison image
+    #[arg(long, value_enum, default_value_t = CompareLayout::SideBySide, requires = "save_comparison")]
+    pub comparison_layout: CompareLayout,
+}
+
+impl ProcessingArgs {
+    pub fn apply(&self, config: &mut crate::ProcessConfig) {
+        config.dpi = self.dpi;
+        config.no_upscale = self.no_upscale;
+        config.premultiply_alpha = !self.no_premultiply;
+        config.linear = self.linear;
+        if let crate::ResizeAlgorithm::Lanczos(_) = config.algorithm {
+            config.algorithm = crate::ResizeAlgorithm::Lanczos(self.lanczos_window);
+        }
+        config.aspect = self.aspect;
+        config.gravity = self.gravity.into();
+        config.exif_thumbnail = self.exif_thumbnail.into();
+        config.auto_orient = !self.no_auto_orient;
+        config.verify = self.verify;
+        config.speed = self.speed;
+        config.tone_map = self.tone_map.into();
+        config.exposure = self.exposure;
+        config.bit_depth = self.bit_depth;
+        config.cmyk = self.cmyk;
+        config.cmyk_profile = self.cmyk_profile.clone();
+        config.denoise = self.denoise;
+        config.denoise_method = self.denoise_method.into();
+        config.auto_level = if self.auto_level {
+            Some(crate::AutoLevel::Levels)
+        } else if self.auto_contrast {
+            Some(crate::AutoLevel::Contrast)
+        } else {
+            None
+        };
+        config.level_clip = self.level_clip;
+        config.lut = self.lut.clone();
+        config.posterize = self.posterize;
+        config.threshold = self.threshold;
+        config.duotone = self.duotone;
+        config.quantize = self.quantize;
+        config.channel_order = self.swap_channels;
+        config.channel = self.channel.map(Into::into);
+        config.invert = self.invert;
+        config.add_alpha = self
+            .transparent_color
+            .map(crate::AlphaSource::ColorKey)
+            .or_else(|| self.add_alpha.clone());
+        config.fuzz = self.fuzz;
+        config.redact = self.redact.clone();
+        config.blur_faces = self.blur_faces.map(Into::into);
+        config.face_detector = self.face_detector.clone();
+        config.trim = self.trim;
+        config.remove_alpha = self.remove_alpha;
+        config.corner_radius = self.corner_radius;
+        config.mask = self.mask.map(Into::into);
+        config.mask_background = self.mask_background.unwrap_or(crate::Color::WHITE);
+        config.shadow = self.shadow;
+        config.padding = self.padding;
+        config.export_metadata = self.export_metadata.map(Into::into);
+        config.preserve_times = self.preserve_times.map(Into::into);
+        config.preserve_attrs = self.preserve_attrs;
+        config.create_dirs = !self.no_create_dirs;
+        config.on_conflict = self.on_conflict.map(Into::into);
+        config.salvage = self.salvage.map(Into::into);
+        config.caption = self.caption.clone();
+        config.caption_font = self.caption_font.clone();
+        config.caption_size = self.caption_size;
+        config.caption_position = self.caption_position.into();
+        config.comparison = self.save_comparison.clone();
+        config.comparison_layout = self.comparison_layout.into();
+        config.measure_quality = self.measure_quality;
+        config.upscaler = self.upscaler.map(|kind| match kind {
+            UpscalerKind::Command => crate::Upscaler::Command(self.upscaler_cmd.clone().unwrap_or_default()),
+            UpscalerKind::RealesrganNcnn => crate::Upscaler::RealEsrganNcnn(
+                self.upscaler_cmd.as_deref().unwrap_or("realesrgan-ncnn-vulkan").into(),
+            ),
+        });
+
+        if let Some(max) = self.max_dimension {
+            config.max_dimension = Some(max);
+            config.width = 0;
+            config.height = 0;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    /// One JSON object per line
+    Json,
+}
+
 #[derive(Parser)]
 #[command(name = "image-tool")]
 #[command(version, about = "Fast image resizer and optimizer", long_about = None)]
+#[command(after_help = "Exit codes:
+  0  success
+  1  other failure
+  2  invalid arguments
+  3  unsupported format
+  4  batch finished with some failed images
+  5  I/O error
+  6  image decoding, encoding or processing error
+  7  security check failed (e.g. path traversal)
+  8  file size or memory limit exceeded")]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    /// Enable verbose logging
-    #[arg(short, long, global = true)]
-    pub verbose: bool,
+    /// Increase logging (-v debug, -vv trace, -vvv trace including dependencies)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log errors and hide progress bars (-q is taken by --quality)
+    #[arg(long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Log output format
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Plain, value_name = "FORMAT")]
+    pub log_format: LogFormat,
+
+    /// Also append log messages to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate the log file once it reaches this size, keeping three old copies (in MB, 0 = never)
+    #[arg(long, global = true, default_value_t = 10, value_name = "MB")]
+    pub log_file_size: u64,
 
     /// Maximum file size to process (in MB)
     #[arg(long, global = true, value_name = "MB")]
     pub max_file_size: Option<u64>,
+
+    /// Maximum estimated decode memory per image (in MB)
+    #[arg(long, global = true, value_name = "MB")]
+    pub max_memory: Option<u64>,
+
+    /// Decode images larger than this in strips when downscaling (in megapixels)
+    #[arg(long, global = true, default_value_t = 100, value_name = "MEGAPIXELS")]
+    pub tile_threshold: u64,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Resize a single image
     Resize {
-        /// Input image file
+        /// Input image file or https:// URL
         #[arg(value_name = "INPUT")]
         input: PathBuf,
 
@@ -80,9 +809,9 @@ pub enum Commands {
         #[arg(short, long, default_value_t = 0.0, value_name = "PERCENT")]
         scale: f32,
 
-        /// JPEG quality (1-100)
-        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
-        quality: u8,
+        /// JPEG quality (1-100), or auto[:SSIM] to pick the lowest JPEG quality reaching a similarity target (default 0.98)
+        #[arg(short, long, default_value = "85", value_name = "QUALITY")]
+        quality: crate::Quality,
 
         /// Output format
         #[arg(short = 'f', long, value_enum, value_name = "FORMAT")]
@@ -103,17 +832,26 @@ pub enum Commands {
         /// Use progressive JPEG encoding
         #[arg(long)]
         progressive: bool,
+
+        /// Emit several widths from one decode (OUTPUT is then a directory)
+        #[arg(long, value_delimiter = ',', value_name = "WIDTHS")]
+        sizes: Vec<u32>,
+
+        #[command(flatten)]
+        processing: ProcessingArgs,
     },
 
     /// Process multiple images in a folder
+    ///
+    /// A NAME.pixie.toml next to an input, e.g. photo.jpg.pixie.toml, overrides settings for that file alone: skip = true, crop = "4:5", quality = 95, or other processing options by their server query names.
     Batch {
-        /// Input directory
-        #[arg(value_name = "INPUT_DIR")]
-        input: PathBuf,
+        /// Input directory, .zip/.cbz archive, - for a tar stream on stdin, or s3://bucket/prefix / gs://bucket/prefix with the `cloud` feature
+        #[arg(value_name = "INPUT_DIR", required_unless_present = "rollback")]
+        input: Option<PathBuf>,
 
-        /// Output directory
-        #[arg(short, long, value_name = "OUTPUT_DIR")]
-        output: PathBuf,
+        /// Output directory or bucket URI, or - to write a tar stream to stdout
+        #[arg(short, long, value_name = "OUTPUT_DIR", required_unless_present_any = ["rollback", "archive_output"])]
+        output: Option<PathBuf>,
 
         /// Width in pixels
         #[arg(short = 'W', long, default_value_t = 800, value_name = "PIXELS")]
@@ -127,11 +865,11 @@ pub enum Commands {
         #[arg(short = 'f', long, value_enum, value_name = "FORMAT")]
         format: Option<OutputFormat>,
 
-        /// JPEG quality (1-100)
-        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
-        quality: u8,
+        /// JPEG quality (1-100), or auto[:SSIM] to pick the lowest JPEG quality reaching a similarity target (default 0.98)
+        #[arg(short, long, default_value = "85", value_name = "QUALITY")]
+        quality: crate::Quality,
 
-        /// Number of parallel threads (0 = auto)
+        /// Number of parallel threads (0 = auto); objects in flight for bucket URIs
         #[arg(short, long, default_value_t = 0, value_name = "THREADS")]
         threads: usize,
 
@@ -150,11 +888,66 @@ pub enum Commands {
         /// Disable PNG optimization
         #[arg(long)]
         no_png_optimize: bool,
+
+        /// Where to record written files (default: OUTPUT_DIR/.pixie-journal-<timestamp>)
+        #[arg(long, value_name = "FILE")]
+        journal: Option<PathBuf>,
+
+        /// Process byte-identical inputs only once and report them
+        #[arg(long)]
+        skip_duplicates: bool,
+
+        /// Keep the estimated decode memory of all images in flight under this (in MB)
+        #[arg(long, value_name = "MB")]
+        memory_budget: Option<u64>,
+
+        /// Where to remember finished files (default: OUTPUT_DIR/.pixie-cache)
+        #[arg(long, value_name = "DIR")]
+        cache_dir: Option<PathBuf>,
+
+        /// Process every input, even if its output from an earlier run is up to date
+        #[arg(long, conflicts_with = "cache_dir")]
+        no_cache: bool,
+
+        /// Only process inputs modified after their existing output
+        #[arg(long)]
+        changed_only: bool,
+
+        /// Record finished inputs here and skip those already recorded, to resume a stopped run
+        #[arg(long, value_name = "FILE")]
+        checkpoint: Option<PathBuf>,
+
+        /// Run even if another batch holds the lock on the output directory
+        #[arg(long)]
+        force: bool,
+
+        /// Write the outputs into this .zip, .tar or .tar.gz instead of OUTPUT_DIR
+        #[arg(long, value_name = "FILE", conflicts_with = "output")]
+        archive_output: Option<PathBuf>,
+
+        /// Print a JSON report of the run, with per-file stage timings, instead of the summary
+        #[arg(long)]
+        json: bool,
+
+        /// Delete every file recorded in a journal from an earlier run, then exit
+        #[arg(long, value_name = "JOURNAL", conflicts_with = "journal")]
+        rollback: Option<PathBuf>,
+
+        /// Condition on an input, e.g. "width>1600" or "format==png && colors<=256"; paired in order with --then
+        #[arg(long = "if", value_name = "CONDITION", requires = "actions")]
+        conditions: Vec<String>,
+
+        /// Options for inputs matching the --if before it, e.g. "resize:1600" or "quantize,quality:80"
+        #[arg(long = "then", value_name = "ACTION", requires = "conditions")]
+        actions: Vec<String>,
+
+        #[command(flatten)]
+        processing: ProcessingArgs,
     },
 
     /// Optimize image without resizing
     Optimize {
-        /// Input image file
+        /// Input image file or https:// URL
         #[arg(value_name = "INPUT")]
         input: PathBuf,
 
@@ -162,9 +955,9 @@ pub enum Commands {
         #[arg(short, long, value_name = "OUTPUT")]
         output: Option<PathBuf>,
 
-        /// JPEG quality (1-100)
-        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
-        quality: u8,
+        /// JPEG quality (1-100), or auto[:SSIM] to pick the lowest JPEG quality reaching a similarity target (default 0.98)
+        #[arg(short, long, default_value = "85", value_name = "QUALITY")]
+        quality: crate::Quality,
 
         /// Strip metadata
         #[arg(short = 'm', long)]
@@ -177,6 +970,9 @@ pub enum Commands {
         /// Disable PNG optimization
         #[arg(long)]
         no_png_optimize: bool,
+
+        #[command(flatten)]
+        processing: ProcessingArgs,
     },
 
     /// Get information about an image
@@ -187,11 +983,21 @@ pub enum Commands {
         /// Show detailed EXIF metadata
         #[arg(short, long)]
         exif: bool,
+
+        /// Print the information as JSON, including placeholder hashes
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Compute values derived from an image's content
+    Analyze {
+        #[command(subcommand)]
+        command: AnalyzeCommand,
     },
 
     /// Convert image to another format
     Convert {
-        /// Input image file
+        /// Input image file or https:// URL
         #[arg(value_name = "INPUT")]
         input: PathBuf,
 
@@ -203,12 +1009,504 @@ pub enum Commands {
         #[arg(short = 'f', long, value_enum, value_name = "FORMAT")]
         format: OutputFormat,
 
-        /// JPEG quality (1-100) for JPEG output
-        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
-        quality: u8,
+        /// JPEG quality (1-100), or auto[:SSIM] to pick the lowest JPEG quality reaching a similarity target (default 0.98)
+        #[arg(short, long, default_value = "85", value_name = "QUALITY")]
+        quality: crate::Quality,
 
         /// Strip metadata
         #[arg(short = 'm', long)]
         strip_metadata: bool,
+
+        #[command(flatten)]
+        processing: ProcessingArgs,
     },
+
+    /// Run the ordered steps of a TOML or YAML recipe on an image or directory
+    Pipeline {
+        /// Recipe file (.toml, .yaml or .yml)
+        #[arg(value_name = "RECIPE")]
+        recipe: PathBuf,
+
+        /// Input image file or directory
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output file, or directory for a directory input (default: input_processed.ext)
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+
+        /// Number of parallel threads for a directory input (0 = auto)
+        #[arg(short, long, default_value_t = 0, value_name = "THREADS")]
+        threads: usize,
+
+        /// Recursively process subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+    },
+
+    /// Generate a responsive image set with a <picture> snippet and JSON manifest
+    Srcset {
+        /// Input image file
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output directory
+        #[arg(short, long, value_name = "OUTPUT_DIR")]
+        output: PathBuf,
+
+        /// Widths to generate
+        #[arg(short = 'w', long, value_delimiter = ',', default_value = "480,800,1200", value_name = "WIDTHS")]
+        widths: Vec<u32>,
+
+        /// Output formats; the last one is used for the <img> fallback
+        #[arg(short = 'f', long = "formats", value_enum, value_delimiter = ',', default_value = "avif,jpeg", value_name = "FORMATS")]
+        formats: Vec<OutputFormat>,
+
+        /// Value of the sizes attribute in the HTML snippet
+        #[arg(long, default_value = "100vw", value_name = "SIZES")]
+        sizes: String,
+
+        /// JPEG quality (1-100)
+        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
+        quality: u8,
+
+        /// Resize algorithm
+        #[arg(short = 'A', long, value_enum, default_value_t = Algorithm::Lanczos3)]
+        algorithm: Algorithm,
+    },
+
+    /// Generate a tiny blurred placeholder (LQIP) to show while an image loads
+    Lqip {
+        /// Input image file
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output directory
+        #[arg(short, long, value_name = "OUTPUT_DIR")]
+        output: PathBuf,
+
+        /// Placeholder width in pixels
+        #[arg(short = 'w', long, default_value_t = 20, value_name = "PIXELS")]
+        width: u32,
+
+        /// Blur sigma in placeholder pixels (0 to disable)
+        #[arg(long, default_value_t = 1.0, value_name = "SIGMA")]
+        blur: f32,
+
+        /// Placeholder format
+        #[arg(short = 'f', long, value_enum, default_value_t = OutputFormat::Jpeg, value_name = "FORMAT")]
+        format: OutputFormat,
+
+        /// Output quality (1-100)
+        #[arg(short, long, default_value_t = 30, value_name = "QUALITY")]
+        quality: u8,
+
+        /// Also write a CSS rule inlining the placeholder as a data URI
+        #[arg(long)]
+        css: bool,
+
+        /// Selector for the CSS rule
+        #[arg(long, default_value = ".lqip", value_name = "SELECTOR", requires = "css")]
+        selector: String,
+
+        /// Resize algorithm
+        #[arg(short = 'A', long, value_enum, default_value_t = Algorithm::Lanczos3)]
+        algorithm: Algorithm,
+    },
+
+    /// Run an HTTP server that processes uploaded images
+    Serve {
+        /// Address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:8080", value_name = "ADDR")]
+        listen: String,
+
+        /// Number of worker threads (0 = auto)
+        #[arg(short, long, default_value_t = 0, value_name = "THREADS")]
+        workers: usize,
+
+        /// Connections allowed to wait for a worker before new ones get 503
+        #[arg(long, default_value_t = 64, value_name = "COUNT")]
+        queue_size: usize,
+
+        /// Maximum request body size in MB
+        #[arg(long, default_value_t = 50, value_name = "MB")]
+        max_body_size: u64,
+
+        /// Largest input or output a request may have, in megapixels
+        #[arg(long, default_value_t = 100, value_name = "MP")]
+        max_pixels: u64,
+
+        /// Default JPEG quality (1-100) when a request does not set one
+        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
+        quality: u8,
+
+        /// Resize algorithm
+        #[arg(short = 'A', long, value_enum, default_value_t = Algorithm::Lanczos3)]
+        algorithm: Algorithm,
+    },
+
+    /// Dump the frames of an animated GIF, WebP or PNG as numbered PNGs
+    Frames {
+        /// Input image file
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output directory
+        #[arg(short, long, value_name = "OUTPUT_DIR")]
+        output: PathBuf,
+
+        /// Keep every Nth frame, starting with the first
+        #[arg(long, default_value_t = 1, value_name = "N")]
+        every: usize,
+    },
+
+    /// Slice an image into a grid of numbered tiles
+    Tiles {
+        /// Input image file
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output directory
+        #[arg(short, long, value_name = "OUTPUT_DIR")]
+        output: PathBuf,
+
+        /// Number of columns (default 1 when only --rows is given)
+        #[arg(long, value_name = "N", conflicts_with = "tile_size")]
+        cols: Option<u32>,
+
+        /// Number of rows (default 1 when only --cols is given)
+        #[arg(long, value_name = "N", conflicts_with = "tile_size")]
+        rows: Option<u32>,
+
+        /// Square tiles of this many pixels; edge tiles may be smaller
+        #[arg(long, value_name = "PIXELS")]
+        tile_size: Option<u32>,
+
+        /// Tile format (default: same as input)
+        #[arg(short = 'f', long, value_enum, value_name = "FORMAT")]
+        format: Option<OutputFormat>,
+
+        /// Output quality (1-100)
+        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
+        quality: u8,
+    },
+
+    /// Generate a deep-zoom tile pyramid (DZI or IIIF) for very large images
+    Dzi {
+        /// Input image file; TIFF and PNG are streamed without loading them whole
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output directory
+        #[arg(short, long, value_name = "OUTPUT_DIR")]
+        output: PathBuf,
+
+        /// Pyramid layout
+        #[arg(long, value_enum, default_value_t = PyramidKind::Dzi)]
+        layout: PyramidKind,
+
+        /// Tile edge in pixels (default: 254 for DZI, 256 for IIIF)
+        #[arg(long, value_name = "PIXELS")]
+        tile_size: Option<u32>,
+
+        /// Pixels shared between neighbouring DZI tiles
+        #[arg(long, default_value_t = 1, value_name = "PIXELS")]
+        overlap: u32,
+
+        /// Tile format
+        #[arg(short = 'f', long, value_enum, value_name = "FORMAT")]
+        format: Option<OutputFormat>,
+
+        /// Output quality (1-100)
+        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
+        quality: u8,
+
+        /// URL the IIIF tiles will be served from (default: the image name)
+        #[arg(long, value_name = "URL")]
+        base_url: Option<String>,
+    },
+
+    /// Extract a poster frame from a video (requires ffmpeg) and resize it
+    Thumb {
+        /// Input video file
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output image file (default: input_thumb.jpg)
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+
+        /// Position of the frame, as HH:MM:SS[.ms] or seconds
+        #[arg(long, default_value = "00:00:01", value_name = "TIME")]
+        at: String,
+
+        /// Width in pixels (0 for auto)
+        #[arg(short = 'W', long, default_value_t = 0, value_name = "PIXELS")]
+        width: u32,
+
+        /// Height in pixels (0 for auto)
+        #[arg(short = 'H', long, default_value_t = 0, value_name = "PIXELS")]
+        height: u32,
+
+        /// JPEG quality (1-100)
+        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
+        quality: u8,
+
+        /// Output format
+        #[arg(short = 'f', long, value_enum, value_name = "FORMAT")]
+        format: Option<OutputFormat>,
+
+        /// Resize algorithm
+        #[arg(short = 'A', long, value_enum, default_value_t = Algorithm::Lanczos3)]
+        algorithm: Algorithm,
+
+        /// ffmpeg executable to use
+        #[arg(long, default_value = "ffmpeg", value_name = "PATH")]
+        ffmpeg: PathBuf,
+
+        #[command(flatten)]
+        processing: ProcessingArgs,
+    },
+
+    /// Compose several images onto one canvas in a grid
+    Collage {
+        /// Input image files, placed left to right, top to bottom
+        #[arg(value_name = "INPUTS", required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Output image file
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Grid as COLUMNSxROWS, e.g. 3x2 (default: as square as the image count allows)
+        #[arg(long, value_name = "LAYOUT")]
+        layout: Option<crate::GridLayout>,
+
+        /// Cell width in pixels (0 = widest input)
+        #[arg(short = 'W', long, default_value_t = 0, value_name = "PIXELS")]
+        width: u32,
+
+        /// Cell height in pixels (0 = tallest input)
+        #[arg(short = 'H', long, default_value_t = 0, value_name = "PIXELS")]
+        height: u32,
+
+        /// Space between cells and around the edge
+        #[arg(long, default_value_t = 0, value_name = "PIXELS")]
+        gap: u32,
+
+        /// Canvas color, as #RRGGBB or a name such as white
+        #[arg(long, default_value = "white", value_name = "COLOR")]
+        background: crate::Color,
+
+        /// Crop images to fill their cells instead of letterboxing them
+        #[arg(long)]
+        fill: bool,
+
+        /// Output quality (1-100)
+        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
+        quality: u8,
+
+        /// Resize algorithm used to fit images into cells
+        #[arg(short = 'A', long, value_enum, default_value_t = Algorithm::Lanczos3)]
+        algorithm: Algorithm,
+    },
+
+    /// Append images side by side or stacked, e.g. for long screenshots
+    Join {
+        /// Input image files, in order
+        #[arg(value_name = "INPUTS", required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Output image file
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// h: left to right, v: top to bottom
+        #[arg(short, long, value_enum, default_value_t = Direction::Horizontal)]
+        direction: Direction,
+
+        /// Placement of images smaller than the strip
+        #[arg(long, value_enum, default_value_t = Align::Center)]
+        align: Align,
+
+        /// Pixels between images
+        #[arg(long, default_value_t = 0, value_name = "PIXELS")]
+        spacing: u32,
+
+        /// Color behind spacing and smaller images, as #RRGGBB or a name such as white
+        #[arg(long, default_value = "white", value_name = "COLOR")]
+        background: crate::Color,
+
+        /// Scale images to a common height (h) or width (v) first
+        #[arg(long)]
+        same_size: bool,
+
+        /// Output quality (1-100)
+        #[arg(short, long, default_value_t = 85, value_name = "QUALITY")]
+        quality: u8,
+
+        /// Resize algorithm used by --same-size
+        #[arg(short = 'A', long, value_enum, default_value_t = Algorithm::Lanczos3)]
+        algorithm: Algorithm,
+    },
+
+    /// Bake an image into a DDS or KTX2 texture with a mip chain
+    Texture {
+        /// Input image file
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output texture file (.dds or .ktx2)
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Container format (default: from the output extension)
+        #[arg(short = 'f', long, value_enum, value_name = "FORMAT")]
+        format: Option<TextureFormat>,
+
+        /// Block compression for every mip level
+        #[arg(short, long, value_enum, default_value_t = BlockCompression::None)]
+        compression: BlockCompression,
+
+        /// Write only the base level
+        #[arg(long)]
+        no_mipmaps: bool,
+
+        /// Resize algorithm used for the mip chain
+        #[arg(short = 'A', long, value_enum, default_value_t = Algorithm::Lanczos3)]
+        algorithm: Algorithm,
+    },
+
+    /// Rename images after the date they were taken, from EXIF DateTimeOriginal
+    Rename {
+        /// Directory of images
+        #[arg(value_name = "DIR")]
+        dir: PathBuf,
+
+        /// New name from {date:FORMAT} (%Y %y %m %d %H %M %S), {stem} and {ext}, relative to DIR; the extension is kept unless {ext} is used
+        #[arg(short, long, default_value = "{date:%Y-%m-%d_%H%M%S}_{stem}", value_name = "PATTERN")]
+        pattern: String,
+
+        /// Also rename images in subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Show the new names without renaming anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Report GPS locations, serial numbers, owner names and thumbnails in image metadata
+    Audit {
+        /// Image file or directory
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Also audit images in subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Remove what is found from JPEGs in place, without re-encoding them
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Fully decode images and report truncation, CRC and marker errors; fails if any file is broken
+    Validate {
+        /// Image files or directories
+        #[arg(value_name = "PATH", required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Also check images in subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+    },
+
+    /// Work with image metadata
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommand,
+    },
+
+    /// Generate roff man pages
+    Man {
+        /// Write a page for the main command and every subcommand into this
+        /// directory instead of printing the main page to stdout
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AnalyzeCommand {
+    /// Print the BlurHash placeholder string
+    Blurhash {
+        /// Input image file
+        input: PathBuf,
+
+        /// Horizontal components (1-9)
+        #[arg(short = 'x', long, default_value_t = 4)]
+        x_components: u32,
+
+        /// Vertical components (1-9)
+        #[arg(short = 'y', long, default_value_t = 3)]
+        y_components: u32,
+    },
+
+    /// Print the base64 ThumbHash, which also keeps alpha and aspect ratio
+    Thumbhash {
+        /// Input image file
+        input: PathBuf,
+
+        /// Also write the placeholder decoded from the hash as a PNG
+        #[arg(long, value_name = "PATH")]
+        preview: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MetaCommand {
+    /// Copy EXIF, XMP and the ICC profile from one image into another, in place and without re-encoding
+    Copy {
+        /// Image to take the metadata from
+        source: PathBuf,
+
+        /// JPEG, PNG or WebP to write it into
+        destination: PathBuf,
+
+        /// Leave the destination's EXIF alone
+        #[arg(long)]
+        no_exif: bool,
+
+        /// Leave the destination's XMP alone
+        #[arg(long)]
+        no_xmp: bool,
+
+        /// Leave the destination's ICC profile alone
+        #[arg(long)]
+        no_icc: bool,
+    },
+
+    /// Turn images upright per their EXIF Orientation tag and reset it to 1; upright files are left untouched
+    FixOrientation {
+        /// Image or directory to fix in place
+        path: PathBuf,
+
+        /// Also fix images in subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Quality for images that can't be turned losslessly (1-100)
+        #[arg(short, long, default_value_t = 95)]
+        quality: u8,
+    },
+}
+
+// Accepts "10%" as well as "10"
+fn parse_percent(value: &str) -> Result<f32, String> {
+    value
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percentage '{}'", value))
 }
\ No newline at end of file
@@ -0,0 +1,366 @@
+// pixie/src/core/builder.rs
+use super::{AspectRatio, CropGravity, ExifThumbnail, ImageToolError, OutputFormat, ProcessConfig, ResizeAlgorithm, Result, ToneMapOperator, Upscaler, DenoiseMethod, AutoLevel, Channel, ChannelOrder, AlphaSource, Color, ComparisonLayout, MaskShape, RedactStyle, Redaction, Shadow, Duotone};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dimensions {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Dimensions {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: Some(width),
+            height: Some(height),
+        }
+    }
+
+    pub fn width(width: u32) -> Self {
+        Self {
+            width: Some(width),
+            height: None,
+        }
+    }
+
+    pub fn height(height: u32) -> Self {
+        Self {
+            width: None,
+            height: Some(height),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProcessConfigBuilder {
+    config: ProcessConfig,
+    dimensions: Option<Dimensions>,
+    scale: Option<f32>,
+}
+
+impl ProcessConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dimensions(mut self, dimensions: Dimensions) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn width(mut self, width: u32) -> Self {
+        let height = self.dimensions.and_then(|d| d.height);
+        self.dimensions = Some(Dimensions { width: Some(width), height });
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        let width = self.dimensions.and_then(|d| d.width);
+        self.dimensions = Some(Dimensions { width, height: Some(height) });
+        self
+    }
+
+    /// Scale percentage, e.g. `50.0` for half size
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.config.quality = quality;
+        self
+    }
+
+    pub fn keep_aspect(mut self, keep_aspect: bool) -> Self {
+        self.config.keep_aspect = keep_aspect;
+        self
+    }
+
+    pub fn strip_metadata(mut self, strip_metadata: bool) -> Self {
+        self.config.strip_metadata = strip_metadata;
+        self
+    }
+
+    pub fn algorithm(mut self, algorithm: ResizeAlgorithm) -> Self {
+        self.config.algorithm = algorithm;
+        self
+    }
+
+    /// Maximum input file size in bytes
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.config.max_file_size = Some(bytes);
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.config.format = Some(format);
+        self
+    }
+
+    /// Pixel count above which supported formats are decoded in strips
+    pub fn tile_threshold(mut self, pixels: Option<u64>) -> Self {
+        self.config.tile_threshold = pixels;
+        self
+    }
+
+    /// Estimated decode memory in bytes above which an image is refused
+    /// (or decoded in strips when that path applies)
+    pub fn max_memory(mut self, bytes: u64) -> Self {
+        self.config.max_memory = Some(bytes);
+        self
+    }
+
+    pub fn dpi(mut self, dpi: u32) -> Self {
+        self.config.dpi = Some(dpi);
+        self
+    }
+
+    pub fn no_upscale(mut self, no_upscale: bool) -> Self {
+        self.config.no_upscale = no_upscale;
+        self
+    }
+
+    pub fn premultiply_alpha(mut self, premultiply: bool) -> Self {
+        self.config.premultiply_alpha = premultiply;
+        self
+    }
+
+    pub fn linear(mut self, linear: bool) -> Self {
+        self.config.linear = linear;
+        self
+    }
+
+    /// Longest side limit, aspect ratio is always kept
+    pub fn max_dimension(mut self, max: u32) -> Self {
+        self.config.max_dimension = Some(max);
+        self
+    }
+
+    pub fn aspect(mut self, aspect: AspectRatio, gravity: CropGravity) -> Self {
+        self.config.aspect = Some(aspect);
+        self.config.gravity = gravity;
+        self
+    }
+
+    pub fn exif_thumbnail(mut self, thumbnail: ExifThumbnail) -> Self {
+        self.config.exif_thumbnail = thumbnail;
+        self
+    }
+
+    pub fn auto_orient(mut self, auto_orient: bool) -> Self {
+        self.config.auto_orient = auto_orient;
+        self
+    }
+
+    /// Re-read every written file and fail if it does not decode to the
+    /// expected dimensions
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.config.verify = verify;
+        self
+    }
+
+    /// Trade encode time for size on AVIF output: 1 is slowest and smallest,
+    /// 10 is fastest
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.config.speed = Some(speed);
+        self
+    }
+
+    pub fn tone_map(mut self, operator: ToneMapOperator, exposure: f32) -> Self {
+        self.config.tone_map = operator;
+        self.config.exposure = exposure;
+        self
+    }
+
+    pub fn bit_depth(mut self, bit_depth: u8) -> Self {
+        self.config.bit_depth = Some(bit_depth);
+        self
+    }
+
+    /// Write CMYK TIFF, optionally converting through an ICC profile
+    pub fn cmyk(mut self, profile: Option<std::path::PathBuf>) -> Self {
+        self.config.cmyk = true;
+        self.config.cmyk_profile = profile;
+        self
+    }
+
+    pub fn upscaler(mut self, upscaler: Upscaler) -> Self {
+        self.config.upscaler = Some(upscaler);
+        self
+    }
+
+    pub fn denoise(mut self, level: u8, method: DenoiseMethod) -> Self {
+        self.config.denoise = Some(level);
+        self.config.denoise_method = method;
+        self
+    }
+
+    /// Stretch the histogram, ignoring `clip` percent at each end
+    pub fn auto_level(mut self, mode: AutoLevel, clip: f32) -> Self {
+        self.config.auto_level = Some(mode);
+        self.config.level_clip = clip;
+        self
+    }
+
+    pub fn lut(mut self, path: std::path::PathBuf) -> Self {
+        self.config.lut = Some(path);
+        self
+    }
+
+    pub fn posterize(mut self, levels: u8) -> Self {
+        self.config.posterize = Some(levels);
+        self
+    }
+
+    pub fn threshold(mut self, cutoff: u8) -> Self {
+        self.config.threshold = Some(cutoff);
+        self
+    }
+
+    pub fn duotone(mut self, duotone: Duotone) -> Self {
+        self.config.duotone = Some(duotone);
+        self
+    }
+
+    pub fn quantize(mut self, colors: u16) -> Self {
+        self.config.quantize = Some(colors);
+        self
+    }
+
+    pub fn channel_order(mut self, order: ChannelOrder) -> Self {
+        self.config.channel_order = Some(order);
+        self
+    }
+
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.config.channel = Some(channel);
+        self
+    }
+
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.config.invert = invert;
+        self
+    }
+
+    pub fn add_alpha(mut self, source: AlphaSource) -> Self {
+        self.config.add_alpha = Some(source);
+        self
+    }
+
+    /// Make pixels within `fuzz` percent of `color` transparent
+    pub fn transparent_color(mut self, color: Color, fuzz: f32) -> Self {
+        self.config.add_alpha = Some(AlphaSource::ColorKey(color));
+        self.config.fuzz = fuzz;
+        self
+    }
+
+    /// Flatten onto `background` and drop the alpha channel
+    pub fn remove_alpha(mut self, background: Color) -> Self {
+        self.config.remove_alpha = Some(background);
+        self
+    }
+
+    /// Blank out `region` before any other processing; may be repeated
+    pub fn redact(mut self, region: Redaction) -> Self {
+        self.config.redact.push(region);
+        self
+    }
+
+    /// Hide each face found by `detector` (see `FaceDetector`) in `style`
+    pub fn blur_faces(mut self, style: RedactStyle, detector: Option<String>) -> Self {
+        self.config.blur_faces = Some(style);
+        self.config.face_detector = detector;
+        self
+    }
+
+    /// Round the corners off to `radius` pixels, filling them with
+    /// `background` in formats without alpha
+    pub fn corner_radius(mut self, radius: u32, background: Color) -> Self {
+        self.config.corner_radius = Some(radius);
+        self.config.mask_background = background;
+        self
+    }
+
+    /// Cut the output to `shape`, filling the outside with `background` in
+    /// formats without alpha
+    pub fn mask(mut self, shape: MaskShape, background: Color) -> Self {
+        self.config.mask = Some(shape);
+        self.config.mask_background = background;
+        self
+    }
+
+    /// Composite the output with a drop shadow onto a larger canvas
+    pub fn shadow(mut self, shadow: Shadow) -> Self {
+        self.config.shadow = Some(shadow);
+        self
+    }
+
+    /// Transparent margin around the output, in pixels on every side
+    pub fn padding(mut self, pixels: u32) -> Self {
+        self.config.padding = Some(pixels);
+        self
+    }
+
+    /// Crop uniform borders, allowing colors within `fuzz` percent
+    pub fn trim(mut self, fuzz: f32) -> Self {
+        self.config.trim = true;
+        self.config.fuzz = fuzz;
+        self
+    }
+
+    /// Also write the original next to the encoded output at `path`
+    pub fn save_comparison(mut self, path: std::path::PathBuf, layout: ComparisonLayout) -> Self {
+        self.config.comparison = Some(path);
+        self.config.comparison_layout = layout;
+        self
+    }
+
+    /// Record SSIM and PSNR of each output in the processing stats
+    pub fn measure_quality(mut self, measure: bool) -> Self {
+        self.config.measure_quality = measure;
+        self
+    }
+
+    /// Search JPEG qualities up to the configured one for the lowest whose
+    /// output reaches `target_ssim`
+    pub fn auto_quality(mut self, target_ssim: f64) -> Self {
+        self.config.auto_quality = Some(target_ssim);
+        self
+    }
+
+    pub fn build(self) -> Result<ProcessConfig> {
+        let mut config = self.config;
+
+        if let Some(dimensions) = self.dimensions {
+            if dimensions.width == Some(0) || dimensions.height == Some(0) {
+                return Err(ImageToolError::InvalidParameter(
+                    "Width and height must be greater than zero when set".to_string(),
+                ));
+            }
+            if self.scale.is_some() && (dimensions.width.is_some() || dimensions.height.is_some()) {
+                return Err(ImageToolError::InvalidParameter(
+                    "Cannot specify both scale and width/height".to_string(),
+                ));
+            }
+            config.width = dimensions.width.unwrap_or(0);
+            config.height = dimensions.height.unwrap_or(0);
+        }
+
+        if let Some(scale) = self.scale {
+            if !scale.is_finite() || scale <= 0.0 {
+                return Err(ImageToolError::InvalidParameter(
+                    "Scale must be a positive percentage".to_string(),
+                ));
+            }
+            config.scale = scale;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl ProcessConfig {
+    pub fn builder() -> ProcessConfigBuilder {
+        ProcessConfigBuilder::new()
+    }
+}
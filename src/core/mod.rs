@@ -1,20 +1,483 @@
 // pixie/src/core/mod.rs
-use std::path::Path;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
+pub mod builder;
+pub mod options;
 pub mod processor;
+pub mod progress;
+pub mod stage;
 
+pub use builder::*;
 pub use processor::*;
+pub use progress::*;
+pub use stage::*;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum ResizeAlgorithm {
     Nearest,
     Bilinear,
     Bicubic,
     Lanczos3,
+    /// Area average; crisp for pixel art at integer ratios
+    Box,
+    Triangle,
+    CatmullRom,
+    /// Mitchell-Netravali (B = C = 1/3), softer than Catmull-Rom with less ringing
+    Mitchell,
+    /// Lanczos with a window of this many lobes
+    Lanczos(u8),
+    /// Content-aware when both width and height are given, Lanczos3 otherwise
+    SeamCarve,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+}
+
+impl std::str::FromStr for AspectRatio {
+    type Err = ImageToolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImageToolError::InvalidParameter(
+            format!("Invalid aspect ratio '{}', expected W:H such as 16:9", s)
+        );
+
+        let (w, h) = s.split_once(':').ok_or_else(invalid)?;
+        let width: u32 = w.trim().parse().map_err(|_| invalid())?;
+        let height: u32 = h.trim().parse().map_err(|_| invalid())?;
+
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self { width, height })
+    }
+}
+
+/// Encoder quality: a fixed value, or `auto[:target-ssim]` to pick the
+/// lowest JPEG quality that still looks like the source. WebP output is
+/// lossless, so quality doesn't apply to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quality {
+    Fixed(u8),
+    Auto(f64),
+}
+
+impl Quality {
+    pub const DEFAULT_TARGET_SSIM: f64 = 0.98;
+
+    // Auto mode never goes above what a fixed high quality would give
+    const AUTO_CEILING: u8 = 95;
+
+    /// The fixed quality, or the highest one auto mode may choose
+    pub fn value(&self) -> u8 {
+        match self {
+            Self::Fixed(quality) => *quality,
+            Self::Auto(_) => Self::AUTO_CEILING,
+        }
+    }
+
+    pub fn target_ssim(&self) -> Option<f64> {
+        match self {
+            Self::Fixed(_) => None,
+            Self::Auto(target) => Some(*target),
+        }
+    }
+}
+
+impl std::str::FromStr for Quality {
+    type Err = ImageToolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImageToolError::InvalidParameter(
+            format!("Invalid quality '{}', expected 1-100, auto or auto:SSIM such as auto:0.98", s)
+        );
+
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto(Self::DEFAULT_TARGET_SSIM));
+        }
+        if let Some(target) = s.strip_prefix("auto:") {
+            let target: f64 = target.trim().parse().map_err(|_| invalid())?;
+            if !(target > 0.0 && target <= 1.0) {
+                return Err(invalid());
+            }
+            return Ok(Self::Auto(target));
+        }
+
+        match s.parse::<u8>() {
+            Ok(quality) if (1..=100).contains(&quality) => Ok(Self::Fixed(quality)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fixed(quality) => write!(f, "{}", quality),
+            Self::Auto(target) => write!(f, "auto:{}", target),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub enum CropGravity {
+    #[default]
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub enum ExifThumbnail {
+    #[default]
+    Regenerate,
+    Strip,
+}
+
+/// How original and processed images are put together for review
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub enum ComparisonLayout {
+    /// Original on the left, processed on the right
+    #[default]
+    SideBySide,
+    /// One frame, original left of a divider and processed right of it
+    Split,
+}
+
+/// Format of the metadata sidecar written next to each output
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SidecarFormat {
+    /// Every EXIF field by IFD, plus the raw XMP packet
+    Json,
+    /// One XMP packet holding the EXIF fields and the source's own XMP
+    Xmp,
+}
+
+/// Which time `--preserve-times` gives each output
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum TimeSource {
+    /// The input file's modification time
+    Modified,
+    /// When the photo was taken, from EXIF; the input's modification
+    /// time when it has no capture date
+    Captured,
+}
+
+/// How to decode a JPEG whose data breaks off
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SalvageMode {
+    /// Keep the full size, with the missing area filled in gray
+    Pad,
+    /// Cut the image off after the last fully decoded row of blocks
+    Crop,
+}
+
+/// What to do when an output file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ConflictPolicy {
+    Overwrite,
+    /// Leave the existing file and don't process the input
+    Skip,
+    /// Write to the first free `name_N.ext` instead
+    Rename,
+    /// Fail the input
+    Error,
+}
+
+/// Curve used to bring HDR/EXR inputs into displayable range
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub enum ToneMapOperator {
+    #[default]
+    Reinhard,
+    Aces,
+    /// Clip everything above 1.0
+    Clamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub enum DenoiseMethod {
+    Median,
+    /// Edge-preserving blur
+    #[default]
+    Bilateral,
+    /// Non-local means over small patches; slowest, keeps the most texture
+    NlMeans,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum AutoLevel {
+    /// Stretch each channel on its own, which also removes color casts
+    Levels,
+    /// One stretch for all channels, keeping colors as they are
+    Contrast,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+    pub const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn is_gray(&self) -> bool {
+        self.r == self.g && self.g == self.b
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = ImageToolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImageToolError::InvalidParameter(
+            format!("Invalid color '{}', expected #RRGGBB, #RGB or a name such as white", s)
+        );
+
+        match s.trim().to_lowercase().as_str() {
+            "white" => return Ok(Self::WHITE),
+            "black" => return Ok(Self::BLACK),
+            "gray" | "grey" => return Ok(Self::new(128, 128, 128)),
+            _ => {}
+        }
+
+        let hex = s.trim().trim_start_matches('#');
+        let digits = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+
+        match digits[..] {
+            [r, g, b] => Ok(Self::new(r * 17, g * 17, b * 17)),
+            [r1, r2, g1, g2, b1, b2] => Ok(Self::new(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The two ends of a duotone gradient
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Duotone {
+    /// What black becomes
+    pub dark: Color,
+    /// What white becomes
+    pub light: Color,
+}
+
+impl std::str::FromStr for Duotone {
+    type Err = ImageToolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(',') {
+            Some((dark, light)) => Ok(Self { dark: dark.parse()?, light: light.parse()? }),
+            None => Err(ImageToolError::InvalidParameter(
+                format!("Invalid duotone '{}', expected DARK,LIGHT such as '#1e3264,#f573a0'", s)
+            )),
+        }
+    }
+}
+
+/// Where `--add-alpha` takes transparency from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum AlphaSource {
+    /// Grayscale mask image, white opaque; stretched to the output size
+    Mask(PathBuf),
+    /// Pixels of this color (within `fuzz`) become transparent
+    ColorKey(Color),
+}
+
+impl std::str::FromStr for AlphaSource {
+    type Err = ImageToolError;
+
+    // Colors are written with a leading '#'; anything else is a mask file
+    fn from_str(s: &str) -> Result<Self> {
+        if s.trim().starts_with('#') {
+            Ok(Self::ColorKey(s.parse()?))
+        } else {
+            Ok(Self::Mask(PathBuf::from(s)))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+/// Source channel for each of the output's red, green, blue and alpha
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ChannelOrder(pub [Channel; 4]);
+
+impl std::str::FromStr for ChannelOrder {
+    type Err = ImageToolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImageToolError::InvalidParameter(
+            format!("Invalid channel order '{}', expected letters from rgba such as bgr or argb", s)
+        );
+
+        let channels = s
+            .trim()
+            .chars()
+            .map(|c| match c.to_ascii_lowercase() {
+                'r' => Ok(Channel::Red),
+                'g' => Ok(Channel::Green),
+                'b' => Ok(Channel::Blue),
+                'a' => Ok(Channel::Alpha),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        match channels[..] {
+            // Alpha stays where it is when only the colors are named
+            [r, g, b] => Ok(Self([r, g, b, Channel::Alpha])),
+            [r, g, b, a] => Ok(Self([r, g, b, a])),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Shape the output is cut to, the rest becoming transparent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum MaskShape {
+    /// Cropped square, then the circle inside it
+    Circle,
+    /// The ellipse touching all four edges
+    Ellipse,
+}
+
+/// How a `Redaction` hides its region
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum RedactStyle {
+    /// Coarse blocks of the region's average colors
+    Pixelate,
+    /// A blur strong enough that text and faces are unreadable
+    Blur,
+    /// Solid black
+    Black,
+}
+
+/// A rectangle, in pixels of the decoded input, to blank out before any
+/// other processing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Redaction {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub style: RedactStyle,
+}
+
+impl std::str::FromStr for Redaction {
+    type Err = ImageToolError;
+
+    // X,Y,W,H with an optional :STYLE, pixelating by default
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImageToolError::InvalidParameter(
+            format!("Invalid redaction '{}', expected X,Y,W,H[:pixelate|blur|black] such as 10,20,200,40:blur", s)
+        );
+
+        let (rect, style) = match s.trim().split_once(':') {
+            Some((rect, style)) => (rect, style),
+            None => (s.trim(), "pixelate"),
+        };
+        let style = match style.trim().to_lowercase().as_str() {
+            "pixelate" => RedactStyle::Pixelate,
+            "blur" => RedactStyle::Blur,
+            "black" => RedactStyle::Black,
+            _ => return Err(invalid()),
+        };
+        let values = rect
+            .split(',')
+            .map(|value| value.trim().parse::<u32>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Self { x, y, width, height, style }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A soft shadow cast by the image onto the canvas around it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Shadow {
+    /// Blur sigma in pixels
+    pub blur: f32,
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub color: Color,
+}
+
+impl std::str::FromStr for Shadow {
+    type Err = ImageToolError;
+
+    // BLUR,DX,DY with an optional :COLOR, black by default
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImageToolError::InvalidParameter(
+            format!("Invalid shadow '{}', expected BLUR,DX,DY[:COLOR] such as 8,0,4:#333333", s)
+        );
+
+        let (geometry, color) = match s.trim().split_once(':') {
+            Some((geometry, color)) => (geometry, color.parse()?),
+            None => (s.trim(), Color::BLACK),
+        };
+        let values = geometry.split(',').map(str::trim).collect::<Vec<_>>();
+        let [blur, offset_x, offset_y] = values[..] else {
+            return Err(invalid());
+        };
+        let blur = blur.parse::<f32>().ok().filter(|b| b.is_finite() && *b >= 0.0).ok_or_else(invalid)?;
+        let offset_x = offset_x.parse().map_err(|_| invalid())?;
+        let offset_y = offset_y.parse().map_err(|_| invalid())?;
+        Ok(Self { blur, offset_x, offset_y, color })
+    }
+}
+
+/// External super-resolution program used for enlargements
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Upscaler {
+    /// Command line template; `{input}`, `{output}` and `{scale}` are
+    /// replaced with the tile paths and the whole-number factor
+    Command(String),
+    /// realesrgan-ncnn-vulkan, or a binary taking the same arguments
+    RealEsrganNcnn(PathBuf),
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ProcessConfig {
     pub width: u32,
     pub height: u32,
@@ -25,16 +488,146 @@ pub struct ProcessConfig {
     pub algorithm: ResizeAlgorithm,
     pub max_file_size: Option<u64>,
     pub format: Option<OutputFormat>,
+    pub tile_threshold: Option<u64>,
+    pub max_memory: Option<u64>,
+    pub dpi: Option<u32>,
+    pub no_upscale: bool,
+    /// Resample images with alpha in premultiplied form
+    pub premultiply_alpha: bool,
+    /// Resample in linear light rather than on sRGB-encoded values
+    pub linear: bool,
+    pub max_dimension: Option<u32>,
+    pub aspect: Option<AspectRatio>,
+    pub gravity: CropGravity,
+    pub exif_thumbnail: ExifThumbnail,
+    /// Turn the pixels upright per the EXIF Orientation tag when decoding;
+    /// kept EXIF then says Orientation 1
+    pub auto_orient: bool,
+    pub verify: bool,
+    /// AVIF encoder speed, 1 (smallest output) to 10 (fastest)
+    pub speed: Option<u8>,
+    pub tone_map: ToneMapOperator,
+    /// Exposure in stops for HDR inputs
+    pub exposure: f32,
+    /// Output sample depth, 8 or 16; `None` keeps the source depth where the
+    /// output format can hold it
+    pub bit_depth: Option<u8>,
+    /// Write CMYK instead of RGB; TIFF output only
+    pub cmyk: bool,
+    /// CMYK ICC profile used for the conversion and embedded in the output
+    pub cmyk_profile: Option<PathBuf>,
+    /// Hand enlargements to an external super-resolution tool
+    pub upscaler: Option<Upscaler>,
+    /// Denoise strength, 1 (light) to 10 (heavy)
+    pub denoise: Option<u8>,
+    pub denoise_method: DenoiseMethod,
+    pub auto_level: Option<AutoLevel>,
+    /// Percentage of samples clipped at each end by auto levels
+    pub level_clip: f32,
+    /// `.cube` 3D LUT applied as the last color step
+    pub lut: Option<PathBuf>,
+    /// Tones per color channel, 2 to 255
+    pub posterize: Option<u8>,
+    /// Brightness cutoff (0-255) for black-and-white output
+    pub threshold: Option<u8>,
+    /// Map brightness onto a gradient between two colors
+    pub duotone: Option<Duotone>,
+    /// Palette size, 2 to 256, for indexed PNG output
+    pub quantize: Option<u16>,
+    /// Rearrange channels, e.g. for BGR consumers
+    pub channel_order: Option<ChannelOrder>,
+    /// Output only this channel, as grayscale
+    pub channel: Option<Channel>,
+    /// Invert the color channels
+    pub invert: bool,
+    /// Give the output an alpha channel
+    pub add_alpha: Option<AlphaSource>,
+    /// Flatten transparency onto this background and drop the alpha channel
+    pub remove_alpha: Option<Color>,
+    /// Round the output's corners off to this radius in pixels
+    pub corner_radius: Option<u32>,
+    /// Cut the output to this shape
+    pub mask: Option<MaskShape>,
+    /// What the rounded-off corners, the outside of the mask and the
+    /// canvas around a shadow become in formats without alpha
+    pub mask_background: Color,
+    /// Drop shadow composited under the output
+    pub shadow: Option<Shadow>,
+    /// Canvas margin around the output on every side; room for the shadow
+    /// when unset
+    pub padding: Option<u32>,
+    /// Regions blanked out right after decoding, in order
+    pub redact: Vec<Redaction>,
+    /// Hide every face an external detector finds, in this style
+    pub blur_faces: Option<RedactStyle>,
+    /// Detector command template, `{input}` standing for the image path
+    pub face_detector: Option<String>,
+    /// Text drawn on the output; `{Tag}` is replaced with the input's EXIF value
+    pub caption: Option<String>,
+    /// TrueType font for the caption; a system font when unset
+    pub caption_font: Option<PathBuf>,
+    /// Caption height in pixels; 1/25 of the shorter side when unset
+    pub caption_size: Option<f32>,
+    pub caption_position: CropGravity,
+    /// Color distance, in percent, still treated as a match by color keys
+    /// and trimming
+    pub fuzz: f32,
+    /// Crop away uniform borders before resizing
+    pub trim: bool,
+    /// Write an original-vs-output image here; a directory in batch mode
+    pub comparison: Option<PathBuf>,
+    pub comparison_layout: ComparisonLayout,
+    /// Score each output against the image it was encoded from
+    pub measure_quality: bool,
+    /// Encode JPEG at the lowest quality, up to `quality`, whose output
+    /// reaches this SSIM; other formats ignore it
+    pub auto_quality: Option<f64>,
+    /// Save the input's EXIF and XMP next to each output before anything
+    /// is stripped
+    pub export_metadata: Option<SidecarFormat>,
+    /// Date outputs like their inputs
+    pub preserve_times: Option<TimeSource>,
+    /// Give outputs their input's permissions, owner and xattrs
+    pub preserve_attrs: bool,
+    /// Create missing directories on the way to the output
+    pub create_dirs: bool,
+    /// None leaves it to the command: generated output names are renamed,
+    /// paths that were asked for are overwritten
+    pub on_conflict: Option<ConflictPolicy>,
+    /// Decode truncated JPEGs instead of failing on them
+    pub salvage: Option<SalvageMode>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum OutputFormat {
     Jpeg,
     Png,
     WebP,
+    Avif,
+    Tga,
+    /// Binary PPM, or PGM for grayscale images
+    Pnm,
+    Qoi,
+    Tiff,
     SameAsInput,
 }
 
+impl OutputFormat {
+    pub fn image_format(&self) -> Option<image::ImageFormat> {
+        match self {
+            OutputFormat::Jpeg => Some(image::ImageFormat::Jpeg),
+            OutputFormat::Png => Some(image::ImageFormat::Png),
+            OutputFormat::WebP => Some(image::ImageFormat::WebP),
+            OutputFormat::Avif => Some(image::ImageFormat::Avif),
+            OutputFormat::Tga => Some(image::ImageFormat::Tga),
+            OutputFormat::Pnm => Some(image::ImageFormat::Pnm),
+            OutputFormat::Qoi => Some(image::ImageFormat::Qoi),
+            OutputFormat::Tiff => Some(image::ImageFormat::Tiff),
+            OutputFormat::SameAsInput => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ImageMetadata {
     pub width: u32,
@@ -44,12 +637,164 @@ pub struct ImageMetadata {
     pub file_size: u64,
 }
 
+impl ImageMetadata {
+    /// Reads only the file headers and EXIF block, never the pixel data, so
+    /// this stays fast however large the image is
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Err(ImageToolError::InvalidParameter(
+                format!("File does not exist: {}", path.display())
+            ));
+        }
+
+        let (width, height, format) = crate::processors::Loader::new().get_dimensions_and_format(path)?;
+        let has_exif = crate::processors::MetadataProcessor::new().has_metadata(path)?;
+
+        Ok(Self {
+            width,
+            height,
+            format,
+            has_exif,
+            file_size: std::fs::metadata(path)?.len(),
+        })
+    }
+}
+
+/// How closely an encoded output matches the image it was encoded from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityScore {
+    /// Structural similarity on luma, 1.0 for identical images
+    pub ssim: f64,
+    /// Peak signal-to-noise ratio in dB; infinite for identical images
+    pub psnr: f64,
+}
+
+/// Wall time one file spent in each processing stage
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StageTimings {
+    pub decode: Duration,
+    /// Tone mapping, trimming, cropping and resizing
+    pub resize: Duration,
+    /// The filters applied to the final-size image
+    pub filter: Duration,
+    /// Encoders that stream into the output file include that write here
+    pub encode: Duration,
+    /// Writing encoded bytes out, and rewriting the file to add EXIF
+    pub write: Duration,
+}
+
+impl StageTimings {
+    pub fn total(&self) -> Duration {
+        self.decode + self.resize + self.filter + self.encode + self.write
+    }
+}
+
+impl std::ops::AddAssign for StageTimings {
+    fn add_assign(&mut self, other: Self) {
+        self.decode += other.decode;
+        self.resize += other.resize;
+        self.filter += other.filter;
+        self.encode += other.encode;
+        self.write += other.write;
+    }
+}
+
+impl std::fmt::Display for StageTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "decode {:.1}ms, resize {:.1}ms, filter {:.1}ms, encode {:.1}ms, write {:.1}ms",
+            ms(self.decode),
+            ms(self.resize),
+            ms(self.filter),
+            ms(self.encode),
+            ms(self.write)
+        )
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ProcessingStats {
     pub processed_count: usize,
     pub total_size_before: u64,
     pub total_size_after: u64,
-    pub errors: Vec<(String, String)>,
+    /// Inputs that failed, with the error each one hit
+    pub errors: Vec<(PathBuf, ImageToolError)>,
+    /// Groups of identical inputs; only the first of each was processed
+    pub duplicates: Vec<Vec<PathBuf>>,
+    /// Per-output scores, when quality measurement is on
+    pub quality: Vec<(PathBuf, QualityScore)>,
+    /// Inputs skipped because the cache showed their output was up to date
+    pub cached_count: usize,
+    /// Inputs skipped by `skip = true` in their overrides file
+    pub skipped_count: usize,
+    /// Inputs skipped because they were not newer than their output
+    pub unchanged_count: usize,
+    /// Inputs skipped because their output already existed
+    pub existing_count: usize,
+    /// Inputs skipped because the checkpoint recorded them as done
+    pub resumed_count: usize,
+    /// Per-output time spent in each stage
+    pub timings: Vec<(PathBuf, StageTimings)>,
+    /// Wall time of the whole run, set by batch processing
+    pub elapsed: Duration,
+    /// Inputs never started because the run was cancelled
+    pub cancelled: Vec<PathBuf>,
+}
+
+impl ProcessingStats {
+    pub fn failed_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn was_cancelled(&self) -> bool {
+        !self.cancelled.is_empty()
+    }
+
+    /// Stage timings summed over every output
+    pub fn total_timings(&self) -> StageTimings {
+        let mut total = StageTimings::default();
+        for (_, timings) in &self.timings {
+            total += *timings;
+        }
+        total
+    }
+
+    /// Outputs produced per second of wall time
+    pub fn images_per_sec(&self) -> f64 {
+        per_second(self.processed_count as f64, self.elapsed)
+    }
+
+    /// Input bytes of the processed files per second of wall time
+    pub fn bytes_per_sec(&self) -> f64 {
+        per_second(self.total_size_before as f64, self.elapsed)
+    }
+
+    /// Mean of the per-file stage totals. Files overlap in a batch, so this
+    /// is usually longer than `elapsed` divided by the file count.
+    pub fn average_time(&self) -> Duration {
+        match self.timings.len() {
+            0 => Duration::ZERO,
+            n => self.total_timings().total() / n as u32,
+        }
+    }
+
+    /// The `n` outputs that took longest, slowest first
+    pub fn slowest(&self, n: usize) -> Vec<&(PathBuf, StageTimings)> {
+        let mut timings: Vec<_> = self.timings.iter().collect();
+        timings.sort_by_key(|(_, t)| std::cmp::Reverse(t.total()));
+        timings.truncate(n);
+        timings
+    }
+}
+
+fn per_second(amount: f64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        amount / elapsed.as_secs_f64()
+    }
 }
 
 impl Default for ProcessConfig {
@@ -64,6 +809,63 @@ impl Default for ProcessConfig {
             algorithm: ResizeAlgorithm::Lanczos3,
             max_file_size: None,
             format: None,
+            tile_threshold: Some(100_000_000),
+            max_memory: None,
+            dpi: None,
+            no_upscale: false,
+            premultiply_alpha: true,
+            linear: false,
+            max_dimension: None,
+            aspect: None,
+            gravity: CropGravity::Center,
+            exif_thumbnail: ExifThumbnail::Regenerate,
+            auto_orient: true,
+            verify: false,
+            speed: None,
+            tone_map: ToneMapOperator::Reinhard,
+            exposure: 0.0,
+            bit_depth: None,
+            cmyk: false,
+            cmyk_profile: None,
+            upscaler: None,
+            denoise: None,
+            denoise_method: DenoiseMethod::Bilateral,
+            auto_level: None,
+            level_clip: 0.5,
+            lut: None,
+            posterize: None,
+            quantize: None,
+            threshold: None,
+            duotone: None,
+            channel_order: None,
+            channel: None,
+            invert: false,
+            add_alpha: None,
+            remove_alpha: None,
+            corner_radius: None,
+            mask: None,
+            mask_background: Color::WHITE,
+            shadow: None,
+            padding: None,
+            redact: Vec::new(),
+            blur_faces: None,
+            face_detector: None,
+            caption: None,
+            caption_font: None,
+            caption_size: None,
+            caption_position: CropGravity::SouthEast,
+            fuzz: 0.0,
+            trim: false,
+            comparison: None,
+            comparison_layout: ComparisonLayout::SideBySide,
+            measure_quality: false,
+            auto_quality: None,
+            export_metadata: None,
+            preserve_times: None,
+            preserve_attrs: false,
+            create_dirs: true,
+            on_conflict: None,
+            salvage: None,
         }
     }
 }
@@ -76,18 +878,169 @@ impl ProcessConfig {
             ));
         }
 
+        if self.max_dimension.is_some() && (self.scale > 0.0 || self.width > 0 || self.height > 0) {
+            return Err(ImageToolError::InvalidParameter(
+                "Cannot combine max dimension with scale or width/height".to_string(),
+            ));
+        }
+
+        if self.max_dimension == Some(0) {
+            return Err(ImageToolError::InvalidParameter(
+                "Max dimension must be greater than zero".to_string(),
+            ));
+        }
+
         if self.width > 100_000 || self.height > 100_000 {
             return Err(ImageToolError::InvalidParameter(
                 "Dimensions too large (max 100,000 pixels)".to_string(),
             ));
         }
 
+        if !(0.0..=1_000.0).contains(&self.scale) {
+            return Err(ImageToolError::InvalidParameter(
+                "Scale must be between 0 and 1,000 percent".to_string(),
+            ));
+        }
+
+        if self.padding.is_some_and(|padding| padding > 10_000) {
+            return Err(ImageToolError::InvalidParameter(
+                "Padding too large (max 10,000 pixels)".to_string(),
+            ));
+        }
+
+        if self.corner_radius.is_some_and(|radius| radius > 100_000) {
+            return Err(ImageToolError::InvalidParameter(
+                "Corner radius too large (max 100,000 pixels)".to_string(),
+            ));
+        }
+
+        if let Some(shadow) = self.shadow {
+            let offset = shadow.offset_x.unsigned_abs().max(shadow.offset_y.unsigned_abs());
+            if !(0.0..=1_000.0).contains(&shadow.blur) || offset > 10_000 {
+                return Err(ImageToolError::InvalidParameter(
+                    "Shadow blur must be at most 1,000 pixels and its offset at most 10,000".to_string(),
+                ));
+            }
+        }
+
+        if self.redact.len() > 1_000 {
+            return Err(ImageToolError::InvalidParameter(
+                "Too many redaction regions (max 1,000)".to_string(),
+            ));
+        }
+
+        if self.dpi == Some(0) {
+            return Err(ImageToolError::InvalidParameter(
+                "DPI must be greater than zero".to_string(),
+            ));
+        }
+
+        if matches!(self.speed, Some(speed) if speed == 0 || speed > 10) {
+            return Err(ImageToolError::InvalidParameter(
+                "Encoder speed must be between 1 and 10".to_string(),
+            ));
+        }
+
+        if matches!(self.algorithm, ResizeAlgorithm::Lanczos(window) if window == 0 || window > 8) {
+            return Err(ImageToolError::InvalidParameter(
+                "Lanczos window must be between 1 and 8 lobes".to_string(),
+            ));
+        }
+
+        if matches!(self.denoise, Some(level) if level == 0 || level > 10) {
+            return Err(ImageToolError::InvalidParameter(
+                "Denoise level must be between 1 and 10".to_string(),
+            ));
+        }
+
+        if matches!(self.posterize, Some(levels) if levels < 2) {
+            return Err(ImageToolError::InvalidParameter(
+                "Posterize needs at least 2 levels".to_string(),
+            ));
+        }
+
+        if matches!(self.quantize, Some(colors) if !(2..=256).contains(&colors)) {
+            return Err(ImageToolError::InvalidParameter(
+                "Quantize needs between 2 and 256 colors".to_string(),
+            ));
+        }
+
+        if matches!(self.caption_size, Some(size) if size <= 0.0 || size.is_nan()) {
+            return Err(ImageToolError::InvalidParameter(
+                "Caption size must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.add_alpha.is_some() && self.remove_alpha.is_some() {
+            return Err(ImageToolError::InvalidParameter(
+                "Cannot both add and remove the alpha channel".to_string(),
+            ));
+        }
+
+        if self.add_alpha.is_some() && matches!(self.format, Some(OutputFormat::Jpeg | OutputFormat::Pnm)) {
+            return Err(ImageToolError::InvalidParameter(
+                "Transparency needs an output format with alpha, such as PNG or WebP".to_string(),
+            ));
+        }
+
+        if !(0.0..=100.0).contains(&self.fuzz) {
+            return Err(ImageToolError::InvalidParameter(
+                "Fuzz must be between 0 and 100 percent".to_string(),
+            ));
+        }
+
+        if !(0.0..50.0).contains(&self.level_clip) {
+            return Err(ImageToolError::InvalidParameter(
+                "Level clip must be at least 0 and below 50 percent".to_string(),
+            ));
+        }
+
+        if !matches!(self.bit_depth, None | Some(8) | Some(16)) {
+            return Err(ImageToolError::InvalidParameter(
+                "Bit depth must be 8 or 16".to_string(),
+            ));
+        }
+
+        if self.cmyk_profile.is_some() && !self.cmyk {
+            return Err(ImageToolError::InvalidParameter(
+                "A CMYK profile needs CMYK output".to_string(),
+            ));
+        }
+
+        if let Some(Upscaler::Command(template)) = &self.upscaler {
+            if !template.contains("{input}") || !template.contains("{output}") {
+                return Err(ImageToolError::InvalidParameter(
+                    "Upscaler command must contain {input} and {output}".to_string(),
+                ));
+            }
+        }
+
+        if self.face_detector.as_deref().is_some_and(|template| !template.contains("{input}")) {
+            return Err(ImageToolError::InvalidParameter(
+                "Face detector command must contain {input}".to_string(),
+            ));
+        }
+
+        if !self.exposure.is_finite() {
+            return Err(ImageToolError::InvalidParameter(
+                "Exposure must be a finite number of stops".to_string(),
+            ));
+        }
+
         if self.quality == 0 || self.quality > 100 {
             return Err(ImageToolError::InvalidParameter(
                 "Quality must be between 1 and 100".to_string(),
             ));
         }
 
+        if let Some(target) = self.auto_quality {
+            if !(target > 0.0 && target <= 1.0) {
+                return Err(ImageToolError::InvalidParameter(
+                    "Auto quality target SSIM must be greater than 0 and at most 1".to_string(),
+                ));
+            }
+        }
+
         Ok(())
     }
 }
@@ -114,10 +1067,58 @@ pub enum ImageToolError {
 
     #[error("Memory limit exceeded: {0}")]
     MemoryLimitExceeded(String),
+
+    #[error("Output locked: {0}")]
+    Locked(String),
 }
 
 pub type Result<T> = std::result::Result<T, ImageToolError>;
 
+/// Process exit codes used by the CLI
+pub mod exit_code {
+    pub const SUCCESS: u8 = 0;
+    /// Any failure without a more specific code
+    pub const FAILURE: u8 = 1;
+    /// Bad command line arguments or parameter values
+    pub const INVALID_ARGS: u8 = 2;
+    pub const UNSUPPORTED_FORMAT: u8 = 3;
+    /// A batch finished but some of its images failed
+    pub const PARTIAL_FAILURE: u8 = 4;
+    pub const IO_ERROR: u8 = 5;
+    /// Decoding, encoding or processing an image failed
+    pub const PROCESSING_ERROR: u8 = 6;
+    pub const SECURITY_ERROR: u8 = 7;
+    /// File size or memory limits were exceeded
+    pub const LIMIT_EXCEEDED: u8 = 8;
+    /// Another batch run holds the output directory's lock
+    pub const LOCKED: u8 = 9;
+    /// An audit found private metadata that is still in place
+    pub const PRIVATE_METADATA: u8 = 10;
+    /// Validation found a truncated or corrupt image
+    pub const BROKEN_IMAGE: u8 = 11;
+    /// A batch was stopped with Ctrl+C; 128 plus SIGINT, as shells report it
+    pub const INTERRUPTED: u8 = 130;
+}
+
+impl ImageToolError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ImageToolError::Io(_) => exit_code::IO_ERROR,
+            ImageToolError::Image(image::ImageError::IoError(_)) => exit_code::IO_ERROR,
+            ImageToolError::Image(image::ImageError::Unsupported(_)) => exit_code::UNSUPPORTED_FORMAT,
+            ImageToolError::Image(image::ImageError::Limits(_)) => exit_code::LIMIT_EXCEEDED,
+            ImageToolError::Image(image::ImageError::Parameter(_)) => exit_code::INVALID_ARGS,
+            ImageToolError::Image(_) => exit_code::PROCESSING_ERROR,
+            ImageToolError::InvalidParameter(_) => exit_code::INVALID_ARGS,
+            ImageToolError::UnsupportedFormat(_) => exit_code::UNSUPPORTED_FORMAT,
+            ImageToolError::ProcessingError(_) => exit_code::PROCESSING_ERROR,
+            ImageToolError::SecurityError(_) => exit_code::SECURITY_ERROR,
+            ImageToolError::MemoryLimitExceeded(_) => exit_code::LIMIT_EXCEEDED,
+            ImageToolError::Locked(_) => exit_code::LOCKED,
+        }
+    }
+}
+
 pub fn validate_config(config: &ProcessConfig) -> Result<()> {
     config.validate()
 }
\ No newline at end of file
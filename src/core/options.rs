@@ -0,0 +1,196 @@
+// pixie/src/core/options.rs
+use super::{AspectRatio, AlphaSource, AutoLevel, Channel, ChannelOrder, Color, CropGravity, DenoiseMethod, ImageToolError, MaskShape, OutputFormat, ProcessConfig, Quality, Result, ToneMapOperator};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+impl ProcessConfig {
+    /// A copy of this config with `options` laid over it. Keys are the CLI
+    /// flag names, as the server's query parameters, pipeline steps and
+    /// per-file overrides use them.
+    pub fn apply_options(&self, options: &HashMap<String, String>) -> Result<ProcessConfig> {
+        let mut config = self.clone();
+        // Both keys set the same field, and the map has no order to pick one by
+        exclusive(options, &["auto_level", "auto-level"], &["auto_contrast", "auto-contrast"])?;
+        exclusive(options, &["add_alpha", "add-alpha"], &["transparent_color", "transparent-color"])?;
+
+        for (key, value) in options {
+            let invalid = || ImageToolError::InvalidParameter(format!("Invalid value for {}: {}", key, value));
+            match key.as_str() {
+                "width" => config.width = value.parse().map_err(|_| invalid())?,
+                "height" => config.height = value.parse().map_err(|_| invalid())?,
+                "scale" => config.scale = value.parse().map_err(|_| invalid())?,
+                "quality" => {
+                    let quality: Quality = value.parse()?;
+                    config.quality = quality.value();
+                    config.auto_quality = quality.target_ssim();
+                }
+                "max_dimension" | "max-dimension" => {
+                    config.max_dimension = Some(value.parse().map_err(|_| invalid())?)
+                }
+                "aspect" => config.aspect = Some(value.parse::<AspectRatio>()?),
+                "gravity" => {
+                    config.gravity = match value.to_lowercase().replace(['-', '_'], "").as_str() {
+                        "center" => CropGravity::Center,
+                        "north" => CropGravity::North,
+                        "south" => CropGravity::South,
+                        "east" => CropGravity::East,
+                        "west" => CropGravity::West,
+                        "northeast" => CropGravity::NorthEast,
+                        "northwest" => CropGravity::NorthWest,
+                        "southeast" => CropGravity::SouthEast,
+                        "southwest" => CropGravity::SouthWest,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "keep_aspect" | "keep-aspect" => config.keep_aspect = parse_bool(value).ok_or_else(invalid)?,
+                "no_upscale" | "no-upscale" => config.no_upscale = parse_bool(value).ok_or_else(invalid)?,
+                "premultiply" => config.premultiply_alpha = parse_bool(value).ok_or_else(invalid)?,
+                "linear" => config.linear = parse_bool(value).ok_or_else(invalid)?,
+                "auto_orient" | "auto-orient" => config.auto_orient = parse_bool(value).ok_or_else(invalid)?,
+                "strip_metadata" | "strip-metadata" => {
+                    config.strip_metadata = parse_bool(value).ok_or_else(invalid)?
+                }
+                "speed" => config.speed = Some(value.parse().map_err(|_| invalid())?),
+                "tone_map" | "tone-map" => {
+                    config.tone_map = match value.to_lowercase().as_str() {
+                        "reinhard" => ToneMapOperator::Reinhard,
+                        "aces" => ToneMapOperator::Aces,
+                        "clamp" => ToneMapOperator::Clamp,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "denoise" => config.denoise = Some(value.parse().map_err(|_| invalid())?),
+                "denoise_method" | "denoise-method" => {
+                    config.denoise_method = match value.to_lowercase().replace(['-', '_'], "").as_str() {
+                        "median" => DenoiseMethod::Median,
+                        "bilateral" => DenoiseMethod::Bilateral,
+                        "nlmeans" => DenoiseMethod::NlMeans,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "auto_level" | "auto-level" => {
+                    config.auto_level = parse_bool(value).ok_or_else(invalid)?.then_some(AutoLevel::Levels)
+                }
+                "auto_contrast" | "auto-contrast" => {
+                    config.auto_level = parse_bool(value).ok_or_else(invalid)?.then_some(AutoLevel::Contrast)
+                }
+                "level_clip" | "level-clip" => config.level_clip = value.parse().map_err(|_| invalid())?,
+                "posterize" => config.posterize = Some(value.parse().map_err(|_| invalid())?),
+                "threshold" => config.threshold = Some(value.parse().map_err(|_| invalid())?),
+                "duotone" => config.duotone = Some(value.parse()?),
+                // A bare flag asks for the largest palette
+                "quantize" => {
+                    config.quantize = match value.parse::<u16>() {
+                        Ok(colors) => Some(colors),
+                        Err(_) => parse_bool(value).ok_or_else(invalid)?.then_some(256),
+                    }
+                }
+                "swap_channels" | "swap-channels" => config.channel_order = Some(value.parse::<ChannelOrder>()?),
+                "channel" => {
+                    config.channel = Some(match value.to_lowercase().as_str() {
+                        "r" | "red" => Channel::Red,
+                        "g" | "green" => Channel::Green,
+                        "b" | "blue" => Channel::Blue,
+                        "a" | "alpha" => Channel::Alpha,
+                        _ => return Err(invalid()),
+                    })
+                }
+                // Mask files stay CLI-only; options take color keys
+                "add_alpha" | "add-alpha" => config.add_alpha = Some(AlphaSource::ColorKey(value.parse()?)),
+                "transparent_color" | "transparent-color" => {
+                    config.add_alpha = Some(AlphaSource::ColorKey(value.parse()?))
+                }
+                // Several regions are separated by ';', as one key can't repeat
+                "redact" => {
+                    for region in value.split(';').filter(|region| !region.is_empty()) {
+                        config.redact.push(region.parse()?);
+                    }
+                }
+                "trim" => config.trim = parse_bool(value).ok_or_else(invalid)?,
+                "fuzz" => config.fuzz = value.trim_end_matches('%').parse().map_err(|_| invalid())?,
+                "remove_alpha" | "remove-alpha" => {
+                    config.remove_alpha = match parse_bool(value) {
+                        Some(remove) => remove.then_some(Color::WHITE),
+                        None => Some(value.parse()?),
+                    }
+                }
+                "corner_radius" | "corner-radius" => config.corner_radius = Some(value.parse().map_err(|_| invalid())?),
+                "mask" => {
+                    config.mask = Some(match value.to_lowercase().as_str() {
+                        "circle" => MaskShape::Circle,
+                        "ellipse" => MaskShape::Ellipse,
+                        _ => return Err(invalid()),
+                    })
+                }
+                "mask_background" | "mask-background" => config.mask_background = value.parse()?,
+                "shadow" => config.shadow = Some(value.parse()?),
+                "padding" => config.padding = Some(value.parse().map_err(|_| invalid())?),
+                "caption" => config.caption = Some(value.to_string()),
+                "caption_size" | "caption-size" => config.caption_size = Some(value.parse().map_err(|_| invalid())?),
+                "invert" => config.invert = parse_bool(value).ok_or_else(invalid)?,
+                "bit_depth" | "bit-depth" => config.bit_depth = Some(value.parse().map_err(|_| invalid())?),
+                "exposure" => config.exposure = value.parse().map_err(|_| invalid())?,
+                "dpi" => config.dpi = Some(value.parse().map_err(|_| invalid())?),
+                "format" => {
+                    config.format = Some(match value.to_lowercase().as_str() {
+                        "jpeg" | "jpg" => OutputFormat::Jpeg,
+                        "png" => OutputFormat::Png,
+                        "webp" => OutputFormat::WebP,
+                        "avif" => OutputFormat::Avif,
+                        "tga" => OutputFormat::Tga,
+                        "pnm" | "ppm" | "pgm" => OutputFormat::Pnm,
+                        "qoi" => OutputFormat::Qoi,
+                        "tiff" | "tif" => OutputFormat::Tiff,
+                        "same" => OutputFormat::SameAsInput,
+                        _ => return Err(invalid()),
+                    })
+                }
+                _ => {
+                    return Err(ImageToolError::InvalidParameter(format!("Unknown parameter: {}", key)))
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn exclusive(options: &HashMap<String, String>, first: &[&str], second: &[&str]) -> Result<()> {
+    let given = |keys: &[&str]| keys.iter().find(|key| options.contains_key(**key)).map(|key| key.to_string());
+    match (given(first), given(second)) {
+        (Some(a), Some(b)) => Err(ImageToolError::InvalidParameter(format!("{} and {} can't be used together", a, b))),
+        _ => Ok(()),
+    }
+}
+
+/// An option value as a TOML or YAML file writes it; `apply_options`
+/// takes it as text
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum OptionValue {
+    Flag(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionValue::Flag(flag) => write!(f, "{}", flag),
+            OptionValue::Integer(number) => write!(f, "{}", number),
+            OptionValue::Float(number) => write!(f, "{}", number),
+            OptionValue::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "" | "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
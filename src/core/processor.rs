@@ -1,7 +1,25 @@
 // pixie/src/core/processor.rs
-use super::{ImageToolError, ProcessConfig, Result, ImageMetadata, ProcessingStats};
-use crate::processors::{Loader, Resizer, Compressor, MetadataProcessor};
+use super::{AspectRatio, ConflictPolicy, MaskShape, ExifThumbnail, ImageToolError, ProcessConfig, ResizeAlgorithm, Result, ImageMetadata, Phase, ProcessingStats, Progress, ProgressHook, ProcessingStage, Redaction, StageContext, StageTimings, TimeSource};
+use crate::processors::{Canvas, Captioner, Cropper, Loader, Resizer, Compressor, MetadataExporter, MetadataProcessor, RemoteFetcher, ResizeMode, TiledLoader, ToneMapper, ExternalUpscaler, Redactor, FaceDetector, Flattener, Comparison, QualityMeter, QualitySearch, builtin_stages};
+use crate::utils::{copy_file_attrs, format_extension, generate_output_path, is_remote_url, resolve_conflict, set_file_time};
+use exif::Exif;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageFormat};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+// Lowest quality auto mode will try; below it JPEG artifacts show on
+// almost any content
+const AUTO_QUALITY_MIN: u8 = 40;
+
+// Output of `process_loaded`, not yet written anywhere
+pub(crate) struct Encoded {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub timings: StageTimings,
+}
 
 pub struct ImageProcessor {
     config: ProcessConfig,
@@ -9,27 +27,137 @@ pub struct ImageProcessor {
     resizer: Resizer,
     compressor: Compressor,
     metadata_processor: MetadataProcessor,
+    tiled_loader: TiledLoader,
+    tone_mapper: ToneMapper,
+    upscaler: Option<ExternalUpscaler>,
+    stages: Vec<Arc<dyn ProcessingStage>>,
+    progress: Option<ProgressHook>,
 }
 
 impl ImageProcessor {
     pub fn new(config: ProcessConfig) -> Self {
-        let resizer = Resizer::new(config.algorithm, config.keep_aspect);
-        let compressor = Compressor::new(config.quality);
+        let resizer = Resizer::new(config.algorithm, config.keep_aspect)
+            .with_no_upscale(config.no_upscale)
+            .with_premultiply(config.premultiply_alpha)
+            .with_linear(config.linear);
+        let compressor = compressor_at(&config, config.quality);
         let metadata_processor = MetadataProcessor::new();
+        let tiled_loader = TiledLoader::new().with_linear(config.linear);
+        let upscaler = config.upscaler.clone().map(ExternalUpscaler::new);
+        let stages = builtin_stages(&config);
+        let tone_mapper = ToneMapper::new(config.tone_map).with_exposure(config.exposure);
+        let loader = match config.max_memory {
+            Some(max_memory) => Loader::new().with_max_memory(max_memory),
+            None => Loader::new(),
+        }
+        .with_salvage(config.salvage);
 
         Self {
             config,
-            loader: Loader::new(),
+            loader,
             resizer,
             compressor,
             metadata_processor,
+            tiled_loader,
+            upscaler,
+            stages,
+            tone_mapper,
+            progress: None,
+        }
+    }
+
+    /// Report the phase each image is in; meant for one image at a time,
+    /// as phases from concurrent calls would interleave
+    pub fn with_progress_hook(mut self, hook: ProgressHook) -> Self {
+        self.progress = Some(hook);
+        self
+    }
+
+    /// Run `stage` after the built-in filters and any stages added before it
+    pub fn with_stage(mut self, stage: Arc<dyn ProcessingStage>) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Run `stage` just before the stage named `before`, or last when no
+    /// stage has that name
+    pub fn with_stage_before(mut self, before: &str, stage: Arc<dyn ProcessingStage>) -> Self {
+        let index = self.stages.iter().position(|s| s.name() == before).unwrap_or(self.stages.len());
+        self.stages.insert(index, stage);
+        self
+    }
+
+    /// Names of the stages in the order they run
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    fn report(&self, phase: Phase) {
+        if let Some(hook) = &self.progress {
+            hook(Progress::Phase(phase));
         }
     }
 
     pub fn process<P: AsRef<Path>>(&self, input_path: P, output_path: P) -> Result<ProcessingStats> {
+        if let Some(url) = input_path.as_ref().to_str().filter(|s| is_remote_url(s)) {
+            return self.process_url(url, output_path.as_ref());
+        }
         self.process_single(input_path, output_path)
     }
 
+    pub fn process_url(&self, url: &str, output_path: &Path) -> Result<ProcessingStats> {
+        let fetcher = match self.config.max_file_size {
+            Some(max_size) => RemoteFetcher::new().with_max_bytes(max_size),
+            None => RemoteFetcher::new(),
+        };
+        let data = fetcher.fetch(url)?;
+
+        self.process_bytes_to_file(&data, output_path)
+    }
+
+    // The output format follows the requested format, then the output
+    // extension, then the input format.
+    pub fn process_bytes_to_file(&self, data: &[u8], output_path: &Path) -> Result<ProcessingStats> {
+        if output_path.to_string_lossy().contains("..") {
+            return Err(ImageToolError::SecurityError(
+                "Path traversal detected in output path".to_string()
+            ));
+        }
+
+        let fallback_format = ImageFormat::from_path(output_path).ok();
+        let (Encoded { data: encoded, width, height, .. }, _) =
+            self.process_bytes_with_fallback(data, fallback_format)?;
+
+        if let Some(parent) = output_path.parent().filter(|_| self.config.create_dirs) {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.export_metadata(data, output_path)?;
+        std::fs::write(output_path, &encoded)?;
+        if let Some(time) = self.source_time(None, Some(data)) {
+            set_file_time(output_path, time)?;
+        }
+
+        if self.config.verify {
+            self.verify_output(output_path, width, height)?;
+        }
+
+        if self.config.measure_quality {
+            log::warn!("--measure-quality is only supported for local files, skipping");
+        }
+
+        if self.config.comparison.is_some() {
+            let original = image::load_from_memory(data)?;
+            self.save_comparison(&original, &image::load_from_memory(&encoded)?)?;
+        }
+
+        Ok(ProcessingStats {
+            processed_count: 1,
+            total_size_before: data.len() as u64,
+            total_size_after: encoded.len() as u64,
+            ..Default::default()
+        })
+    }
+
     pub fn process_single<P: AsRef<Path>>(
         &self,
         input_path: P,
@@ -40,78 +168,918 @@ impl ImageProcessor {
 
         self.validate_paths(input_path, output_path)?;
 
-        // Load image with memory limit check
         let original_size = std::fs::metadata(input_path)?.len();
-        if let Some(max_size) = self.config.max_file_size {
-            if original_size > max_size {
-                return Err(ImageToolError::MemoryLimitExceeded(
-                    format!("File size {} exceeds limit {}", original_size, max_size)
-                ));
-            }
+        self.check_input_size(original_size)?;
+
+        if self.config.export_metadata.is_some() {
+            self.export_metadata(&std::fs::read(input_path)?, output_path)?;
         }
 
-        let mut image = self.loader.load(input_path)?;
+        self.report(Phase::Decode);
+        let decode_start = Instant::now();
+        let mut image = self.decode(input_path, self.resize_mode())?;
+        let mut timings = StageTimings {
+            decode: decode_start.elapsed(),
+            ..Default::default()
+        };
         
         // Strip metadata if requested
         if self.config.strip_metadata {
             self.metadata_processor.strip_metadata(&mut image, input_path)?;
         }
 
-        // Resize if needed
-        if self.config.width > 0 || self.config.height > 0 || self.config.scale > 0.0 {
-            let mode = if self.config.scale > 0.0 {
-                crate::processors::ResizeMode::Scale(self.config.scale)
-            } else {
-                crate::processors::ResizeMode::Absolute(self.config.width, self.config.height)
-            };
-            
-            image = self.resizer.resize(&image, mode);
+        // Before the comparison copy, so it doesn't show what was hidden
+        image = self.redact(self.tone_map(image))?;
+        let original = self.config.comparison.as_ref().map(|_| image.clone());
+
+        image = self.process_image_timed(image, Some(input_path), &mut timings)?;
+        let exif = self.caption_exif(|| self.metadata_processor.read_metadata(input_path));
+        image = self.caption(image, exif.as_ref())?;
+
+        let output_format = self.output_format(input_path, Some(output_path))?;
+        image = self.fill_masked(image, output_format);
+
+        // Compress and save
+        self.report(Phase::Encode);
+        let encode_start = Instant::now();
+        match self.auto_quality(&image, output_format)? {
+            Some((quality, _)) => {
+                compressor_at(&self.config, quality).save_with_format(&image, output_path, output_format)?
+            }
+            None => self.compressor.save_with_format(&image, output_path, output_format)?,
+        }
+        timings.encode = encode_start.elapsed();
+
+        if !self.config.strip_metadata && output_format == ImageFormat::Jpeg {
+            self.report(Phase::Write);
+            let write_start = Instant::now();
+            self.carry_over_exif(input_path, output_path, &image)?;
+            timings.write = write_start.elapsed();
+        }
+
+        if self.config.verify {
+            self.verify_output(output_path, image.width(), image.height())?;
         }
 
-        // Determine output format
-        let output_format = match self.config.format {
-            Some(crate::core::OutputFormat::Jpeg) => image::ImageFormat::Jpeg,
-            Some(crate::core::OutputFormat::Png) => image::ImageFormat::Png,
-            Some(crate::core::OutputFormat::WebP) => image::ImageFormat::WebP,
-            _ => self.loader.detect_format(input_path)?,
+        let written = if original.is_some() || self.config.measure_quality {
+            Some(self.loader.load(output_path)?)
+        } else {
+            None
         };
 
-        // Compress and save
-        self.compressor.save_with_format(&image, output_path, output_format)?;
+        if let (Some(original), Some(written)) = (&original, &written) {
+            self.save_comparison(original, written)?;
+        }
+
+        if let Some(time) = self.source_time(Some(input_path), None) {
+            set_file_time(output_path, time)?;
+        }
+        if self.config.preserve_attrs {
+            copy_file_attrs(input_path, output_path)?;
+        }
 
         let new_size = std::fs::metadata(output_path)?.len();
         
-        let mut stats = ProcessingStats::default();
-        stats.processed_count = 1;
-        stats.total_size_before = original_size;
-        stats.total_size_after = new_size;
+        let mut stats = ProcessingStats {
+            processed_count: 1,
+            total_size_before: original_size,
+            total_size_after: new_size,
+            ..Default::default()
+        };
+
+        log::debug!("{}: {}", output_path.display(), timings);
+        stats.timings.push((output_path.to_path_buf(), timings));
+
+        // Against the image handed to the encoder, so only encoding loss counts
+        if let Some(written) = written.as_ref().filter(|_| self.config.measure_quality) {
+            let score = QualityMeter::new().measure(&image, written)?;
+            log::info!("{}: SSIM {:.4}, PSNR {:.2} dB", output_path.display(), score.ssim, score.psnr);
+            stats.quality.push((output_path.to_path_buf(), score));
+        }
         
         Ok(stats)
     }
 
-    pub fn get_metadata<P: AsRef<Path>>(&self, path: P) -> Result<ImageMetadata> {
-        let path = path.as_ref();
-        
-        if !path.exists() {
+    // Decodes the input once and writes one output per width into
+    // `output_dir` as `{stem}_{width}w.{ext}`.
+    pub fn process_sizes<P: AsRef<Path>>(
+        &self,
+        input_path: P,
+        output_dir: P,
+        widths: &[u32],
+    ) -> Result<ProcessingStats> {
+        let input_path = input_path.as_ref();
+        let output_dir = output_dir.as_ref();
+
+        if input_path.to_str().is_some_and(is_remote_url) {
             return Err(ImageToolError::InvalidParameter(
-                format!("File does not exist: {}", path.display())
+                "Multiple sizes need a local input file".to_string()
             ));
         }
 
-        let metadata = std::fs::metadata(path)?;
-        let (width, height, format) = self.loader.get_dimensions_and_format(path)?;
-        let has_exif = self.metadata_processor.has_metadata(path)?;
+        if widths.iter().all(|&w| w == 0) {
+            return Err(ImageToolError::InvalidParameter(
+                "At least one output width must be greater than zero".to_string()
+            ));
+        }
 
-        Ok(ImageMetadata {
-            width,
-            height,
-            format,
-            has_exif,
-            file_size: metadata.len(),
+        let original_size = std::fs::metadata(input_path)?.len();
+        self.check_input_size(original_size)?;
+
+        // Decoding can only shrink towards the largest width when nothing
+        // is cut away before the resize
+        let largest = widths.iter().copied().max().unwrap_or(0);
+        let reduction = Some(ResizeMode::Absolute(largest, 0))
+            .filter(|_| !self.config.trim && self.crop_aspect().is_none());
+
+        self.report(Phase::Decode);
+        let mut image = self.decode(input_path, reduction)?;
+        if self.config.strip_metadata {
+            self.metadata_processor.strip_metadata(&mut image, input_path)?;
+        }
+        let image = self.crop_for_resize(self.redact(self.tone_map(image))?);
+
+        let output_format = self.output_format(input_path, None)?;
+        let extension = format_extension(output_format);
+        let stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("image");
+
+        let mut stats = ProcessingStats {
+            total_size_before: original_size,
+            ..Default::default()
+        };
+
+        self.report(Phase::Resize);
+        let exif = self.caption_exif(|| self.metadata_processor.read_metadata(input_path));
+        for (width, resized) in self.render_sizes(&image, widths)? {
+            let resized = self.caption(resized, exif.as_ref())?;
+            let resized = self.fill_masked(resized, output_format);
+            let output_path = output_dir.join(format!("{}_{}w.{}", stem, width, extension));
+            let policy = self.config.on_conflict.unwrap_or(ConflictPolicy::Overwrite);
+            let Some(output_path) = resolve_conflict(&output_path, policy)? else {
+                log::info!("{} already exists, skipping", output_path.display());
+                continue;
+            };
+            self.validate_paths(input_path, &output_path)?;
+            self.report(Phase::Encode);
+            match self.auto_quality(&resized, output_format)? {
+                Some((quality, _)) => {
+                    compressor_at(&self.config, quality).save_with_format(&resized, &output_path, output_format)?
+                }
+                None => self.compressor.save_with_format(&resized, &output_path, output_format)?,
+            }
+            if self.config.verify {
+                self.verify_output(&output_path, resized.width(), resized.height())?;
+            }
+
+            stats.processed_count += 1;
+            stats.total_size_after += std::fs::metadata(&output_path)?.len();
+        }
+
+        Ok(stats)
+    }
+
+    // The in-memory part of `process_single`, for callers doing their own
+    // IO: `data` holds the contents of `input_path`, and the result is what
+    // `process_single` would have written to `output_path`. Only valid when
+    // `can_process_loaded` agrees.
+    pub(crate) fn process_loaded(&self, input_path: &Path, data: &[u8], output_path: &Path) -> Result<Encoded> {
+        self.validate_names(input_path, output_path)?;
+        if let Some(parent) = output_path.parent().filter(|_| self.config.create_dirs) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        self.check_input_size(data.len() as u64)?;
+
+        if self.loader.max_memory().is_some() {
+            self.loader.check_memory_budget(self.loader.estimate_memory_from_bytes(data)?)?;
+        }
+
+        let decode_start = Instant::now();
+        let mut image = self.loader.load_from_bytes(data)?;
+        image.apply_orientation(self.orientation(|| self.metadata_processor.read_metadata_from_bytes(data)));
+        let image = self.redact(self.tone_map(image))?;
+        let mut timings = StageTimings {
+            decode: decode_start.elapsed(),
+            ..Default::default()
+        };
+
+        let image = self.process_image_timed(image, Some(input_path), &mut timings)?;
+        let exif = self.caption_exif(|| self.metadata_processor.read_metadata_from_bytes(data));
+        let image = self.caption(image, exif.as_ref())?;
+        let output_format = self.output_format(input_path, Some(output_path))?;
+        let image = self.fill_masked(image, output_format);
+
+        let encode_start = Instant::now();
+        let mut encoded = match self.auto_quality(&image, output_format)? {
+            Some((_, encoded)) => encoded,
+            None => self.compressor.compress_to_bytes(&image, output_format)?,
+        };
+
+        if !self.config.strip_metadata && output_format == ImageFormat::Jpeg {
+            let exif = self.metadata_processor.read_metadata_from_bytes(data);
+            if let Some(tiff) = self.exif_for(exif, input_path, &image)? {
+                encoded = self.metadata_processor.embed_exif_jpeg(&encoded, &tiff)?;
+            }
+        }
+        timings.encode = encode_start.elapsed();
+
+        Ok(Encoded {
+            data: encoded,
+            width: image.width(),
+            height: image.height(),
+            timings,
         })
     }
 
+    // Whether `process_loaded` gives the same output as `process_single`.
+    // Quality scores and comparisons are taken from the written file, the
+    // reduced-size JPEG and strip decoders read the input file themselves,
+    // and the PNM and WebP writers depend on the output path.
+    pub(crate) fn can_process_loaded(&self, input_path: &Path, output_path: &Path) -> bool {
+        if self.config.measure_quality || self.config.comparison.is_some() {
+            return false;
+        }
+
+        let input_format = self.loader.detect_format(input_path).ok();
+        if self.resize_mode().is_some() {
+            if cfg!(feature = "jpeg-scale") && input_format == Some(ImageFormat::Jpeg) {
+                return false;
+            }
+            let may_tile = self.config.tile_threshold.is_some() || self.loader.max_memory().is_some();
+            if may_tile && input_format.is_some_and(|format| self.tiled_loader.supports(format)) {
+                return false;
+            }
+        }
+
+        !matches!(
+            self.output_format(input_path, Some(output_path)),
+            Ok(ImageFormat::WebP | ImageFormat::Pnm) | Err(_)
+        )
+    }
+
+    // Resizes from the largest width downwards, each step starting from the
+    // previous intermediate instead of the full-size source. Heights are
+    // always derived from the source aspect ratio to avoid rounding drift.
+    pub fn render_sizes(&self, image: &DynamicImage, widths: &[u32]) -> Result<Vec<(u32, DynamicImage)>> {
+        let (orig_w, orig_h) = (image.width(), image.height());
+        let mut widths: Vec<u32> = widths
+            .iter()
+            .copied()
+            .filter(|&w| w > 0 && !(self.config.no_upscale && w > orig_w))
+            .collect();
+        widths.sort_unstable_by(|a, b| b.cmp(a));
+        widths.dedup();
+
+        let mut results: Vec<(u32, DynamicImage)> = Vec::with_capacity(widths.len());
+
+        for width in widths {
+            let height = ((orig_h as f64 * width as f64 / orig_w as f64).round() as u32).max(1);
+            let source = results.last().map(|(_, img)| img).unwrap_or(image);
+            let resized = match self.upscale(source, width, height)? {
+                Some(upscaled) => upscaled,
+                None => self.resizer.resize_exact(source, width, height),
+            };
+            results.push((width, resized));
+        }
+
+        // Each size is resized from the unfiltered one above it
+        results.into_iter().map(|(width, image)| Ok((width, self.adjust(image)?))).collect()
+    }
+
+    /// Processes an encoded image held in memory, returning the encoded
+    /// output. Unlike `process`, the whole image is decoded at once: there
+    /// is no tiled or reduced-size JPEG decoding, though `max_memory` is
+    /// still checked against the estimated decode size first. The input's
+    /// EXIF is not carried over, and `--verify` decodes the returned bytes.
+    pub fn process_bytes(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.process_bytes_with_format(data).map(|(encoded, _)| encoded)
+    }
+
+    /// `process_bytes`, also returning the format the output was encoded
+    /// in: the requested one, or else the input's
+    pub fn process_bytes_with_format(&self, data: &[u8]) -> Result<(Vec<u8>, ImageFormat)> {
+        let (encoded, format) = self.process_bytes_with_fallback(data, None)?;
+        if self.config.verify {
+            self.verify_encoded(&encoded.data, &"processed bytes", encoded.width, encoded.height)?;
+        }
+        Ok((encoded.data, format))
+    }
+
+    fn process_bytes_with_fallback(
+        &self,
+        data: &[u8],
+        fallback_format: Option<ImageFormat>,
+    ) -> Result<(Encoded, ImageFormat)> {
+        if let Some(max_size) = self.config.max_file_size {
+            if data.len() as u64 > max_size {
+                return Err(ImageToolError::MemoryLimitExceeded(
+                    format!("Input size {} exceeds limit {}", data.len(), max_size)
+                ));
+            }
+        }
+
+        let input_format = image::guess_format(data).map_err(|_| {
+            ImageToolError::UnsupportedFormat("Could not detect format of input bytes".to_string())
+        })?;
+
+        if self.loader.max_memory().is_some() {
+            self.loader.check_memory_budget(self.loader.estimate_memory_from_bytes(data)?)?;
+        }
+
+        let mut image = self.loader.load_from_bytes(data)?;
+        image.apply_orientation(self.orientation(|| self.metadata_processor.read_metadata_from_bytes(data)));
+        let image = self.process_image(image)?;
+        let exif = self.caption_exif(|| self.metadata_processor.read_metadata_from_bytes(data));
+        let image = self.caption(image, exif.as_ref())?;
+
+        let output_format = self.requested_format()
+            .or(fallback_format)
+            .unwrap_or(match input_format {
+                // Tone mapped to 8 bits, so HDR can't be written back out
+                ImageFormat::Hdr | ImageFormat::OpenExr => ImageFormat::Jpeg,
+                format => format,
+            });
+        let image = self.fill_masked(image, output_format);
+        let data = match self.auto_quality(&image, output_format)? {
+            Some((_, encoded)) => encoded,
+            None => self.compressor.compress_to_bytes(&image, output_format)?,
+        };
+        let encoded = Encoded {
+            data,
+            width: image.width(),
+            height: image.height(),
+            timings: StageTimings::default(),
+        };
+        Ok((encoded, output_format))
+    }
+
+    // The lowest quality, up to the configured one, whose output reaches
+    // the auto quality target, with the bytes it encoded to. JPEG only:
+    // WebP is written lossless, so its quality changes nothing.
+    fn auto_quality(&self, image: &DynamicImage, format: ImageFormat) -> Result<Option<(u8, Vec<u8>)>> {
+        let Some(target) = self.config.auto_quality else {
+            return Ok(None);
+        };
+        if format != ImageFormat::Jpeg {
+            log::debug!("Auto quality only applies to JPEG output, skipping");
+            return Ok(None);
+        }
+
+        let (quality, encoded) = QualitySearch::new(target)
+            .with_range(AUTO_QUALITY_MIN, self.config.quality)
+            .search(image, |quality| compressor_at(&self.config, quality).compress_to_bytes(image, format))?;
+        log::info!("Auto quality: {} (target SSIM {})", quality, target);
+        Ok(Some((quality, encoded)))
+    }
+
+    // The time `--preserve-times` gives the output: from the input file, or
+    // only a capture date from `data` when there is no file
+    pub(crate) fn source_time(&self, input_path: Option<&Path>, data: Option<&[u8]>) -> Option<SystemTime> {
+        let source = self.config.preserve_times?;
+        let modified = || input_path.and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+        match source {
+            TimeSource::Modified => modified(),
+            TimeSource::Captured => {
+                let exif = match (data, input_path) {
+                    (Some(data), _) => self.metadata_processor.read_metadata_from_bytes(data),
+                    (None, Some(path)) => self.metadata_processor.read_metadata(path),
+                    (None, None) => Ok(None),
+                };
+                let captured = exif.ok().flatten().and_then(|exif| self.metadata_processor.capture_time(&exif));
+                captured.or_else(modified)
+            }
+        }
+    }
+
+    // Saves the input's metadata in a sidecar next to `output_path`. Done
+    // before the output is written, so stripping never loses anything.
+    fn export_metadata(&self, data: &[u8], output_path: &Path) -> Result<()> {
+        let Some(format) = self.config.export_metadata else {
+            return Ok(());
+        };
+        let exporter = MetadataExporter::new(format);
+        match exporter.export(data)? {
+            Some(sidecar) => {
+                let path = exporter.sidecar_path(output_path);
+                if let Some(parent) = path.parent().filter(|_| self.config.create_dirs) {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, sidecar)?;
+                log::info!("Saved metadata: {}", path.display());
+            }
+            None => log::debug!("No metadata to export for {}", output_path.display()),
+        }
+        Ok(())
+    }
+
+    fn caption(&self, image: DynamicImage, exif: Option<&Exif>) -> Result<DynamicImage> {
+        let Some(template) = &self.config.caption else {
+            return Ok(image);
+        };
+        let captioner = Captioner::new(self.config.caption_font.as_deref())?
+            .with_size(self.config.caption_size)
+            .with_gravity(self.config.caption_position);
+        let text = Captioner::text(template, exif);
+        Ok(captioner.apply(&image, &text))
+    }
+
+    // The input's EXIF, read only when the caption refers to it
+    fn caption_exif(&self, read: impl FnOnce() -> Result<Option<Exif>>) -> Option<Exif> {
+        if !self.config.caption.as_deref().is_some_and(|template| template.contains('{')) {
+            return None;
+        }
+        read().unwrap_or_else(|e| {
+            log::debug!("No EXIF for the caption: {}", e);
+            None
+        })
+    }
+
+    /// Turns `image`, decoded from `input_path`, upright per its EXIF
+    /// Orientation tag; unchanged when auto-orientation is off
+    pub fn orient(&self, mut image: DynamicImage, input_path: &Path) -> DynamicImage {
+        image.apply_orientation(self.orientation(|| self.metadata_processor.read_metadata(input_path)));
+        image
+    }
+
+    // How the decoded pixels have to be turned to stand upright
+    fn orientation(&self, read: impl FnOnce() -> Result<Option<Exif>>) -> Orientation {
+        if !self.config.auto_orient {
+            return Orientation::NoTransforms;
+        }
+        let exif = read().unwrap_or_else(|e| {
+            log::debug!("No EXIF orientation: {}", e);
+            None
+        });
+        exif.and_then(|exif| self.metadata_processor.orientation(&exif))
+            .and_then(Orientation::from_exif)
+            .unwrap_or(Orientation::NoTransforms)
+    }
+
+    pub fn process_image(&self, image: DynamicImage) -> Result<DynamicImage> {
+        let image = self.redact(self.tone_map(image))?;
+        self.process_image_timed(image, None, &mut StageTimings::default())
+    }
+
+    // `process_image`, recording how long resizing and filtering took
+    fn process_image_timed(
+        &self,
+        image: DynamicImage,
+        input_path: Option<&Path>,
+        timings: &mut StageTimings,
+    ) -> Result<DynamicImage> {
+        self.report(Phase::Resize);
+        let resize_start = Instant::now();
+        let image = self.crop_for_resize(self.tone_map(image));
+
+        // Resize if needed
+        let image = match self.resize_mode() {
+            Some(mode) => self.resize(image, mode)?,
+            None => image,
+        };
+        timings.resize = resize_start.elapsed();
+
+        self.report(Phase::Filter);
+        let filter_start = Instant::now();
+        let image = self.run_stages(image, input_path)?;
+        timings.filter = filter_start.elapsed();
+        Ok(image)
+    }
+
+    // Trimming and cropping to the target aspect ratio, which come before
+    // the resize
+    fn crop_for_resize(&self, image: DynamicImage) -> DynamicImage {
+        // Scanner margins and letterboxing go before any other cropping
+        let image = if self.config.trim {
+            Cropper::default().trim(&image, self.config.fuzz)
+        } else {
+            image
+        };
+
+        match self.crop_aspect() {
+            Some(aspect) => Cropper::new(self.config.gravity).crop_to_aspect(&image, aspect),
+            None => image,
+        }
+    }
+
+    // Circles start from a square so the resize doesn't stretch them
+    fn crop_aspect(&self) -> Option<AspectRatio> {
+        match self.config.mask {
+            Some(MaskShape::Circle) => Some(self.config.aspect.unwrap_or(AspectRatio::new(1, 1))),
+            _ => self.config.aspect,
+        }
+    }
+
+    /// Size of the image a `width`x`height` input comes out at, before any
+    /// trimming; lets callers refuse oversized output without decoding
+    pub fn output_dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+        let (width, height) = match self.crop_aspect() {
+            Some(aspect) => Cropper::new(self.config.gravity).aspect_box(width, height, aspect),
+            None => (width, height),
+        };
+        let (width, height) = match self.resize_mode() {
+            Some(mode) => self.resizer.target_dimensions(width, height, mode),
+            None => (width, height),
+        };
+        let margin = Canvas::new(self.config.padding).with_shadow(self.config.shadow).margin();
+        (
+            width.saturating_add(margin.saturating_mul(2)),
+            height.saturating_add(margin.saturating_mul(2)),
+        )
+    }
+
+    fn resize(&self, image: DynamicImage, mode: ResizeMode) -> Result<DynamicImage> {
+        if self.upscaler.is_some() {
+            let (width, height) = self.resizer.target_dimensions(image.width(), image.height(), mode);
+            if let Some(upscaled) = self.upscale(&image, width, height)? {
+                return Ok(upscaled);
+            }
+        }
+        Ok(self.resizer.resize(&image, mode))
+    }
+
+    // `image` enlarged to exactly `width`x`height` by the external
+    // upscaler, when there is one and the size calls for it
+    fn upscale(&self, image: &DynamicImage, width: u32, height: u32) -> Result<Option<DynamicImage>> {
+        let Some(upscaler) = &self.upscaler else {
+            return Ok(None);
+        };
+        let scale = (width as f32 / image.width() as f32).max(height as f32 / image.height() as f32);
+        if scale <= 1.0 {
+            return Ok(None);
+        }
+        // The tool only does whole factors; land on the exact size afterwards
+        let upscaled = upscaler.upscale(image, scale)?;
+        Ok(Some(self.resizer.resize_exact(&upscaled, width, height)))
+    }
+
+    /// Filter stages, run on the final-size image right before encoding
+    pub fn adjust(&self, image: DynamicImage) -> Result<DynamicImage> {
+        self.run_stages(image, None)
+    }
+
+    fn run_stages(&self, image: DynamicImage, input_path: Option<&Path>) -> Result<DynamicImage> {
+        let ctx = StageContext {
+            config: &self.config,
+            input_path,
+        };
+        self.stages.iter().try_fold(image, |image, stage| {
+            log::debug!("Running stage {}", stage.name());
+            stage.apply(image, &ctx)
+        })
+    }
+
+    // Encoders without alpha would bring the masked-off parts back, so
+    // they are filled with the mask background instead
+    fn fill_masked(&self, image: DynamicImage, format: ImageFormat) -> DynamicImage {
+        let drops_alpha = matches!(format, ImageFormat::Jpeg | ImageFormat::Pnm) || self.config.cmyk;
+        let masked = self.config.corner_radius.is_some()
+            || self.config.mask.is_some()
+            || self.config.shadow.is_some()
+            || self.config.padding.is_some();
+        if !masked || !drops_alpha {
+            return image;
+        }
+        Flattener::new(self.config.mask_background).apply(&image)
+    }
+
+    /// Brings float (HDR/EXR) images into 8-bit sRGB; others pass through
+    pub fn tone_map(&self, image: DynamicImage) -> DynamicImage {
+        self.tone_mapper.map(image)
+    }
+
+    /// Blanks out the configured regions, which are in full-size pixels,
+    /// and any faces found, so this has to see the image as decoded
+    pub fn redact(&self, image: DynamicImage) -> Result<DynamicImage> {
+        let mut regions = self.config.redact.clone();
+        if let Some(style) = self.config.blur_faces {
+            let faces = FaceDetector::new(self.config.face_detector.as_deref()).detect(&image)?;
+            // Detectors box faces tightly; a quarter more on each side
+            // takes in hair, ears and chin
+            regions.extend(faces.into_iter().map(|(x, y, width, height)| Redaction {
+                x: x.saturating_sub(width / 4),
+                y: y.saturating_sub(height / 4),
+                width: width + width / 2,
+                height: height + height / 2,
+                style,
+            }));
+        }
+        if regions.is_empty() {
+            return Ok(image);
+        }
+        Ok(Redactor::new(regions).apply(&image))
+    }
+
+    /// Output name for `input_path` with `suffix` added, as
+    /// `generate_output_path` gives it, with the extension of the format
+    /// that will actually be written when the two differ
+    pub fn generated_output_path(&self, input_path: &Path, suffix: &str) -> PathBuf {
+        let path = generate_output_path(input_path, None, suffix);
+        match self.output_format(input_path, None) {
+            Ok(format) if ImageFormat::from_path(&path).ok() != Some(format) => {
+                path.with_extension(format_extension(format))
+            }
+            _ => path,
+        }
+    }
+
+    // Requested format, else the input's own format. Tone-mapped HDR inputs
+    // can't be written back as HDR, so they follow the output extension and
+    // fall back to JPEG.
+    fn output_format(&self, input_path: &Path, output_path: Option<&Path>) -> Result<ImageFormat> {
+        if let Some(format) = self.requested_format() {
+            return Ok(format);
+        }
+
+        match self.loader.detect_format(input_path)? {
+            ImageFormat::Hdr | ImageFormat::OpenExr => Ok(output_path
+                .and_then(|path| ImageFormat::from_path(path).ok())
+                .filter(|format| !matches!(format, ImageFormat::Hdr | ImageFormat::OpenExr))
+                .unwrap_or(ImageFormat::Jpeg)),
+            format => Ok(format),
+        }
+    }
+
+    fn resize_mode(&self) -> Option<ResizeMode> {
+        if let Some(max) = self.config.max_dimension {
+            Some(ResizeMode::MaxDimension(max))
+        } else if self.config.scale > 0.0 {
+            Some(ResizeMode::Scale(self.config.scale))
+        } else if self.config.algorithm == ResizeAlgorithm::SeamCarve && self.config.width > 0 && self.config.height > 0 {
+            Some(ResizeMode::SeamCarve(self.config.width, self.config.height))
+        } else if self.config.width > 0 || self.config.height > 0 {
+            Some(ResizeMode::Absolute(self.config.width, self.config.height))
+        } else {
+            None
+        }
+    }
+
+    // Size the early JPEG and strip reductions may shrink to. Seam carving
+    // needs the source proportions intact, so it gets the covering size.
+    fn reduction_target(&self, width: u32, height: u32, mode: ResizeMode) -> (u32, u32) {
+        let (target_w, target_h) = self.resizer.target_dimensions(width, height, mode);
+        match mode {
+            ResizeMode::SeamCarve(..) => {
+                let scale = (target_w as f64 / width as f64).max(target_h as f64 / height as f64);
+                (
+                    ((width as f64 * scale).round() as u32).max(1),
+                    ((height as f64 * scale).round() as u32).max(1),
+                )
+            }
+            _ => (target_w, target_h),
+        }
+    }
+
+    // `reduction_target` for a file stored sideways: the target is worked
+    // out for the upright image and turned back to the stored layout
+    fn stored_reduction_target(&self, width: u32, height: u32, mode: ResizeMode, orientation: Orientation) -> (u32, u32) {
+        use Orientation::{Rotate270, Rotate270FlipH, Rotate90, Rotate90FlipH};
+        if matches!(orientation, Rotate90 | Rotate270 | Rotate90FlipH | Rotate270FlipH) {
+            let (target_w, target_h) = self.reduction_target(height, width, mode);
+            (target_h, target_w)
+        } else {
+            self.reduction_target(width, height, mode)
+        }
+    }
+
+    // Decodes `input_path` upright, checking the decode against the memory
+    // budget first. `reduction` is the resize the image is headed for, which
+    // lets large JPEGs and strip-decodable files shrink while decoding.
+    fn decode(&self, input_path: &Path, reduction: Option<ResizeMode>) -> Result<DynamicImage> {
+        // Estimate decode memory from the header before allocating anything
+        let estimated_memory = match self.loader.max_memory() {
+            Some(_) => Some(self.loader.estimate_memory(input_path)?),
+            None => None,
+        };
+        let over_budget = estimated_memory
+            .is_some_and(|estimated| self.loader.check_memory_budget(estimated).is_err());
+
+        let orientation = self.orientation(|| self.metadata_processor.read_metadata(input_path));
+        let reduced = match self.load_scaled_jpeg(input_path, reduction, orientation)? {
+            Some(image) => Some(image),
+            None => self.load_tiled(input_path, reduction, over_budget, orientation)?,
+        };
+
+        let mut image = match reduced {
+            Some(image) => image,
+            None => {
+                if let Some(estimated) = estimated_memory {
+                    self.loader.check_memory_budget(estimated)?;
+                }
+                self.loader.load(input_path)?
+            }
+        };
+        image.apply_orientation(orientation);
+        Ok(image)
+    }
+
+    fn check_input_size(&self, size: u64) -> Result<()> {
+        match self.config.max_file_size {
+            Some(max_size) if size > max_size => Err(ImageToolError::MemoryLimitExceeded(
+                format!("File size {} exceeds limit {}", size, max_size)
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "jpeg-scale")]
+    fn load_scaled_jpeg(&self, input_path: &Path, mode: Option<ResizeMode>, orientation: Orientation) -> Result<Option<DynamicImage>> {
+        let Some(mode) = mode.filter(|_| self.config.redact.is_empty()) else {
+            return Ok(None);
+        };
+
+        if !matches!(self.loader.detect_format(input_path), Ok(ImageFormat::Jpeg)) {
+            return Ok(None);
+        }
+
+        // The scaled decoder has no CMYK conversion, and damaged files are
+        // left to the loader to refuse or salvage
+        if self.loader.cmyk_jpeg_info(input_path).is_some() || self.loader.jpeg_damage(input_path)?.is_some() {
+            return Ok(None);
+        }
+
+        let (width, height, _) = self.loader.get_dimensions_and_format(input_path)?;
+        let (target_w, target_h) = self.stored_reduction_target(width, height, mode, orientation);
+        if target_w > width / 2 || target_h > height / 2 {
+            return Ok(None);
+        }
+
+        self.loader.load_jpeg_scaled(input_path, target_w, target_h).map(Some)
+    }
+
+    #[cfg(not(feature = "jpeg-scale"))]
+    fn load_scaled_jpeg(&self, _input_path: &Path, _mode: Option<ResizeMode>, _orientation: Orientation) -> Result<Option<DynamicImage>> {
+        Ok(None)
+    }
+
+    // Very large downscales of strip-decodable formats are box-reduced while
+    // decoding to roughly twice the target size; the regular resize pass then
+    // produces the final pixels from that intermediate.
+    // Redaction regions are in full-size pixels, so neither reduction is
+    // used with them
+    fn load_tiled(
+        &self,
+        input_path: &Path,
+        mode: Option<ResizeMode>,
+        over_budget: bool,
+        orientation: Orientation,
+    ) -> Result<Option<DynamicImage>> {
+        let Some(mode) = mode.filter(|_| self.config.redact.is_empty()) else {
+            return Ok(None);
+        };
+
+        let format = match self.loader.detect_format(input_path) {
+            Ok(format) if self.tiled_loader.supports(format) => format,
+            _ => return Ok(None),
+        };
+
+        let (width, height, _) = self.loader.get_dimensions_and_format(input_path)?;
+        let pixels = (width as u64) * (height as u64);
+        let over_threshold = self.config.tile_threshold.is_some_and(|t| pixels > t);
+        if !over_threshold && !over_budget {
+            return Ok(None);
+        }
+
+        let (target_w, target_h) = self.stored_reduction_target(width, height, mode, orientation);
+        if target_w >= width / 2 || target_h >= height / 2 {
+            return Ok(None);
+        }
+
+        log::info!("Image is {}x{}, decoding in strips", width, height);
+
+        let image = self.tiled_loader.load_downscaled(
+            input_path,
+            format,
+            target_w.saturating_mul(2),
+            target_h.saturating_mul(2),
+        )?;
+
+        Ok(Some(image))
+    }
+
+    fn carry_over_exif(&self, input_path: &Path, output_path: &Path, image: &DynamicImage) -> Result<()> {
+        let exif = self.metadata_processor.read_metadata(input_path);
+        let Some(tiff) = self.exif_for(exif, input_path, image)? else {
+            return Ok(());
+        };
+
+        let jpeg = std::fs::read(output_path)?;
+        std::fs::write(output_path, self.metadata_processor.embed_exif_jpeg(&jpeg, &tiff)?)?;
+
+        log::debug!("Carried over EXIF to {}", output_path.display());
+        Ok(())
+    }
+
+    // The input's EXIF rebuilt for the processed image, if it has any
+    fn exif_for(
+        &self,
+        exif: Result<Option<exif::Exif>>,
+        input_path: &Path,
+        image: &DynamicImage,
+    ) -> Result<Option<Vec<u8>>> {
+        let exif = match exif {
+            Ok(Some(exif)) => exif,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                log::warn!("Not carrying over EXIF from {}: {}", input_path.display(), e);
+                return Ok(None);
+            }
+        };
+
+        let thumbnail = match self.config.exif_thumbnail {
+            ExifThumbnail::Regenerate => Some(self.metadata_processor.make_thumbnail(image)?),
+            ExifThumbnail::Strip => None,
+        };
+
+        self.metadata_processor
+            .rebuild_exif(&exif, image, thumbnail.as_deref(), self.config.auto_orient)
+            .map(Some)
+    }
+
+    // Fully decodes the written file rather than just reading its header, so
+    // truncated writes are caught as well
+    pub(crate) fn verify_output(&self, output_path: &Path, width: u32, height: u32) -> Result<()> {
+        self.verify_encoded(&std::fs::read(output_path)?, &output_path.display(), width, height)
+    }
+
+    // `verify_output` for encoded bytes that aren't written anywhere;
+    // `name` says which output failed
+    fn verify_encoded(&self, data: &[u8], name: &dyn std::fmt::Display, width: u32, height: u32) -> Result<()> {
+        let fail = |reason: String| {
+            ImageToolError::ProcessingError(format!("Output verification failed for {}: {}", name, reason))
+        };
+
+        let decoded = image::ImageReader::new(std::io::Cursor::new(data))
+            .with_guessed_format()?
+            .decode()
+            .map_err(|e| fail(e.to_string()))?;
+
+        if (decoded.width(), decoded.height()) != (width, height) {
+            return Err(fail(format!(
+                "expected {}x{}, found {}x{}",
+                width,
+                height,
+                decoded.width(),
+                decoded.height()
+            )));
+        }
+
+        log::debug!("Verified {}", name);
+        Ok(())
+    }
+
+    // `written` is decoded back from the output file so the comparison
+    // shows what the encoder actually kept
+    fn save_comparison(&self, original: &DynamicImage, written: &DynamicImage) -> Result<()> {
+        let Some(path) = &self.config.comparison else {
+            return Ok(());
+        };
+
+        let composite = Comparison::new(self.config.comparison_layout).compose(original, written)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let format = ImageFormat::from_path(path).unwrap_or(ImageFormat::Png);
+        Compressor::new(95).save_with_format(&composite, path, format)?;
+        log::info!("Saved comparison: {}", path.display());
+        Ok(())
+    }
+
+    fn requested_format(&self) -> Option<ImageFormat> {
+        self.config.format.and_then(|f| f.image_format())
+    }
+
+    pub fn get_metadata<P: AsRef<Path>>(&self, path: P) -> Result<ImageMetadata> {
+        ImageMetadata::read(path.as_ref())
+    }
+
     fn validate_paths(&self, input_path: &Path, output_path: &Path) -> Result<()> {
+        self.validate_names(input_path, output_path)?;
+
+        if !input_path.exists() {
+            return Err(ImageToolError::InvalidParameter(
+                format!("Input file does not exist: {}", input_path.display())
+            ));
+        }
+
+        match output_path.parent() {
+            Some(parent) if self.config.create_dirs => std::fs::create_dir_all(parent)?,
+            Some(parent) if !parent.as_os_str().is_empty() && !parent.is_dir() => {
+                return Err(ImageToolError::InvalidParameter(
+                    format!("Output directory does not exist: {}", parent.display())
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // What `validate_paths` checks short of the input existing, as inputs
+    // already in memory may not come from a file of their own
+    fn validate_names(&self, input_path: &Path, output_path: &Path) -> Result<()> {
         // Security: Prevent path traversal
         if input_path.to_string_lossy().contains("..") {
             return Err(ImageToolError::SecurityError(
@@ -125,16 +1093,121 @@ impl ImageProcessor {
             ));
         }
 
-        if !input_path.exists() {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl ImageProcessor {
+    /// `process` for async callers: the input is read and the output
+    /// written with `tokio::fs`, and only decoding, processing and encoding
+    /// run on the blocking thread pool. Inputs the in-memory path can't
+    /// take (see `can_process_loaded`), remote inputs, and configs that
+    /// export metadata or copy times or attributes go through `process` on
+    /// the blocking pool as a whole.
+    pub async fn process_async<P: AsRef<Path>>(
+        &self,
+        input_path: P,
+        output_path: P,
+    ) -> Result<ProcessingStats> {
+        let input_path = input_path.as_ref().to_path_buf();
+        let policy = self.config.on_conflict.unwrap_or(ConflictPolicy::Overwrite);
+        let Some(output_path) = resolve_conflict(output_path.as_ref(), policy)? else {
+            log::info!("{} already exists, skipping", output_path.as_ref().display());
+            return Ok(ProcessingStats {
+                existing_count: 1,
+                ..Default::default()
+            });
+        };
+
+        let mut processor = ImageProcessor::new(self.config.clone());
+        processor.stages = self.stages.clone();
+        processor.progress = self.progress.clone();
+
+        let in_memory = !input_path.to_str().is_some_and(is_remote_url)
+            && self.config.export_metadata.is_none()
+            && self.config.preserve_times.is_none()
+            && !self.config.preserve_attrs
+            && self.can_process_loaded(&input_path, &output_path);
+        if !in_memory {
+            return run_blocking(move || processor.process(&input_path, &output_path)).await;
+        }
+
+        self.validate_paths_async(&input_path, &output_path).await?;
+        let data = tokio::fs::read(&input_path).await?;
+        let size_before = data.len() as u64;
+
+        let (input, output) = (input_path.clone(), output_path.clone());
+        let encoded = run_blocking(move || {
+            let encoded = processor.process_loaded(&input, &data, &output)?;
+            if processor.config.verify {
+                processor.verify_encoded(&encoded.data, &output.display(), encoded.width, encoded.height)?;
+            }
+            Ok(encoded)
+        })
+        .await?;
+
+        self.report(Phase::Write);
+        let write_start = Instant::now();
+        tokio::fs::write(&output_path, &encoded.data).await?;
+        let timings = StageTimings {
+            write: write_start.elapsed(),
+            ..encoded.timings
+        };
+        log::info!("Saved image: {} ({} bytes)", output_path.display(), encoded.data.len());
+        log::debug!("{}: {}", output_path.display(), timings);
+
+        Ok(ProcessingStats {
+            processed_count: 1,
+            total_size_before: size_before,
+            total_size_after: encoded.data.len() as u64,
+            timings: vec![(output_path, timings)],
+            ..Default::default()
+        })
+    }
+
+    // `validate_paths` through `tokio::fs`
+    async fn validate_paths_async(&self, input_path: &Path, output_path: &Path) -> Result<()> {
+        self.validate_names(input_path, output_path)?;
+
+        if !tokio::fs::try_exists(input_path).await? {
             return Err(ImageToolError::InvalidParameter(
                 format!("Input file does not exist: {}", input_path.display())
             ));
         }
 
-        if let Some(parent) = output_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        match output_path.parent() {
+            Some(parent) if self.config.create_dirs => tokio::fs::create_dir_all(parent).await?,
+            Some(parent)
+                if !parent.as_os_str().is_empty()
+                    && !tokio::fs::metadata(parent).await.is_ok_and(|metadata| metadata.is_dir()) =>
+            {
+                return Err(ImageToolError::InvalidParameter(
+                    format!("Output directory does not exist: {}", parent.display())
+                ));
+            }
+            _ => {}
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Decoding, resizing and encoding are CPU-bound, so they are kept off the
+// async runtime
+#[cfg(feature = "async")]
+async fn run_blocking<T: Send + 'static>(task: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| ImageToolError::ProcessingError(format!("Processing task failed: {}", e)))?
+}
+
+fn compressor_at(config: &ProcessConfig, quality: u8) -> Compressor {
+    Compressor::new(quality)
+        .with_dpi(config.dpi)
+        .with_speed(config.speed)
+        .with_bit_depth(config.bit_depth)
+        .with_cmyk(config.cmyk, config.cmyk_profile.clone())
+        .with_create_dirs(config.create_dirs)
+        .with_indexed(config.quantize.is_some())
+}
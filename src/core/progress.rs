@@ -0,0 +1,81 @@
+// pixie/src/core/progress.rs
+use std::sync::Arc;
+
+/// Receives progress from long-running operations. Called from worker
+/// threads, so it must be cheap and thread-safe.
+pub type ProgressHook = Arc<dyn Fn(Progress) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// A batch of `total` images is starting
+    Start { total: u64 },
+    /// The single image being processed entered a phase
+    Phase(Phase),
+    /// One more image of a batch is done
+    Advance,
+    /// A batch stopped starting new images until resumed
+    Paused,
+    Resumed,
+    /// Everything is done
+    Finish(String),
+}
+
+/// The stages of processing one image, as timed in `StageTimings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Decode,
+    Resize,
+    Filter,
+    Encode,
+    Write,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Phase::Decode => "Decoding",
+            Phase::Resize => "Resizing",
+            Phase::Filter => "Filtering",
+            Phase::Encode => "Encoding",
+            Phase::Write => "Writing",
+        })
+    }
+}
+
+/// Draws progress on stderr: a bar for batches, and a spinner naming the
+/// current phase for single images, which is cleared when it finishes
+#[cfg(not(target_arch = "wasm32"))]
+pub fn terminal_progress() -> ProgressHook {
+    use indicatif::{ProgressBar, ProgressStyle};
+    use std::time::Duration;
+
+    let bar = ProgressBar::new_spinner();
+    Arc::new(move |progress| match progress {
+        Progress::Start { total } => {
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
+                    .unwrap()
+                    .progress_chars("#>-"),
+            );
+            bar.set_length(total);
+        }
+        Progress::Phase(phase) => {
+            // Ticks on its own so the spinner moves during a long decode
+            if bar.length().is_none() {
+                bar.set_style(
+                    ProgressStyle::default_spinner()
+                        .template("{spinner:.green} [{elapsed_precise}] {msg}")
+                        .unwrap(),
+                );
+                bar.enable_steady_tick(Duration::from_millis(100));
+            }
+            bar.set_message(format!("{}...", phase));
+        }
+        Progress::Advance => bar.inc(1),
+        Progress::Paused => bar.set_message("paused"),
+        Progress::Resumed => bar.set_message(""),
+        Progress::Finish(message) if bar.length().is_some() => bar.finish_with_message(message),
+        Progress::Finish(_) => bar.finish_and_clear(),
+    })
+}
@@ -0,0 +1,22 @@
+// pixie/src/core/stage.rs
+use super::{ProcessConfig, Result};
+use image::DynamicImage;
+use std::path::Path;
+
+/// One step of the filter pipeline that runs on the final-size image
+/// before encoding. `ImageProcessor` runs its stages in order: first the
+/// built-in ones set up from the config, then any added with
+/// `ImageProcessor::with_stage`.
+pub trait ProcessingStage: Send + Sync {
+    /// Short name for logs and for finding a stage's place in the order
+    fn name(&self) -> &str;
+
+    fn apply(&self, image: DynamicImage, ctx: &StageContext) -> Result<DynamicImage>;
+}
+
+/// What a stage knows about the image it is given
+pub struct StageContext<'a> {
+    pub config: &'a ProcessConfig,
+    /// Where the image was decoded from; None for in-memory input
+    pub input_path: Option<&'a Path>,
+}
@@ -1,26 +1,42 @@
 mod cli;
 mod core;
 mod processors;
+mod server;
 mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use cli::{Algorithm, Cli, Commands};
+pub use cli::{Algorithm, Align, AnalyzeCommand, Direction, PyramidKind, BlockCompression, Cli, Commands, LogFormat, MetaCommand, ProcessingArgs, TextureFormat};
+// The CLI's format enum; `OutputFormat` is the one the library works with
+pub use cli::OutputFormat as CliOutputFormat;
 pub use core::{
     ImageProcessor, ImageToolError, ProcessConfig, ResizeAlgorithm, Result, 
-    ImageMetadata, ProcessingStats, validate_config, OutputFormat
+    ImageMetadata, ProcessingStats, Quality, QualityScore, StageTimings, Progress, ProgressHook, Phase, validate_config, OutputFormat, exit_code,
+    Dimensions, ProcessConfigBuilder, AspectRatio, CropGravity, ExifThumbnail, ToneMapOperator, Upscaler, DenoiseMethod, AutoLevel, Channel, ChannelOrder, Color, AlphaSource, ComparisonLayout, SidecarFormat, TimeSource, ConflictPolicy, SalvageMode, RedactStyle, Redaction, MaskShape, Shadow, Duotone, ProcessingStage, StageContext
 };
+#[cfg(not(target_arch = "wasm32"))]
+pub use processors::{BatchProcessor, FileAudit, OrientationFix, OrientationFixer, PrivacyAuditor, RenameReport, Renamer};
+#[cfg(not(target_arch = "wasm32"))]
+pub use core::terminal_progress;
 pub use processors::{
-    BatchProcessor, Compressor, Loader, MetadataProcessor, Resizer
+    Captioner, Compressor, Cropper, Defect, FileCheck, ImageValidator, ProcessCache, Checkpoint, FrameExtractor, Journal, Loader, TiledLoader, OutputLock, ArchiveFormat, ArchiveWriter, TarEntry, TarReader, ZipEntry, ZipReader, MetadataProcessor, MetadataExporter, MetadataCopier, CopyReport, PrivacyRisk, RemoteFetcher, Resizer,
+    Srcset, SrcsetEntry, SrcsetGenerator, Lqip, LqipGenerator, FileOverrides, Pipeline, ImageFacts, Rule, CloudBatch, StorageLocation,
+    rollback_journal, RollbackReport, TextureBaker, TextureCompression, TextureContainer,
+    ToneMapper, VideoFrameGrabber, ExternalUpscaler, SeamCarver, Canvas, Denoiser, LevelStretcher, Lut3d, Posterizer, Thresholder, DuotoneMapper, Quantizer, ChannelOps, AlphaMasker, CornerRounder, EllipseMasker, Flattener, Collage, GridLayout, Comparison, QualityMeter, QualitySearch, Joiner, JoinDirection, JoinAlign, TileSlicer, TileGrid, TileBounds, PyramidGenerator, PyramidLayout, PyramidSummary, Redactor, FaceDetector, BlurHasher, ThumbHash, JpegColorInfo, decode_cmyk_jpeg, scan_jpeg, write_cmyk_tiff
 };
+pub use server::{config_from_query, Server, ServerConfig, ServerMetrics};
 pub use utils::{
-    calculate_aspect_ratio, format_file_size, generate_output_path,
-    get_image_info, is_supported_format, validate_dimensions
+    calculate_aspect_ratio, format_extension, format_file_size, generate_output_path,
+    get_image_info, is_remote_url, is_supported_format, json_escape, parse_flat_json, resolve_conflict, sniff_format, validate_dimensions, RotatingFile
 };
 
 pub mod prelude {
     pub use crate::{
-        ImageProcessor, ProcessConfig, ResizeAlgorithm,
-        BatchProcessor, Compressor, Loader, MetadataProcessor, Resizer
+        ImageProcessor, ProcessConfig, ProcessConfigBuilder, ResizeAlgorithm,
+        Compressor, Loader, MetadataProcessor, Resizer, SrcsetGenerator
     };
+    #[cfg(not(target_arch = "wasm32"))]
+    pub use crate::BatchProcessor;
 }
 
 // Re-export commonly used types
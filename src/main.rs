@@ -1,22 +1,46 @@
 // pixie/src/main.rs
-use image_tool::prelude::*;
-use image_tool::{Cli, Commands, Algorithm, OutputFormat};
+use pixie::prelude::*;
+use pixie::{exit_code, format_file_size, AnalyzeCommand, MetaCommand, ImageMetadata, ProcessingStats, Quality, Cli, Commands, ImageToolError, Algorithm, CliOutputFormat as OutputFormat, ProcessingArgs, Server, ServerConfig, CloudBatch, StorageLocation, LogFormat, json_escape, RotatingFile, Journal, rollback_journal, ProcessCache, FrameExtractor, VideoFrameGrabber, TextureFormat, BlockCompression, ZipReader, Pipeline, Rule};
 use clap::Parser;
 use log::LevelFilter;
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    // Initialize logger
-    env_logger::Builder::new()
-        .filter_level(if cli.verbose {
-            LevelFilter::Debug
-        } else {
-            LevelFilter::Info
-        })
-        .init();
+    let log_file = match &cli.log_file {
+        Some(path) => match RotatingFile::open(path, cli.log_file_size * 1024 * 1024) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!("Error: cannot open log file {}: {}", path.display(), e);
+                return ExitCode::from(e.exit_code());
+            }
+        },
+        None => None,
+    };
+
+    init_logger(cli.verbose, cli.quiet, cli.log_format, log_file);
+
+    match run(cli) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            let code = e
+                .downcast_ref::<ImageToolError>()
+                .map(|e| e.exit_code())
+                .or_else(|| e.downcast_ref::<std::io::Error>().map(|_| exit_code::IO_ERROR))
+                .unwrap_or(exit_code::FAILURE);
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<u8, Box<dyn std::error::Error>> {
+    let show_progress = !cli.quiet && cli.log_format == LogFormat::Plain;
 
     let max_file_size = cli.max_file_size.map(|mb| mb * 1024 * 1024);
+    let max_memory = cli.max_memory.map(|mb| mb * 1024 * 1024);
+    let tile_threshold = Some(cli.tile_threshold * 1_000_000);
 
     match cli.command {
         Commands::Resize {
@@ -31,11 +55,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             strip_metadata,
             algorithm,
             progressive,
+            sizes,
+            processing,
         } => {
             process_resize(
                 input, output, width, height, scale, quality,
                 format, keep_aspect, strip_metadata, algorithm,
-                progressive, max_file_size,
+                progressive, sizes, processing, max_file_size, max_memory,
+                tile_threshold, show_progress,
             )?;
         }
         Commands::Batch {
@@ -50,12 +77,58 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             strip_metadata,
             algorithm,
             no_png_optimize,
+            journal,
+            skip_duplicates,
+            memory_budget,
+            cache_dir,
+            no_cache,
+            changed_only,
+            checkpoint,
+            force,
+            archive_output,
+            json,
+            rollback,
+            conditions,
+            actions,
+            processing,
         } => {
-            process_batch(
+            if let Some(journal) = rollback {
+                process_rollback(journal)?;
+                return Ok(exit_code::SUCCESS);
+            }
+
+            // clap enforces these unless --rollback is given
+            let Some(input) = input else {
+                return Err("INPUT_DIR is required".into());
+            };
+            let output = match (output, &archive_output) {
+                (Some(output), _) => output,
+                // Outputs are staged next to the archive
+                (None, Some(archive)) => match archive.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                    _ => std::path::PathBuf::from("."),
+                },
+                (None, None) => return Err("--output or --archive-output is required".into()),
+            };
+            if conditions.len() != actions.len() {
+                return Err("Each --if needs a --then".into());
+            }
+            let rules = conditions
+                .iter()
+                .zip(&actions)
+                .map(|(condition, action)| Rule::new(condition, action))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let code = process_batch(
                 input, output, width, height, format, quality,
                 threads, recursive, strip_metadata, algorithm,
-                no_png_optimize, max_file_size,
+                no_png_optimize, journal, skip_duplicates, memory_budget,
+                cache_dir, no_cache, changed_only, checkpoint, force, archive_output, json, rules, processing,
+                max_file_size, max_memory, tile_threshold, show_progress,
             )?;
+            if code != exit_code::SUCCESS {
+                return Ok(code);
+            }
         }
         Commands::Optimize {
             input,
@@ -64,30 +137,283 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             strip_metadata,
             progressive,
             no_png_optimize,
+            processing,
         } => {
             process_optimize(
                 input, output, quality, strip_metadata,
-                progressive, no_png_optimize, max_file_size,
+                progressive, no_png_optimize, processing, max_file_size,
+                max_memory, show_progress,
             )?;
         }
-        Commands::Info { input, exif } => {
-            process_info(input, exif)?;
+        Commands::Info { input, exif, json } => {
+            process_info(input, exif, json)?;
         }
+        Commands::Analyze { command } => match command {
+            AnalyzeCommand::Blurhash {
+                input,
+                x_components,
+                y_components,
+            } => {
+                process_blurhash(input, x_components, y_components)?;
+            }
+            AnalyzeCommand::Thumbhash { input, preview } => {
+                process_thumbhash(input, preview)?;
+            }
+        },
         Commands::Convert {
             input,
             output,
             format,
             quality,
             strip_metadata,
+            processing,
         } => {
             process_convert(
                 input, output, format, quality,
-                strip_metadata, max_file_size,
+                strip_metadata, processing, max_file_size, max_memory,
+                show_progress,
+            )?;
+        }
+        Commands::Pipeline {
+            recipe,
+            input,
+            output,
+            threads,
+            recursive,
+        } => {
+            let mut pipeline = Pipeline::from_file(&recipe)?;
+            pipeline.config_mut().max_file_size = max_file_size;
+            pipeline.config_mut().max_memory = max_memory;
+            let code = process_pipeline(pipeline, input, output, threads, recursive, show_progress)?;
+            if code != exit_code::SUCCESS {
+                return Ok(code);
+            }
+        }
+        Commands::Srcset {
+            input,
+            output,
+            widths,
+            formats,
+            sizes,
+            quality,
+            algorithm,
+        } => {
+            process_srcset(
+                input, output, widths, formats, sizes, quality,
+                algorithm, max_file_size, max_memory,
+            )?;
+        }
+        Commands::Lqip {
+            input,
+            output,
+            width,
+            blur,
+            format,
+            quality,
+            css,
+            selector,
+            algorithm,
+        } => {
+            let selector = css.then_some(selector);
+            process_lqip(
+                input, output, width, blur, format, quality, selector,
+                algorithm, max_file_size, max_memory,
+            )?;
+        }
+        Commands::Frames { input, output, every } => {
+            process_frames(input, output, every)?;
+        }
+        Commands::Tiles {
+            input,
+            output,
+            cols,
+            rows,
+            tile_size,
+            format,
+            quality,
+        } => {
+            process_tiles(input, output, cols, rows, tile_size, format, quality)?;
+        }
+        Commands::Dzi {
+            input,
+            output,
+            layout,
+            tile_size,
+            overlap,
+            format,
+            quality,
+            base_url,
+        } => {
+            process_dzi(input, output, layout, tile_size, overlap, format, quality, base_url)?;
+        }
+        Commands::Thumb {
+            input,
+            output,
+            at,
+            width,
+            height,
+            quality,
+            format,
+            algorithm,
+            ffmpeg,
+            processing,
+        } => {
+            process_thumb(
+                input, output, at, width, height, quality, format,
+                algorithm, ffmpeg, processing, max_memory,
+            )?;
+        }
+        Commands::Texture {
+            input,
+            output,
+            format,
+            compression,
+            no_mipmaps,
+            algorithm,
+        } => {
+            process_texture(input, output, format, compression, no_mipmaps, algorithm, max_memory)?;
+        }
+        Commands::Collage {
+            inputs,
+            output,
+            layout,
+            width,
+            height,
+            gap,
+            background,
+            fill,
+            quality,
+            algorithm,
+        } => {
+            process_collage(
+                inputs, output, layout, width, height, gap, background,
+                fill, quality, algorithm, max_memory,
+            )?;
+        }
+        Commands::Join {
+            inputs,
+            output,
+            direction,
+            align,
+            spacing,
+            background,
+            same_size,
+            quality,
+            algorithm,
+        } => {
+            process_join(
+                inputs, output, direction, align, spacing, background,
+                same_size, quality, algorithm, max_memory,
+            )?;
+        }
+        Commands::Rename {
+            dir,
+            pattern,
+            recursive,
+            dry_run,
+        } => {
+            process_rename(dir, pattern, recursive, dry_run)?;
+        }
+        Commands::Audit { input, recursive, fix } => {
+            let code = process_audit(input, recursive, fix)?;
+            if code != exit_code::SUCCESS {
+                return Ok(code);
+            }
+        }
+        Commands::Validate { paths, recursive } => {
+            let code = process_validate(paths, recursive)?;
+            if code != exit_code::SUCCESS {
+                return Ok(code);
+            }
+        }
+        Commands::Meta { command } => match command {
+            MetaCommand::Copy {
+                source,
+                destination,
+                no_exif,
+                no_xmp,
+                no_icc,
+            } => {
+                process_meta_copy(source, destination, !no_exif, !no_xmp, !no_icc)?;
+            }
+            MetaCommand::FixOrientation { path, recursive, quality } => {
+                let code = process_fix_orientation(path, recursive, quality)?;
+                if code != exit_code::SUCCESS {
+                    return Ok(code);
+                }
+            }
+        },
+        Commands::Man { output } => {
+            process_man(output)?;
+        }
+        Commands::Serve {
+            listen,
+            workers,
+            queue_size,
+            max_body_size,
+            max_pixels,
+            quality,
+            algorithm,
+        } => {
+            process_serve(
+                listen, workers, queue_size, max_body_size, max_pixels, quality,
+                algorithm, max_memory, tile_threshold,
             )?;
         }
     }
 
-    Ok(())
+    Ok(exit_code::SUCCESS)
+}
+
+fn init_logger(verbose: u8, quiet: bool, log_format: LogFormat, log_file: Option<RotatingFile>) {
+    let mut builder = env_logger::Builder::new();
+
+    match (quiet, verbose) {
+        (true, _) => builder.filter_level(LevelFilter::Error),
+        (false, 0) => builder.filter_level(LevelFilter::Info),
+        (false, 1) => builder.filter_level(LevelFilter::Debug),
+        // Keep dependency chatter out until -vvv
+        (false, 2) => builder
+            .filter_level(LevelFilter::Info)
+            .filter_module(env!("CARGO_CRATE_NAME"), LevelFilter::Trace),
+        (false, _) => builder.filter_level(LevelFilter::Trace),
+    };
+
+    if log_format == LogFormat::Json {
+        builder.format(|buf, record| {
+            use std::io::Write;
+            writeln!(
+                buf,
+                "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"target\":\"{}\",\"message\":\"{}\"}}",
+                buf.timestamp(),
+                record.level(),
+                json_escape(record.target()),
+                json_escape(&record.args().to_string())
+            )
+        });
+    }
+
+    if let Some(file) = log_file {
+        builder.target(env_logger::Target::Pipe(Box::new(TeeLog(file))));
+    }
+
+    builder.init();
+}
+
+// Log records still reach stderr while a copy goes to the log file
+struct TeeLog(RotatingFile);
+
+impl std::io::Write for TeeLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // A closed stderr shouldn't stop the file from being written
+        let _ = std::io::stderr().write_all(buf);
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let _ = std::io::stderr().flush();
+        self.0.flush()
+    }
 }
 
 fn process_resize(
@@ -96,35 +422,60 @@ fn process_resize(
     width: u32,
     height: u32,
     scale: f32,
-    quality: u8,
+    quality: Quality,
     format: Option<OutputFormat>,
     keep_aspect: bool,
     strip_metadata: bool,
     algorithm: Algorithm,
     progressive: bool,
+    sizes: Vec<u32>,
+    processing: ProcessingArgs,
     max_file_size: Option<u64>,
+    max_memory: Option<u64>,
+    tile_threshold: Option<u64>,
+    show_progress: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use crate::utils::generate_output_path;
-    
-    let output_path = generate_output_path(&input, output.as_deref(), "resized");
-
-    let config = ProcessConfig {
+    let mut config = ProcessConfig {
         width,
         height,
         scale,
-        quality,
+        quality: quality.value(),
+        auto_quality: quality.target_ssim(),
         keep_aspect,
         strip_metadata,
         algorithm: algorithm.into(),
         max_file_size,
+        max_memory,
         format: format.map(|f| f.into()),
+        tile_threshold,
         ..Default::default()
     };
 
+    processing.apply(&mut config);
     config.validate()?;
 
+    let on_conflict = config.on_conflict;
     let processor = ImageProcessor::new(config);
-    let stats = processor.process(&input, &output_path)?;
+
+    if !sizes.is_empty() {
+        let output_dir = output
+            .or_else(|| input.parent().map(|p| p.to_path_buf()))
+            .unwrap_or_default();
+        let stats = with_spinner(processor, show_progress, |p| p.process_sizes(&input, &output_dir, &sizes))?;
+
+        println!("✓ {} sizes saved to: {}", stats.processed_count, output_dir.display());
+        print_stats(&stats);
+        return Ok(());
+    }
+
+    let generated = match output.as_deref() {
+        Some(path) => path.to_path_buf(),
+        None => processor.generated_output_path(&input, "resized"),
+    };
+    let Some(output_path) = resolve_output(generated, output.is_none(), on_conflict)? else {
+        return Ok(());
+    };
+    let stats = with_spinner(processor, show_progress, |p| p.process(&input, &output_path))?;
 
     println!("✓ Resized image saved to: {}", output_path.display());
     print_stats(&stats);
@@ -138,75 +489,469 @@ fn process_batch(
     width: u32,
     height: u32,
     format: Option<OutputFormat>,
-    quality: u8,
+    quality: Quality,
     threads: usize,
     recursive: bool,
     strip_metadata: bool,
     algorithm: Algorithm,
     no_png_optimize: bool,
+    journal: Option<std::path::PathBuf>,
+    skip_duplicates: bool,
+    memory_budget: Option<u64>,
+    cache_dir: Option<std::path::PathBuf>,
+    no_cache: bool,
+    changed_only: bool,
+    checkpoint: Option<std::path::PathBuf>,
+    force: bool,
+    archive_output: Option<std::path::PathBuf>,
+    json: bool,
+    rules: Vec<Rule>,
+    processing: ProcessingArgs,
     max_file_size: Option<u64>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let config = ProcessConfig {
+    max_memory: Option<u64>,
+    tile_threshold: Option<u64>,
+    show_progress: bool,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let mut config = ProcessConfig {
         width,
         height,
         scale: 0.0,
-        quality,
+        quality: quality.value(),
+        auto_quality: quality.target_ssim(),
         keep_aspect: true,
         strip_metadata,
         algorithm: algorithm.into(),
         max_file_size,
+        max_memory,
         format: format.map(|f| f.into()),
+        tile_threshold,
+        ..Default::default()
     };
 
+    processing.apply(&mut config);
     config.validate()?;
 
-    let processor = BatchProcessor::new(config, threads)?;
-    processor.validate_paths(&input, &output)?;
+    let streaming = input.as_os_str() == "-";
+    if streaming != (output.as_os_str() == "-") {
+        return Err("A tar stream is read from stdin and written to stdout, pass - for both INPUT_DIR and --output".into());
+    }
+    if streaming {
+        if json {
+            log::warn!("--json is not supported for tar streams, ignoring");
+        }
+        if skip_duplicates || memory_budget.is_some() || changed_only || checkpoint.is_some() || journal.is_some() || !rules.is_empty() {
+            log::warn!("Only processing options apply to tar streams, ignoring the rest");
+        }
+        return process_tar_stream(config, threads, show_progress);
+    }
 
-    let stats = processor.process_directory(&input, &output, recursive)?;
+    let source: StorageLocation = input.to_string_lossy().parse()?;
+    let target: StorageLocation = output.to_string_lossy().parse()?;
 
-    println!("✓ Batch processing complete.");
+    let mut journal_path = None;
+    let stats = if source.is_remote() || target.is_remote() {
+        if skip_duplicates {
+            log::warn!("--skip-duplicates is not supported for bucket URIs, ignoring");
+        }
+        if memory_budget.is_some() {
+            log::warn!("--memory-budget is not supported for bucket URIs, ignoring");
+        }
+        if cache_dir.is_some() {
+            log::warn!("--cache-dir is not supported for bucket URIs, ignoring");
+        }
+        if changed_only {
+            log::warn!("--changed-only is not supported for bucket URIs, ignoring");
+        }
+        if checkpoint.is_some() {
+            log::warn!("--checkpoint is not supported for bucket URIs, ignoring");
+        }
+        if config.comparison.is_some() {
+            log::warn!("--save-comparison is not supported for bucket URIs, ignoring");
+        }
+        if archive_output.is_some() {
+            log::warn!("--archive-output is not supported for bucket URIs, ignoring");
+        }
+        if !rules.is_empty() {
+            log::warn!("--if/--then rules are not supported for bucket URIs, ignoring");
+        }
+        CloudBatch::new(config, threads).run(&source, &target, recursive)?
+    } else {
+        let journal = journal.unwrap_or_else(|| Journal::default_path(&output));
+        let processor = BatchProcessor::new(config, threads)?
+            .with_progress(show_progress)
+            .with_journal(journal.clone())
+            .with_skip_duplicates(skip_duplicates)
+            .with_memory_budget(memory_budget.map(|mb| mb * 1024 * 1024))
+            .with_cache((!no_cache).then(|| cache_dir.unwrap_or_else(|| ProcessCache::default_dir(&output))))
+            .with_changed_only(changed_only)
+            .with_checkpoint(checkpoint)
+            .with_output_lock(true, force)
+            .with_archive_output(archive_output.clone())
+            .with_cancel(install_interrupt_handler())
+            .with_pause(install_pause_handler())
+            .with_rules(rules);
+        let stats = if ZipReader::is_zip(&input) && input.is_file() {
+            processor.process_archive(&input, &output)?
+        } else {
+            processor.validate_paths(&input, &output)?;
+            processor.process_directory(&input, &output, recursive)?
+        };
+        journal_path = Some(journal);
+        stats
+    };
+
+    let code = if stats.was_cancelled() {
+        exit_code::INTERRUPTED
+    } else if stats.failed_count() > 0 {
+        exit_code::PARTIAL_FAILURE
+    } else {
+        exit_code::SUCCESS
+    };
+
+    if json {
+        print_batch_json(&stats);
+        return Ok(code);
+    }
+
+    if stats.was_cancelled() {
+        println!("⚠  Batch processing interrupted.");
+    } else {
+        println!("✓ Batch processing complete.");
+    }
     print_stats(&stats);
+    if stats.cached_count > 0 {
+        println!("  Up to date (cached): {} file(s)", stats.cached_count);
+    }
+    if stats.unchanged_count > 0 {
+        println!("  Unchanged since last run: {} file(s)", stats.unchanged_count);
+    }
+    if stats.skipped_count > 0 {
+        println!("  Skipped by their .pixie.toml: {} file(s)", stats.skipped_count);
+    }
+    if stats.existing_count > 0 {
+        println!("  Output already there: {} file(s)", stats.existing_count);
+    }
+    if stats.resumed_count > 0 {
+        println!("  Done before resuming: {} file(s)", stats.resumed_count);
+    }
+    print_throughput(&stats);
+
+    if !stats.duplicates.is_empty() {
+        println!("\n  Duplicate inputs (only the first of each group was processed):");
+        for group in &stats.duplicates {
+            let names: Vec<String> = group.iter().map(|p| p.display().to_string()).collect();
+            println!("  - {}", names.join(", "));
+        }
+    }
+
+    if let Some(archive) = archive_output.as_ref().filter(|p| p.exists()) {
+        println!("  Archive: {}", archive.display());
+    }
+    if let Some(journal) = journal_path.filter(|p| p.exists()) {
+        println!("  Journal: {} (undo with --rollback)", journal.display());
+    }
+
+    if stats.failed_count() > 0 {
+        println!("\n⚠  {} file(s) failed:", stats.failed_count());
+        for (path, error) in &stats.errors {
+            println!("  - {}: {}", path.display(), error);
+        }
+    }
+
+    if stats.was_cancelled() {
+        println!("\n⚠  {} file(s) not started.", stats.cancelled.len());
+        if archive_output.is_some() {
+            println!("  The archive holds the files finished so far; run the same command again to rebuild it.");
+        } else if no_cache {
+            println!("  Rerun with --changed-only to resume where this run stopped.");
+        } else {
+            println!("  Run the same command again to resume; finished files are skipped.");
+        }
+    }
+
+    Ok(code)
+}
+
+// Stdout carries the tar, so the summary goes to stderr
+fn process_tar_stream(config: ProcessConfig, threads: usize, show_progress: bool) -> Result<u8, Box<dyn std::error::Error>> {
+    let interrupted = install_interrupt_handler();
+    let stats = BatchProcessor::new(config, threads)?
+        .with_progress(show_progress)
+        .with_cancel(interrupted.clone())
+        .with_pause(install_pause_handler())
+        .process_tar_stream(std::io::stdin(), std::io::BufWriter::new(std::io::stdout().lock()))?;
+
+    eprintln!(
+        "Processed {} file(s), {} -> {}",
+        stats.processed_count,
+        format_file_size(stats.total_size_before),
+        format_file_size(stats.total_size_after)
+    );
+    if stats.failed_count() > 0 {
+        eprintln!("⚠  {} file(s) failed:", stats.failed_count());
+        for (path, error) in &stats.errors {
+            eprintln!("  - {}: {}", path.display(), error);
+        }
+    }
+
+    Ok(if interrupted.load(std::sync::atomic::Ordering::Relaxed) {
+        exit_code::INTERRUPTED
+    } else if stats.failed_count() > 0 {
+        exit_code::PARTIAL_FAILURE
+    } else {
+        exit_code::SUCCESS
+    })
+}
+
+/// The returned flag is set by the first Ctrl+C, which lets a batch finish
+/// the files in flight. A second Ctrl+C kills the process as usual.
+#[cfg(unix)]
+fn install_interrupt_handler() -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+
+    static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        if let Some(flag) = INTERRUPTED.get() {
+            flag.store(true, Ordering::Relaxed);
+        }
+        // An atomic store and signal() are safe to call from a handler
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+
+    let flag = INTERRUPTED.get_or_init(Default::default).clone();
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+    flag
+}
+
+#[cfg(not(unix))]
+fn install_interrupt_handler() -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+    Default::default()
+}
+
+/// The returned flag is toggled by each SIGUSR1, pausing a batch once the
+/// files in flight finish and resuming it again. Ctrl+Z still suspends the
+/// process at once, which is safe since outputs are renamed into place.
+#[cfg(unix)]
+fn install_pause_handler() -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+
+    static PAUSED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+    extern "C" fn on_pause(_: libc::c_int) {
+        if let Some(flag) = PAUSED.get() {
+            flag.fetch_xor(true, Ordering::Relaxed);
+        }
+    }
+
+    let flag = PAUSED.get_or_init(Default::default).clone();
+    unsafe {
+        libc::signal(libc::SIGUSR1, on_pause as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+    log::info!("Send SIGUSR1 to process {} to pause or resume", std::process::id());
+    flag
+}
+
+#[cfg(not(unix))]
+fn install_pause_handler() -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+    Default::default()
+}
+
+const MB: f64 = 1024.0 * 1024.0;
+const SLOWEST_SHOWN: usize = 10;
+
+fn print_throughput(stats: &ProcessingStats) {
+    if stats.processed_count == 0 {
+        return;
+    }
+
+    let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+    println!(
+        "  Throughput: {:.1} images/s, {:.2} MB/s ({:.1}s total)",
+        stats.images_per_sec(),
+        stats.bytes_per_sec() / MB,
+        stats.elapsed.as_secs_f64()
+    );
+    println!("  Average: {:.1}ms per file", ms(stats.average_time()));
 
-    if !stats.errors.is_empty() {
-        println!("\n⚠  Errors encountered:");
-        for (context, error) in &stats.errors {
-            println!("  - {}: {}", context, error);
+    if stats.timings.len() > 1 {
+        println!("\n  Slowest files:");
+        for (path, timings) in stats.slowest(SLOWEST_SHOWN) {
+            println!("  - {:.1}ms {} ({})", ms(timings.total()), path.display(), timings);
         }
     }
+}
+
+fn print_batch_json(stats: &ProcessingStats) {
+    let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+    let timings_json = |t: &pixie::StageTimings| {
+        format!(
+            "\"decode_ms\": {:.3}, \"resize_ms\": {:.3}, \"filter_ms\": {:.3}, \"encode_ms\": {:.3}, \"write_ms\": {:.3}",
+            ms(t.decode),
+            ms(t.resize),
+            ms(t.filter),
+            ms(t.encode),
+            ms(t.write)
+        )
+    };
+
+    let files: Vec<String> = stats
+        .timings
+        .iter()
+        .map(|(path, timings)| {
+            format!(
+                "    {{\"output\": \"{}\", {}}}",
+                json_escape(&path.display().to_string()),
+                timings_json(timings)
+            )
+        })
+        .collect();
+    let slowest: Vec<String> = stats
+        .slowest(SLOWEST_SHOWN)
+        .into_iter()
+        .map(|(path, timings)| {
+            format!(
+                "    {{\"output\": \"{}\", \"total_ms\": {:.3}}}",
+                json_escape(&path.display().to_string()),
+                ms(timings.total())
+            )
+        })
+        .collect();
+    let errors: Vec<String> = stats
+        .errors
+        .iter()
+        .map(|(path, error)| {
+            format!(
+                "    {{\"input\": \"{}\", \"error\": \"{}\"}}",
+                json_escape(&path.display().to_string()),
+                json_escape(&error.to_string())
+            )
+        })
+        .collect();
+
+    let list = |items: Vec<String>| {
+        if items.is_empty() {
+            "[]".to_string()
+        } else {
+            format!("[\n{}\n  ]", items.join(",\n"))
+        }
+    };
+
+    println!("{{");
+    println!("  \"processed\": {},", stats.processed_count);
+    println!("  \"failed\": {},", stats.failed_count());
+    println!("  \"cached\": {},", stats.cached_count);
+    println!("  \"unchanged\": {},", stats.unchanged_count);
+    println!("  \"skipped\": {},", stats.skipped_count);
+    println!("  \"existing\": {},", stats.existing_count);
+    println!("  \"resumed\": {},", stats.resumed_count);
+    println!("  \"cancelled\": {},", stats.cancelled.len());
+    println!("  \"bytes_before\": {},", stats.total_size_before);
+    println!("  \"bytes_after\": {},", stats.total_size_after);
+    println!("  \"elapsed_ms\": {:.3},", ms(stats.elapsed));
+    println!("  \"images_per_sec\": {:.3},", stats.images_per_sec());
+    println!("  \"mb_per_sec\": {:.3},", stats.bytes_per_sec() / MB);
+    println!("  \"average_ms\": {:.3},", ms(stats.average_time()));
+    println!("  \"timings\": {{{}}},", timings_json(&stats.total_timings()));
+    println!("  \"slowest\": {},", list(slowest));
+    println!("  \"files\": {},", list(files));
+    println!("  \"errors\": {}", list(errors));
+    println!("}}");
+}
+
+fn process_rollback(journal: std::path::PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let report = rollback_journal(&journal)?;
+
+    println!("✓ Rolled back batch run from: {}", journal.display());
+    println!("  Removed: {} file(s)", report.removed);
+    if report.missing > 0 {
+        println!("  Already gone: {} file(s)", report.missing);
+    }
 
     Ok(())
 }
 
+/// Applies --on-conflict to a single command's output path; None when the
+/// existing file is kept. Names made up from the input are renamed by
+/// default, so a second run never clobbers the first.
+fn resolve_output(
+    path: std::path::PathBuf,
+    generated: bool,
+    policy: Option<pixie::ConflictPolicy>,
+) -> Result<Option<std::path::PathBuf>, ImageToolError> {
+    use pixie::{resolve_conflict, ConflictPolicy};
+
+    let default = if generated { ConflictPolicy::Rename } else { ConflictPolicy::Overwrite };
+    let resolved = resolve_conflict(&path, policy.unwrap_or(default))?;
+    if resolved.is_none() {
+        println!("Skipped: {} already exists", path.display());
+    }
+    Ok(resolved)
+}
+
+/// Runs `op` with a spinner naming the current phase, cleared again before
+/// the caller prints anything, whether or not `op` succeeded
+fn with_spinner<T>(
+    processor: ImageProcessor,
+    show_progress: bool,
+    op: impl FnOnce(&ImageProcessor) -> pixie::Result<T>,
+) -> pixie::Result<T> {
+    if !show_progress {
+        return op(&processor);
+    }
+
+    let hook = pixie::terminal_progress();
+    let result = op(&processor.with_progress_hook(hook.clone()));
+    hook(pixie::Progress::Finish(String::new()));
+    result
+}
+
 fn process_optimize(
     input: std::path::PathBuf,
     output: Option<std::path::PathBuf>,
-    quality: u8,
+    quality: Quality,
     strip_metadata: bool,
     progressive: bool,
     no_png_optimize: bool,
+    processing: ProcessingArgs,
     max_file_size: Option<u64>,
+    max_memory: Option<u64>,
+    show_progress: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use crate::utils::generate_output_path;
-    
-    let output_path = generate_output_path(&input, output.as_deref(), "optimized");
-
-    let config = ProcessConfig {
+    let mut config = ProcessConfig {
         width: 0,
         height: 0,
         scale: 0.0,
-        quality,
+        quality: quality.value(),
+        auto_quality: quality.target_ssim(),
         keep_aspect: true,
         strip_metadata,
         algorithm: ResizeAlgorithm::Lanczos3,
         max_file_size,
+        max_memory,
         format: None,
+        ..Default::default()
     };
 
+    processing.apply(&mut config);
     config.validate()?;
 
+    let on_conflict = config.on_conflict;
     let processor = ImageProcessor::new(config);
-    let stats = processor.process(&input, &output_path)?;
+    let generated = match output.as_deref() {
+        Some(path) => path.to_path_buf(),
+        None => processor.generated_output_path(&input, "optimized"),
+    };
+    let Some(output_path) = resolve_output(generated, output.is_none(), on_conflict)? else {
+        return Ok(());
+    };
+    let stats = with_spinner(processor, show_progress, |p| p.process(&input, &output_path))?;
 
     println!("✓ Optimized image saved to: {}", output_path.display());
     print_stats(&stats);
@@ -217,15 +962,31 @@ fn process_optimize(
 fn process_info(
     input: std::path::PathBuf,
     exif: bool,
+    json: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use crate::utils::{format_file_size, get_image_info};
-    
     if !input.exists() {
         return Err(format!("File does not exist: {}", input.display()).into());
     }
 
-    let processor = ImageProcessor::new(ProcessConfig::default());
-    let metadata = processor.get_metadata(&input)?;
+    // Headers only; the pixels are decoded just for the JSON placeholders
+    let metadata = ImageMetadata::read(&input)?;
+
+    if json {
+        let image = Loader::new().load(&input)?;
+        let blurhash = pixie::BlurHasher::new().encode(&image)?;
+        let thumbhash = pixie::ThumbHash::from_image(&image);
+        println!("{{");
+        println!("  \"file\": \"{}\",", json_escape(&input.display().to_string()));
+        println!("  \"bytes\": {},", metadata.file_size);
+        println!("  \"width\": {},", metadata.width);
+        println!("  \"height\": {},", metadata.height);
+        println!("  \"format\": \"{}\",", json_escape(&metadata.format));
+        println!("  \"has_exif\": {},", metadata.has_exif);
+        println!("  \"blurhash\": \"{}\",", json_escape(&blurhash));
+        println!("  \"thumbhash\": \"{}\"", thumbhash);
+        println!("}}");
+        return Ok(());
+    }
 
     println!("=== Image Information ===");
     println!("File: {}", input.display());
@@ -245,34 +1006,77 @@ fn process_info(
     Ok(())
 }
 
+fn process_blurhash(
+    input: std::path::PathBuf,
+    x_components: u32,
+    y_components: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let image = Loader::new().load(&input)?;
+    let hash = pixie::BlurHasher::new()
+        .with_components(x_components, y_components)
+        .encode(&image)?;
+
+    println!("{}", hash);
+
+    Ok(())
+}
+
+fn process_thumbhash(
+    input: std::path::PathBuf,
+    preview: Option<std::path::PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let image = Loader::new().load(&input)?;
+    let hash = pixie::ThumbHash::from_image(&image);
+
+    println!("{}", hash);
+
+    if let Some(preview) = preview {
+        Compressor::new(100).save_with_format(&hash.to_image(), &preview, image::ImageFormat::Png)?;
+        log::info!("Wrote ThumbHash preview to {}", preview.display());
+    }
+
+    Ok(())
+}
+
 fn process_convert(
     input: std::path::PathBuf,
     output: Option<std::path::PathBuf>,
     format: OutputFormat,
-    quality: u8,
+    quality: Quality,
     strip_metadata: bool,
+    processing: ProcessingArgs,
     max_file_size: Option<u64>,
+    max_memory: Option<u64>,
+    show_progress: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use crate::utils::generate_output_path;
-    
-    let output_path = generate_output_path(&input, output.as_deref(), "converted");
-
-    let config = ProcessConfig {
+    let mut config = ProcessConfig {
         width: 0,
         height: 0,
         scale: 0.0,
-        quality,
+        quality: quality.value(),
+        auto_quality: quality.target_ssim(),
         keep_aspect: true,
         strip_metadata,
         algorithm: ResizeAlgorithm::Lanczos3,
         max_file_size,
+        max_memory,
         format: Some(format.into()),
+        ..Default::default()
     };
 
+    processing.apply(&mut config);
     config.validate()?;
 
+    let on_conflict = config.on_conflict;
     let processor = ImageProcessor::new(config);
-    let stats = processor.process(&input, &output_path)?;
+    let generated = match output.as_deref() {
+        Some(path) => path.to_path_buf(),
+        None => processor.generated_output_path(&input, "converted"),
+    };
+    let Some(output_path) = resolve_output(generated, output.is_none(), on_conflict)? else {
+        return Ok(());
+    };
+    let stats = with_spinner(processor, show_progress, |p| p.process(&input, &output_path))?;
 
     println!("✓ Converted image saved to: {}", output_path.display());
     print_stats(&stats);
@@ -280,6 +1084,645 @@ fn process_convert(
     Ok(())
 }
 
+fn process_pipeline(
+    pipeline: Pipeline,
+    input: std::path::PathBuf,
+    output: Option<std::path::PathBuf>,
+    threads: usize,
+    recursive: bool,
+    show_progress: bool,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    log::info!("Pipeline steps: {}", pipeline.step_names().join(" → "));
+
+    if input.is_dir() {
+        let Some(output) = output else {
+            return Err("--output is required for a directory input".into());
+        };
+        let processor = pipeline
+            .batch(threads)?
+            .with_progress(show_progress)
+            .with_cancel(install_interrupt_handler());
+        processor.validate_paths(&input, &output)?;
+        let stats = processor.process_directory(&input, &output, recursive)?;
+
+        println!("✓ Pipeline complete.");
+        print_stats(&stats);
+        if stats.failed_count() > 0 {
+            println!("\n⚠  {} file(s) failed:", stats.failed_count());
+            for (path, error) in &stats.errors {
+                println!("  - {}: {}", path.display(), error);
+            }
+            return Ok(exit_code::PARTIAL_FAILURE);
+        }
+        return Ok(exit_code::SUCCESS);
+    }
+
+    let on_conflict = pipeline.config().on_conflict;
+    let processor = pipeline.processor();
+    let generated = match output.as_deref() {
+        Some(path) => path.to_path_buf(),
+        None => processor.generated_output_path(&input, "processed"),
+    };
+    let Some(output_path) = resolve_output(generated, output.is_none(), on_conflict)? else {
+        return Ok(exit_code::SUCCESS);
+    };
+    let stats = with_spinner(processor, show_progress, |p| p.process(&input, &output_path))?;
+
+    println!("✓ Pipeline output saved to: {}", output_path.display());
+    print_stats(&stats);
+
+    Ok(exit_code::SUCCESS)
+}
+
+fn process_srcset(
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+    widths: Vec<u32>,
+    formats: Vec<OutputFormat>,
+    sizes: String,
+    quality: u8,
+    algorithm: Algorithm,
+    max_file_size: Option<u64>,
+    max_memory: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = ProcessConfig {
+        quality,
+        algorithm: algorithm.into(),
+        max_file_size,
+        max_memory,
+        ..Default::default()
+    };
+
+    config.validate()?;
+
+    let formats: Vec<image::ImageFormat> = formats
+        .into_iter()
+        .filter_map(|f| pixie::OutputFormat::from(f).image_format())
+        .collect();
+
+    let generator = SrcsetGenerator::new(config).with_sizes_attr(&sizes);
+    let srcset = generator.generate(&input, &output, &widths, &formats)?;
+    let (html_path, json_path) = generator.write_snippets(&srcset, &output)?;
+
+    println!("✓ Generated {} images in: {}", srcset.entries.len(), output.display());
+    println!("  HTML snippet: {}", html_path.display());
+    println!("  Manifest: {}", json_path.display());
+    println!("\n{}", srcset.to_html(&sizes, ""));
+
+    Ok(())
+}
+
+fn process_lqip(
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+    width: u32,
+    blur: f32,
+    format: OutputFormat,
+    quality: u8,
+    css_selector: Option<String>,
+    algorithm: Algorithm,
+    max_file_size: Option<u64>,
+    max_memory: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::LqipGenerator;
+
+    let config = ProcessConfig {
+        quality,
+        algorithm: algorithm.into(),
+        max_file_size,
+        max_memory,
+        ..Default::default()
+    };
+
+    config.validate()?;
+
+    let format = pixie::OutputFormat::from(format)
+        .image_format()
+        .unwrap_or(image::ImageFormat::Jpeg);
+
+    let lqip = LqipGenerator::new(config)
+        .with_width(width)
+        .with_blur(blur)
+        .with_format(format)
+        .generate(&input, &output)?;
+
+    println!(
+        "✓ {}x{} placeholder ({} bytes): {}",
+        lqip.width,
+        lqip.height,
+        lqip.data.len(),
+        lqip.path.display()
+    );
+
+    if let Some(selector) = css_selector {
+        let css_path = lqip.path.with_extension("css");
+        std::fs::write(&css_path, lqip.to_css(&selector))?;
+        println!("  CSS: {}", css_path.display());
+    }
+
+    Ok(())
+}
+
+fn process_serve(
+    listen: String,
+    workers: usize,
+    queue_size: usize,
+    max_body_size: u64,
+    max_pixels: u64,
+    quality: u8,
+    algorithm: Algorithm,
+    max_memory: Option<u64>,
+    tile_threshold: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base_config = ProcessConfig {
+        quality,
+        algorithm: algorithm.into(),
+        max_memory,
+        tile_threshold,
+        ..Default::default()
+    };
+
+    base_config.validate()?;
+
+    let server = Server::new(ServerConfig {
+        listen,
+        workers,
+        queue_size,
+        max_body_size: max_body_size * 1024 * 1024,
+        max_pixels: max_pixels * 1_000_000,
+        base_config,
+    });
+
+    server.run()?;
+
+    Ok(())
+}
+
+fn process_frames(
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+    every: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let written = FrameExtractor::new().with_stride(every).extract(&input, &output)?;
+
+    println!("✓ Extracted {} frame(s) to: {}", written.len(), output.display());
+
+    Ok(())
+}
+
+fn process_tiles(
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+    cols: Option<u32>,
+    rows: Option<u32>,
+    tile_size: Option<u32>,
+    format: Option<OutputFormat>,
+    quality: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::{TileGrid, TileSlicer};
+
+    let grid = match (tile_size, cols, rows) {
+        (Some(size), _, _) => TileGrid::Size { width: size, height: size },
+        (None, None, None) => {
+            return Err(ImageToolError::InvalidParameter(
+                "Give --cols/--rows or --tile-size".to_string(),
+            ).into());
+        }
+        (None, cols, rows) => TileGrid::Count {
+            columns: cols.unwrap_or(1),
+            rows: rows.unwrap_or(1),
+        },
+    };
+
+    let format: Option<pixie::OutputFormat> = format.map(|f| f.into());
+    let format = format.and_then(|format| format.image_format());
+    let written = TileSlicer::new(grid)
+        .with_format(format)
+        .with_quality(quality)
+        .write(&input, &output)?;
+
+    println!("✓ Wrote {} tile(s) to: {}", written.len(), output.display());
+
+    Ok(())
+}
+
+fn process_dzi(
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+    layout: pixie::PyramidKind,
+    tile_size: Option<u32>,
+    overlap: u32,
+    format: Option<OutputFormat>,
+    quality: u8,
+    base_url: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::PyramidGenerator;
+
+    let mut generator = PyramidGenerator::new(layout.into())
+        .with_overlap(overlap)
+        .with_quality(quality)
+        .with_base_url(base_url);
+    if let Some(tile_size) = tile_size {
+        generator = generator.with_tile_size(tile_size);
+    }
+    let format: Option<pixie::OutputFormat> = format.map(|f| f.into());
+    if let Some(format) = format.and_then(|format| format.image_format()) {
+        generator = generator.with_format(format);
+    }
+
+    let summary = generator.generate(&input, &output)?;
+
+    println!(
+        "✓ {}x{} pyramid: {} levels, {} tiles, described by {}",
+        summary.width,
+        summary.height,
+        summary.levels,
+        summary.tiles,
+        summary.descriptor.display()
+    );
+
+    Ok(())
+}
+
+fn process_thumb(
+    input: std::path::PathBuf,
+    output: Option<std::path::PathBuf>,
+    at: String,
+    width: u32,
+    height: u32,
+    quality: u8,
+    format: Option<OutputFormat>,
+    algorithm: Algorithm,
+    ffmpeg: std::path::PathBuf,
+    processing: ProcessingArgs,
+    max_memory: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::generate_output_path;
+
+    let mut config = ProcessConfig {
+        width,
+        height,
+        quality,
+        keep_aspect: true,
+        algorithm: algorithm.into(),
+        max_memory,
+        format: format.map(|f| f.into()),
+        ..Default::default()
+    };
+
+    processing.apply(&mut config);
+    config.validate()?;
+
+    // Default to a JPEG poster next to the video
+    let generated = generate_output_path(&input.with_extension("jpg"), output.as_deref(), "thumb");
+    let Some(output_path) = resolve_output(generated, output.is_none(), config.on_conflict)? else {
+        return Ok(());
+    };
+
+    let frame = VideoFrameGrabber::new().with_ffmpeg(ffmpeg).grab_frame(&input, &at)?;
+    let processor = ImageProcessor::new(config);
+    let stats = processor.process_bytes_to_file(&frame, &output_path)?;
+
+    println!("✓ Thumbnail saved to: {}", output_path.display());
+    print_stats(&stats);
+
+    Ok(())
+}
+
+fn process_texture(
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+    format: Option<TextureFormat>,
+    compression: BlockCompression,
+    no_mipmaps: bool,
+    algorithm: Algorithm,
+    max_memory: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::{Loader, TextureBaker, TextureContainer};
+
+    let container = match format {
+        Some(format) => format.into(),
+        None => TextureContainer::from_path(&output).ok_or_else(|| {
+            ImageToolError::InvalidParameter(format!(
+                "Cannot tell texture format from {}; use --format dds|ktx2",
+                output.display()
+            ))
+        })?,
+    };
+
+    let loader = match max_memory {
+        Some(max_memory) => Loader::new().with_max_memory(max_memory),
+        None => Loader::new(),
+    };
+    let image = loader.load(&input)?;
+
+    TextureBaker::new(container)
+        .with_compression(compression.into())
+        .with_mipmaps(!no_mipmaps)
+        .with_algorithm(algorithm.into())
+        .bake(&image, &output)?;
+
+    println!("✓ Texture saved to: {}", output.display());
+
+    Ok(())
+}
+
+fn process_collage(
+    inputs: Vec<std::path::PathBuf>,
+    output: std::path::PathBuf,
+    layout: Option<pixie::GridLayout>,
+    width: u32,
+    height: u32,
+    gap: u32,
+    background: pixie::Color,
+    fill: bool,
+    quality: u8,
+    algorithm: Algorithm,
+    max_memory: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::{Collage, Loader};
+
+    let loader = match max_memory {
+        Some(max_memory) => Loader::new().with_max_memory(max_memory),
+        None => Loader::new(),
+    };
+    let images = inputs
+        .iter()
+        .map(|input| loader.load(input))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let collage = Collage::new()
+        .with_layout(layout)
+        .with_cell_size(width, height)
+        .with_gap(gap)
+        .with_background(background)
+        .with_fill(fill)
+        .with_algorithm(algorithm.into())
+        .compose(&images)?;
+
+    Compressor::new(quality).save(&collage, &output)?;
+
+    println!(
+        "✓ Collage of {} image(s) ({}x{}) saved to: {}",
+        images.len(),
+        collage.width(),
+        collage.height(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn process_join(
+    inputs: Vec<std::path::PathBuf>,
+    output: std::path::PathBuf,
+    direction: pixie::Direction,
+    align: pixie::Align,
+    spacing: u32,
+    background: pixie::Color,
+    same_size: bool,
+    quality: u8,
+    algorithm: Algorithm,
+    max_memory: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::{Joiner, Loader};
+
+    let loader = match max_memory {
+        Some(max_memory) => Loader::new().with_max_memory(max_memory),
+        None => Loader::new(),
+    };
+    let images = inputs
+        .iter()
+        .map(|input| loader.load(input))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let joined = Joiner::new(direction.into())
+        .with_align(align.into())
+        .with_spacing(spacing)
+        .with_background(background)
+        .with_same_size(same_size)
+        .with_algorithm(algorithm.into())
+        .join(&images)?;
+
+    Compressor::new(quality).save(&joined, &output)?;
+
+    println!(
+        "✓ Joined {} image(s) ({}x{}) into: {}",
+        images.len(),
+        joined.width(),
+        joined.height(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn process_rename(
+    dir: std::path::PathBuf,
+    pattern: String,
+    recursive: bool,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::Renamer;
+
+    let report = Renamer::new(&pattern)?
+        .with_recursive(recursive)
+        .with_dry_run(dry_run)
+        .rename_directory(&dir)?;
+
+    let relative = |path: &std::path::Path| path.strip_prefix(&dir).unwrap_or(path).display().to_string();
+    for (from, to) in &report.renamed {
+        println!("  {} -> {}", relative(from), relative(to));
+    }
+    if dry_run {
+        println!("Would rename {} file(s)", report.renamed.len());
+    } else {
+        println!("✓ Renamed {} file(s)", report.renamed.len());
+    }
+    if report.unchanged > 0 {
+        println!("  Already named: {} file(s)", report.unchanged);
+    }
+
+    if !report.skipped.is_empty() {
+        println!("\n⚠  {} file(s) skipped:", report.skipped.len());
+        for (path, reason) in &report.skipped {
+            println!("  - {}: {}", relative(path), reason);
+        }
+    }
+
+    Ok(())
+}
+
+fn process_meta_copy(
+    source: std::path::PathBuf,
+    destination: std::path::PathBuf,
+    exif: bool,
+    xmp: bool,
+    icc: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use pixie::MetadataCopier;
+
+    let report = MetadataCopier::new()
+        .with_exif(exif)
+        .with_xmp(xmp)
+        .with_icc(icc)
+        .copy(&source, &destination)?;
+    if report.is_empty() {
+        println!("Nothing to copy: {} has no EXIF, XMP or ICC profile", source.display());
+        return Ok(());
+    }
+
+    let copied: Vec<&str> = [(report.exif, "EXIF"), (report.xmp, "XMP"), (report.icc, "ICC profile")]
+        .iter()
+        .filter(|(copied, _)| *copied)
+        .map(|(_, name)| *name)
+        .collect();
+    println!("✓ Copied {} to {}", copied.join(", "), destination.display());
+    Ok(())
+}
+
+fn process_fix_orientation(
+    path: std::path::PathBuf,
+    recursive: bool,
+    quality: u8,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    use pixie::OrientationFixer;
+
+    let fixes = OrientationFixer::new()
+        .with_recursive(recursive)
+        .with_quality(quality)
+        .fix(&path)?;
+
+    for fix in fixes.iter().filter(|fix| fix.fixed || fix.error.is_some()) {
+        match &fix.error {
+            Some(error) => println!("⚠  {}: {}", fix.path.display(), error),
+            None if fix.reencoded => {
+                println!("✓ {} (orientation {}, re-encoded)", fix.path.display(), fix.orientation)
+            }
+            None => println!("✓ {} (orientation {})", fix.path.display(), fix.orientation),
+        }
+    }
+
+    let fixed = fixes.iter().filter(|fix| fix.fixed).count();
+    let failed = fixes.iter().filter(|fix| fix.error.is_some()).count();
+    if fixed == 0 && failed == 0 {
+        println!("✓ All {} file(s) already upright", fixes.len());
+    } else {
+        println!("\nTurned {} of {} file(s) upright", fixed, fixes.len());
+    }
+    if failed > 0 {
+        println!("⚠  {} file(s) could not be checked or fixed", failed);
+    }
+
+    Ok(if fixes.iter().all(|fix| fix.is_upright()) {
+        exit_code::SUCCESS
+    } else {
+        exit_code::PARTIAL_FAILURE
+    })
+}
+
+fn process_audit(
+    input: std::path::PathBuf,
+    recursive: bool,
+    fix: bool,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    use pixie::PrivacyAuditor;
+
+    let audits = PrivacyAuditor::new()
+        .with_recursive(recursive)
+        .with_fix(fix)
+        .audit(&input)?;
+
+    for audit in audits.iter().filter(|audit| !audit.risks.is_empty() || audit.error.is_some()) {
+        println!("{}", audit.path.display());
+        for (risk, detail) in &audit.risks {
+            println!("  - {}: {}", risk, detail);
+        }
+        if audit.fixed {
+            println!("  ✓ Removed");
+        }
+        if let Some(error) = &audit.error {
+            println!("  ⚠  {}", error);
+        }
+    }
+
+    let flagged = audits.iter().filter(|audit| !audit.risks.is_empty()).count();
+    let fixed = audits.iter().filter(|audit| audit.fixed).count();
+    let failed = audits.iter().filter(|audit| audit.error.is_some()).count();
+    if flagged == 0 {
+        println!("✓ No private metadata found in {} file(s)", audits.len() - failed);
+    } else if fix {
+        println!("\nPrivate metadata in {} of {} file(s), removed from {}", flagged, audits.len(), fixed);
+    } else {
+        println!("\nPrivate metadata in {} of {} file(s); rerun with --fix to remove it", flagged, audits.len());
+    }
+    if failed > 0 {
+        println!("⚠  {} file(s) could not be checked or fixed", failed);
+    }
+
+    Ok(if audits.iter().all(|audit| audit.is_clean()) {
+        exit_code::SUCCESS
+    } else if flagged > fixed {
+        exit_code::PRIVATE_METADATA
+    } else {
+        exit_code::PARTIAL_FAILURE
+    })
+}
+
+fn process_validate(
+    paths: Vec<std::path::PathBuf>,
+    recursive: bool,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    use pixie::ImageValidator;
+
+    let checks = ImageValidator::new().with_recursive(recursive).validate(&paths)?;
+
+    for check in checks.iter().filter(|check| !check.is_valid()) {
+        match check.format {
+            Some(format) => println!("✗ {} ({:?})", check.path.display(), format),
+            None => println!("✗ {}", check.path.display()),
+        }
+        for defect in &check.defects {
+            println!("  - {}", defect);
+        }
+    }
+
+    let broken = checks.iter().filter(|check| !check.is_valid()).count();
+    if broken == 0 {
+        println!("✓ All {} file(s) are intact", checks.len());
+        Ok(exit_code::SUCCESS)
+    } else {
+        println!("
+{} of {} file(s) are broken", broken, checks.len());
+        Ok(exit_code::BROKEN_IMAGE)
+    }
+}
+
+#[cfg(feature = "man")]
+fn process_man(output: Option<std::path::PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    use clap::CommandFactory;
+
+    let command = Cli::command();
+
+    match output {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            clap_mangen::generate_to(command, &dir)?;
+            println!("✓ Man pages written to: {}", dir.display());
+        }
+        None => {
+            clap_mangen::Man::new(command).render(&mut std::io::stdout())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "man"))]
+fn process_man(_output: Option<std::path::PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    Err("pixie was built without the `man` feature".into())
+}
+
 fn print_stats(stats: &ProcessingStats) {
     if stats.processed_count > 0 && stats.total_size_before > 0 {
         let reduction = if stats.total_size_after < stats.total_size_before {
@@ -294,4 +1737,22 @@ fn print_stats(stats: &ProcessingStats) {
         println!("  Original size: {}", format_file_size(stats.total_size_before));
         println!("  Final size: {}{}", format_file_size(stats.total_size_after), reduction);
     }
+
+    if log::log_enabled!(log::Level::Debug) && !stats.timings.is_empty() {
+        println!("  Time: {}", stats.total_timings());
+    }
+
+    match stats.quality.as_slice() {
+        [] => {}
+        [(_, score)] => println!("  Quality: SSIM {:.4}, PSNR {:.2} dB", score.ssim, score.psnr),
+        scores => {
+            let mean = scores.iter().map(|(_, score)| score.ssim).sum::<f64>() / scores.len() as f64;
+            let (worst_path, worst) = scores
+                .iter()
+                .min_by(|a, b| a.1.ssim.total_cmp(&b.1.ssim))
+                .expect("not empty");
+            println!("  Quality: mean SSIM {:.4}", mean);
+            println!("  Lowest: SSIM {:.4}, PSNR {:.2} dB ({})", worst.ssim, worst.psnr, worst_path.display());
+        }
+    }
 }
\ No newline at end of file
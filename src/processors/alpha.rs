@@ -0,0 +1,174 @@
+// pixie/src/processors/alpha.rs
+use crate::core::{AlphaSource, Color, Result};
+use crate::processors::Loader;
+use crate::utils::into_color;
+use image::{imageops, ColorType, DynamicImage};
+
+/// Adds an alpha channel, taking transparency from a mask image or a color key
+pub struct AlphaMasker {
+    source: AlphaSource,
+    fuzz: f32,
+}
+
+impl AlphaMasker {
+    pub fn new(source: AlphaSource) -> Self {
+        Self { source, fuzz: 0.0 }
+    }
+
+    /// Color distance in percent that still counts as the key color
+    pub fn with_fuzz(mut self, percent: f32) -> Self {
+        self.fuzz = percent.clamp(0.0, 100.0);
+        self
+    }
+
+    /// Existing transparency is kept and combined with the new mask
+    pub fn apply(&self, image: &DynamicImage) -> Result<DynamicImage> {
+        let mut working = image.to_rgba32f();
+
+        match &self.source {
+            AlphaSource::Mask(path) => {
+                let mask = Loader::new().load(path)?.into_luma16();
+                let mask = if mask.dimensions() != working.dimensions() {
+                    log::debug!(
+                        "Stretching {}x{} alpha mask to {}x{}",
+                        mask.width(),
+                        mask.height(),
+                        working.width(),
+                        working.height()
+                    );
+                    imageops::resize(&mask, working.width(), working.height(), imageops::FilterType::Triangle)
+                } else {
+                    mask
+                };
+                for (pixel, coverage) in working.pixels_mut().zip(mask.pixels()) {
+                    pixel.0[3] *= coverage.0[0] as f32 / u16::MAX as f32;
+                }
+            }
+            AlphaSource::ColorKey(key) => {
+                let key = [key.r, key.g, key.b];
+                // RMS distance over the three channels, in 8-bit steps
+                let tolerance = self.fuzz / 100.0 * 255.0;
+                let mut keyed = 0usize;
+                for pixel in working.pixels_mut() {
+                    let rgb: [u8; 3] = std::array::from_fn(|c| (pixel.0[c].clamp(0.0, 1.0) * 255.0).round() as u8);
+                    let distance = ((0..3).map(|c| (rgb[c] as f32 - key[c] as f32).powi(2)).sum::<f32>() / 3.0).sqrt();
+                    if distance <= tolerance {
+                        pixel.0[3] = 0.0;
+                        keyed += 1;
+                    }
+                }
+                log::debug!("Color key made {} pixels transparent", keyed);
+            }
+        }
+
+        Ok(into_color(DynamicImage::ImageRgba32F(working), with_alpha(image.color())))
+    }
+}
+
+/// Rounds the corners off by making them transparent, with an
+/// anti-aliased edge
+pub struct CornerRounder {
+    radius: u32,
+}
+
+impl CornerRounder {
+    pub fn new(radius: u32) -> Self {
+        Self { radius }
+    }
+
+    /// Radii past half the shorter side are cut down to it
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let (width, height) = (image.width(), image.height());
+        let radius = self.radius.min(width.min(height) / 2) as f32;
+        if radius == 0.0 {
+            return image.clone();
+        }
+
+        let mut working = image.to_rgba32f();
+        for (x, y, pixel) in working.enumerate_pixels_mut() {
+            // Distance past the corner's arc, from the pixel center
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let dx = (radius - px).max(px - (width as f32 - radius));
+            let dy = (radius - py).max(py - (height as f32 - radius));
+            if dx <= 0.0 || dy <= 0.0 {
+                continue;
+            }
+            let coverage = (radius - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            pixel.0[3] *= coverage;
+        }
+        into_color(DynamicImage::ImageRgba32F(working), with_alpha(image.color()))
+    }
+}
+
+/// Makes everything outside the ellipse touching the image's four edges
+/// transparent, with an anti-aliased edge; a circle for square images
+pub struct EllipseMasker;
+
+impl EllipseMasker {
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let (a, b) = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+        let mut working = image.to_rgba32f();
+        for (x, y, pixel) in working.enumerate_pixels_mut() {
+            let u = (x as f32 + 0.5 - a) / a;
+            let v = (y as f32 + 0.5 - b) / b;
+            let f = (u * u + v * v).sqrt();
+            if f == 0.0 {
+                continue;
+            }
+            // First-order distance to the edge, in pixels
+            let gradient = ((u / a).powi(2) + (v / b).powi(2)).sqrt() / f;
+            let distance = (f - 1.0) / gradient;
+            pixel.0[3] *= (0.5 - distance).clamp(0.0, 1.0);
+        }
+        into_color(DynamicImage::ImageRgba32F(working), with_alpha(image.color()))
+    }
+}
+
+// The same depth and channels, plus alpha
+fn with_alpha(color: ColorType) -> ColorType {
+    match color {
+        ColorType::L8 | ColorType::La8 => ColorType::La8,
+        ColorType::L16 | ColorType::La16 => ColorType::La16,
+        ColorType::Rgb16 | ColorType::Rgba16 => ColorType::Rgba16,
+        ColorType::Rgb32F | ColorType::Rgba32F => ColorType::Rgba32F,
+        _ => ColorType::Rgba8,
+    }
+}
+
+/// Composites the image over a solid background and drops the alpha channel
+pub struct Flattener {
+    background: Color,
+}
+
+impl Flattener {
+    pub fn new(background: Color) -> Self {
+        Self { background }
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        if !image.color().has_alpha() {
+            return image.clone();
+        }
+
+        let background = [self.background.r, self.background.g, self.background.b].map(|v| v as f32 / 255.0);
+        let mut working = image.to_rgba32f();
+        for pixel in working.pixels_mut() {
+            let alpha = pixel.0[3].clamp(0.0, 1.0);
+            for (value, background) in pixel.0.iter_mut().zip(background) {
+                *value = *value * alpha + background * (1.0 - alpha);
+            }
+            pixel.0[3] = 1.0;
+        }
+
+        // A colored background turns grayscale images into color ones
+        let gray = !image.color().has_color() && self.background.is_gray();
+        let color = match (image.color(), gray) {
+            (ColorType::Rgba32F, _) => ColorType::Rgb32F,
+            (ColorType::La16 | ColorType::Rgba16, true) => ColorType::L16,
+            (ColorType::La16 | ColorType::Rgba16, false) => ColorType::Rgb16,
+            (_, true) => ColorType::L8,
+            (_, false) => ColorType::Rgb8,
+        };
+        into_color(DynamicImage::ImageRgba32F(working), color)
+    }
+}
@@ -0,0 +1,657 @@
+// pixie/src/processors/archive.rs
+use crate::core::{ImageToolError, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIR: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_EXTRA: u16 = 0x0001;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const UTF8_NAMES: u16 = 0x0800;
+
+const TAR_BLOCK: usize = 512;
+
+/// One file in a ZIP archive, as listed in its central directory
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    /// Uncompressed size
+    pub size: u64,
+    compressed_size: u64,
+    method: u16,
+    crc32: u32,
+    encrypted: bool,
+    header_offset: u64,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+/// Reads the files in a ZIP archive, `.cbz` comic archives included, one
+/// at a time without extracting it. Stored and deflated entries are
+/// supported, as are ZIP64 archives; encrypted entries are not.
+pub struct ZipReader {
+    path: PathBuf,
+    file: Mutex<File>,
+    entries: Vec<ZipEntry>,
+    by_name: HashMap<String, usize>,
+}
+
+impl ZipReader {
+    /// Whether `path` names a ZIP archive, going by its extension
+    pub fn is_zip(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip") || ext.eq_ignore_ascii_case("cbz"))
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        // The end record sits in the last 22 bytes, followed by a comment
+        // of at most 64 KiB
+        let tail_len = len.min(22 + u16::MAX as u64);
+        let mut tail = vec![0; tail_len as usize];
+        file.seek(SeekFrom::Start(len - tail_len))?;
+        file.read_exact(&mut tail)?;
+        let end = (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|&pos| u32_at(&tail, pos) == END_OF_CENTRAL_DIR)
+            .ok_or_else(|| corrupt(path, "no end of central directory"))?;
+
+        let mut count = u16_at(&tail, end + 10) as u64;
+        let mut directory_size = u32_at(&tail, end + 12) as u64;
+        let mut directory_offset = u32_at(&tail, end + 16) as u64;
+
+        // Values that don't fit are found in the ZIP64 end record instead
+        if end >= 20 && u32_at(&tail, end - 20) == ZIP64_LOCATOR {
+            let mut record = [0; 56];
+            file.seek(SeekFrom::Start(u64_at(&tail, end - 12)))?;
+            file.read_exact(&mut record)?;
+            if u32_at(&record, 0) != ZIP64_END_OF_CENTRAL_DIR {
+                return Err(corrupt(path, "bad ZIP64 end record"));
+            }
+            count = u64_at(&record, 32);
+            directory_size = u64_at(&record, 40);
+            directory_offset = u64_at(&record, 48);
+        }
+
+        if directory_offset.saturating_add(directory_size) > len {
+            return Err(corrupt(path, "central directory lies past the end of the file"));
+        }
+        let mut directory = vec![0; directory_size as usize];
+        file.seek(SeekFrom::Start(directory_offset))?;
+        file.read_exact(&mut directory)?;
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while entries.len() as u64 != count {
+            if pos + 46 > directory.len() || u32_at(&directory, pos) != CENTRAL_HEADER {
+                return Err(corrupt(path, "truncated central directory"));
+            }
+            let name_len = u16_at(&directory, pos + 28) as usize;
+            let extra_len = u16_at(&directory, pos + 30) as usize;
+            let comment_len = u16_at(&directory, pos + 32) as usize;
+            let next = pos + 46 + name_len + extra_len + comment_len;
+            if next > directory.len() {
+                return Err(corrupt(path, "truncated central directory"));
+            }
+
+            let mut entry = ZipEntry {
+                name: String::from_utf8_lossy(&directory[pos + 46..pos + 46 + name_len]).into_owned(),
+                size: u32_at(&directory, pos + 24) as u64,
+                compressed_size: u32_at(&directory, pos + 20) as u64,
+                method: u16_at(&directory, pos + 10),
+                crc32: u32_at(&directory, pos + 16),
+                encrypted: u16_at(&directory, pos + 8) & 1 != 0,
+                header_offset: u32_at(&directory, pos + 42) as u64,
+            };
+            read_zip64_extra(&mut entry, &directory[pos + 46 + name_len..pos + 46 + name_len + extra_len]);
+            entries.push(entry);
+            pos = next;
+        }
+
+        let by_name = entries.iter().enumerate().map(|(index, entry)| (entry.name.clone(), index)).collect();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            entries,
+            by_name,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&ZipEntry> {
+        self.by_name.get(name).map(|&index| &self.entries[index])
+    }
+
+    /// Decompresses one entry, checking its size and CRC against the
+    /// central directory. Never returns more than `entry.size` bytes, so
+    /// callers can bound memory by checking the size up front.
+    pub fn read(&self, entry: &ZipEntry) -> Result<Vec<u8>> {
+        if entry.encrypted {
+            return Err(ImageToolError::UnsupportedFormat(format!("{} is encrypted", entry.name)));
+        }
+
+        let compressed = {
+            let mut file = self
+                .file
+                .lock()
+                .map_err(|_| ImageToolError::ProcessingError("Archive lock poisoned".to_string()))?;
+            let mut header = [0; 30];
+            file.seek(SeekFrom::Start(entry.header_offset))?;
+            file.read_exact(&mut header)?;
+            if u32_at(&header, 0) != LOCAL_HEADER {
+                return Err(corrupt(&self.path, "bad local header"));
+            }
+            let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+            file.seek(SeekFrom::Current(skip))?;
+
+            let mut compressed = Vec::new();
+            (&mut *file).take(entry.compressed_size).read_to_end(&mut compressed)?;
+            compressed
+        };
+
+        let mut data = Vec::new();
+        match entry.method {
+            STORED => data = compressed,
+            DEFLATED => {
+                // One byte past the size shows an entry lying about it
+                DeflateDecoder::new(compressed.as_slice())
+                    .take(entry.size.saturating_add(1))
+                    .read_to_end(&mut data)?;
+            }
+            method => {
+                return Err(ImageToolError::UnsupportedFormat(format!(
+                    "{} uses compression method {}",
+                    entry.name, method
+                )))
+            }
+        }
+
+        if data.len() as u64 != entry.size || crc32fast::hash(&data) != entry.crc32 {
+            return Err(corrupt(&self.path, &format!("{} is damaged", entry.name)));
+        }
+        Ok(data)
+    }
+}
+
+/// One regular file in a tar stream
+#[derive(Debug, Clone)]
+pub struct TarEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Reads the files in a tar stream in order, as from `tar c` on a pipe.
+/// Directories, links and other special entries are skipped; ustar, PAX
+/// and GNU long names are understood.
+pub struct TarReader<R: Read> {
+    input: R,
+    // Of the entry last returned by `next_entry`
+    data_left: u64,
+    padding: u64,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            data_left: 0,
+            padding: 0,
+        }
+    }
+
+    /// Moves to the next regular file, skipping whatever of the current
+    /// one's contents wasn't read. None at the end of the archive.
+    pub fn next_entry(&mut self) -> Result<Option<TarEntry>> {
+        let mut long_name = None;
+        let mut long_size = None;
+        loop {
+            self.skip_data()?;
+
+            let mut header = [0; TAR_BLOCK];
+            if !read_block(&mut self.input, &mut header)? || header.iter().all(|&b| b == 0) {
+                return Ok(None);
+            }
+
+            let expected = parse_octal(&header[148..156])?;
+            let checksum: u64 = header
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 })
+                .sum();
+            if checksum != expected {
+                return Err(tar_corrupt("bad header checksum"));
+            }
+
+            let mut name = c_string(&header[..100]);
+            if &header[257..262] == b"ustar" {
+                let prefix = c_string(&header[345..500]);
+                if !prefix.is_empty() {
+                    name = format!("{}/{}", prefix, name);
+                }
+            }
+            let kind = header[156];
+            let mut size = parse_size(&header[124..136])?;
+            // Long names and sizes apply to the next entry that isn't another header
+            if !matches!(kind, b'x' | b'g' | b'L') {
+                name = long_name.take().unwrap_or(name);
+                size = long_size.take().unwrap_or(size);
+            }
+            self.data_left = size;
+            self.padding = (TAR_BLOCK as u64 - size % TAR_BLOCK as u64) % TAR_BLOCK as u64;
+
+            match kind {
+                // PAX extended header for the next entry
+                b'x' => {
+                    let records = self.read_data()?;
+                    for (key, value) in pax_records(&records)? {
+                        match key {
+                            "path" => long_name = Some(value.to_string()),
+                            "size" => {
+                                long_size = Some(value.parse().map_err(|_| tar_corrupt("bad PAX size"))?)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                // GNU long name for the next entry
+                b'L' => long_name = Some(c_string(&self.read_data()?)),
+                b'0' | 0 | b'7' if !name.ends_with('/') => return Ok(Some(TarEntry { name, size })),
+                _ => {}
+            }
+        }
+    }
+
+    /// The contents of the entry last returned by `next_entry`
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        (&mut self.input).take(self.data_left).read_to_end(&mut data)?;
+        if data.len() as u64 != self.data_left {
+            return Err(tar_corrupt("truncated entry"));
+        }
+        self.data_left = 0;
+        self.skip_data()?;
+        Ok(data)
+    }
+
+    /// Skips the contents of the entry last returned by `next_entry`
+    pub fn skip_data(&mut self) -> Result<()> {
+        let skip = self.data_left + self.padding;
+        if std::io::copy(&mut (&mut self.input).take(skip), &mut std::io::sink())? != skip {
+            return Err(tar_corrupt("truncated entry"));
+        }
+        self.data_left = 0;
+        self.padding = 0;
+        Ok(())
+    }
+}
+
+// False on a clean end of input before the block
+fn read_block(input: &mut impl Read, block: &mut [u8; TAR_BLOCK]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < TAR_BLOCK {
+        match input.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(tar_corrupt("truncated header")),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+fn c_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64> {
+    let text = std::str::from_utf8(field).map_err(|_| tar_corrupt("bad number"))?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| tar_corrupt("bad number"))
+}
+
+// GNU tar writes sizes too large for octal as big-endian binary, flagged
+// by the high bit
+fn parse_size(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 == 0 {
+        return parse_octal(field);
+    }
+    Ok(field[field.len() - 8..].iter().fold(0, |size, &b| (size << 8) | b as u64))
+}
+
+fn pax_records(mut data: &[u8]) -> Result<Vec<(&str, &str)>> {
+    let mut records = Vec::new();
+    while !data.is_empty() && data[0] != 0 {
+        let space = data.iter().position(|&b| b == b' ').ok_or_else(|| tar_corrupt("bad PAX record"))?;
+        let len: usize = std::str::from_utf8(&data[..space])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .filter(|&len| len > space + 1 && len <= data.len())
+            .ok_or_else(|| tar_corrupt("bad PAX record"))?;
+        let record = std::str::from_utf8(&data[space + 1..len - 1]).map_err(|_| tar_corrupt("bad PAX record"))?;
+        if let Some((key, value)) = record.split_once('=') {
+            records.push((key, value));
+        }
+        data = &data[len..];
+    }
+    Ok(records)
+}
+
+fn tar_corrupt(reason: &str) -> ImageToolError {
+    ImageToolError::ProcessingError(format!("Corrupt tar stream: {}", reason))
+}
+
+/// The kinds of archive `ArchiveWriter` can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Picks the format from a `.zip`, `.cbz`, `.tar`, `.tar.gz` or `.tgz` name
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".zip") || name.ends_with(".cbz") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes files into a ZIP or tar archive as they arrive, so nothing has
+/// to be staged on disk first. ZIP entries are stored uncompressed, as
+/// images rarely shrink further; archives over 4 GiB need tar.
+pub struct ArchiveWriter<W: Write> {
+    sink: Sink<W>,
+    names: HashSet<String>,
+    modified: u64,
+}
+
+enum Sink<W: Write> {
+    Zip {
+        out: W,
+        offset: u64,
+        directory: Vec<u8>,
+        count: u64,
+    },
+    Tar(W),
+    TarGz(GzEncoder<W>),
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(format: ArchiveFormat, out: W) -> Self {
+        let sink = match format {
+            ArchiveFormat::Zip => Sink::Zip {
+                out,
+                offset: 0,
+                directory: Vec::new(),
+                count: 0,
+            },
+            ArchiveFormat::Tar => Sink::Tar(out),
+            ArchiveFormat::TarGz => Sink::TarGz(GzEncoder::new(out, Compression::default())),
+        };
+        let modified = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            sink,
+            names: HashSet::new(),
+            modified,
+        }
+    }
+
+    /// Adds one file. Names must be unique within the archive.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        if name.is_empty() || !self.names.insert(name.to_string()) {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "{} is already in the archive",
+                name
+            )));
+        }
+
+        match &mut self.sink {
+            Sink::Zip {
+                out,
+                offset,
+                directory,
+                count,
+            } => {
+                if *offset + data.len() as u64 + 30 + name.len() as u64 > u32::MAX as u64 || *count == u16::MAX as u64 {
+                    self.names.remove(name);
+                    return Err(ImageToolError::InvalidParameter(
+                        "ZIP output is limited to 4 GiB and 65535 files, use .tar for more".to_string(),
+                    ));
+                }
+
+                let (time, date) = dos_time(self.modified);
+                let mut fields = Vec::with_capacity(26);
+                fields.extend_from_slice(&20u16.to_le_bytes());
+                fields.extend_from_slice(&UTF8_NAMES.to_le_bytes());
+                fields.extend_from_slice(&STORED.to_le_bytes());
+                fields.extend_from_slice(&time.to_le_bytes());
+                fields.extend_from_slice(&date.to_le_bytes());
+                fields.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+                fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                fields.extend_from_slice(&0u16.to_le_bytes());
+
+                out.write_all(&LOCAL_HEADER.to_le_bytes())?;
+                out.write_all(&fields)?;
+                out.write_all(name.as_bytes())?;
+                out.write_all(data)?;
+
+                // Made by Unix, so the mode in the external attributes counts
+                directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+                directory.extend_from_slice(&0x031Eu16.to_le_bytes());
+                directory.extend_from_slice(&fields);
+                directory.extend_from_slice(&[0; 6]);
+                directory.extend_from_slice(&(0o100644u32 << 16).to_le_bytes());
+                directory.extend_from_slice(&(*offset as u32).to_le_bytes());
+                directory.extend_from_slice(name.as_bytes());
+
+                *offset += 30 + name.len() as u64 + data.len() as u64;
+                *count += 1;
+            }
+            Sink::Tar(out) => write_tar_entry(out, name, data, self.modified)?,
+            Sink::TarGz(out) => write_tar_entry(out, name, data, self.modified)?,
+        }
+        Ok(())
+    }
+
+    /// Writes the trailing index or end blocks and hands back the writer
+    pub fn finish(self) -> Result<W> {
+        let mut out = match self.sink {
+            Sink::Zip {
+                mut out,
+                offset,
+                directory,
+                count,
+            } => {
+                if offset + directory.len() as u64 > u32::MAX as u64 {
+                    return Err(ImageToolError::InvalidParameter(
+                        "ZIP output is limited to 4 GiB and 65535 files, use .tar for more".to_string(),
+                    ));
+                }
+                out.write_all(&directory)?;
+                out.write_all(&END_OF_CENTRAL_DIR.to_le_bytes())?;
+                out.write_all(&[0; 4])?;
+                out.write_all(&(count as u16).to_le_bytes())?;
+                out.write_all(&(count as u16).to_le_bytes())?;
+                out.write_all(&(directory.len() as u32).to_le_bytes())?;
+                out.write_all(&(offset as u32).to_le_bytes())?;
+                out.write_all(&0u16.to_le_bytes())?;
+                out
+            }
+            Sink::Tar(mut out) => {
+                out.write_all(&[0; TAR_BLOCK * 2])?;
+                out
+            }
+            Sink::TarGz(mut out) => {
+                out.write_all(&[0; TAR_BLOCK * 2])?;
+                out.finish()?
+            }
+        };
+        out.flush()?;
+        Ok(out)
+    }
+}
+
+// A ustar header, preceded by a PAX header when the name or size doesn't
+// fit in it
+fn write_tar_entry(out: &mut impl Write, name: &str, data: &[u8], modified: u64) -> Result<()> {
+    let size = data.len() as u64;
+    let mut records = String::new();
+    if name.len() > 100 {
+        records.push_str(&pax_record("path", name));
+    }
+    if size > 0o77777777777 {
+        records.push_str(&pax_record("size", &size.to_string()));
+    }
+    if !records.is_empty() {
+        out.write_all(&tar_header("pax_header", records.len() as u64, modified, b'x'))?;
+        write_tar_data(out, records.as_bytes())?;
+    }
+
+    let short_name = match name.char_indices().nth(100) {
+        Some((end, _)) => &name[..end],
+        None => name,
+    };
+    out.write_all(&tar_header(short_name, size, modified, b'0'))?;
+    write_tar_data(out, data)
+}
+
+fn tar_header(name: &str, size: u64, modified: u64, kind: u8) -> [u8; TAR_BLOCK] {
+    let mut header = [0; TAR_BLOCK];
+    let name = &name.as_bytes()[..name.len().min(100)];
+    header[..name.len()].copy_from_slice(name);
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    // Sizes past the octal field's range are given in the PAX header
+    header[124..136].copy_from_slice(format!("{:011o}\0", size.min(0o77777777777)).as_bytes());
+    header[136..148].copy_from_slice(format!("{:011o}\0", modified.min(0o77777777777)).as_bytes());
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is summed with its own field as spaces
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    header
+}
+
+fn write_tar_data(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    out.write_all(data)?;
+    let padding = (TAR_BLOCK - data.len() % TAR_BLOCK) % TAR_BLOCK;
+    out.write_all(&[0; TAR_BLOCK][..padding])?;
+    Ok(())
+}
+
+// "<length> <key>=<value>\n", where the length counts its own digits
+fn pax_record(key: &str, value: &str) -> String {
+    let body = key.len() + value.len() + 3;
+    let mut len = body + body.to_string().len();
+    if len.to_string().len() + body > len {
+        len += 1;
+    }
+    format!("{} {}={}\n", len, key, value)
+}
+
+// MS-DOS time and date fields, in UTC
+fn dos_time(secs: u64) -> (u16, u16) {
+    let days = (secs / 86400) as i64;
+    let secs = secs % 86400;
+    let time = (((secs / 3600) << 11) | ((secs % 3600 / 60) << 5) | (secs % 60 / 2)) as u16;
+
+    // Days since 1970 to a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    let date = (((year - 1980).clamp(0, 127) << 9) | (month << 5) | day) as u16;
+    (time, date)
+}
+
+// Sizes and offsets of 0xFFFFFFFF are stored in the ZIP64 extra field, in
+// this order, and only those that overflowed
+fn read_zip64_extra(entry: &mut ZipEntry, mut extra: &[u8]) {
+    while extra.len() >= 4 {
+        let id = u16_at(extra, 0);
+        let len = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+        if id == ZIP64_EXTRA {
+            let mut values = extra[4..4 + len].chunks_exact(8).map(|value| u64_at(value, 0));
+            for field in [&mut entry.size, &mut entry.compressed_size, &mut entry.header_offset] {
+                if *field == u32::MAX as u64 {
+                    match values.next() {
+                        Some(value) => *field = value,
+                        None => break,
+                    }
+                }
+            }
+            return;
+        }
+        extra = &extra[4 + len..];
+    }
+}
+
+fn corrupt(path: &Path, reason: &str) -> ImageToolError {
+    ImageToolError::ProcessingError(format!("Corrupt ZIP archive {}: {}", path.display(), reason))
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap())
+}
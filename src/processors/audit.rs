@@ -0,0 +1,132 @@
+// pixie/src/processors/audit.rs
+use crate::core::{ImageToolError, Result};
+use crate::processors::batch::collect_image_paths;
+use crate::processors::metadata::PrivacyRisk;
+use crate::processors::MetadataProcessor;
+use std::path::{Path, PathBuf};
+
+/// Scans images for metadata that can identify the photographer or where
+/// a picture was taken, optionally removing it. JPEGs are fixed in place
+/// by rewriting their EXIF segment, leaving the image data untouched.
+pub struct PrivacyAuditor {
+    metadata: MetadataProcessor,
+    recursive: bool,
+    fix: bool,
+}
+
+#[derive(Debug)]
+pub struct FileAudit {
+    pub path: PathBuf,
+    pub risks: Vec<(PrivacyRisk, String)>,
+    /// Whether the risks were removed from the file
+    pub fixed: bool,
+    /// Why the file couldn't be read or fixed
+    pub error: Option<String>,
+}
+
+impl FileAudit {
+    /// Nothing private is left in the file
+    pub fn is_clean(&self) -> bool {
+        self.error.is_none() && (self.risks.is_empty() || self.fixed)
+    }
+}
+
+impl PrivacyAuditor {
+    pub fn new() -> Self {
+        Self {
+            metadata: MetadataProcessor::new(),
+            recursive: false,
+            fix: false,
+        }
+    }
+
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Remove what is found instead of only reporting it
+    pub fn with_fix(mut self, fix: bool) -> Self {
+        self.fix = fix;
+        self
+    }
+
+    /// Audits one image, or every image in a directory
+    pub fn audit(&self, path: &Path) -> Result<Vec<FileAudit>> {
+        if path.is_dir() {
+            let mut paths = collect_image_paths(path, self.recursive);
+            paths.sort();
+            Ok(paths.iter().map(|path| self.audit_file(path)).collect())
+        } else if path.is_file() {
+            Ok(vec![self.audit_file(path)])
+        } else {
+            Err(ImageToolError::InvalidParameter(format!(
+                "Input does not exist: {}",
+                path.display()
+            )))
+        }
+    }
+
+    pub fn audit_file(&self, path: &Path) -> FileAudit {
+        let mut audit = FileAudit {
+            path: path.to_path_buf(),
+            risks: Vec::new(),
+            fixed: false,
+            error: None,
+        };
+
+        let exif = match self.metadata.read_metadata(path) {
+            Ok(Some(exif)) => exif,
+            Ok(None) => return audit,
+            Err(e) => {
+                audit.error = Some(e.to_string());
+                return audit;
+            }
+        };
+        audit.risks = self.metadata.privacy_risks(&exif);
+
+        if self.fix && !audit.risks.is_empty() {
+            match self.fix_jpeg(path, &exif) {
+                Ok(()) => {
+                    log::info!("Removed private metadata from {}", path.display());
+                    audit.fixed = true;
+                }
+                Err(e) => audit.error = Some(e.to_string()),
+            }
+        }
+        audit
+    }
+
+    // Written next to the file and renamed over it, so a failed write
+    // never leaves a truncated image
+    fn fix_jpeg(&self, path: &Path, exif: &exif::Exif) -> Result<()> {
+        let data = std::fs::read(path)?;
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return Err(ImageToolError::UnsupportedFormat(
+                "only JPEGs can be fixed in place, reprocess others with --strip-metadata".to_string(),
+            ));
+        }
+
+        let fixed = match self.metadata.strip_private_exif(exif)? {
+            Some(tiff) => self.metadata.embed_exif_jpeg(&data, &tiff)?,
+            None => self.metadata.remove_exif_jpeg(&data)?,
+        };
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let part = path.with_file_name(format!(".pixie-part-{}", file_name));
+        let written = std::fs::write(&part, &fixed)
+            .and_then(|()| std::fs::set_permissions(&part, std::fs::metadata(path)?.permissions()))
+            .and_then(|()| std::fs::rename(&part, path));
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&part);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for PrivacyAuditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
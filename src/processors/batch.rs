@@ -1,37 +1,205 @@
-use crate::core::{ImageToolError, ProcessConfig, Result, ProcessingStats};
-use crate::processors::prelude::*;
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
+use crate::core::processor::{Encoded, ImageProcessor};
+use crate::core::{terminal_progress, ConflictPolicy, ImageToolError, ProcessingStage, Progress, ProgressHook, ProcessConfig, Result, ProcessingStats, StageTimings, TimeSource};
+use crate::processors::Loader;
+use crate::processors::archive::{ArchiveFormat, ArchiveWriter, TarReader, ZipEntry, ZipReader};
+use crate::processors::cache::ProcessCache;
+use crate::processors::checkpoint::Checkpoint;
+use crate::processors::journal::Journal;
+use crate::processors::sidecar::MetadataExporter;
+use crate::processors::lock::OutputLock;
+use crate::processors::overrides::FileOverrides;
+use crate::processors::rules::{ImageFacts, Rule};
+use crate::utils::{copy_file_attrs, format_extension, hash_file, renamed_path, resolve_conflict, set_file_time, sniff_format};
 use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::time::{Instant, SystemTime};
 use walkdir::WalkDir;
 
+// Clones share the thread pool
+#[derive(Clone)]
 pub struct BatchProcessor {
     config: ProcessConfig,
-    max_threads: usize,
-    thread_pool: Option<rayon::ThreadPool>,
+    thread_pool: Arc<rayon::ThreadPool>,
+    progress: Option<ProgressHook>,
+    journal_path: Option<PathBuf>,
+    skip_duplicates: bool,
+    memory_budget: Option<u64>,
+    cache_dir: Option<PathBuf>,
+    changed_only: bool,
+    checkpoint_path: Option<PathBuf>,
+    lock_output: bool,
+    force_lock: bool,
+    archive_output: Option<PathBuf>,
+    cancel: Option<Arc<AtomicBool>>,
+    pause: Option<Arc<AtomicBool>>,
+    stages: Vec<Arc<dyn ProcessingStage>>,
+    rules: Vec<Rule>,
 }
 
 impl BatchProcessor {
     pub fn new(config: ProcessConfig, max_threads: usize) -> Result<Self> {
-        let mut processor = Self {
+        // Owned rather than global, so several processors can live in one
+        // process; zero threads lets rayon pick one per CPU
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(max_threads)
+            .build()
+            .map_err(|e| {
+                ImageToolError::ProcessingError(format!("Failed to create thread pool: {}", e))
+            })?;
+
+        Ok(Self {
             config,
-            max_threads,
-            thread_pool: None,
-        };
+            thread_pool: Arc::new(thread_pool),
+            progress: Some(terminal_progress()),
+            journal_path: None,
+            skip_duplicates: false,
+            memory_budget: None,
+            cache_dir: None,
+            changed_only: false,
+            checkpoint_path: None,
+            lock_output: false,
+            force_lock: false,
+            archive_output: None,
+            cancel: None,
+            pause: None,
+            stages: Vec::new(),
+            rules: Vec::new(),
+        })
+    }
+
+    /// Draw a progress bar on stderr, or nothing
+    pub fn with_progress(mut self, show_progress: bool) -> Self {
+        self.progress = show_progress.then(terminal_progress);
+        self
+    }
 
-        // Initialize thread pool once
-        if max_threads > 0 {
-            let pool = rayon::ThreadPoolBuilder::new()
-                .num_threads(max_threads)
-                .build()
-                .map_err(|e| {
-                    ImageToolError::ProcessingError(format!("Failed to create thread pool: {}", e))
-                })?;
-            processor.thread_pool = Some(pool);
+    /// Report progress somewhere other than the terminal
+    pub fn with_progress_hook(mut self, hook: ProgressHook) -> Self {
+        self.progress = Some(hook);
+        self
+    }
+
+    /// Record every output written by `process_directory` so the run can be
+    /// undone with `rollback_journal`
+    pub fn with_journal(mut self, path: PathBuf) -> Self {
+        self.journal_path = Some(path);
+        self
+    }
+
+    /// Process only the first of several byte-identical inputs; the groups
+    /// are reported in `ProcessingStats::duplicates`
+    pub fn with_skip_duplicates(mut self, skip_duplicates: bool) -> Self {
+        self.skip_duplicates = skip_duplicates;
+        self
+    }
+
+    /// Cap the estimated decode memory of all images in flight, in bytes.
+    /// Workers wait for room before decoding, so large files run alone
+    /// while small ones still run in parallel.
+    pub fn with_memory_budget(mut self, budget: Option<u64>) -> Self {
+        self.memory_budget = budget;
+        self
+    }
+
+    /// Skip inputs whose output from the same content and settings is
+    /// still in place, as recorded in `dir` by earlier runs
+    pub fn with_cache(mut self, dir: Option<PathBuf>) -> Self {
+        self.cache_dir = dir;
+        self
+    }
+
+    /// Skip inputs whose output exists and was modified no earlier than the
+    /// input; counted in `ProcessingStats::unchanged_count`
+    pub fn with_changed_only(mut self, changed_only: bool) -> Self {
+        self.changed_only = changed_only;
+        self
+    }
+
+    /// Record finished inputs in `path`, and skip those an earlier run with
+    /// the same settings recorded. The file is deleted once a run leaves
+    /// nothing to resume.
+    pub fn with_checkpoint(mut self, path: Option<PathBuf>) -> Self {
+        self.checkpoint_path = path;
+        self
+    }
+
+    /// Hold a lock on the output directory for the whole run, failing with
+    /// `ImageToolError::Locked` if another run holds it. `force` takes the
+    /// lock over instead.
+    pub fn with_output_lock(mut self, lock: bool, force: bool) -> Self {
+        self.lock_output = lock;
+        self.force_lock = force;
+        self
+    }
+
+    /// Write the outputs into a `.zip`, `.tar` or `.tar.gz` archive at
+    /// `path` as they finish, rather than into the output directory, which
+    /// then only holds the run's temporary files. The cache, checkpoint
+    /// and changed-only checks need outputs on disk and are ignored.
+    pub fn with_archive_output(mut self, path: Option<PathBuf>) -> Self {
+        self.archive_output = path;
+        self
+    }
+
+    /// Stop starting new files once `flag` is set. Files already in flight
+    /// are finished, and the rest are listed in `ProcessingStats::cancelled`.
+    pub fn with_cancel(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Hold off starting new files while `flag` is set, letting the files
+    /// in flight finish, so a long run stops using the CPU until resumed
+    pub fn with_pause(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.pause = Some(flag);
+        self
+    }
+
+    /// Run `stage` on every image, after the built-in filters
+    pub fn with_stage(mut self, stage: Arc<dyn ProcessingStage>) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Rules checked against each input of a directory, in order; every
+    /// one that matches changes the options for that input
+    pub fn with_rules(mut self, rules: Vec<Rule>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    fn image_processor(&self, config: ProcessConfig) -> ImageProcessor {
+        with_stages(ImageProcessor::new(config), &self.stages)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    // Returns once resumed or cancelled
+    fn wait_while_paused(&self) {
+        if !self.is_paused() {
+            return;
         }
 
-        Ok(processor)
+        log::info!("Paused, the files in progress will finish");
+        self.report(Progress::Paused);
+        while self.is_paused() && !self.is_cancelled() {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        if !self.is_cancelled() {
+            log::info!("Resumed");
+            self.report(Progress::Resumed);
+        }
     }
 
     pub fn process_directory(
@@ -40,145 +208,513 @@ impl BatchProcessor {
         output_dir: &Path,
         recursive: bool,
     ) -> Result<ProcessingStats> {
+        let started = Instant::now();
         self.validate_paths(input_dir, output_dir)?;
 
         // Collect image files
-        let image_paths = self.collect_image_paths(input_dir, recursive)?;
+        let image_paths = collect_image_paths(input_dir, recursive);
 
         if image_paths.is_empty() {
             log::warn!("No image files found in {}", input_dir.display());
             return Ok(ProcessingStats::default());
         }
 
+        self.run_batch(started, input_dir, image_paths, output_dir, None)
+    }
+
+    /// Like `process_directory`, for the images in a ZIP or CBZ archive.
+    /// Entries are read into memory one at a time rather than extracted,
+    /// and each output is named after its entry's file name.
+    pub fn process_archive(&self, archive: &Path, output_dir: &Path) -> Result<ProcessingStats> {
+        let started = Instant::now();
+        if output_dir.to_string_lossy().contains("..") {
+            return Err(ImageToolError::SecurityError(
+                "Path traversal detected in output path".to_string()
+            ));
+        }
+
+        let reader = ZipReader::open(archive)?;
+        let entries = ArchiveEntries::new(&reader);
+        let image_paths: Vec<PathBuf> = entries.paths().cloned().collect();
+
+        if image_paths.is_empty() {
+            log::warn!("No image files found in {}", archive.display());
+            return Ok(ProcessingStats::default());
+        }
+
+        self.run_batch(started, archive, image_paths, output_dir, Some(&entries))
+    }
+
+    /// Reads a tar stream of images, plain or gzipped, and writes a tar of
+    /// the outputs under the same names, so a batch can run between two
+    /// pipes without touching the disk. Entries are written as they finish
+    /// rather than in input order; other files are left out. A broken
+    /// input or output stream ends the run with an error, while images
+    /// that fail are reported in the stats as usual.
+    pub fn process_tar_stream<R: Read + Send, W: Write>(&self, input: R, output: W) -> Result<ProcessingStats> {
+        let started = Instant::now();
+        let mut input = BufReader::new(input);
+        let input: Box<dyn Read + Send> = if input.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            Box::new(flate2::bufread::MultiGzDecoder::new(input))
+        } else {
+            Box::new(input)
+        };
+        let mut reader = TarReader::new(input);
+        let mut writer = ArchiveWriter::new(ArchiveFormat::Tar, output);
+
+        if !self.rules.is_empty() {
+            log::warn!("Rules are not supported for tar streams, ignoring");
+        }
+        let processor = self.image_processor(self.config.clone());
+        let exporter = self.config.export_metadata.map(MetadataExporter::new);
+        let max_size = self.config.max_file_size;
+        let depth = self.thread_pool.current_num_threads() * 2;
+        let (read_tx, read_rx) = mpsc::sync_channel::<(String, Result<Vec<u8>>)>(depth);
+        let (write_tx, write_rx) = mpsc::sync_channel(depth);
+        // Set when the output breaks, so reading stops too
+        let broken = AtomicBool::new(false);
+        let mut stats = ProcessingStats::default();
+
+        let finished = std::thread::scope(|scope| {
+            let broken = &broken;
+            let reading = scope.spawn(move || -> Result<()> {
+                while let Some(entry) = reader.next_entry()? {
+                    if !has_image_extension(Path::new(&entry.name)) {
+                        log::debug!("Skipping {}, not an image", entry.name);
+                        continue;
+                    }
+                    self.wait_while_paused();
+                    if self.is_cancelled() {
+                        log::warn!("Interrupted, finishing the files in progress");
+                        break;
+                    }
+                    let data = match max_size.filter(|&max| entry.size > max) {
+                        Some(max) => Err(ImageToolError::MemoryLimitExceeded(
+                            format!("File size {} exceeds limit {}", entry.size, max)
+                        )),
+                        None => Ok(reader.read_data()?),
+                    };
+                    if broken.load(Ordering::Relaxed) || read_tx.send((entry.name, data)).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            });
+
+            scope.spawn(|| {
+                self.thread_pool.install(|| {
+                    read_rx.into_iter().par_bridge().for_each_with(write_tx, |write_tx, (name, data)| {
+                        let processed = data.and_then(|data| {
+                            let sidecar = match &exporter {
+                                Some(exporter) => exporter.export(&data)?.map(|sidecar| {
+                                    (exporter.sidecar_path(Path::new(&name)).to_string_lossy().into_owned(), sidecar)
+                                }),
+                                None => None,
+                            };
+                            let encoded = processor.process_bytes(&data)?;
+                            Ok((data.len() as u64, encoded, sidecar))
+                        });
+                        let _ = write_tx.send((name, processed));
+                    });
+                });
+            });
+
+            let mut written = Ok(());
+            for (name, processed) in write_rx {
+                match processed {
+                    Ok((size_before, encoded, sidecar)) => {
+                        let added = match &sidecar {
+                            Some((sidecar_name, sidecar)) => writer.add(sidecar_name, sidecar),
+                            None => Ok(()),
+                        };
+                        if let Err(e) = added.and_then(|()| writer.add(&name, &encoded)) {
+                            broken.store(true, Ordering::Relaxed);
+                            written = Err(e);
+                            break;
+                        }
+                        log::info!("Wrote {} ({} bytes)", name, encoded.len());
+                        stats.processed_count += 1;
+                        stats.total_size_before += size_before;
+                        stats.total_size_after += encoded.len() as u64;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to process {}: {}", name, e);
+                        stats.errors.push((PathBuf::from(name), e));
+                    }
+                }
+                self.report(Progress::Advance);
+            }
+            let read = reading.join().expect("tar reader panicked");
+            written.and(read)
+        });
+        finished?;
+        writer.finish()?;
+
+        stats.elapsed = started.elapsed();
+        self.report(Progress::Finish(format!(
+            "Processed {} images, {} failed ({}% size reduction)",
+            stats.processed_count,
+            stats.failed_count(),
+            self.calculate_overall_savings(&stats)
+        )));
+
+        Ok(stats)
+    }
+
+    // Everything after collecting the inputs, which are files unless
+    // `archive` is given
+    fn run_batch(
+        &self,
+        started: Instant,
+        source: &Path,
+        mut image_paths: Vec<PathBuf>,
+        output_dir: &Path,
+        archive: Option<&ArchiveEntries<'_>>,
+    ) -> Result<ProcessingStats> {
+        let archive_format = self
+            .archive_output
+            .as_deref()
+            .map(|path| {
+                ArchiveFormat::from_path(path).ok_or_else(|| {
+                    ImageToolError::InvalidParameter(format!(
+                        "Archive output must end in .zip, .tar, .tar.gz or .tgz: {}",
+                        path.display()
+                    ))
+                })
+            })
+            .transpose()?;
+
+        let input_dir = archive.is_none().then_some(source);
+
+        // Create the output directory, and lock it before anything in it changes
+        std::fs::create_dir_all(output_dir)?;
+        let _lock = self
+            .lock_output
+            .then(|| OutputLock::acquire(output_dir, self.force_lock))
+            .transpose()?;
+        remove_part_files(output_dir);
+
+        let mut duplicates = Vec::new();
+        if self.skip_duplicates && archive.is_some() {
+            log::warn!("Skipping duplicates is not supported for archives, ignoring");
+        } else if self.skip_duplicates {
+            duplicates = self.thread_pool.install(|| find_duplicates(&image_paths))?;
+            let skipped: std::collections::HashSet<&PathBuf> =
+                duplicates.iter().flat_map(|group| group.iter().skip(1)).collect();
+            if !skipped.is_empty() {
+                log::info!("Skipping {} duplicate input(s)", skipped.len());
+            }
+            image_paths.retain(|path| !skipped.contains(path));
+        }
+
+        // Archive entries have no files next to them to take overrides
+        // from, and are not probed for rules
+        let mut overrides = HashMap::new();
+        let mut rejected = Vec::new();
+        let mut skipped_count = 0;
+        if archive.is_none() {
+            let settings: Vec<Result<FileSettings>> =
+                self.thread_pool.install(|| image_paths.par_iter().map(|path| self.file_settings(path)).collect());
+            let mut settings = settings.into_iter();
+            let before = image_paths.len();
+            image_paths.retain(|path| match settings.next().expect("settings for every input") {
+                Ok(FileSettings::Batch) => true,
+                Ok(FileSettings::Skip) => false,
+                Ok(FileSettings::Custom(config)) => {
+                    let processor = self.image_processor(*config.clone());
+                    overrides.insert(path.clone(), Overridden { config: *config, processor });
+                    true
+                }
+                Err(e) => {
+                    log::warn!("Failed to process {}: {}", path.display(), e);
+                    rejected.push((path.clone(), e));
+                    false
+                }
+            });
+            skipped_count = before - image_paths.len() - rejected.len();
+            if skipped_count > 0 {
+                log::info!("Skipping {} input(s) marked skip in their overrides", skipped_count);
+            }
+        } else if !self.rules.is_empty() {
+            log::warn!("Rules are not supported for archives, ignoring");
+        }
+
+        if self.archive_output.is_some() && self.checkpoint_path.is_some() {
+            log::warn!("Checkpoints are not supported with archive output, ignoring");
+        }
+        let checkpoint = self
+            .checkpoint_path
+            .as_deref()
+            .filter(|_| self.archive_output.is_none())
+            .map(|path| Checkpoint::open(path, &self.config))
+            .transpose()?;
+        let mut resumed_count = 0;
+        if let Some(checkpoint) = &checkpoint {
+            let before = image_paths.len();
+            image_paths.retain(|path| !checkpoint.is_done(path));
+            resumed_count = before - image_paths.len();
+            if resumed_count > 0 {
+                log::info!("Resuming from {}, {} input(s) already done", checkpoint.path().display(), resumed_count);
+            }
+        }
+
+        let mut unchanged_count = 0;
+        if self.changed_only && self.archive_output.is_some() {
+            log::warn!("Skipping unchanged inputs is not supported with archive output, ignoring");
+        } else if self.changed_only {
+            if self.config.preserve_times == Some(TimeSource::Captured) {
+                log::warn!("Outputs dated by capture time look older than their inputs, so all are reprocessed");
+            }
+            let before = image_paths.len();
+            // Entries are only as new as their archive, and editing an
+            // input's overrides counts as changing it
+            image_paths.retain(|path| match output_name(input_dir, path, &self.config) {
+                Some(name) => {
+                    let output = output_dir.join(name);
+                    is_newer(archive.map_or(path.as_path(), |a| a.reader.path()), &output)
+                        || is_newer_overrides(path, &output)
+                }
+                None => true,
+            });
+            unchanged_count = before - image_paths.len();
+            if unchanged_count > 0 {
+                log::info!("Skipping {} unchanged input(s)", unchanged_count);
+            }
+        }
+
+        // Renaming happens as each input is read, so names taken earlier in
+        // the run count too
+        let policy = self.config.on_conflict.unwrap_or(ConflictPolicy::Overwrite);
+        let mut existing_count = 0;
+        let mut conflicts = Vec::new();
+        if policy != ConflictPolicy::Overwrite && self.archive_output.is_some() {
+            log::warn!("Conflict policies are not supported with archive output, ignoring");
+        } else if matches!(policy, ConflictPolicy::Skip | ConflictPolicy::Error) {
+            let before = image_paths.len();
+            image_paths.retain(|path| {
+                let Some(name) = output_name(input_dir, path, &self.config) else { return true };
+                match resolve_conflict(&output_dir.join(name), policy) {
+                    Ok(Some(_)) => true,
+                    Ok(None) => false,
+                    Err(e) => {
+                        log::warn!("Failed to process {}: {}", path.display(), e);
+                        conflicts.push((path.clone(), e));
+                        false
+                    }
+                }
+            });
+            existing_count = before - image_paths.len() - conflicts.len();
+            if existing_count > 0 {
+                log::info!("Skipping {} input(s) whose output exists", existing_count);
+            }
+        }
+
         log::info!(
             "Processing {} images from {}",
             image_paths.len(),
-            input_dir.display()
+            source.display()
         );
 
-        // Create output directory
-        std::fs::create_dir_all(output_dir)?;
+        let journal = self.journal_path.as_deref().map(Journal::create).transpose()?;
 
-        // Create progress bar
-        let pb = self.create_progress_bar(image_paths.len());
-
-        // Process images in parallel
-        let config = Arc::new(self.config.clone());
-        let output_dir = Arc::new(output_dir.to_path_buf());
-        
-        let results: Vec<Result<ProcessingStats>> = if let Some(pool) = &self.thread_pool {
-            // Use custom thread pool
-            pool.install(|| {
-                image_paths
-                    .par_iter()
-                    .progress_with(pb.clone())
-                    .map(|input_path| {
-                        self.process_single_image_in_batch(
-                            input_path,
-                            &output_dir,
-                            config.as_ref(),
-                        )
-                    })
-                    .collect()
-            })
-        } else {
-            // Use global thread pool
-            image_paths
-                .par_iter()
-                .progress_with(pb.clone())
-                .map(|input_path| {
-                    self.process_single_image_in_batch(
-                        input_path,
-                        &output_dir,
-                        config.as_ref(),
-                    )
-                })
-                .collect()
+        let cache = match &self.cache_dir {
+            Some(_) if self.config.measure_quality => {
+                log::info!("Not using the cache, quality measurement needs every file processed");
+                None
+            }
+            Some(_) if self.archive_output.is_some() => {
+                log::info!("Not using the cache, archive output needs every file processed");
+                None
+            }
+            Some(dir) => Some(ProcessCache::open(dir, &self.config)?),
+            None => None,
         };
 
-        // Aggregate results
-        let mut stats = ProcessingStats::default();
-        for result in results {
+        self.report(Progress::Start {
+            total: image_paths.len() as u64,
+        });
+
+        // Written under a part name like the outputs, and renamed when complete
+        let archive_writer = match (&self.archive_output, archive_format) {
+            (Some(path), Some(format)) => {
+                let file = File::create(part_path(path))?;
+                Some(Mutex::new(ArchiveWriter::new(format, BufWriter::new(file))))
+            }
+            _ => None,
+        };
+
+        let budget = self.memory_budget.map(MemoryBudget::new);
+        let run = Run {
+            config: &self.config,
+            processor: self.image_processor(self.config.clone()),
+            overrides,
+            stages: &self.stages,
+            input_dir,
+            output_dir,
+            journal: journal.as_ref(),
+            budget: budget.as_ref(),
+            cache: cache.as_ref(),
+            checkpoint: checkpoint.as_ref(),
+            archive,
+            archive_writer: archive_writer.as_ref(),
+            claimed: (policy == ConflictPolicy::Rename && self.archive_output.is_none())
+                .then(|| Mutex::new(std::collections::HashSet::new())),
+        };
+        let results = self.pipeline(&image_paths, &run);
+
+        // Finished even when interrupted, holding the files done so far
+        if let (Some(path), Some(writer)) = (&self.archive_output, archive_writer) {
+            let part = part_path(path);
+            let finished = writer
+                .into_inner()
+                .map_err(|_| ImageToolError::ProcessingError("Archive lock poisoned".to_string()))
+                .and_then(|writer| writer.finish())
+                .and_then(|out| out.into_inner().map_err(|e| e.into_error().into()))
+                .and_then(|_| Ok(std::fs::rename(&part, path)?));
+            if let Err(e) = finished {
+                let _ = std::fs::remove_file(&part);
+                return Err(e);
+            }
+            log::info!("Saved archive: {}", path.display());
+            if let Some(journal) = &journal {
+                journal.record(path)?;
+            }
+        }
+
+        // Results come back in input order, so they line up with paths
+        let mut stats = ProcessingStats {
+            errors: rejected.into_iter().chain(conflicts).collect(),
+            duplicates,
+            skipped_count,
+            unchanged_count,
+            existing_count,
+            resumed_count,
+            ..Default::default()
+        };
+        for (input_path, result) in image_paths.into_iter().zip(results) {
+            let Some(result) = result else {
+                stats.cancelled.push(input_path);
+                continue;
+            };
             match result {
                 Ok(image_stats) => {
                     stats.processed_count += image_stats.processed_count;
                     stats.total_size_before += image_stats.total_size_before;
                     stats.total_size_after += image_stats.total_size_after;
+                    stats.quality.extend(image_stats.quality);
+                    stats.cached_count += image_stats.cached_count;
+                    stats.timings.extend(image_stats.timings);
                 }
                 Err(e) => {
-                    stats.errors.push(("Processing error".to_string(), e.to_string()));
+                    log::warn!("Failed to process {}: {}", input_path.display(), e);
+                    stats.errors.push((input_path, e));
                 }
             }
         }
 
-        pb.finish_with_message(format!(
-            "Processed {} images ({}% size reduction)",
+        if let Some(checkpoint) = checkpoint {
+            if !stats.was_cancelled() && stats.errors.is_empty() {
+                log::info!("Batch finished, removing checkpoint {}", checkpoint.path().display());
+                checkpoint.remove()?;
+            }
+        }
+
+        stats.elapsed = started.elapsed();
+        self.report(Progress::Finish(format!(
+            "Processed {} images, {} failed ({}% size reduction)",
             stats.processed_count,
+            stats.failed_count(),
             self.calculate_overall_savings(&stats)
-        ));
+        )));
 
         Ok(stats)
     }
 
-    fn process_single_image_in_batch(
-        &self,
-        input_path: &Path,
-        output_dir: &Arc<PathBuf>,
-        config: &ProcessConfig,
-    ) -> Result<ProcessingStats> {
-        // Calculate output path
-        let file_name = input_path
-            .file_name()
-            .ok_or_else(|| {
-                ImageToolError::InvalidParameter(format!("Invalid file name: {}", input_path.display()))
-            })?;
+    // Inputs move through three stages joined by bounded channels: one
+    // thread reads files, the pool decodes, resizes and encodes them, and
+    // one thread writes the outputs. Disk and CPU work then overlap instead
+    // of each worker waiting on its own reads and writes. Inputs left unread
+    // after a cancel come back as None.
+    fn pipeline(&self, paths: &[PathBuf], run: &Run<'_>) -> Vec<Option<Result<ProcessingStats>>> {
+        let depth = self.thread_pool.current_num_threads() * 2;
+        let (read_tx, read_rx) = mpsc::sync_channel(depth);
+        let (write_tx, write_rx) = mpsc::sync_channel::<(usize, Result<Processed>)>(depth);
+        let mut results: Vec<Option<Result<ProcessingStats>>> = paths.iter().map(|_| None).collect();
 
-        let output_path = output_dir.join(file_name);
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for (index, path) in paths.iter().enumerate() {
+                    self.wait_while_paused();
+                    if self.is_cancelled() {
+                        log::warn!("Interrupted, finishing the files in progress");
+                        break;
+                    }
+                    if read_tx.send((index, run.read(path))).is_err() {
+                        break;
+                    }
+                }
+            });
 
-        // Create processor and process
-        let processor = crate::core::processor::ImageProcessor::new(config.clone());
-        processor.process(input_path, &output_path)
-    }
+            let writer = scope.spawn(move || {
+                let mut written = Vec::with_capacity(paths.len());
+                for (index, processed) in write_rx {
+                    let result = processed.and_then(|processed| run.write(processed)).and_then(|stats| {
+                        if let Some(checkpoint) = run.checkpoint {
+                            checkpoint.record(&paths[index])?;
+                        }
+                        Ok(stats)
+                    });
+                    written.push((index, result));
+                    self.report(Progress::Advance);
+                }
+                written
+            });
 
-    fn collect_image_paths(&self, input_dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
-        let walker = if recursive {
-            WalkDir::new(input_dir)
-        } else {
-            WalkDir::new(input_dir).max_depth(1)
-        };
+            self.thread_pool.install(|| {
+                read_rx.into_iter().par_bridge().for_each_with(write_tx, |write_tx, (index, job)| {
+                    let processed = job.and_then(|job| match job {
+                        Some(job) => run.process(job),
+                        None => Ok(Processed::Cached),
+                    });
+                    // Only fails if the writer panicked, which the join below reports
+                    let _ = write_tx.send((index, processed));
+                });
+            });
 
-        let image_extensions = [
-            "jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "webp",
-        ];
-
-        let paths: Vec<PathBuf> = walker
-            .into_iter()
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| entry.file_type().is_file())
-            .filter(|entry| {
-                entry.path().extension()
-                    .and_then(|ext| ext.to_str())
-                    .map(|ext| {
-                        let ext_lower = ext.to_lowercase();
-                        image_extensions.contains(&ext_lower.as_str())
-                    })
-                    .unwrap_or(false)
-            })
-            .map(|entry| entry.into_path())
-            .collect();
+            for (index, result) in writer.join().expect("batch writer panicked") {
+                results[index] = Some(result);
+            }
+        });
 
-        Ok(paths)
+        results
     }
 
-    fn create_progress_bar(&self, total: usize) -> ProgressBar {
-        let pb = ProgressBar::new(total as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
-                .unwrap()
-                .progress_chars("#>-"),
-        );
-        pb
+    // The batch config with the matching rules, then the input's overrides
+    // file, laid over it
+    fn file_settings(&self, path: &Path) -> Result<FileSettings> {
+        let overrides = FileOverrides::load(path)?;
+        if overrides.as_ref().is_some_and(|overrides| overrides.skip) {
+            return Ok(FileSettings::Skip);
+        }
+
+        let mut config = None;
+        if !self.rules.is_empty() {
+            let colors_limit = self.rules.iter().filter_map(Rule::colors_limit).max();
+            let facts = ImageFacts::read(path, colors_limit)?;
+            for rule in self.rules.iter().filter(|rule| rule.matches(&facts)) {
+                config = Some(rule.apply(config.as_ref().unwrap_or(&self.config))?);
+            }
+        }
+        if let Some(overrides) = overrides {
+            config = Some(overrides.apply(config.as_ref().unwrap_or(&self.config))?);
+        }
+        Ok(config.map_or(FileSettings::Batch, |config| FileSettings::Custom(Box::new(config))))
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(hook) = &self.progress {
+            hook(progress);
+        }
     }
 
     fn calculate_overall_savings(&self, stats: &ProcessingStats) -> f64 {
@@ -232,4 +768,634 @@ impl BatchProcessor {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// What the stages of one `process_directory` run share
+struct Run<'a> {
+    config: &'a ProcessConfig,
+    processor: ImageProcessor,
+    // Inputs that rules or an overrides file change, and the settings they get
+    overrides: HashMap<PathBuf, Overridden>,
+    stages: &'a [Arc<dyn ProcessingStage>],
+    // None for archive input, whose outputs are named after the entry alone
+    input_dir: Option<&'a Path>,
+    output_dir: &'a Path,
+    journal: Option<&'a Journal>,
+    budget: Option<&'a MemoryBudget>,
+    cache: Option<&'a ProcessCache>,
+    checkpoint: Option<&'a Checkpoint>,
+    archive: Option<&'a ArchiveEntries<'a>>,
+    archive_writer: Option<&'a Mutex<ArchiveWriter<BufWriter<File>>>>,
+    // Output paths handed out so far, when renaming on conflict
+    claimed: Option<Mutex<std::collections::HashSet<PathBuf>>>,
+}
+
+// What rules and an overrides file make of the batch config for one input
+enum FileSettings {
+    Batch,
+    Skip,
+    Custom(Box<ProcessConfig>),
+}
+
+struct Overridden {
+    config: ProcessConfig,
+    processor: ImageProcessor,
+}
+
+// The image entries of an input archive, by the path each is reported
+// under: the archive's path joined with the entry name
+struct ArchiveEntries<'a> {
+    reader: &'a ZipReader,
+    entries: Vec<(PathBuf, &'a ZipEntry)>,
+    by_path: HashMap<PathBuf, &'a ZipEntry>,
+}
+
+impl<'a> ArchiveEntries<'a> {
+    fn new(reader: &'a ZipReader) -> Self {
+        let entries: Vec<(PathBuf, &ZipEntry)> = reader
+            .entries()
+            .iter()
+            .filter(|entry| is_image_entry(entry))
+            .map(|entry| (reader.path().join(entry.name.trim_start_matches('/')), entry))
+            .collect();
+        let by_path = entries.iter().cloned().collect();
+        Self { reader, entries, by_path }
+    }
+
+    // In archive order
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.entries.iter().map(|(path, _)| path)
+    }
+
+    fn read(&self, path: &Path, max_size: Option<u64>) -> Result<Vec<u8>> {
+        let entry = self.by_path[path];
+        // Checked before decompressing, which never yields more than this
+        if let Some(max_size) = max_size.filter(|&max| entry.size > max) {
+            return Err(ImageToolError::MemoryLimitExceeded(
+                format!("File size {} exceeds limit {}", entry.size, max_size)
+            ));
+        }
+        self.reader.read(entry)
+    }
+}
+
+// Leaves out the resource forks macOS adds to archives it creates
+fn is_image_entry(entry: &ZipEntry) -> bool {
+    if entry.is_dir() || entry.name.split('/').any(|part| part == "__MACOSX") {
+        return false;
+    }
+    let file_name = entry.name.rsplit('/').next().unwrap_or_default();
+    !file_name.starts_with("._") && has_image_extension(Path::new(file_name))
+}
+
+// One input on its way through the pipeline
+struct Job<'a> {
+    input_path: &'a Path,
+    output_path: PathBuf,
+    // The input's contents when it can be processed in memory; otherwise
+    // the processor reads and writes the files itself
+    data: Option<Vec<u8>>,
+    cache_key: Option<String>,
+    // Metadata saved next to the output
+    sidecar: Option<Vec<u8>>,
+    // For `--preserve-times`
+    time: Option<SystemTime>,
+    // Held until the output is written
+    _reservation: Option<Reservation<'a>>,
+}
+
+enum Processed<'a> {
+    Cached,
+    Encoded {
+        job: Job<'a>,
+        encoded: Encoded,
+        size_before: u64,
+    },
+    Written {
+        job: Job<'a>,
+        stats: ProcessingStats,
+    },
+}
+
+impl<'a> Run<'a> {
+    // None when the cache shows the output is up to date
+    fn read(&self, input_path: &'a Path) -> Result<Option<Job<'a>>> {
+        let (config, processor) = self.settings(input_path);
+        let name = output_name(self.input_dir, input_path, config).ok_or_else(|| {
+            ImageToolError::InvalidParameter(format!("Invalid file name: {}", input_path.display()))
+        })?;
+        let output_path = self.claim(self.output_dir.join(name));
+
+        let data = match self.archive {
+            Some(archive) => Some(archive.read(input_path, self.config.max_file_size)?),
+            None if processor.can_process_loaded(input_path, &output_path) => Some(std::fs::read(input_path)?),
+            None => None,
+        };
+
+        // The cache knows the batch config only, so inputs with their own bypass it
+        let cache_key = self
+            .cache
+            .filter(|_| !self.overrides.contains_key(input_path))
+            .map(|cache| match &data {
+                Some(data) => Ok(cache.key_for_bytes(data)),
+                None => cache.key(input_path),
+            })
+            .transpose()?;
+        if let (Some(cache), Some(key)) = (self.cache, &cache_key) {
+            if cache.is_fresh(key, &output_path) {
+                log::debug!("{} is up to date, skipping", output_path.display());
+                return Ok(None);
+            }
+        }
+
+        // Unreadable headers reserve nothing and fail in the processor as usual
+        let reservation = self.budget.map(|budget| {
+            let loader = Loader::new();
+            let estimated = match &data {
+                Some(data) => loader.estimate_memory_from_bytes(data),
+                None => loader.estimate_memory(input_path),
+            };
+            budget.reserve(input_path, estimated.unwrap_or(0))
+        });
+
+        let sidecar = match self.config.export_metadata.map(MetadataExporter::new) {
+            Some(exporter) => match &data {
+                Some(data) => exporter.export(data)?,
+                None => exporter.export(&std::fs::read(input_path)?)?,
+            },
+            None => None,
+        };
+
+        // Archive entries have no file to take a modification time from
+        let file = self.archive.is_none().then_some(input_path);
+        let time = processor.source_time(file, data.as_deref());
+
+        Ok(Some(Job {
+            input_path,
+            output_path,
+            data,
+            cache_key,
+            sidecar,
+            time,
+            _reservation: reservation,
+        }))
+    }
+
+    fn process(&self, mut job: Job<'a>) -> Result<Processed<'a>> {
+        // Inputs from subfolders go to the same subfolders of the output
+        if let Some(parent) = job.output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let Some(data) = job.data.take() else {
+            return self.process_file(job, None);
+        };
+
+        let (_, processor) = self.settings(job.input_path);
+        if processor.can_process_loaded(job.input_path, &job.output_path) {
+            let encoded = processor.process_loaded(job.input_path, &data, &job.output_path)?;
+            return Ok(Processed::Encoded {
+                job,
+                encoded,
+                size_before: data.len() as u64,
+            });
+        }
+
+        // Archive entries the processor needs as a file get one next to the
+        // output, under a name cleaned up after interrupted runs
+        let file_name = job.input_path.file_name().unwrap_or_default().to_string_lossy();
+        let spilled = self.output_dir.join(format!("{}src-{}", PART_PREFIX, file_name));
+        std::fs::write(&spilled, &data)?;
+        let processed = self.process_file(job, Some(&spilled));
+        let _ = std::fs::remove_file(&spilled);
+        processed
+    }
+
+    // Processes from `source`, or the job's own input path
+    fn process_file(&self, job: Job<'a>, source: Option<&Path>) -> Result<Processed<'a>> {
+        // A comparison path names a directory in batch mode
+        let mut config = self.settings(job.input_path).0.clone();
+        if let (Some(dir), Some(file_name)) = (&config.comparison, job.output_path.file_name()) {
+            config.comparison = Some(dir.join(file_name));
+        }
+        // Written with the output instead, where archives can take it
+        config.export_metadata = None;
+        config.preserve_times = None;
+        config.preserve_attrs = false;
+
+        let part = part_path(&job.output_path);
+        let source = source.unwrap_or(job.input_path);
+        let mut stats = match with_stages(ImageProcessor::new(config), self.stages).process(source, &part) {
+            Ok(stats) => stats,
+            Err(e) => {
+                let _ = std::fs::remove_file(&part);
+                return Err(e);
+            }
+        };
+        // Entries name the part file; the writer renames it into place
+        for (path, _) in &mut stats.timings {
+            path.clone_from(&job.output_path);
+        }
+        for (path, _) in &mut stats.quality {
+            path.clone_from(&job.output_path);
+        }
+        Ok(Processed::Written { job, stats })
+    }
+
+    fn write(&self, processed: Processed<'a>) -> Result<ProcessingStats> {
+        if let Processed::Encoded { job, .. } | Processed::Written { job, .. } = &processed {
+            if let Err(e) = self.write_sidecar(job) {
+                if let Processed::Written { job, .. } = &processed {
+                    let _ = std::fs::remove_file(part_path(&job.output_path));
+                }
+                return Err(e);
+            }
+        }
+
+        let (job, stats) = match processed {
+            Processed::Cached => {
+                return Ok(ProcessingStats {
+                    cached_count: 1,
+                    ..Default::default()
+                })
+            }
+            Processed::Written { job, stats } => {
+                let part = part_path(&job.output_path);
+                if let Err(e) = self.set_attrs(&part, &job).and_then(|()| self.place(&part, &job.output_path)) {
+                    let _ = std::fs::remove_file(&part);
+                    return Err(e);
+                }
+                (job, stats)
+            }
+            Processed::Encoded { job, encoded, size_before } => {
+                let write_start = Instant::now();
+                let part = part_path(&job.output_path);
+                let written = match self.archive_writer {
+                    Some(writer) if !self.config.verify => add_to_archive(writer, &self.entry_name(&job.output_path), &encoded.data),
+                    _ => std::fs::write(&part, &encoded.data).map_err(ImageToolError::from).and_then(|()| {
+                        if self.config.verify {
+                            self.settings(job.input_path).1.verify_output(&part, encoded.width, encoded.height)?;
+                        }
+                        self.set_attrs(&part, &job)?;
+                        self.place(&part, &job.output_path)
+                    }),
+                };
+                if let Err(e) = written {
+                    let _ = std::fs::remove_file(&part);
+                    return Err(e);
+                }
+                let timings = StageTimings {
+                    write: write_start.elapsed(),
+                    ..encoded.timings
+                };
+                log::info!("Saved image: {} ({} bytes)", job.output_path.display(), encoded.data.len());
+                log::debug!("{}: {}", job.output_path.display(), timings);
+
+                let stats = ProcessingStats {
+                    processed_count: 1,
+                    total_size_before: size_before,
+                    total_size_after: encoded.data.len() as u64,
+                    timings: vec![(job.output_path.clone(), timings)],
+                    ..Default::default()
+                };
+                (job, stats)
+            }
+        };
+
+        // The archive itself is recorded once it is complete
+        if let Some(journal) = self.journal.filter(|_| self.archive_writer.is_none()) {
+            journal.record(&job.output_path)?;
+        }
+
+        if let (Some(cache), Some(key)) = (self.cache, &job.cache_key) {
+            cache.record(key, &job.output_path)?;
+        }
+
+        Ok(stats)
+    }
+
+    // The config and processor for an input, with its overrides applied
+    fn settings(&self, input_path: &Path) -> (&ProcessConfig, &ImageProcessor) {
+        match self.overrides.get(input_path) {
+            Some(overridden) => (&overridden.config, &overridden.processor),
+            None => (self.config, &self.processor),
+        }
+    }
+
+    // The first free name for an output, when renaming on conflict
+    fn claim(&self, output_path: PathBuf) -> PathBuf {
+        let Some(claimed) = &self.claimed else {
+            return output_path;
+        };
+        let mut claimed = claimed.lock().unwrap_or_else(|e| e.into_inner());
+        let path = renamed_path(&output_path, |path| path.exists() || claimed.contains(path));
+        claimed.insert(path.clone());
+        path
+    }
+
+    // Not kept in archives, whose entries are dated when written. Only
+    // inputs on disk have attributes to copy.
+    fn set_attrs(&self, part: &Path, job: &Job<'a>) -> Result<()> {
+        if self.archive_writer.is_some() {
+            return Ok(());
+        }
+        if let Some(time) = job.time {
+            set_file_time(part, time)?;
+        }
+        if self.config.preserve_attrs && self.archive.is_none() {
+            copy_file_attrs(job.input_path, part)?;
+        }
+        Ok(())
+    }
+
+    // Goes in before the output, so no output is left without its metadata
+    fn write_sidecar(&self, job: &Job<'a>) -> Result<()> {
+        let (Some(sidecar), Some(format)) = (&job.sidecar, self.config.export_metadata) else {
+            return Ok(());
+        };
+        let path = MetadataExporter::new(format).sidecar_path(&job.output_path);
+        if let Some(writer) = self.archive_writer {
+            return add_to_archive(writer, &self.entry_name(&path), sidecar);
+        }
+
+        let part = part_path(&path);
+        if let Err(e) = std::fs::write(&part, sidecar).and_then(|()| std::fs::rename(&part, &path)) {
+            let _ = std::fs::remove_file(&part);
+            return Err(e.into());
+        }
+        if let Some(journal) = self.journal {
+            journal.record(&path)?;
+        }
+        Ok(())
+    }
+
+    // The archive entry for an output, named by its place under the output
+    // directory
+    fn entry_name(&self, output_path: &Path) -> String {
+        let relative = output_path.strip_prefix(self.output_dir).unwrap_or(output_path);
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // Moves a finished part file to its output, or into the archive
+    fn place(&self, part: &Path, output_path: &Path) -> Result<()> {
+        match self.archive_writer {
+            Some(writer) => {
+                add_to_archive(writer, &self.entry_name(output_path), &std::fs::read(part)?)?;
+                Ok(std::fs::remove_file(part)?)
+            }
+            None => Ok(std::fs::rename(part, output_path)?),
+        }
+    }
+}
+
+fn with_stages(processor: ImageProcessor, stages: &[Arc<dyn ProcessingStage>]) -> ImageProcessor {
+    stages.iter().cloned().fold(processor, ImageProcessor::with_stage)
+}
+
+// Entries are named by `Run::entry_name`
+fn add_to_archive(writer: &Mutex<ArchiveWriter<BufWriter<File>>>, name: &str, data: &[u8]) -> Result<()> {
+    writer
+        .lock()
+        .map_err(|_| ImageToolError::ProcessingError("Archive lock poisoned".to_string()))?
+        .add(name, data)
+}
+
+// Estimated decode bytes of the images currently being processed. A
+// reservation larger than the whole budget is cut down to it, so such a
+// file waits for everything else to finish and then runs alone.
+struct MemoryBudget {
+    limit: u64,
+    in_use: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            in_use: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn reserve(&self, path: &Path, estimated: u64) -> Reservation<'_> {
+        let bytes = estimated.min(self.limit);
+        if estimated > self.limit {
+            log::debug!(
+                "{} needs an estimated {} bytes, over the batch budget; running it alone",
+                path.display(),
+                estimated
+            );
+        }
+
+        let mut in_use = self.in_use.lock().unwrap_or_else(|e| e.into_inner());
+        while *in_use + bytes > self.limit {
+            in_use = self.freed.wait(in_use).unwrap_or_else(|e| e.into_inner());
+        }
+        *in_use += bytes;
+        Reservation { budget: self, bytes }
+    }
+}
+
+struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut in_use = self.budget.in_use.lock().unwrap_or_else(|e| e.into_inner());
+        *in_use -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
+
+// Groups byte-identical files. Files are bucketed by size and a streamed
+// hash, then confirmed with a byte comparison so a hash collision can never
+// cause an input to be skipped. Each group keeps the collection order, so the
+// first entry is the one that gets processed.
+fn find_duplicates(paths: &[PathBuf]) -> Result<Vec<Vec<PathBuf>>> {
+    use std::collections::HashMap;
+
+    let keys: Vec<(u64, u64)> = paths
+        .par_iter()
+        .map(|path| Ok((std::fs::metadata(path)?.len(), hash_file(path)?)))
+        .collect::<Result<_>>()?;
+
+    let mut buckets: HashMap<(u64, u64), Vec<&PathBuf>> = HashMap::new();
+    for (path, key) in paths.iter().zip(keys) {
+        buckets.entry(key).or_default().push(path);
+    }
+
+    let mut groups = Vec::new();
+    for (_, mut candidates) in buckets.into_iter().filter(|(_, c)| c.len() > 1) {
+        while candidates.len() > 1 {
+            let first = candidates.remove(0);
+            let mut group = vec![first.clone()];
+            let mut rest = Vec::new();
+            for candidate in candidates {
+                if files_equal(first, candidate)? {
+                    group.push(candidate.clone());
+                } else {
+                    rest.push(candidate);
+                }
+            }
+            if group.len() > 1 {
+                groups.push(group);
+            }
+            candidates = rest;
+        }
+    }
+
+    groups.sort_by(|a, b| a[0].cmp(&b[0]));
+    Ok(groups)
+}
+
+fn files_equal(a: &Path, b: &Path) -> Result<bool> {
+    use std::io::{BufReader, Read};
+
+    let mut a = BufReader::new(std::fs::File::open(a)?);
+    let mut b = BufReader::new(std::fs::File::open(b)?);
+    let mut buf_a = [0u8; 64 * 1024];
+    let mut buf_b = [0u8; 64 * 1024];
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        if b.read_exact(&mut buf_b[..read]).is_err() || buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+// Image files in `input_dir`, and in its subdirectories if `recursive`.
+// Files without an image extension are included when their content is
+// an image anyway.
+pub(crate) fn collect_image_paths(input_dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let walker = if recursive {
+        WalkDir::new(input_dir)
+    } else {
+        WalkDir::new(input_dir).max_depth(1)
+    };
+
+    walker
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| has_image_extension(entry.path()) || is_misnamed_image(entry.path()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+fn has_image_extension(path: &Path) -> bool {
+    const IMAGE_EXTENSIONS: [&str; 15] = [
+        "jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "webp",
+        "tga", "ppm", "pgm", "pnm", "qoi", "hdr", "exr",
+    ];
+
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+// Outputs keep their input's name, and inputs from a directory also their
+// folder under it, so same-named files in different folders don't
+// overwrite each other. Inputs without an extension get the one of the
+// format written.
+fn output_name(input_dir: Option<&Path>, input_path: &Path, config: &ProcessConfig) -> Option<PathBuf> {
+    let name = Path::new(input_path.file_name()?);
+    let folder: PathBuf = input_dir
+        .and_then(|dir| input_path.parent()?.strip_prefix(dir).ok())
+        .into_iter()
+        .flat_map(Path::components)
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .collect();
+    if name.extension().is_some() {
+        return Some(folder.join(name));
+    }
+    let format = config.format.and_then(|f| f.image_format()).or_else(|| sniff_format(input_path));
+    Some(match format {
+        Some(format) => folder.join(name.with_extension(format_extension(format))),
+        None => folder.join(name),
+    })
+}
+
+fn is_misnamed_image(path: &Path) -> bool {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    if file_name.starts_with(PART_PREFIX) {
+        return false;
+    }
+    match sniff_format(path) {
+        Some(format) => {
+            log::debug!("Including {}, which is {:?} despite its extension", path.display(), format);
+            true
+        }
+        None => false,
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Outputs are written under this name and renamed into place once complete,
+// so an interrupted write never leaves a truncated image behind
+const PART_PREFIX: &str = ".pixie-part-";
+
+fn part_path(output: &Path) -> PathBuf {
+    let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}{}", PART_PREFIX, file_name))
+}
+
+// Part files left by a run that was killed outright
+fn remove_part_files(output_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(output_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            remove_part_files(&entry.path());
+        } else if entry.file_name().to_string_lossy().starts_with(PART_PREFIX) {
+            log::debug!("Removing partial output {}", entry.path().display());
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+// An overrides file edited since `output` was written
+fn is_newer_overrides(input: &Path, output: &Path) -> bool {
+    let overrides = FileOverrides::path_for(input);
+    overrides.exists() && is_newer(&overrides, output)
+}
+
+// True when `output` is missing or its time can't be read, so such inputs
+// are always processed
+fn is_newer(input: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified());
+    match (modified(input), modified(output)) {
+        (Ok(input), Ok(output)) => input > output,
+        _ => true,
+    }
+}
+
+#[cfg(feature = "async")]
+impl BatchProcessor {
+    pub async fn process_directory_async(
+        &self,
+        input_dir: &Path,
+        output_dir: &Path,
+        recursive: bool,
+    ) -> Result<ProcessingStats> {
+        let processor = self.clone();
+        let input_dir = input_dir.to_path_buf();
+        let output_dir = output_dir.to_path_buf();
+
+        // The batch already fans out over rayon, so run it as one blocking task
+        tokio::task::spawn_blocking(move || processor.process_directory(&input_dir, &output_dir, recursive))
+            .await
+            .map_err(|e| ImageToolError::ProcessingError(format!("Batch task failed: {}", e)))?
+    }
+}
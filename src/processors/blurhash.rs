@@ -0,0 +1,122 @@
+// pixie/src/processors/blurhash.rs
+use crate::core::{ImageToolError, Result};
+use crate::utils::{linear_to_srgb, srgb_to_linear};
+use image::{imageops, DynamicImage};
+use std::f32::consts::PI;
+
+const BASE83: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+// The hash only keeps a handful of cosine components, so a small copy of
+// the image gives the same result far faster than the full-size one
+const SAMPLE_SIZE: u32 = 128;
+
+/// Computes the BlurHash (https://blurha.sh) placeholder string of an image.
+/// Alpha is ignored, as in the reference encoder.
+pub struct BlurHasher {
+    components_x: u32,
+    components_y: u32,
+}
+
+impl BlurHasher {
+    pub fn new() -> Self {
+        Self {
+            components_x: 4,
+            components_y: 3,
+        }
+    }
+
+    /// Horizontal and vertical component counts, 1-9 each; more components
+    /// keep more detail in a longer string
+    pub fn with_components(mut self, x: u32, y: u32) -> Self {
+        self.components_x = x;
+        self.components_y = y;
+        self
+    }
+
+    pub fn encode(&self, image: &DynamicImage) -> Result<String> {
+        let (cx, cy) = (self.components_x, self.components_y);
+        if !(1..=9).contains(&cx) || !(1..=9).contains(&cy) {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "BlurHash components must be between 1 and 9, got {}x{}",
+                cx, cy
+            )));
+        }
+
+        let rgb = if image.width() > SAMPLE_SIZE || image.height() > SAMPLE_SIZE {
+            image.resize(SAMPLE_SIZE, SAMPLE_SIZE, imageops::FilterType::Triangle).to_rgb8()
+        } else {
+            image.to_rgb8()
+        };
+        let (width, height) = rgb.dimensions();
+        let linear: Vec<[f32; 3]> = rgb
+            .pixels()
+            .map(|p| p.0.map(|v| srgb_to_linear(v as f32 / 255.0)))
+            .collect();
+
+        let mut factors = Vec::with_capacity((cx * cy) as usize);
+        for j in 0..cy {
+            let basis_y: Vec<f32> = (0..height).map(|y| (PI * j as f32 * y as f32 / height as f32).cos()).collect();
+            for i in 0..cx {
+                let basis_x: Vec<f32> = (0..width).map(|x| (PI * i as f32 * x as f32 / width as f32).cos()).collect();
+                let mut sum = [0.0f32; 3];
+                for (index, pixel) in linear.iter().enumerate() {
+                    let basis = basis_x[index % width as usize] * basis_y[index / width as usize];
+                    for c in 0..3 {
+                        sum[c] += basis * pixel[c];
+                    }
+                }
+                let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+                let scale = normalisation / (width * height) as f32;
+                factors.push(sum.map(|v| v * scale));
+            }
+        }
+
+        let mut hash = String::with_capacity(4 + 2 * factors.len());
+        push_base83(&mut hash, (cx - 1) + (cy - 1) * 9, 1);
+
+        let (dc, ac) = factors.split_first().expect("at least one component");
+        let maximum = if ac.is_empty() {
+            push_base83(&mut hash, 0, 1);
+            1.0
+        } else {
+            let actual = ac.iter().flatten().fold(0.0f32, |max, v| max.max(v.abs()));
+            let quantised = ((actual * 166.0 - 0.5).floor()).clamp(0.0, 82.0) as u32;
+            push_base83(&mut hash, quantised, 1);
+            (quantised + 1) as f32 / 166.0
+        };
+
+        let dc_value = dc.iter().fold(0, |value, &c| (value << 8) + to_srgb8(c) as u32);
+        push_base83(&mut hash, dc_value, 4);
+
+        for factor in ac {
+            let value = factor.iter().fold(0, |value, &c| {
+                let quantised = (sign_pow(c / maximum, 0.5) * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32;
+                value * 19 + quantised
+            });
+            push_base83(&mut hash, value, 2);
+        }
+
+        Ok(hash)
+    }
+}
+
+impl Default for BlurHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn push_base83(hash: &mut String, value: u32, digits: u32) {
+    for digit in (0..digits).rev() {
+        let index = (value / 83u32.pow(digit)) % 83;
+        hash.push(BASE83[index as usize] as char);
+    }
+}
+
+fn to_srgb8(linear: f32) -> u8 {
+    (linear_to_srgb(linear.clamp(0.0, 1.0)) * 255.0 + 0.5) as u8
+}
+
+fn sign_pow(value: f32, exponent: f32) -> f32 {
+    value.abs().powf(exponent).copysign(value)
+}
@@ -0,0 +1,81 @@
+// pixie/src/processors/cache.rs
+use crate::core::{ProcessConfig, Result};
+use crate::utils::{hash_bytes, hash_file};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+/// Remembers which output each (input content, settings) pair produced, so
+/// a rerun can skip inputs whose output is still in place and unchanged.
+/// One small file per key holds the size and hash of the output.
+pub struct ProcessCache {
+    dir: PathBuf,
+    config_hash: u64,
+}
+
+impl ProcessCache {
+    pub fn open(dir: &Path, config: &ProcessConfig) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        // Every setting and the tool version go into the key, so changing
+        // either reprocesses everything
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            config_hash: settings_hash(config),
+        })
+    }
+
+    pub fn default_dir(output_dir: &Path) -> PathBuf {
+        output_dir.join(".pixie-cache")
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Reads the whole input, but never decodes it
+    pub fn key(&self, input: &Path) -> Result<String> {
+        let size = std::fs::metadata(input)?.len();
+        Ok(self.key_from(hash_file(input)?, size))
+    }
+
+    /// Same as `key`, for an input already read into memory
+    pub fn key_for_bytes(&self, data: &[u8]) -> String {
+        self.key_from(hash_bytes(data), data.len() as u64)
+    }
+
+    fn key_from(&self, hash: u64, size: u64) -> String {
+        format!("{:016x}{:016x}{:x}", hash, self.config_hash, size)
+    }
+
+    /// Whether `output` is exactly what this key produced last time
+    pub fn is_fresh(&self, key: &str, output: &Path) -> bool {
+        let Ok(entry) = std::fs::read_to_string(self.dir.join(key)) else {
+            return false;
+        };
+        let Some((size, hash)) = entry.trim().split_once(' ') else {
+            return false;
+        };
+
+        match std::fs::metadata(output) {
+            Ok(metadata) if size.parse() == Ok(metadata.len()) => {
+                hash_file(output).map(|h| format!("{:016x}", h) == hash).unwrap_or(false)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn record(&self, key: &str, output: &Path) -> Result<()> {
+        let size = std::fs::metadata(output)?.len();
+        std::fs::write(self.dir.join(key), format!("{} {:016x}\n", size, hash_file(output)?))?;
+        Ok(())
+    }
+}
+
+// Changes with any setting or the tool version. The config is hashed in
+// its JSON form, which unlike its Debug output has a fixed layout.
+pub(crate) fn settings_hash(config: &ProcessConfig) -> u64 {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.write(&serde_json::to_vec(config).expect("the config serializes to JSON"));
+    hasher.finish()
+}
@@ -0,0 +1,153 @@
+// pixie/src/processors/caption.rs
+use crate::core::{CropGravity, ImageToolError, Result};
+use crate::processors::MetadataProcessor;
+use exif::Exif;
+use image::{DynamicImage, ImageBuffer, Pixel, Rgb, Rgba};
+use rusttype::{point, Font, Scale};
+use std::path::Path;
+
+// Tried in order when no font is given
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/// Draws a line of text in a corner of the image, white with a dark
+/// shadow so it stays readable on any background. The text can pull
+/// values from the source's EXIF with `{Tag}` placeholders.
+pub struct Captioner {
+    font: Font<'static>,
+    size: Option<f32>,
+    gravity: CropGravity,
+}
+
+impl Captioner {
+    /// Uses the TrueType font at `font`, or a common system font
+    pub fn new(font: Option<&Path>) -> Result<Self> {
+        let (path, data) = match font {
+            Some(path) => (path.to_path_buf(), std::fs::read(path)?),
+            None => SYSTEM_FONTS
+                .iter()
+                .map(Path::new)
+                .find_map(|path| std::fs::read(path).ok().map(|data| (path.to_path_buf(), data)))
+                .ok_or_else(|| {
+                    ImageToolError::InvalidParameter("No system font found for the caption, pass --caption-font".to_string())
+                })?,
+        };
+        let font = Font::try_from_vec(data).ok_or_else(|| {
+            ImageToolError::InvalidParameter(format!("{} is not a TrueType font", path.display()))
+        })?;
+        Ok(Self { font, size: None, gravity: CropGravity::SouthEast })
+    }
+
+    /// Text height in pixels; by default 1/25 of the shorter side
+    pub fn with_size(mut self, size: Option<f32>) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: CropGravity) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Fills in `{Tag}` placeholders, named as in EXIF (`{Artist}`,
+    /// `{DateTimeOriginal}`, ...). Tags the image doesn't have come out
+    /// empty, and the spacing around them is collapsed.
+    pub fn text(template: &str, exif: Option<&Exif>) -> String {
+        let metadata = MetadataProcessor::new();
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else { break };
+            text.push_str(&rest[..start]);
+            let name = &rest[start + 1..start + len];
+            if let Some(value) = exif.and_then(|exif| metadata.field_text(exif, name)) {
+                text.push_str(&value);
+            } else {
+                log::debug!("Caption field {{{}}} not found", name);
+            }
+            rest = &rest[start + len + 1..];
+        }
+        text.push_str(rest);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn apply(&self, image: &DynamicImage, text: &str) -> DynamicImage {
+        if text.is_empty() {
+            return image.clone();
+        }
+        if image.color().has_alpha() {
+            let mut canvas = image.to_rgba8();
+            self.draw(&mut canvas, Rgba([255, 255, 255, 255]), Rgba([32, 32, 32, 255]), text);
+            DynamicImage::ImageRgba8(canvas)
+        } else {
+            let mut canvas = image.to_rgb8();
+            self.draw(&mut canvas, Rgb([255, 255, 255]), Rgb([32, 32, 32]), text);
+            DynamicImage::ImageRgb8(canvas)
+        }
+    }
+
+    fn draw<P: Pixel<Subpixel = u8>>(&self, canvas: &mut ImageBuffer<P, Vec<u8>>, color: P, shadow: P, text: &str) {
+        let (width, height) = canvas.dimensions();
+        let size = self.size.unwrap_or((width.min(height) as f32 / 25.0).max(12.0));
+        let scale = Scale::uniform(size);
+        let metrics = self.font.v_metrics(scale);
+        let text_width = self
+            .font
+            .layout(text, scale, point(0.0, 0.0))
+            .last()
+            .map_or(0.0, |glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width);
+        let text_height = metrics.ascent - metrics.descent;
+        let margin = size / 2.0;
+        let (width, height) = (width as f32, height as f32);
+
+        let x = match self.gravity {
+            CropGravity::West | CropGravity::NorthWest | CropGravity::SouthWest => margin,
+            CropGravity::East | CropGravity::NorthEast | CropGravity::SouthEast => width - text_width - margin,
+            _ => (width - text_width) / 2.0,
+        };
+        let y = match self.gravity {
+            CropGravity::North | CropGravity::NorthEast | CropGravity::NorthWest => margin,
+            CropGravity::South | CropGravity::SouthEast | CropGravity::SouthWest => height - text_height - margin,
+            _ => (height - text_height) / 2.0,
+        };
+
+        let offset = (size / 16.0).max(1.0).round();
+        self.draw_layer(canvas, shadow, x + offset, y + metrics.ascent + offset, scale, text);
+        self.draw_layer(canvas, color, x, y + metrics.ascent, scale, text);
+    }
+
+    // Blends `color` in by each glyph's coverage, with the baseline at `y`
+    fn draw_layer<P: Pixel<Subpixel = u8>>(
+        &self,
+        canvas: &mut ImageBuffer<P, Vec<u8>>,
+        color: P,
+        x: f32,
+        baseline: f32,
+        scale: Scale,
+        text: &str,
+    ) {
+        let (width, height) = canvas.dimensions();
+        for glyph in self.font.layout(text, scale, point(x, baseline)) {
+            let Some(bounds) = glyph.pixel_bounding_box() else { continue };
+            glyph.draw(|gx, gy, coverage| {
+                let px = bounds.min.x + gx as i32;
+                let py = bounds.min.y + gy as i32;
+                if px < 0 || py < 0 || px as u32 >= width || py as u32 >= height {
+                    return;
+                }
+                let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+                let coverage = coverage.clamp(0.0, 1.0);
+                pixel.apply2(&color, |under, over| {
+                    (under as f32 * (1.0 - coverage) + over as f32 * coverage).round() as u8
+                });
+            });
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// pixie/src/processors/channels.rs
+use crate::core::{Channel, ChannelOrder};
+use crate::utils::into_color;
+use image::{ColorType, DynamicImage};
+
+/// Per-pixel channel stages: swizzling, single-channel extraction and
+/// inversion, applied in that order so an extracted mask can be inverted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChannelOps {
+    order: Option<ChannelOrder>,
+    extract: Option<Channel>,
+    invert: bool,
+}
+
+impl ChannelOps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_order(mut self, order: Option<ChannelOrder>) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn with_extract(mut self, channel: Option<Channel>) -> Self {
+        self.extract = channel;
+        self
+    }
+
+    pub fn with_invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.order.is_none() && self.extract.is_none() && !self.invert
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        if self.is_noop() {
+            return image.clone();
+        }
+
+        let mut working = image.to_rgba32f();
+        for pixel in working.pixels_mut() {
+            let source = pixel.0;
+            if let Some(ChannelOrder(order)) = self.order {
+                pixel.0 = order.map(|channel| source[index(channel)]);
+            }
+            if let Some(channel) = self.extract {
+                let value = pixel.0[index(channel)];
+                pixel.0 = [value, value, value, 1.0];
+            }
+            if self.invert {
+                pixel.0[..3].iter_mut().for_each(|value| *value = 1.0 - *value);
+            }
+        }
+
+        let color = match self.extract {
+            Some(_) if image.color().bytes_per_pixel() / image.color().channel_count() > 1 => ColorType::L16,
+            Some(_) => ColorType::L8,
+            None => image.color(),
+        };
+        into_color(DynamicImage::ImageRgba32F(working), color)
+    }
+}
+
+fn index(channel: Channel) -> usize {
+    match channel {
+        Channel::Red => 0,
+        Channel::Green => 1,
+        Channel::Blue => 2,
+        Channel::Alpha => 3,
+    }
+}
@@ -0,0 +1,85 @@
+// pixie/src/processors/checkpoint.rs
+use crate::core::{ImageToolError, ProcessConfig, Result};
+use crate::processors::cache::settings_hash;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const HEADER: &str = "# pixie checkpoint";
+
+/// The inputs a batch run has finished, one per line and flushed as each
+/// completes, so a stopped run can pick up where it left off. A checkpoint
+/// written with other settings is started over.
+pub struct Checkpoint {
+    path: PathBuf,
+    done: HashSet<PathBuf>,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    pub fn open(path: &Path, config: &ProcessConfig) -> Result<Self> {
+        let header = format!("{} {:016x}", HEADER, settings_hash(config));
+
+        let mut done = HashSet::new();
+        let mut resume = false;
+        if let Ok(contents) = std::fs::read_to_string(path) {
+            let mut lines = contents.lines();
+            if lines.next() == Some(header.as_str()) {
+                done.extend(lines.filter(|line| !line.is_empty()).map(PathBuf::from));
+                resume = true;
+            } else if !contents.is_empty() {
+                log::warn!("{} was written with other settings, starting over", path.display());
+            }
+        }
+
+        let file = if resume {
+            OpenOptions::new().append(true).open(path)?
+        } else {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut file = File::create(path)?;
+            writeln!(file, "{}", header)?;
+            file
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            done,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether an earlier run recorded `input` as finished
+    pub fn is_done(&self, input: &Path) -> bool {
+        self.done.contains(&entry(input))
+    }
+
+    pub fn record(&self, input: &Path) -> Result<()> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| ImageToolError::ProcessingError("Checkpoint lock poisoned".to_string()))?;
+        writeln!(file, "{}", entry(input).display())?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Deletes the checkpoint once there is nothing left to resume
+    pub fn remove(self) -> Result<()> {
+        drop(self.file);
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+// Absolute, so a run resumed from another directory still matches
+fn entry(input: &Path) -> PathBuf {
+    std::fs::canonicalize(input).unwrap_or_else(|_| input.to_path_buf())
+}
@@ -0,0 +1,207 @@
+// pixie/src/processors/cmyk.rs
+use crate::core::{ImageToolError, Result};
+use image::{DynamicImage, RgbImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+use std::io::{Read, Seek, Write};
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+/// What the JPEG headers up to the first frame say about its color layout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JpegColorInfo {
+    pub components: u8,
+    /// Transform flag of an Adobe APP14 segment, if there is one
+    pub adobe_transform: Option<u8>,
+}
+
+impl JpegColorInfo {
+    pub fn is_cmyk(&self) -> bool {
+        self.components == 4
+    }
+}
+
+/// Walks the JPEG markers up to the frame header. Returns `None` for
+/// anything that does not look like a JPEG.
+pub fn scan_jpeg<R: Read>(mut reader: R) -> Result<Option<JpegColorInfo>> {
+    let mut soi = [0u8; 2];
+    if reader.read_exact(&mut soi).is_err() || soi != [0xFF, 0xD8] {
+        return Ok(None);
+    }
+
+    let mut info = JpegColorInfo::default();
+    loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        if byte[0] != 0xFF {
+            return Ok(None);
+        }
+        // Markers may be padded with any number of 0xFF fill bytes
+        let mut marker = 0xFF;
+        while marker == 0xFF {
+            reader.read_exact(&mut byte)?;
+            marker = byte[0];
+        }
+
+        match marker {
+            0x01 | 0xD0..=0xD7 => continue,
+            0xD9 | 0xDA => return Ok(None),
+            _ => {}
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length).saturating_sub(2) as usize;
+        let mut segment = vec![0u8; length];
+        reader.read_exact(&mut segment)?;
+
+        match marker {
+            // SOF0-SOF15, except DHT, JPG and DAC which share the range
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                info.components = *segment.get(5).ok_or_else(|| {
+                    ImageToolError::ProcessingError("Truncated JPEG frame header".to_string())
+                })?;
+                return Ok(Some(info));
+            }
+            0xEE if segment.starts_with(b"Adobe") && segment.len() >= 12 => {
+                info.adobe_transform = Some(segment[11]);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decodes a four-component (CMYK or YCCK) JPEG to RGB. An embedded CMYK
+/// ICC profile is used for the conversion when present; otherwise the
+/// plain `(1 - C) * (1 - K)` formula is applied.
+pub fn decode_cmyk_jpeg(data: &[u8], info: JpegColorInfo) -> Result<DynamicImage> {
+    let mut decoder = JpegDecoder::new(ZCursor::new(data));
+    decoder.decode_headers().map_err(jpeg_error)?;
+    let input = decoder.input_colorspace().unwrap_or(ColorSpace::CMYK);
+    let (width, height) = decoder.dimensions().ok_or_else(|| {
+        ImageToolError::ProcessingError("JPEG dimensions missing after header decode".to_string())
+    })?;
+
+    // Ask for the raw components so the color conversion happens here
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(input);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    let mut samples = decoder.decode().map_err(jpeg_error)?;
+    let icc = decoder.icc_profile();
+
+    if input == ColorSpace::YCCK {
+        ycck_to_cmyk(&mut samples);
+    }
+
+    // Adobe applications store CMYK inverted (0 = full ink) and flag it
+    // with an APP14 segment
+    if info.adobe_transform.is_some() {
+        samples.iter_mut().for_each(|s| *s = 255 - *s);
+    }
+
+    log::debug!(
+        "Converting {:?} JPEG to RGB ({})",
+        input,
+        if icc.is_some() { "embedded ICC profile" } else { "no profile" }
+    );
+
+    let rgb = match icc.as_deref().and_then(cmyk_profile) {
+        Some(profile) => {
+            let transform = profile
+                .create_transform_8bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgb, TransformOptions::default())
+                .map_err(cms_error)?;
+            let mut rgb = vec![0u8; samples.len() / 4 * 3];
+            transform.transform(&samples, &mut rgb).map_err(cms_error)?;
+            rgb
+        }
+        None => samples
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let k = 255 - p[3] as u32;
+                [0, 1, 2].map(|c| ((255 - p[c] as u32) * k / 255) as u8)
+            })
+            .collect(),
+    };
+
+    let image = RgbImage::from_raw(width as u32, height as u32, rgb).ok_or_else(|| {
+        ImageToolError::ProcessingError("CMYK buffer size mismatch".to_string())
+    })?;
+    Ok(DynamicImage::ImageRgb8(image))
+}
+
+/// Writes `image` as an uncompressed CMYK TIFF. With a CMYK ICC profile the
+/// conversion goes through it and the profile is embedded; otherwise a
+/// naive under-color removal is used.
+pub fn write_cmyk_tiff<W: Write + Seek>(image: &DynamicImage, writer: W, profile: Option<&[u8]>) -> Result<()> {
+    use tiff::encoder::{colortype::CMYK8, TiffEncoder};
+    use tiff::tags::Tag;
+
+    let rgb = image.to_rgb8();
+    let cmyk = match profile {
+        Some(icc) => {
+            let profile = cmyk_profile(icc).ok_or_else(|| {
+                ImageToolError::InvalidParameter("ICC profile is not a CMYK profile".to_string())
+            })?;
+            let transform = ColorProfile::new_srgb()
+                .create_transform_8bit(Layout::Rgb, &profile, Layout::Rgba, TransformOptions::default())
+                .map_err(cms_error)?;
+            let mut cmyk = vec![0u8; rgb.as_raw().len() / 3 * 4];
+            transform.transform(rgb.as_raw(), &mut cmyk).map_err(cms_error)?;
+            cmyk
+        }
+        None => rgb
+            .pixels()
+            .flat_map(|p| {
+                let k = 255 - *p.0.iter().max().unwrap_or(&0) as u32;
+                let scale = 255 - k;
+                let ink = |c: u8| ((scale - c as u32) * 255).checked_div(scale).unwrap_or(0) as u8;
+                [ink(p.0[0]), ink(p.0[1]), ink(p.0[2]), k as u8]
+            })
+            .collect(),
+    };
+
+    let tiff_error = |e: tiff::TiffError| ImageToolError::ProcessingError(format!("TIFF encode error: {}", e));
+    let mut encoder = TiffEncoder::new(writer).map_err(tiff_error)?;
+    let mut tiff = encoder
+        .new_image::<CMYK8>(image.width(), image.height())
+        .map_err(tiff_error)?;
+    if let Some(icc) = profile {
+        tiff.encoder().write_tag(Tag::IccProfile, icc).map_err(tiff_error)?;
+    }
+    tiff.write_data(&cmyk).map_err(tiff_error)?;
+    Ok(())
+}
+
+fn cmyk_profile(icc: &[u8]) -> Option<ColorProfile> {
+    match ColorProfile::new_from_slice(icc) {
+        Ok(profile) if profile.color_space == DataColorSpace::Cmyk => Some(profile),
+        Ok(_) => None,
+        Err(e) => {
+            log::warn!("Ignoring unreadable ICC profile: {:?}", e);
+            None
+        }
+    }
+}
+
+// YCC -> RGB with the JFIF matrix, then CMY = 255 - RGB; K is untouched
+fn ycck_to_cmyk(samples: &mut [u8]) {
+    for pixel in samples.chunks_exact_mut(4) {
+        let y = pixel[0] as f32;
+        let cb = pixel[1] as f32 - 128.0;
+        let cr = pixel[2] as f32 - 128.0;
+        let r = y + 1.402 * cr;
+        let g = y - 0.344_136 * cb - 0.714_136 * cr;
+        let b = y + 1.772 * cb;
+        pixel[0] = 255 - r.round().clamp(0.0, 255.0) as u8;
+        pixel[1] = 255 - g.round().clamp(0.0, 255.0) as u8;
+        pixel[2] = 255 - b.round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+fn jpeg_error(e: zune_jpeg::errors::DecodeErrors) -> ImageToolError {
+    ImageToolError::ProcessingError(format!("JPEG decode error: {:?}", e))
+}
+
+fn cms_error(e: moxcms::CmsError) -> ImageToolError {
+    ImageToolError::ProcessingError(format!("Color conversion failed: {}", e))
+}
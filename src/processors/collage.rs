@@ -0,0 +1,175 @@
+// pixie/src/processors/collage.rs
+use crate::core::{AspectRatio, Color, ImageToolError, ResizeAlgorithm, Result};
+use crate::processors::{Cropper, Resizer, ResizeMode};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+/// Columns by rows, written as `3x2`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridLayout {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl GridLayout {
+    pub fn new(columns: u32, rows: u32) -> Self {
+        Self { columns, rows }
+    }
+
+    /// Roughly square grid with room for `count` images
+    pub fn for_count(count: usize) -> Self {
+        let columns = (count as f64).sqrt().ceil().max(1.0) as u32;
+        let rows = (count as u32).div_ceil(columns).max(1);
+        Self { columns, rows }
+    }
+
+    pub fn cells(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+impl std::str::FromStr for GridLayout {
+    type Err = ImageToolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ImageToolError::InvalidParameter(
+            format!("Invalid layout '{}', expected COLUMNSxROWS such as 3x2", s)
+        );
+
+        let lower = s.to_lowercase();
+        let (columns, rows) = lower.split_once('x').ok_or_else(invalid)?;
+        match (columns.trim().parse(), rows.trim().parse()) {
+            (Ok(columns), Ok(rows)) if columns > 0 && rows > 0 => Ok(Self { columns, rows }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Lays images out on a grid of equal cells. Each image is fitted into its
+/// cell (or cropped to fill it) and centered, left to right, top to bottom.
+pub struct Collage {
+    layout: Option<GridLayout>,
+    cell_width: u32,
+    cell_height: u32,
+    gap: u32,
+    background: Color,
+    fill: bool,
+    algorithm: ResizeAlgorithm,
+}
+
+impl Collage {
+    pub fn new() -> Self {
+        Self {
+            layout: None,
+            cell_width: 0,
+            cell_height: 0,
+            gap: 0,
+            background: Color::WHITE,
+            fill: false,
+            algorithm: ResizeAlgorithm::Lanczos3,
+        }
+    }
+
+    /// Grid to use; by default the squarest one that fits every image
+    pub fn with_layout(mut self, layout: Option<GridLayout>) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Cell size; a zero side is taken from the largest input
+    pub fn with_cell_size(mut self, width: u32, height: u32) -> Self {
+        self.cell_width = width;
+        self.cell_height = height;
+        self
+    }
+
+    /// Spacing between cells and around the edge, in pixels
+    pub fn with_gap(mut self, gap: u32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    pub fn with_background(mut self, background: Color) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Crop images to the cell's aspect instead of letterboxing them
+    pub fn with_fill(mut self, fill: bool) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    pub fn with_algorithm(mut self, algorithm: ResizeAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn compose(&self, images: &[DynamicImage]) -> Result<DynamicImage> {
+        if images.is_empty() {
+            return Err(ImageToolError::InvalidParameter(
+                "A collage needs at least one image".to_string(),
+            ));
+        }
+
+        let layout = self.layout.unwrap_or_else(|| GridLayout::for_count(images.len()));
+        if images.len() > layout.cells() {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "{} images don't fit a {}x{} layout",
+                images.len(),
+                layout.columns,
+                layout.rows
+            )));
+        }
+
+        let cell_width = match self.cell_width {
+            0 => images.iter().map(|image| image.width()).max().unwrap_or(1),
+            width => width,
+        };
+        let cell_height = match self.cell_height {
+            0 => images.iter().map(|image| image.height()).max().unwrap_or(1),
+            height => height,
+        };
+
+        let width = layout.columns * cell_width + (layout.columns + 1) * self.gap;
+        let height = layout.rows * cell_height + (layout.rows + 1) * self.gap;
+        crate::utils::validate_dimensions(width, height)?;
+
+        log::debug!(
+            "Composing {} image(s) on a {}x{} grid of {}x{} cells ({}x{})",
+            images.len(),
+            layout.columns,
+            layout.rows,
+            cell_width,
+            cell_height,
+            width,
+            height
+        );
+
+        let background = Rgba([self.background.r, self.background.g, self.background.b, 255]);
+        let mut canvas = RgbaImage::from_pixel(width, height, background);
+        let resizer = Resizer::new(self.algorithm, true);
+
+        for (index, image) in images.iter().enumerate() {
+            let (column, row) = (index as u32 % layout.columns, index as u32 / layout.columns);
+            let fitted = if self.fill {
+                let cropped = Cropper::default().crop_to_aspect(image, AspectRatio::new(cell_width, cell_height));
+                resizer.resize_exact(&cropped, cell_width, cell_height)
+            } else {
+                resizer.resize(image, ResizeMode::Absolute(cell_width, cell_height))
+            };
+
+            let x = self.gap + column * (cell_width + self.gap) + (cell_width - fitted.width().min(cell_width)) / 2;
+            let y = self.gap + row * (cell_height + self.gap) + (cell_height - fitted.height().min(cell_height)) / 2;
+            imageops::overlay(&mut canvas, &fitted.to_rgba8(), x as i64, y as i64);
+        }
+
+        // The background is opaque, so nothing is lost dropping alpha
+        Ok(DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).into_rgb8()))
+    }
+}
+
+impl Default for Collage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,53 @@
+// pixie/src/processors/comparison.rs
+use crate::core::{Color, ComparisonLayout, Result};
+use crate::processors::{JoinDirection, Joiner};
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+
+/// Puts an original and its processed version into one image so encoder
+/// settings can be checked at a glance. The original is scaled to the
+/// processed size, so both show the same pixels at the same scale.
+pub struct Comparison {
+    layout: ComparisonLayout,
+    divider: Color,
+}
+
+impl Comparison {
+    pub fn new(layout: ComparisonLayout) -> Self {
+        Self {
+            layout,
+            divider: Color::WHITE,
+        }
+    }
+
+    /// Color of the gap or split line between the two halves
+    pub fn with_divider(mut self, divider: Color) -> Self {
+        self.divider = divider;
+        self
+    }
+
+    pub fn compose(&self, original: &DynamicImage, processed: &DynamicImage) -> Result<DynamicImage> {
+        let (width, height) = (processed.width(), processed.height());
+        let original = if original.width() != width || original.height() != height {
+            original.resize_exact(width, height, imageops::FilterType::CatmullRom)
+        } else {
+            original.clone()
+        };
+
+        match self.layout {
+            ComparisonLayout::SideBySide => Joiner::new(JoinDirection::Horizontal)
+                .with_spacing(4)
+                .with_background(self.divider)
+                .join(&[original, processed.clone()]),
+            ComparisonLayout::Split => {
+                let mut canvas = processed.to_rgb8();
+                let middle = width / 2;
+                let left = original.crop_imm(0, 0, middle, height).to_rgb8();
+                imageops::replace(&mut canvas, &left, 0, 0);
+
+                let line = RgbImage::from_pixel(2.min(width), height, Rgb([self.divider.r, self.divider.g, self.divider.b]));
+                imageops::replace(&mut canvas, &line, middle.saturating_sub(1) as i64, 0);
+                Ok(DynamicImage::ImageRgb8(canvas))
+            }
+        }
+    }
+}
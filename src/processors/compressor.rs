@@ -1,16 +1,28 @@
 // pixie/src/processors/compressor.rs
 use crate::core::{ImageToolError, Result};
+use crate::processors::cmyk::write_cmyk_tiff;
+use crate::processors::dpi::apply_dpi;
+use image::codecs::jpeg::JpegEncoder;
 use image::{DynamicImage, ImageFormat};
-use image::ImageOutputFormat;
 use oxipng::{optimize_from_memory, Options};
 use std::fs::File;
-use std::io::{BufWriter, Cursor};
-use std::path::Path;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+// Same default as the underlying encoder: a balance of size and encode time
+const DEFAULT_AVIF_SPEED: u8 = 6;
 
 pub struct Compressor {
     quality: u8,
     optimize_png: bool,
     progressive_jpeg: bool,
+    dpi: Option<u32>,
+    speed: Option<u8>,
+    bit_depth: Option<u8>,
+    cmyk: bool,
+    cmyk_profile: Option<PathBuf>,
+    create_dirs: bool,
+    indexed: bool,
 }
 
 impl Compressor {
@@ -19,6 +31,13 @@ impl Compressor {
             quality: quality.clamp(1, 100),
             optimize_png: true,
             progressive_jpeg: false,
+            dpi: None,
+            speed: None,
+            bit_depth: None,
+            cmyk: false,
+            cmyk_profile: None,
+            create_dirs: true,
+            indexed: false,
         }
     }
 
@@ -27,11 +46,50 @@ impl Compressor {
         self
     }
 
+    /// The JPEG encoder only writes baseline files, so this falls back to
+    /// baseline with a warning
     pub fn with_progressive_jpeg(mut self, progressive: bool) -> Self {
         self.progressive_jpeg = progressive;
         self
     }
 
+    pub fn with_dpi(mut self, dpi: Option<u32>) -> Self {
+        self.dpi = dpi;
+        self
+    }
+
+    /// AVIF encoder speed (1-10); `None` uses the encoder default
+    pub fn with_speed(mut self, speed: Option<u8>) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Force 8 or 16 bits per sample; `None` keeps the image's own depth
+    pub fn with_bit_depth(mut self, bit_depth: Option<u8>) -> Self {
+        self.bit_depth = bit_depth;
+        self
+    }
+
+    /// Write TIFF output as CMYK, converting through `profile` if given
+    pub fn with_cmyk(mut self, cmyk: bool, profile: Option<PathBuf>) -> Self {
+        self.cmyk = cmyk;
+        self.cmyk_profile = profile;
+        self
+    }
+
+    /// Write PNG output with a palette whenever the image has 256 colors or
+    /// fewer, as it does after quantizing
+    pub fn with_indexed(mut self, indexed: bool) -> Self {
+        self.indexed = indexed;
+        self
+    }
+
+    /// Create missing parent directories of the output path (the default)
+    pub fn with_create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
     pub fn save(&self, image: &DynamicImage, path: &Path) -> Result<()> {
         let format = self.detect_format(path);
         self.save_with_format(image, path, format)
@@ -50,36 +108,68 @@ impl Compressor {
             self.quality
         );
 
+        match path.parent() {
+            Some(parent) if self.create_dirs => std::fs::create_dir_all(parent)?,
+            Some(parent) if !parent.as_os_str().is_empty() && !parent.is_dir() => {
+                return Err(ImageToolError::InvalidParameter(format!(
+                    "Output directory does not exist: {}",
+                    parent.display()
+                )));
+            }
+            _ => {}
+        }
+
+        if self.cmyk {
+            let file = File::create(path)?;
+            self.write_cmyk(image, BufWriter::new(file), format)?;
+            return self.log_save_result(path);
+        }
+
+        let converted = self.convert_depth(image, format)?;
+        let image = converted.as_ref().unwrap_or(image);
+
         match format {
             ImageFormat::Jpeg => self.save_jpeg(image, path),
             ImageFormat::Png => self.save_png(image, path),
             ImageFormat::WebP => self.save_webp(image, path),
+            ImageFormat::Avif => self.save_avif(image, path),
+            ImageFormat::Pnm => self.save_pnm(image, path),
             _ => self.save_generic(image, path, format),
+        }?;
+
+        if let Some(dpi) = self.dpi {
+            let data = std::fs::read(path)?;
+            std::fs::write(path, apply_dpi(data, format, dpi)?)?;
         }
+
+        Ok(())
     }
 
     fn save_jpeg(&self, image: &DynamicImage, path: &Path) -> Result<()> {
         let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-
-        let output_format = if self.progressive_jpeg {
-            ImageOutputFormat::JpegProgressive(self.quality)
-        } else {
-            ImageOutputFormat::Jpeg(self.quality)
-        };
-
-        image.write_to(writer, output_format)?;
+        self.write_jpeg(image, BufWriter::new(file))?;
         self.log_save_result(path)
     }
 
+    fn write_jpeg(&self, image: &DynamicImage, writer: impl Write) -> Result<()> {
+        if self.progressive_jpeg {
+            log::warn!("Progressive JPEG encoding is not supported, writing a baseline JPEG");
+        }
+        image.write_with_encoder(JpegEncoder::new_with_quality(writer, self.quality))?;
+        Ok(())
+    }
+
     fn save_png(&self, image: &DynamicImage, path: &Path) -> Result<()> {
-        if self.optimize_png {
+        if let Some(indexed) = self.encode_indexed_png(image)? {
+            let data = if self.optimize_png { self.optimize_png_bytes(&indexed)? } else { indexed };
+            std::fs::write(path, data)?;
+        } else if self.optimize_png {
             // First save to memory
             let mut buffer = Cursor::new(Vec::new());
-            image.write_to(&mut buffer, ImageOutputFormat::Png)?;
+            image.write_to(&mut buffer, ImageFormat::Png)?;
             
             // Optimize with oxipng
-            let optimized_data = optimize_from_memory(&buffer.into_inner(), &Options::default())
+            let optimized_data = optimize_from_memory(&buffer.into_inner(), &self.png_options())
                 .map_err(|e| ImageToolError::ProcessingError(format!("PNG optimization failed: {}", e)))?;
             
             // Write optimized data
@@ -87,7 +177,7 @@ impl Compressor {
         } else {
             let file = File::create(path)?;
             let writer = BufWriter::new(file);
-            image.write_to(writer, ImageOutputFormat::Png)?;
+            image.write_to(writer, ImageFormat::Png)?;
         }
 
         self.log_save_result(path)
@@ -119,15 +209,129 @@ impl Compressor {
         self.log_save_result(path)
     }
 
+    fn save_avif(&self, image: &DynamicImage, path: &Path) -> Result<()> {
+        let file = File::create(path)?;
+        self.write_avif(image, BufWriter::new(file))?;
+        self.log_save_result(path)
+    }
+
+    fn write_avif<W: std::io::Write>(&self, image: &DynamicImage, writer: W) -> Result<()> {
+        use image::codecs::avif::AvifEncoder;
+
+        let speed = self.speed.unwrap_or(DEFAULT_AVIF_SPEED).clamp(1, 10);
+        log::debug!("Encoding AVIF at speed {}", speed);
+
+        let encoder = AvifEncoder::new_with_speed_quality(writer, speed, self.quality);
+        // The AVIF encoder only takes 8-bit RGB(A)
+        let image = if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        };
+        image.write_with_encoder(encoder)?;
+        Ok(())
+    }
+
+    fn save_pnm(&self, image: &DynamicImage, path: &Path) -> Result<()> {
+        // An explicit .ppm/.pgm extension wins over the image's own color type
+        let graymap = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pgm") => true,
+            Some("ppm") => false,
+            _ => !image.color().has_color(),
+        };
+
+        let file = File::create(path)?;
+        self.write_pnm(image, BufWriter::new(file), graymap)?;
+        self.log_save_result(path)
+    }
+
+    fn write_pnm<W: std::io::Write>(&self, image: &DynamicImage, mut writer: W, graymap: bool) -> Result<()> {
+        use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+
+        // The encoder only writes 8-bit PPM/PGM; 16-bit samples are big-endian
+        // with a maxval of 65535
+        if is_16_bit(image) {
+            let (magic, samples) = if graymap {
+                ("P5", image.to_luma16().into_raw())
+            } else {
+                ("P6", image.to_rgb16().into_raw())
+            };
+            write!(writer, "{}\n{} {}\n65535\n", magic, image.width(), image.height())?;
+            let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+            writer.write_all(&bytes)?;
+            writer.flush()?;
+            return Ok(());
+        }
+
+        // PPM and PGM have no alpha channel
+        let (image, subtype) = if graymap {
+            (DynamicImage::ImageLuma8(image.to_luma8()), PnmSubtype::Graymap(SampleEncoding::Binary))
+        } else {
+            (DynamicImage::ImageRgb8(image.to_rgb8()), PnmSubtype::Pixmap(SampleEncoding::Binary))
+        };
+        image.write_with_encoder(PnmEncoder::new(writer).with_subtype(subtype))?;
+        Ok(())
+    }
+
+    fn write_cmyk<W: std::io::Write + std::io::Seek>(&self, image: &DynamicImage, writer: W, format: ImageFormat) -> Result<()> {
+        if format != ImageFormat::Tiff {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "CMYK output requires TIFF, not {:?}",
+                format
+            )));
+        }
+
+        let profile = self.cmyk_profile.as_deref().map(std::fs::read).transpose()?;
+        write_cmyk_tiff(image, writer, profile.as_deref())
+    }
+
+    fn convert_depth(&self, image: &DynamicImage, format: ImageFormat) -> Result<Option<DynamicImage>> {
+        match self.bit_depth {
+            Some(16) if !supports_16_bit(format) => Err(ImageToolError::InvalidParameter(format!(
+                "{:?} output cannot hold 16-bit samples",
+                format
+            ))),
+            Some(16) if !is_16_bit(image) => Ok(Some(with_depth(image, true))),
+            Some(8) if is_16_bit(image) => Ok(Some(with_depth(image, false))),
+            _ => {
+                if is_16_bit(image) && !supports_16_bit(format) {
+                    log::debug!("{:?} output is 8-bit only, reducing sample depth", format);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    // TGA and QOI only take 8-bit samples, and QOI has no grayscale mode
+    fn to_encodable(&self, image: &DynamicImage, format: ImageFormat) -> Option<DynamicImage> {
+        let color = image.color();
+        match format {
+            ImageFormat::Qoi if color.has_alpha() => Some(DynamicImage::ImageRgba8(image.to_rgba8())),
+            ImageFormat::Qoi => Some(DynamicImage::ImageRgb8(image.to_rgb8())),
+            ImageFormat::Tga if color.bytes_per_pixel() / color.channel_count() > 1 => {
+                Some(match (color.has_color(), color.has_alpha()) {
+                    (true, true) => DynamicImage::ImageRgba8(image.to_rgba8()),
+                    (true, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+                    (false, true) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+                    (false, false) => DynamicImage::ImageLuma8(image.to_luma8()),
+                })
+            }
+            _ => None,
+        }
+    }
+
     fn save_generic(
         &self,
         image: &DynamicImage,
         path: &Path,
         format: ImageFormat,
     ) -> Result<()> {
+        let converted = self.to_encodable(image, format);
+        let image = converted.as_ref().unwrap_or(image);
+
         let file = File::create(path)?;
         let writer = BufWriter::new(file);
-        image.write_to(writer, ImageOutputFormat::from(format))?;
+        image.write_to(writer, format)?;
         
         self.log_save_result(path)
     }
@@ -138,35 +342,106 @@ impl Compressor {
         format: ImageFormat,
     ) -> Result<Vec<u8>> {
         let mut buffer = Cursor::new(Vec::new());
+        if self.cmyk {
+            self.write_cmyk(image, &mut buffer, format)?;
+            return Ok(buffer.into_inner());
+        }
+
+        let converted = self.convert_depth(image, format)?;
+        let image = converted.as_ref().unwrap_or(image);
 
         match format {
             ImageFormat::Jpeg => {
-                let output_format = if self.progressive_jpeg {
-                    ImageOutputFormat::JpegProgressive(self.quality)
-                } else {
-                    ImageOutputFormat::Jpeg(self.quality)
-                };
-                image.write_to(&mut buffer, output_format)?;
+                self.write_jpeg(image, &mut buffer)?;
             }
             ImageFormat::Png => {
-                image.write_to(&mut buffer, ImageOutputFormat::Png)?;
+                match self.encode_indexed_png(image)? {
+                    Some(indexed) => buffer = Cursor::new(indexed),
+                    None => image.write_to(&mut buffer, ImageFormat::Png)?,
+                }
                 if self.optimize_png {
-                    return self.optimize_png_bytes(&buffer.into_inner());
+                    let optimized = self.optimize_png_bytes(&buffer.into_inner())?;
+                    return match self.dpi {
+                        Some(dpi) => apply_dpi(optimized, format, dpi),
+                        None => Ok(optimized),
+                    };
                 }
             }
+            ImageFormat::Avif => {
+                self.write_avif(image, &mut buffer)?;
+            }
+            ImageFormat::Pnm => {
+                self.write_pnm(image, &mut buffer, !image.color().has_color())?;
+            }
             _ => {
-                image.write_to(&mut buffer, ImageOutputFormat::from(format))?;
+                let converted = self.to_encodable(image, format);
+                let image = converted.as_ref().unwrap_or(image);
+                image.write_to(&mut buffer, format)?;
             }
         }
 
-        Ok(buffer.into_inner())
+        match self.dpi {
+            Some(dpi) => apply_dpi(buffer.into_inner(), format, dpi),
+            None => Ok(buffer.into_inner()),
+        }
     }
 
     fn optimize_png_bytes(&self, data: &[u8]) -> Result<Vec<u8>> {
-        optimize_from_memory(data, &Options::default())
+        optimize_from_memory(data, &self.png_options())
             .map_err(|e| ImageToolError::ProcessingError(format!("PNG optimization failed: {}", e)))
     }
 
+    fn png_options(&self) -> Options {
+        Options {
+            // oxipng would otherwise undo an explicit 16-bit request when
+            // the samples fit in 8 bits
+            bit_depth_reduction: self.bit_depth != Some(16),
+            // and may store a palette image as RGB when that comes out smaller
+            color_type_reduction: !self.indexed,
+            ..Default::default()
+        }
+    }
+
+    // `image` as an 8-bit palette PNG, with a tRNS chunk when any color is
+    // translucent; None when not asked for, or when it has too many colors
+    fn encode_indexed_png(&self, image: &DynamicImage) -> Result<Option<Vec<u8>>> {
+        if !self.indexed || self.bit_depth == Some(16) {
+            return Ok(None);
+        }
+
+        let rgba = image.to_rgba8();
+        let mut palette: Vec<[u8; 4]> = Vec::new();
+        let mut lookup = std::collections::HashMap::new();
+        let mut indices = Vec::with_capacity(rgba.len() / 4);
+        for pixel in rgba.pixels() {
+            let index = match lookup.get(&pixel.0) {
+                Some(&index) => index,
+                None if palette.len() == 256 => return Ok(None),
+                None => {
+                    palette.push(pixel.0);
+                    lookup.insert(pixel.0, (palette.len() - 1) as u8);
+                    (palette.len() - 1) as u8
+                }
+            };
+            indices.push(index);
+        }
+
+        let png_error =
+            |e: png::EncodingError| ImageToolError::ProcessingError(format!("PNG encoding failed: {}", e));
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, rgba.width(), rgba.height());
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette.iter().flat_map(|color| [color[0], color[1], color[2]]).collect::<Vec<_>>());
+        if palette.iter().any(|color| color[3] < 255) {
+            encoder.set_trns(palette.iter().map(|color| color[3]).collect::<Vec<_>>());
+        }
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&indices).map_err(png_error)?;
+        writer.finish().map_err(png_error)?;
+        Ok(Some(data))
+    }
+
     fn detect_format(&self, path: &Path) -> ImageFormat {
         match path.extension().and_then(|ext| ext.to_str()) {
             Some("jpg") | Some("jpeg") => ImageFormat::Jpeg,
@@ -175,6 +450,10 @@ impl Compressor {
             Some("bmp") => ImageFormat::Bmp,
             Some("webp") => ImageFormat::WebP,
             Some("tiff") | Some("tif") => ImageFormat::Tiff,
+            Some("avif") => ImageFormat::Avif,
+            Some("tga") => ImageFormat::Tga,
+            Some("ppm") | Some("pgm") | Some("pnm") => ImageFormat::Pnm,
+            Some("qoi") => ImageFormat::Qoi,
             _ => ImageFormat::Jpeg,
         }
     }
@@ -193,4 +472,33 @@ impl Compressor {
         let savings = (original_size as f64 - compressed_size as f64) / original_size as f64 * 100.0;
         savings.max(0.0)
     }
+}
+
+fn supports_16_bit(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Png | ImageFormat::Tiff | ImageFormat::Pnm)
+}
+
+fn is_16_bit(image: &DynamicImage) -> bool {
+    matches!(
+        image,
+        DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+    )
+}
+
+// Same channel layout at 8 or 16 bits per sample
+fn with_depth(image: &DynamicImage, sixteen: bool) -> DynamicImage {
+    let color = image.color();
+    match (color.has_color(), color.has_alpha(), sixteen) {
+        (true, true, true) => DynamicImage::ImageRgba16(image.to_rgba16()),
+        (true, false, true) => DynamicImage::ImageRgb16(image.to_rgb16()),
+        (false, true, true) => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        (false, false, true) => DynamicImage::ImageLuma16(image.to_luma16()),
+        (true, true, false) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        (true, false, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        (false, true, false) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        (false, false, false) => DynamicImage::ImageLuma8(image.to_luma8()),
+    }
 }
\ No newline at end of file
@@ -0,0 +1,106 @@
+// pixie/src/processors/cropper.rs
+use crate::core::{AspectRatio, CropGravity};
+use image::DynamicImage;
+
+pub struct Cropper {
+    gravity: CropGravity,
+}
+
+impl Cropper {
+    pub fn new(gravity: CropGravity) -> Self {
+        Self { gravity }
+    }
+
+    pub fn crop_to_aspect(&self, image: &DynamicImage, aspect: AspectRatio) -> DynamicImage {
+        let (width, height) = (image.width(), image.height());
+        let (crop_w, crop_h) = self.aspect_box(width, height, aspect);
+
+        if crop_w == width && crop_h == height {
+            log::debug!("Image already matches aspect {}:{}", aspect.width, aspect.height);
+            return image.clone();
+        }
+
+        let (x, y) = self.anchor(width, height, crop_w, crop_h);
+        log::debug!(
+            "Cropping {}x{} to {}x{} at ({}, {}) for aspect {}:{}",
+            width, height, crop_w, crop_h, x, y, aspect.width, aspect.height
+        );
+
+        image.crop_imm(x, y, crop_w, crop_h)
+    }
+
+    /// Crops away borders matching the top-left pixel's color, within `fuzz`
+    /// percent. Images that are a single color throughout are left alone.
+    pub fn trim(&self, image: &DynamicImage, fuzz: f32) -> DynamicImage {
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        if width == 0 || height == 0 {
+            return image.clone();
+        }
+
+        let border = rgba.get_pixel(0, 0).0;
+        let tolerance = fuzz.clamp(0.0, 100.0) / 100.0 * 255.0;
+        let matches = |x: u32, y: u32| {
+            let pixel = rgba.get_pixel(x, y).0;
+            let sum: f32 = (0..4).map(|c| (pixel[c] as f32 - border[c] as f32).powi(2)).sum();
+            (sum / 4.0).sqrt() <= tolerance
+        };
+
+        let Some(top) = (0..height).find(|&y| !(0..width).all(|x| matches(x, y))) else {
+            log::debug!("Nothing to trim: image is a single color");
+            return image.clone();
+        };
+        let bottom = (top..height).rev().find(|&y| !(0..width).all(|x| matches(x, y))).unwrap_or(top);
+        let column = |x: u32| (top..=bottom).all(|y| matches(x, y));
+        let left = (0..width).find(|&x| !column(x)).unwrap_or(0);
+        let right = (left..width).rev().find(|&x| !column(x)).unwrap_or(left);
+
+        let (crop_w, crop_h) = (right - left + 1, bottom - top + 1);
+        if (crop_w, crop_h) == (width, height) {
+            return image.clone();
+        }
+
+        log::debug!("Trimming {}x{} to {}x{} at ({}, {})", width, height, crop_w, crop_h, left, top);
+        image.crop_imm(left, top, crop_w, crop_h)
+    }
+
+    /// Largest box of the requested aspect that fits inside the image
+    pub fn aspect_box(&self, width: u32, height: u32, aspect: AspectRatio) -> (u32, u32) {
+        let target = aspect.ratio();
+        let current = width as f64 / height as f64;
+
+        if (current - target).abs() < f64::EPSILON {
+            (width, height)
+        } else if current > target {
+            let crop_w = (height as f64 * target).round() as u32;
+            (crop_w.clamp(1, width), height)
+        } else {
+            let crop_h = (width as f64 / target).round() as u32;
+            (width, crop_h.clamp(1, height))
+        }
+    }
+
+    pub fn anchor(&self, width: u32, height: u32, crop_w: u32, crop_h: u32) -> (u32, u32) {
+        let spare_x = width.saturating_sub(crop_w);
+        let spare_y = height.saturating_sub(crop_h);
+
+        let x = match self.gravity {
+            CropGravity::West | CropGravity::NorthWest | CropGravity::SouthWest => 0,
+            CropGravity::East | CropGravity::NorthEast | CropGravity::SouthEast => spare_x,
+            _ => spare_x / 2,
+        };
+        let y = match self.gravity {
+            CropGravity::North | CropGravity::NorthEast | CropGravity::NorthWest => 0,
+            CropGravity::South | CropGravity::SouthEast | CropGravity::SouthWest => spare_y,
+            _ => spare_y / 2,
+        };
+
+        (x, y)
+    }
+}
+
+impl Default for Cropper {
+    fn default() -> Self {
+        Self::new(CropGravity::Center)
+    }
+}
@@ -0,0 +1,120 @@
+// pixie/src/processors/denoise.rs
+use crate::core::DenoiseMethod;
+use crate::utils::into_color;
+use image::{DynamicImage, Rgba32FImage};
+
+/// Smooths sensor noise while keeping edges. `level` runs from 1 (light)
+/// to 10 (heavy) and scales the window and tolerance of each method.
+pub struct Denoiser {
+    method: DenoiseMethod,
+    level: u8,
+}
+
+impl Denoiser {
+    pub fn new(method: DenoiseMethod, level: u8) -> Self {
+        Self {
+            method,
+            level: level.clamp(1, 10),
+        }
+    }
+
+    /// Filters the color channels; alpha is left alone
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        log::debug!("Denoising with {:?} at level {}", self.method, self.level);
+
+        let source = image.to_rgba32f();
+        let level = self.level as f32;
+        let denoised = match self.method {
+            DenoiseMethod::Median => median(&source, if self.level <= 5 { 1 } else { 2 }),
+            DenoiseMethod::Bilateral => bilateral(&source, 1.0 + 0.2 * level, 0.03 * level),
+            DenoiseMethod::NlMeans => nl_means(&source, 0.03 * level),
+        };
+        into_color(DynamicImage::ImageRgba32F(denoised), image.color())
+    }
+}
+
+fn median(source: &Rgba32FImage, radius: i64) -> Rgba32FImage {
+    let mut output = source.clone();
+    let mut window = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        for c in 0..3 {
+            window.clear();
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    window.push(sample(source, x as i64 + dx, y as i64 + dy)[c]);
+                }
+            }
+            let middle = window.len() / 2;
+            pixel.0[c] = *window.select_nth_unstable_by(middle, f32::total_cmp).1;
+        }
+    }
+    output
+}
+
+// Weights neighbours by distance and by color similarity, so averaging
+// stops at edges
+fn bilateral(source: &Rgba32FImage, sigma_space: f32, sigma_range: f32) -> Rgba32FImage {
+    let radius = (2.0 * sigma_space).ceil() as i64;
+    let space = -0.5 / (sigma_space * sigma_space);
+    let range = -0.5 / (sigma_range * sigma_range);
+
+    let mut output = source.clone();
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        let center = source.get_pixel(x, y).0;
+        let mut sum = [0.0; 3];
+        let mut total = 0.0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let neighbour = sample(source, x as i64 + dx, y as i64 + dy);
+                let distance = (dx * dx + dy * dy) as f32;
+                let difference: f32 = (0..3).map(|c| (neighbour[c] - center[c]).powi(2)).sum();
+                let weight = (distance * space + difference * range).exp();
+                (0..3).for_each(|c| sum[c] += neighbour[c] * weight);
+                total += weight;
+            }
+        }
+        (0..3).for_each(|c| pixel.0[c] = sum[c] / total);
+    }
+    output
+}
+
+// Non-local means with a 3x3 patch and a 7x7 search window: neighbours
+// whose surroundings look alike are averaged, wherever the edges run
+fn nl_means(source: &Rgba32FImage, strength: f32) -> Rgba32FImage {
+    const SEARCH: i64 = 3;
+    const PATCH: i64 = 1;
+    let falloff = -1.0 / (strength * strength);
+
+    let mut output = source.clone();
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        let (x, y) = (x as i64, y as i64);
+        let mut sum = [0.0; 3];
+        let mut total = 0.0;
+        for sy in -SEARCH..=SEARCH {
+            for sx in -SEARCH..=SEARCH {
+                let mut distance = 0.0;
+                for py in -PATCH..=PATCH {
+                    for px in -PATCH..=PATCH {
+                        let a = sample(source, x + px, y + py);
+                        let b = sample(source, x + sx + px, y + sy + py);
+                        distance += (0..3).map(|c| (a[c] - b[c]).powi(2)).sum::<f32>();
+                    }
+                }
+                let distance = distance / (3 * (2 * PATCH + 1) * (2 * PATCH + 1)) as f32;
+                let weight = (distance * falloff).exp();
+                let neighbour = sample(source, x + sx, y + sy);
+                (0..3).for_each(|c| sum[c] += neighbour[c] * weight);
+                total += weight;
+            }
+        }
+        (0..3).for_each(|c| pixel.0[c] = sum[c] / total);
+    }
+    output
+}
+
+// Edge pixels repeat outwards
+fn sample(image: &Rgba32FImage, x: i64, y: i64) -> [f32; 4] {
+    let x = x.clamp(0, image.width() as i64 - 1) as u32;
+    let y = y.clamp(0, image.height() as i64 - 1) as u32;
+    image.get_pixel(x, y).0
+}
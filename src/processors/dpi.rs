@@ -0,0 +1,142 @@
+// pixie/src/processors/dpi.rs
+use crate::core::{ImageToolError, Result};
+use image::ImageFormat;
+
+// Rewrites the physical resolution fields of an already encoded image.
+// Formats without a resolution field are returned unchanged.
+pub fn apply_dpi(data: Vec<u8>, format: ImageFormat, dpi: u32) -> Result<Vec<u8>> {
+    if dpi == 0 {
+        return Err(ImageToolError::InvalidParameter("DPI must be greater than zero".to_string()));
+    }
+
+    match format {
+        ImageFormat::Png => set_png_dpi(&data, dpi),
+        ImageFormat::Jpeg => set_jpeg_dpi(data, dpi),
+        ImageFormat::Tiff => set_tiff_dpi(data, dpi),
+        _ => {
+            log::debug!("{:?} has no resolution field, DPI not written", format);
+            Ok(data)
+        }
+    }
+}
+
+fn set_png_dpi(data: &[u8], dpi: u32) -> Result<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return Err(ImageToolError::ProcessingError("Not a PNG stream".to_string()));
+    }
+
+    let pixels_per_meter = (dpi as f64 / 0.0254).round() as u32;
+    let mut phys = Vec::with_capacity(9);
+    phys.extend_from_slice(&pixels_per_meter.to_be_bytes());
+    phys.extend_from_slice(&pixels_per_meter.to_be_bytes());
+    phys.push(1); // unit: meter
+
+    let mut output = Vec::with_capacity(data.len() + 21);
+    output.extend_from_slice(SIGNATURE);
+
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let end = pos + 12 + length;
+        if end > data.len() {
+            return Err(ImageToolError::ProcessingError("Truncated PNG chunk".to_string()));
+        }
+
+        // Drop any existing pHYs, ours goes right after IHDR
+        if chunk_type != b"pHYs" {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        if chunk_type == b"IHDR" {
+            write_png_chunk(&mut output, b"pHYs", &phys);
+        }
+        pos = end;
+    }
+
+    Ok(output)
+}
+
+pub(crate) fn write_png_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(chunk_type);
+    hasher.update(data);
+
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(data);
+    output.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+fn set_jpeg_dpi(mut data: Vec<u8>, dpi: u32) -> Result<Vec<u8>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(ImageToolError::ProcessingError("Not a JPEG stream".to_string()));
+    }
+
+    let density = dpi.min(u16::MAX as u32) as u16;
+
+    // JFIF APP0: FFE0, length, "JFIF\0", version (2), units (1), Xdensity (2), Ydensity (2)
+    if data.len() >= 18 && data[2..4] == [0xFF, 0xE0] && &data[6..11] == b"JFIF\0" {
+        data[13] = 1; // dots per inch
+        data[14..16].copy_from_slice(&density.to_be_bytes());
+        data[16..18].copy_from_slice(&density.to_be_bytes());
+        return Ok(data);
+    }
+
+    let mut app0 = vec![0xFF, 0xE0, 0x00, 0x10];
+    app0.extend_from_slice(b"JFIF\0");
+    app0.extend_from_slice(&[1, 1, 1]);
+    app0.extend_from_slice(&density.to_be_bytes());
+    app0.extend_from_slice(&density.to_be_bytes());
+    app0.extend_from_slice(&[0, 0]);
+
+    data.splice(2..2, app0);
+    Ok(data)
+}
+
+fn set_tiff_dpi(mut data: Vec<u8>, dpi: u32) -> Result<Vec<u8>> {
+    let invalid = || ImageToolError::ProcessingError("Malformed TIFF header".to_string());
+
+    let little_endian = match data.get(0..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return Err(invalid()),
+    };
+    let read_u16 = |d: &[u8], at: usize| -> Option<u16> {
+        let b = d.get(at..at + 2)?;
+        Some(if little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    };
+    let read_u32 = |d: &[u8], at: usize| -> Option<u32> {
+        let b = d.get(at..at + 4)?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Some(if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+    let encode_u32 = |v: u32| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+    let encode_u16 = |v: u16| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+
+    let ifd = read_u32(&data, 4).ok_or_else(invalid)? as usize;
+    let count = read_u16(&data, ifd).ok_or_else(invalid)? as usize;
+
+    for i in 0..count {
+        let entry = ifd + 2 + i * 12;
+        let tag = read_u16(&data, entry).ok_or_else(invalid)?;
+        match tag {
+            // XResolution / YResolution: a single RATIONAL stored at an offset
+            282 | 283 => {
+                let offset = read_u32(&data, entry + 8).ok_or_else(invalid)? as usize;
+                if offset + 8 > data.len() {
+                    return Err(invalid());
+                }
+                data[offset..offset + 4].copy_from_slice(&encode_u32(dpi));
+                data[offset + 4..offset + 8].copy_from_slice(&encode_u32(1));
+            }
+            // ResolutionUnit: SHORT stored inline, 2 = inch
+            296 => {
+                data[entry + 8..entry + 10].copy_from_slice(&encode_u16(2));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(data)
+}
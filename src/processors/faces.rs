@@ -0,0 +1,86 @@
+// pixie/src/processors/faces.rs
+use crate::core::{ImageToolError, Result};
+use crate::processors::upscaler::ScratchDir;
+use image::{DynamicImage, ImageFormat};
+use std::process::{Command, Stdio};
+
+/// Detector run when none is configured; prints `X Y W H` per face
+const DEFAULT_FACE_DETECTOR: &str = "facedetect {input}";
+
+/// Finds faces with an external detector. The image is written to a
+/// scratch PNG, `{input}` in the command template is replaced with its
+/// path, and the program prints one face per line as four numbers, x, y,
+/// width and height, separated by spaces or commas.
+pub struct FaceDetector {
+    command: String,
+}
+
+impl FaceDetector {
+    pub fn new(command: Option<&str>) -> Self {
+        Self {
+            command: command.unwrap_or(DEFAULT_FACE_DETECTOR).to_string(),
+        }
+    }
+
+    /// Face boxes as (x, y, width, height) in pixels of `image`
+    pub fn detect(&self, image: &DynamicImage) -> Result<Vec<(u32, u32, u32, u32)>> {
+        let scratch = ScratchDir::new("faces")?;
+        let input = scratch.path().join("image.png");
+        image.save_with_format(&input, ImageFormat::Png)?;
+
+        // Split before substituting so paths with spaces stay one argument
+        let mut args = self
+            .command
+            .split_whitespace()
+            .map(|part| part.replace("{input}", &input.to_string_lossy()));
+        let program = args.next().ok_or_else(|| {
+            ImageToolError::InvalidParameter("Face detector command is empty".to_string())
+        })?;
+
+        let result = Command::new(&program)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => ImageToolError::UnsupportedFormat(format!(
+                    "{} not found; install it or pass --face-detector",
+                    program
+                )),
+                _ => ImageToolError::Io(e),
+            })?;
+
+        if !result.status.success() {
+            return Err(ImageToolError::ProcessingError(format!(
+                "Face detector failed: {}",
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+
+        let faces = parse_faces(&String::from_utf8_lossy(&result.stdout))?;
+        log::info!("Found {} face(s)", faces.len());
+        Ok(faces)
+    }
+}
+
+// A line that isn't four numbers fails the whole image, since guessing
+// could leave a face unblurred
+fn parse_faces(output: &str) -> Result<Vec<(u32, u32, u32, u32)>> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let values = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<f64>().map(|v| v.max(0.0).round() as u32))
+                .collect::<std::result::Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok(&[x, y, width, height]) => Ok((x, y, width, height)),
+                _ => Err(ImageToolError::ProcessingError(format!(
+                    "Face detector printed '{}', expected X Y W H",
+                    line.trim()
+                ))),
+            }
+        })
+        .collect()
+}
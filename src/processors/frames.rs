@@ -0,0 +1,100 @@
+// pixie/src/processors/frames.rs
+use crate::core::{ImageToolError, Result};
+use crate::processors::{Compressor, Loader};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, Frames, ImageFormat};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Writes the frames of an animated GIF, WebP or PNG as numbered PNGs.
+pub struct FrameExtractor {
+    stride: usize,
+}
+
+impl FrameExtractor {
+    pub fn new() -> Self {
+        Self { stride: 1 }
+    }
+
+    /// Keep only every Nth frame, starting with the first
+    pub fn with_stride(mut self, stride: usize) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    pub fn extract(&self, input_path: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
+        if self.stride == 0 {
+            return Err(ImageToolError::InvalidParameter(
+                "Frame stride must be at least 1".to_string(),
+            ));
+        }
+
+        std::fs::create_dir_all(output_dir)?;
+
+        let stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("frame");
+        let compressor = Compressor::new(100);
+        let mut written = Vec::new();
+
+        let frames = match self.open_frames(input_path)? {
+            Some(frames) => frames,
+            None => {
+                // Still image: the single frame is the image itself
+                log::info!("{} is not animated, writing a single frame", input_path.display());
+                let image = Loader::new().load(input_path)?;
+                let path = output_dir.join(format!("{}_{:04}.png", stem, 0));
+                compressor.save_with_format(&image, &path, ImageFormat::Png)?;
+                written.push(path);
+                return Ok(written);
+            }
+        };
+
+        for (index, frame) in frames.enumerate().step_by(self.stride) {
+            let frame = frame?;
+            let path = output_dir.join(format!("{}_{:04}.png", stem, index));
+            let image = DynamicImage::ImageRgba8(frame.into_buffer());
+            compressor.save_with_format(&image, &path, ImageFormat::Png)?;
+            written.push(path);
+        }
+
+        log::info!("Wrote {} frame(s) to {}", written.len(), output_dir.display());
+        Ok(written)
+    }
+
+    fn open_frames(&self, input_path: &Path) -> Result<Option<Frames<'static>>> {
+        let format = Loader::new().detect_format(input_path)?;
+        let reader = BufReader::new(File::open(input_path)?);
+
+        let frames = match format {
+            ImageFormat::Gif => GifDecoder::new(reader)?.into_frames(),
+            ImageFormat::WebP => {
+                let decoder = WebPDecoder::new(reader)?;
+                if !decoder.has_animation() {
+                    return Ok(None);
+                }
+                decoder.into_frames()
+            }
+            ImageFormat::Png => {
+                let decoder = PngDecoder::new(reader)?;
+                if !decoder.is_apng()? {
+                    return Ok(None);
+                }
+                decoder.apng()?.into_frames()
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(frames))
+    }
+}
+
+impl Default for FrameExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,103 @@
+// pixie/src/processors/journal.rs
+use crate::core::{ImageToolError, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const HEADER: &str = "# pixie batch journal";
+
+/// Append-only list of files written by a batch run. Each entry is flushed
+/// as soon as it is written so an interrupted run can still be rolled back.
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+#[derive(Debug, Default)]
+pub struct RollbackReport {
+    pub removed: usize,
+    /// Entries whose file was already gone
+    pub missing: usize,
+}
+
+impl Journal {
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(path)?;
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        writeln!(file, "{} (started {})", HEADER, started)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, output_path: &Path) -> Result<()> {
+        let output_path = std::fs::canonicalize(output_path)?;
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| ImageToolError::ProcessingError("Journal lock poisoned".to_string()))?;
+        writeln!(file, "{}", output_path.display())?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn default_path(output_dir: &Path) -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        output_dir.join(format!(".pixie-journal-{}", timestamp))
+    }
+}
+
+/// Deletes every file listed in a journal, then the journal itself.
+pub fn rollback_journal(journal_path: &Path) -> Result<RollbackReport> {
+    let reader = BufReader::new(File::open(journal_path)?);
+    let mut lines = reader.lines();
+
+    match lines.next() {
+        Some(Ok(first)) if first.starts_with(HEADER) => {}
+        _ => {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "{} is not a pixie batch journal",
+                journal_path.display()
+            )))
+        }
+    }
+
+    let mut report = RollbackReport::default();
+    for line in lines {
+        let line = line?;
+        let entry = line.trim();
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+
+        match std::fs::remove_file(entry) {
+            Ok(()) => {
+                log::debug!("Removed {}", entry);
+                report.removed += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => report.missing += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    std::fs::remove_file(journal_path)?;
+    Ok(report)
+}
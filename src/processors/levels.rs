@@ -0,0 +1,81 @@
+// pixie/src/processors/levels.rs
+use crate::core::AutoLevel;
+use crate::utils::into_color;
+use image::DynamicImage;
+
+const BINS: usize = 4096;
+
+/// Stretches the histogram so the darkest and brightest samples (after
+/// clipping a small percentage as outliers) span the full range.
+pub struct LevelStretcher {
+    mode: AutoLevel,
+    clip: f32,
+}
+
+impl LevelStretcher {
+    pub fn new(mode: AutoLevel) -> Self {
+        Self { mode, clip: 0.5 }
+    }
+
+    /// Percentage of samples ignored at each end of the histogram
+    pub fn with_clip(mut self, percent: f32) -> Self {
+        self.clip = percent.clamp(0.0, 49.0);
+        self
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let mut working = image.to_rgba32f();
+
+        // Transparent pixels often hold junk colors; leave them out
+        let mut histograms = [[0u64; BINS]; 3];
+        for pixel in working.pixels().filter(|p| p.0[3] > 0.0) {
+            for (c, histogram) in histograms.iter_mut().enumerate() {
+                histogram[bin(pixel.0[c])] += 1;
+            }
+        }
+
+        let ranges = match self.mode {
+            AutoLevel::Levels => histograms.map(|histogram| self.range(&histogram)),
+            AutoLevel::Contrast => {
+                let mut combined = [0u64; BINS];
+                for histogram in &histograms {
+                    combined.iter_mut().zip(histogram).for_each(|(sum, count)| *sum += count);
+                }
+                [self.range(&combined); 3]
+            }
+        };
+
+        log::debug!("Auto levels ({:?}): {:?}", self.mode, ranges);
+
+        for pixel in working.pixels_mut() {
+            for (c, &(low, high)) in ranges.iter().enumerate() {
+                if high > low {
+                    pixel.0[c] = ((pixel.0[c] - low) / (high - low)).clamp(0.0, 1.0);
+                }
+            }
+        }
+
+        into_color(DynamicImage::ImageRgba32F(working), image.color())
+    }
+
+    // Values at the clip percentiles
+    fn range(&self, histogram: &[u64; BINS]) -> (f32, f32) {
+        let total: u64 = histogram.iter().sum();
+        let clipped = (total as f64 * self.clip as f64 / 100.0) as u64;
+
+        let percentile = |mut bins: Box<dyn Iterator<Item = usize>>| {
+            let mut seen = 0;
+            bins.find(|&i| {
+                seen += histogram[i];
+                seen > clipped
+            })
+        };
+        let low = percentile(Box::new(0..BINS)).unwrap_or(0);
+        let high = percentile(Box::new((0..BINS).rev())).unwrap_or(BINS - 1);
+        (low as f32 / (BINS - 1) as f32, high as f32 / (BINS - 1) as f32)
+    }
+}
+
+fn bin(value: f32) -> usize {
+    (value.clamp(0.0, 1.0) * (BINS - 1) as f32).round() as usize
+}
@@ -1,18 +1,30 @@
 // pixie/src/processors/loader.rs
-use crate::core::{ImageToolError, Result};
-use image::{DynamicImage, ImageFormat, ImageReader, GenericImageView};
-use crate::utils::image_format_to_string;
+use crate::core::{ImageToolError, Result, SalvageMode};
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, GenericImageView, Limits};
+use crate::processors::cmyk::{decode_cmyk_jpeg, scan_jpeg, JpegColorInfo};
+use crate::processors::validate::{jpeg_damage, JpegDamage};
+use crate::utils::{image_format_to_string, sniff_format};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 
+// Files at least this large are memory-mapped by the `mmap` feature, so the
+// decoder reads straight from the page cache rather than through a copy
+#[cfg(feature = "mmap")]
+const MMAP_MIN_BYTES: u64 = 32 * 1024 * 1024;
+
 #[derive(Clone)]
 pub struct Loader {
     max_dimensions: Option<(u32, u32)>,
+    max_memory: Option<u64>,
+    salvage: Option<SalvageMode>,
 }
 
 impl Loader {
     pub fn new() -> Self {
         Self {
             max_dimensions: Some((100_000, 100_000)),
+            max_memory: None,
+            salvage: None,
         }
     }
 
@@ -21,18 +33,48 @@ impl Loader {
         self
     }
 
+    pub fn with_max_memory(mut self, bytes: u64) -> Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    /// Decode JPEGs that break off early rather than refusing them
+    pub fn with_salvage(mut self, salvage: Option<SalvageMode>) -> Self {
+        self.salvage = salvage;
+        self
+    }
+
     pub fn load(&self, path: &Path) -> Result<DynamicImage> {
         log::debug!("Loading image from: {}", path.display());
 
         self.validate_path(path)?;
+        let damage = self.jpeg_damage(path)?;
+        self.check_damage(&path.display().to_string(), damage.as_ref())?;
 
-        let image = ImageReader::open(path)?
-            .with_guessed_format()?
-            .decode()
-            .map_err(|e| {
-                ImageToolError::ProcessingError(format!("Failed to decode image: {}", e))
-            })?;
+        #[cfg(feature = "mmap")]
+        if let Some(map) = map_file(path)? {
+            let image = self.decode_bytes(&map)?;
+            return self.check_loaded(self.salvaged(image, damage)?);
+        }
+
+        let image = match self.cmyk_jpeg_info(path) {
+            Some(info) => decode_cmyk_jpeg(&std::fs::read(path)?, info)?,
+            None => {
+                let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+                reader.limits(self.decode_limits());
+
+                reader
+                    .decode()
+                    .map_err(|e| {
+                        ImageToolError::ProcessingError(format!("Failed to decode image: {}", e))
+                    })?
+            }
+        };
+
+        self.check_loaded(self.salvaged(image, damage)?)
+    }
 
+    fn check_loaded(&self, image: DynamicImage) -> Result<DynamicImage> {
         // Validate dimensions
         if let Some((max_w, max_h)) = self.max_dimensions {
             let (width, height) = image.dimensions();
@@ -56,7 +98,22 @@ impl Loader {
     }
 
     pub fn load_from_bytes(&self, data: &[u8]) -> Result<DynamicImage> {
-        let image = image::load_from_memory(data)
+        let damage = jpeg_damage(data);
+        self.check_damage("in memory", damage.as_ref())?;
+        let image = self.decode_bytes(data)?;
+        self.salvaged(image, damage)
+    }
+
+    fn decode_bytes(&self, data: &[u8]) -> Result<DynamicImage> {
+        if let Some(info) = scan_jpeg(data).ok().flatten().filter(|info| info.is_cmyk()) {
+            return decode_cmyk_jpeg(data, info);
+        }
+
+        let mut reader = ImageReader::new(std::io::Cursor::new(data)).with_guessed_format()?;
+        reader.limits(self.decode_limits());
+
+        let image = reader
+            .decode()
             .map_err(|e| {
                 ImageToolError::ProcessingError(format!("Failed to decode image from bytes: {}", e))
             })?;
@@ -64,6 +121,106 @@ impl Loader {
         Ok(image)
     }
 
+    // Lets the JPEG decoder skip DCT coefficients so it produces an image of
+    // 1/2, 1/4 or 1/8 size that is still at least `min_width`x`min_height`.
+    #[cfg(feature = "jpeg-scale")]
+    pub fn load_jpeg_scaled(&self, path: &Path, min_width: u32, min_height: u32) -> Result<DynamicImage> {
+        use jpeg_decoder::{Decoder, PixelFormat};
+
+        self.validate_path(path)?;
+
+        let file = std::fs::File::open(path)?;
+        let mut decoder = Decoder::new(std::io::BufReader::new(file));
+        let jpeg_error = |e: jpeg_decoder::Error| {
+            ImageToolError::ProcessingError(format!("Failed to decode JPEG: {}", e))
+        };
+
+        decoder.read_info().map_err(jpeg_error)?;
+        let requested_w = min_width.clamp(1, u16::MAX as u32) as u16;
+        let requested_h = min_height.clamp(1, u16::MAX as u32) as u16;
+        let (width, height) = decoder.scale(requested_w, requested_h).map_err(jpeg_error)?;
+
+        let pixels = decoder.decode().map_err(jpeg_error)?;
+        let info = decoder.info().ok_or_else(|| {
+            ImageToolError::ProcessingError("JPEG header missing after decode".to_string())
+        })?;
+
+        let (width, height) = (width as u32, height as u32);
+        let invalid = || ImageToolError::ProcessingError("JPEG buffer size mismatch".to_string());
+        let image = match info.pixel_format {
+            PixelFormat::L8 => DynamicImage::ImageLuma8(
+                image::ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?,
+            ),
+            PixelFormat::L16 => {
+                let samples = pixels
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect();
+                DynamicImage::ImageLuma16(
+                    image::ImageBuffer::from_raw(width, height, samples).ok_or_else(invalid)?,
+                )
+            }
+            PixelFormat::RGB24 => DynamicImage::ImageRgb8(
+                image::ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?,
+            ),
+            PixelFormat::CMYK32 => {
+                return Err(ImageToolError::UnsupportedFormat(
+                    "Scaled decoding of CMYK JPEG".to_string(),
+                ))
+            }
+        };
+
+        log::info!(
+            "Decoded JPEG at reduced size {}x{} (requested at least {}x{})",
+            width, height, min_width, min_height
+        );
+
+        Ok(image)
+    }
+
+    // Reads only the header, so the estimate is available before any pixel
+    // buffer is allocated.
+    pub fn estimate_memory(&self, path: &Path) -> Result<u64> {
+        let decoder = ImageReader::open(path)?
+            .with_guessed_format()?
+            .into_decoder()?;
+        Ok(decoder.total_bytes())
+    }
+
+    pub fn estimate_memory_from_bytes(&self, data: &[u8]) -> Result<u64> {
+        let decoder = ImageReader::new(std::io::Cursor::new(data))
+            .with_guessed_format()?
+            .into_decoder()?;
+        Ok(decoder.total_bytes())
+    }
+
+    pub fn check_memory_budget(&self, estimated: u64) -> Result<()> {
+        if let Some(limit) = self.max_memory {
+            if estimated > limit {
+                return Err(ImageToolError::MemoryLimitExceeded(
+                    format!("Decoding needs an estimated {} bytes, limit is {}", estimated, limit)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn max_memory(&self) -> Option<u64> {
+        self.max_memory
+    }
+
+    fn decode_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        if let Some((max_w, max_h)) = self.max_dimensions {
+            limits.max_image_width = Some(max_w);
+            limits.max_image_height = Some(max_h);
+        }
+        if let Some(max_memory) = self.max_memory {
+            limits.max_alloc = Some(max_memory);
+        }
+        limits
+    }
+
     pub fn get_dimensions_and_format(&self, path: &Path) -> Result<(u32, u32, String)> {
         let file = std::fs::File::open(path)?;
         let reader = image::io::Reader::new(std::io::BufReader::new(file))
@@ -78,11 +235,68 @@ impl Loader {
         Ok((dimensions.0, dimensions.1, format))
     }
 
+    /// Header info for four-component JPEGs, which the regular decoder
+    /// converts without regard for Adobe inversion or ICC profiles
+    pub fn cmyk_jpeg_info(&self, path: &Path) -> Option<JpegColorInfo> {
+        let file = std::fs::File::open(path).ok()?;
+        scan_jpeg(std::io::BufReader::new(file))
+            .ok()
+            .flatten()
+            .filter(|info| info.is_cmyk())
+    }
+
+    /// Where a JPEG file breaks off, if it does. Only files that don't end
+    /// in the end-of-image marker are read in full.
+    pub(crate) fn jpeg_damage(&self, path: &Path) -> Result<Option<JpegDamage>> {
+        let mut file = std::fs::File::open(path)?;
+        let mut start = [0u8; 2];
+        if file.read_exact(&mut start).is_err() || start != [0xFF, 0xD8] {
+            return Ok(None);
+        }
+        let mut end = [0u8; 2];
+        if file.seek(SeekFrom::End(-2)).is_ok() && file.read_exact(&mut end).is_ok() && end == [0xFF, 0xD9] {
+            return Ok(None);
+        }
+        Ok(jpeg_damage(&std::fs::read(path)?))
+    }
+
+    // Damaged JPEGs decode with the missing area filled in gray, which is
+    // only wanted when salvaging
+    fn check_damage(&self, source: &str, damage: Option<&JpegDamage>) -> Result<()> {
+        let Some(damage) = damage else {
+            return Ok(());
+        };
+        if self.salvage.is_none() {
+            return Err(ImageToolError::ProcessingError(format!(
+                "Damaged JPEG ({}): {}; pass --salvage to recover what is readable",
+                source, damage.defect
+            )));
+        }
+        log::warn!("Salvaging damaged JPEG ({}): {}", source, damage.defect);
+        Ok(())
+    }
+
+    fn salvaged(&self, image: DynamicImage, damage: Option<JpegDamage>) -> Result<DynamicImage> {
+        match (damage, self.salvage) {
+            (Some(damage), Some(SalvageMode::Crop)) => crop_missing(image, damage.mcu_height),
+            _ => Ok(image),
+        }
+    }
+
+    /// The format the file's content is in, falling back to its extension
+    /// for formats without magic bytes such as TGA
     pub fn detect_format(&self, path: &Path) -> Result<ImageFormat> {
-        let format = image::ImageFormat::from_path(path)
-            .map_err(|_| ImageToolError::ProcessingError(format!("Failed to detect format for: {}", path.display())))?;
-        
-        Ok(format)
+        let by_extension = image::ImageFormat::from_path(path).ok();
+        match (sniff_format(path), by_extension) {
+            (Some(sniffed), Some(named)) if sniffed != named => {
+                log::debug!("{} is {:?} despite its extension, reading it as such", path.display(), sniffed);
+                Ok(sniffed)
+            }
+            (Some(format), _) | (None, Some(format)) => Ok(format),
+            (None, None) => Err(ImageToolError::ProcessingError(
+                format!("Failed to detect format for: {}", path.display())
+            )),
+        }
     }
 
     fn validate_path(&self, path: &Path) -> Result<()> {
@@ -103,8 +317,52 @@ impl Loader {
     }
 }
 
+// The decoder fills everything after the break with one color, starting
+// from the bottom-right block, so rows of only that color are missing, and
+// so is the band of blocks the data broke off in
+fn crop_missing(image: DynamicImage, mcu_height: u32) -> Result<DynamicImage> {
+    let (width, height) = image.dimensions();
+    let fill = image.get_pixel(width - 1, height - 1);
+    let mut bottom = height;
+    while bottom > 0 && (0..width).all(|x| image.get_pixel(x, bottom - 1) == fill) {
+        bottom -= 1;
+    }
+
+    let mut kept = (bottom.div_ceil(mcu_height) * mcu_height).min(height);
+    if kept > 0 && image.get_pixel(width - 1, kept - 1) == fill {
+        kept = (kept - 1) / mcu_height * mcu_height;
+    }
+    if kept == 0 {
+        return Err(ImageToolError::ProcessingError(
+            "Nothing readable is left in the damaged JPEG".to_string(),
+        ));
+    }
+    log::info!("Cropped the damaged JPEG from {} to {} rows", height, kept);
+    Ok(image.crop_imm(0, 0, width, kept))
+}
+
 impl Default for Loader {
     fn default() -> Self {
         Self::new()
     }
+}
+
+#[cfg(feature = "mmap")]
+fn map_file(path: &Path) -> Result<Option<memmap2::Mmap>> {
+    let file = std::fs::File::open(path)?;
+    if file.metadata()?.len() < MMAP_MIN_BYTES {
+        return Ok(None);
+    }
+
+    // SAFETY: the map is only read, and only while decoding. Another process
+    // truncating the file meanwhile can still fault, as with any mapped
+    // reader; inputs are not expected to change under a running job.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    #[cfg(unix)]
+    if let Err(e) = map.advise(memmap2::Advice::Sequential) {
+        log::debug!("madvise failed for {}: {}", path.display(), e);
+    }
+
+    log::debug!("Memory-mapped {} ({} bytes)", path.display(), map.len());
+    Ok(Some(map))
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+// pixie/src/processors/lock.rs
+use crate::core::{ImageToolError, Result};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const LOCK_FILE: &str = ".pixie-lock";
+
+/// Marks an output directory as in use by one batch run, so runs started
+/// on a schedule can't overlap. The lock file holds the owner's process id
+/// and is removed again on drop.
+pub struct OutputLock {
+    path: PathBuf,
+}
+
+impl OutputLock {
+    /// Fails with `ImageToolError::Locked` while another running process
+    /// holds the lock. A lock whose owner has exited is taken over, and so
+    /// is any lock when `force` is set.
+    pub fn acquire(output_dir: &Path, force: bool) -> Result<Self> {
+        let path = output_dir.join(LOCK_FILE);
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id())?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let owner = read_owner(&path);
+                    if !force && owner.is_none_or(is_running) {
+                        let owner = owner.map(|pid| format!(" (process {})", pid)).unwrap_or_default();
+                        return Err(ImageToolError::Locked(format!(
+                            "{} is in use by another run{}; pass --force if it is not",
+                            output_dir.display(),
+                            owner
+                        )));
+                    }
+
+                    log::warn!("Taking over the lock on {}", output_dir.display());
+                    match std::fs::remove_file(&path) {
+                        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        // Leave the file alone if a forced run has taken it over since
+        if read_owner(&self.path) == Some(std::process::id()) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn read_owner(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks that the process exists; EPERM means it does,
+    // under another user
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    let exists = unsafe { libc::kill(pid, 0) } == 0;
+    exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Without a way to check, every lock counts as held
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
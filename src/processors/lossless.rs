@@ -0,0 +1,777 @@
+// pixie/src/processors/lossless.rs
+use crate::core::{ImageToolError, Result};
+
+// Natural (row-major) position of each coefficient in zigzag order
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61,
+    54, 47, 55, 62, 63,
+];
+
+// Marker for a restart in the list of symbols to encode
+const RESTART: u8 = 0xFF;
+
+/// Turns a JPEG upright for EXIF orientation `orientation` (2 to 8) by
+/// moving and sign-flipping its DCT coefficients, so no quality is lost.
+///
+/// Only baseline Huffman-coded JPEGs with all components in one scan are
+/// handled, and only when both sides are a whole number of MCUs, since a
+/// partial MCU at an edge would end up on the wrong side. Anything else
+/// gives None, for the caller to re-encode instead. APPn and COM segments
+/// are copied as they are; updating the Orientation tag is left to the
+/// caller.
+pub fn transform_jpeg(data: &[u8], orientation: u8) -> Result<Option<Vec<u8>>> {
+    let Some(transform) = Transform::from_exif(orientation) else {
+        return Ok(None);
+    };
+    let Some(mut jpeg) = Jpeg::parse(data)? else {
+        return Ok(None);
+    };
+    if !jpeg.is_mcu_aligned() {
+        log::debug!("{}x{} is not a whole number of MCUs, no lossless transform", jpeg.width, jpeg.height);
+        return Ok(None);
+    }
+    jpeg.transform(transform);
+    jpeg.encode().map(Some)
+}
+
+// Transpose first, then mirror within the transposed frame
+#[derive(Debug, Clone, Copy)]
+struct Transform {
+    transpose: bool,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl Transform {
+    fn from_exif(orientation: u8) -> Option<Self> {
+        let (transpose, flip_x, flip_y) = match orientation {
+            2 => (false, true, false),
+            3 => (false, true, true),
+            4 => (false, false, true),
+            5 => (true, false, false),
+            6 => (true, true, false),
+            7 => (true, true, true),
+            8 => (true, false, true),
+            _ => return None,
+        };
+        Some(Self { transpose, flip_x, flip_y })
+    }
+
+    fn block(&self, block: &[i16; 64]) -> [i16; 64] {
+        let mut turned = [0; 64];
+        for v in 0..8 {
+            for u in 0..8 {
+                let source = if self.transpose { u * 8 + v } else { v * 8 + u };
+                let negate = (self.flip_x && u % 2 == 1) != (self.flip_y && v % 2 == 1);
+                turned[v * 8 + u] = if negate { -block[source] } else { block[source] };
+            }
+        }
+        turned
+    }
+}
+
+struct Component {
+    id: u8,
+    h: u8,
+    v: u8,
+    quant_table: u8,
+    dc_table: u8,
+    ac_table: u8,
+    blocks_w: usize,
+    blocks_h: usize,
+    blocks: Vec<[i16; 64]>,
+}
+
+struct HuffmanTable {
+    counts: [u8; 16],
+    symbols: Vec<u8>,
+}
+
+struct Jpeg {
+    // APPn and COM segments, markers included
+    kept: Vec<u8>,
+    sof_marker: u8,
+    width: u16,
+    height: u16,
+    // Precision and natural-order values per table slot
+    quant_tables: [Option<(u8, [u16; 64])>; 4],
+    restart_interval: u16,
+    components: Vec<Component>,
+}
+
+impl Jpeg {
+    // None for JPEG variants this doesn't handle
+    fn parse(data: &[u8]) -> Result<Option<Self>> {
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return Err(corrupt("not a JPEG stream"));
+        }
+
+        let mut jpeg = Jpeg {
+            kept: Vec::new(),
+            sof_marker: 0,
+            width: 0,
+            height: 0,
+            quant_tables: [None; 4],
+            restart_interval: 0,
+            components: Vec::new(),
+        };
+        let mut dc_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut ac_tables: [Option<HuffmanTable>; 4] = Default::default();
+
+        let mut pos = 2;
+        loop {
+            while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+                pos += 1;
+            }
+            if data.get(pos) != Some(&0xFF) || pos + 4 > data.len() {
+                return Err(corrupt("expected a marker"));
+            }
+            let marker = data[pos + 1];
+            let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            let end = pos + 2 + len;
+            if len < 2 || end > data.len() {
+                return Err(corrupt("segment runs past the end"));
+            }
+            let payload = &data[pos + 4..end];
+
+            match marker {
+                0xE0..=0xEF | 0xFE => jpeg.kept.extend_from_slice(&data[pos..end]),
+                0xDB => jpeg.parse_dqt(payload)?,
+                0xC4 => parse_dht(payload, &mut dc_tables, &mut ac_tables)?,
+                0xDD if payload.len() >= 2 => jpeg.restart_interval = u16::from_be_bytes([payload[0], payload[1]]),
+                0xC0 | 0xC1 => {
+                    if !jpeg.parse_sof(marker, payload)? {
+                        return Ok(None);
+                    }
+                }
+                0xDA => {
+                    if jpeg.components.is_empty() || !jpeg.parse_sos(payload)? {
+                        return Ok(None);
+                    }
+                    let scan_end = jpeg.decode_scan(&data[end..], &dc_tables, &ac_tables)?;
+                    // A second scan means a multi-scan file, which isn't handled
+                    let rest = &data[end + scan_end..];
+                    let next = rest.windows(2).find(|pair| pair[0] == 0xFF && pair[1] != 0 && pair[1] != 0xFF);
+                    return Ok(next.filter(|pair| pair[1] == 0xD9).map(|_| jpeg));
+                }
+                // Progressive, lossless, arithmetic coding and anything unexpected
+                _ => return Ok(None),
+            }
+            pos = end;
+        }
+    }
+
+    fn parse_dqt(&mut self, mut payload: &[u8]) -> Result<()> {
+        while let Some(&info) = payload.first() {
+            let (precision, slot) = (info >> 4, (info & 0x0F) as usize);
+            let size = if precision == 0 { 64 } else { 128 };
+            if slot > 3 || payload.len() < 1 + size {
+                return Err(corrupt("bad quantization table"));
+            }
+            let mut table = [0u16; 64];
+            for (k, &position) in ZIGZAG.iter().enumerate() {
+                table[position] = match precision {
+                    0 => payload[1 + k] as u16,
+                    _ => u16::from_be_bytes([payload[1 + 2 * k], payload[2 + 2 * k]]),
+                };
+            }
+            self.quant_tables[slot] = Some((precision, table));
+            payload = &payload[1 + size..];
+        }
+        Ok(())
+    }
+
+    // False for frames this doesn't handle, such as 12-bit ones
+    fn parse_sof(&mut self, marker: u8, payload: &[u8]) -> Result<bool> {
+        if payload.len() < 6 {
+            return Err(corrupt("short frame header"));
+        }
+        let count = payload[5] as usize;
+        if payload[0] != 8 || count == 0 || count > 4 || payload.len() < 6 + 3 * count {
+            return Ok(false);
+        }
+        self.sof_marker = marker;
+        self.height = u16::from_be_bytes([payload[1], payload[2]]);
+        self.width = u16::from_be_bytes([payload[3], payload[4]]);
+        if self.width == 0 || self.height == 0 {
+            return Ok(false);
+        }
+
+        for i in 0..count {
+            let at = 6 + 3 * i;
+            let (h, v) = (payload[at + 1] >> 4, payload[at + 1] & 0x0F);
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || payload[at + 2] > 3 {
+                return Err(corrupt("bad component"));
+            }
+            self.components.push(Component {
+                id: payload[at],
+                // One block per MCU when there's only one component
+                h: if count == 1 { 1 } else { h },
+                v: if count == 1 { 1 } else { v },
+                quant_table: payload[at + 2],
+                dc_table: 0,
+                ac_table: 0,
+                blocks_w: 0,
+                blocks_h: 0,
+                blocks: Vec::new(),
+            });
+        }
+
+        let (mcus_x, mcus_y) = self.mcus();
+        for component in &mut self.components {
+            component.blocks_w = mcus_x * component.h as usize;
+            component.blocks_h = mcus_y * component.v as usize;
+            component.blocks = vec![[0; 64]; component.blocks_w * component.blocks_h];
+        }
+        Ok(true)
+    }
+
+    // False unless the scan holds every component in frame order
+    fn parse_sos(&mut self, payload: &[u8]) -> Result<bool> {
+        let count = *payload.first().ok_or_else(|| corrupt("short scan header"))? as usize;
+        if payload.len() < 4 + 2 * count {
+            return Err(corrupt("short scan header"));
+        }
+        if count != self.components.len() {
+            return Ok(false);
+        }
+        for (i, component) in self.components.iter_mut().enumerate() {
+            let (id, tables) = (payload[1 + 2 * i], payload[2 + 2 * i]);
+            if id != component.id {
+                return Ok(false);
+            }
+            component.dc_table = tables >> 4;
+            component.ac_table = tables & 0x0F;
+        }
+        let spectral = &payload[1 + 2 * count..];
+        Ok(spectral[0] == 0 && spectral[1] == 63 && spectral[2] == 0)
+    }
+
+    fn mcu_size(&self) -> (usize, usize) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1) as usize;
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1) as usize;
+        (8 * h, 8 * v)
+    }
+
+    fn mcus(&self) -> (usize, usize) {
+        let (mcu_w, mcu_h) = self.mcu_size();
+        ((self.width as usize).div_ceil(mcu_w), (self.height as usize).div_ceil(mcu_h))
+    }
+
+    fn is_mcu_aligned(&self) -> bool {
+        let (mcu_w, mcu_h) = self.mcu_size();
+        (self.width as usize).is_multiple_of(mcu_w) && (self.height as usize).is_multiple_of(mcu_h)
+    }
+
+    // Returns how many bytes of `data` the entropy-coded segment took
+    fn decode_scan(
+        &mut self,
+        data: &[u8],
+        dc_tables: &[Option<HuffmanTable>; 4],
+        ac_tables: &[Option<HuffmanTable>; 4],
+    ) -> Result<usize> {
+        fn lookup(tables: &[Option<HuffmanTable>; 4], slot: u8) -> Result<Decoding<'_>> {
+            tables[slot as usize].as_ref().map(Decoding::new).ok_or_else(|| corrupt("missing Huffman table"))
+        }
+        let decoders = self
+            .components
+            .iter()
+            .map(|c| Ok((lookup(dc_tables, c.dc_table)?, lookup(ac_tables, c.ac_table)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let (mcus_x, mcus_y) = self.mcus();
+        let mut reader = BitReader::new(data);
+        let mut predictions = vec![0i32; self.components.len()];
+        for mcu in 0..mcus_x * mcus_y {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval as usize == 0 {
+                reader.restart()?;
+                predictions.iter_mut().for_each(|p| *p = 0);
+            }
+            let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+            for (index, component) in self.components.iter_mut().enumerate() {
+                let (dc, ac) = &decoders[index];
+                for by in 0..component.v as usize {
+                    for bx in 0..component.h as usize {
+                        let x = mcu_x * component.h as usize + bx;
+                        let y = mcu_y * component.v as usize + by;
+                        let block = &mut component.blocks[y * component.blocks_w + x];
+
+                        let size = dc.decode(&mut reader)?;
+                        predictions[index] += extend(reader.bits(size)?, size);
+                        block[0] = predictions[index] as i16;
+                        let mut k = 1;
+                        while k < 64 {
+                            let symbol = ac.decode(&mut reader)?;
+                            let (run, size) = (symbol >> 4, symbol & 0x0F);
+                            if size == 0 {
+                                if run != 15 {
+                                    break;
+                                }
+                                k += 16;
+                                continue;
+                            }
+                            k += run as usize;
+                            if k > 63 {
+                                return Err(corrupt("coefficient out of range"));
+                            }
+                            block[ZIGZAG[k]] = extend(reader.bits(size)?, size) as i16;
+                            k += 1;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(reader.pos)
+    }
+
+    fn transform(&mut self, transform: Transform) {
+        if transform.transpose {
+            std::mem::swap(&mut self.width, &mut self.height);
+            for (_, table) in self.quant_tables.iter_mut().flatten() {
+                let original = *table;
+                for v in 0..8 {
+                    for u in 0..8 {
+                        table[v * 8 + u] = original[u * 8 + v];
+                    }
+                }
+            }
+        }
+
+        for component in &mut self.components {
+            let (old_w, old_h) = (component.blocks_w, component.blocks_h);
+            let (new_w, new_h) = if transform.transpose { (old_h, old_w) } else { (old_w, old_h) };
+            let mut blocks = vec![[0; 64]; component.blocks.len()];
+            for y in 0..old_h {
+                for x in 0..old_w {
+                    let (mut nx, mut ny) = if transform.transpose { (y, x) } else { (x, y) };
+                    if transform.flip_x {
+                        nx = new_w - 1 - nx;
+                    }
+                    if transform.flip_y {
+                        ny = new_h - 1 - ny;
+                    }
+                    blocks[ny * new_w + nx] = transform.block(&component.blocks[y * old_w + x]);
+                }
+            }
+            component.blocks = blocks;
+            component.blocks_w = new_w;
+            component.blocks_h = new_h;
+            if transform.transpose {
+                std::mem::swap(&mut component.h, &mut component.v);
+            }
+        }
+    }
+
+    // Huffman tables are rebuilt for the new symbol counts, since the
+    // transposed blocks have different zero runs
+    fn encode(&self) -> Result<Vec<u8>> {
+        // (table slot, symbol, extra bits, extra bit count); DC slots are
+        // 0-3 and AC slots 4-7
+        let mut symbols: Vec<(u8, u8, u16, u8)> = Vec::new();
+        let (mcus_x, mcus_y) = self.mcus();
+        let mut predictions = vec![0i32; self.components.len()];
+        for mcu in 0..mcus_x * mcus_y {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval as usize == 0 {
+                symbols.push((RESTART, 0, 0, 0));
+                predictions.iter_mut().for_each(|p| *p = 0);
+            }
+            let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+            for (index, component) in self.components.iter().enumerate() {
+                let (dc_slot, ac_slot) = (component.dc_table, 4 + component.ac_table);
+                for by in 0..component.v as usize {
+                    for bx in 0..component.h as usize {
+                        let x = mcu_x * component.h as usize + bx;
+                        let y = mcu_y * component.v as usize + by;
+                        let block = &component.blocks[y * component.blocks_w + x];
+
+                        let diff = block[0] as i32 - predictions[index];
+                        predictions[index] = block[0] as i32;
+                        let (size, bits) = magnitude(diff);
+                        symbols.push((dc_slot, size, bits, size));
+
+                        let mut run = 0;
+                        for &position in &ZIGZAG[1..] {
+                            let value = block[position] as i32;
+                            if value == 0 {
+                                run += 1;
+                                continue;
+                            }
+                            while run > 15 {
+                                symbols.push((ac_slot, 0xF0, 0, 0));
+                                run -= 16;
+                            }
+                            let (size, bits) = magnitude(value);
+                            symbols.push((ac_slot, (run << 4) | size, bits, size));
+                            run = 0;
+                        }
+                        if run > 0 {
+                            symbols.push((ac_slot, 0x00, 0, 0));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut frequencies = vec![[0u32; 256]; 8];
+        for &(slot, symbol, _, _) in &symbols {
+            if slot != RESTART {
+                frequencies[slot as usize][symbol as usize] += 1;
+            }
+        }
+        let tables: Vec<Option<HuffmanTable>> = frequencies
+            .iter()
+            .map(|counts| counts.iter().any(|&n| n > 0).then(|| HuffmanTable::optimal(counts)))
+            .collect();
+        let codes: Vec<Option<[(u16, u8); 256]>> =
+            tables.iter().map(|table| table.as_ref().map(HuffmanTable::codes)).collect();
+
+        let mut output = vec![0xFF, 0xD8];
+        output.extend_from_slice(&self.kept);
+        for (slot, (precision, table)) in self.quant_tables.iter().enumerate().filter_map(|(i, t)| Some((i, (*t)?))) {
+            let mut payload = vec![(precision << 4) | slot as u8];
+            for &position in &ZIGZAG {
+                match precision {
+                    0 => payload.push(table[position] as u8),
+                    _ => payload.extend_from_slice(&table[position].to_be_bytes()),
+                }
+            }
+            push_segment(&mut output, 0xDB, &payload);
+        }
+
+        let mut frame = vec![8];
+        frame.extend_from_slice(&self.height.to_be_bytes());
+        frame.extend_from_slice(&self.width.to_be_bytes());
+        frame.push(self.components.len() as u8);
+        for component in &self.components {
+            frame.extend_from_slice(&[component.id, (component.h << 4) | component.v, component.quant_table]);
+        }
+        push_segment(&mut output, self.sof_marker, &frame);
+
+        let mut huffman = Vec::new();
+        for (slot, table) in tables.iter().enumerate().filter_map(|(i, t)| Some((i, t.as_ref()?))) {
+            let class = if slot < 4 { slot as u8 } else { 0x10 | (slot as u8 - 4) };
+            huffman.push(class);
+            huffman.extend_from_slice(&table.counts);
+            huffman.extend_from_slice(&table.symbols);
+        }
+        push_segment(&mut output, 0xC4, &huffman);
+
+        if self.restart_interval > 0 {
+            push_segment(&mut output, 0xDD, &self.restart_interval.to_be_bytes());
+        }
+
+        let mut scan = vec![self.components.len() as u8];
+        for component in &self.components {
+            scan.extend_from_slice(&[component.id, (component.dc_table << 4) | component.ac_table]);
+        }
+        scan.extend_from_slice(&[0, 63, 0]);
+        push_segment(&mut output, 0xDA, &scan);
+
+        let mut writer = BitWriter::new(output);
+        let mut restarts = 0u8;
+        for &(slot, symbol, bits, size) in &symbols {
+            if slot == RESTART {
+                writer.flush();
+                writer.output.extend_from_slice(&[0xFF, 0xD0 + restarts % 8]);
+                restarts = restarts.wrapping_add(1);
+                continue;
+            }
+            let (code, length) = codes[slot as usize].as_ref().map(|codes| codes[symbol as usize]).unwrap_or((0, 0));
+            writer.write(code as u32, length);
+            writer.write(bits as u32, size);
+        }
+        writer.flush();
+        let mut output = writer.output;
+        output.extend_from_slice(&[0xFF, 0xD9]);
+        Ok(output)
+    }
+}
+
+fn parse_dht(
+    mut payload: &[u8],
+    dc_tables: &mut [Option<HuffmanTable>; 4],
+    ac_tables: &mut [Option<HuffmanTable>; 4],
+) -> Result<()> {
+    while let Some(&info) = payload.first() {
+        let (class, slot) = (info >> 4, (info & 0x0F) as usize);
+        if slot > 3 || class > 1 || payload.len() < 17 {
+            return Err(corrupt("bad Huffman table"));
+        }
+        let mut counts = [0u8; 16];
+        counts.copy_from_slice(&payload[1..17]);
+        let total: usize = counts.iter().map(|&n| n as usize).sum();
+        if payload.len() < 17 + total {
+            return Err(corrupt("bad Huffman table"));
+        }
+        let table = HuffmanTable { counts, symbols: payload[17..17 + total].to_vec() };
+        match class {
+            0 => dc_tables[slot] = Some(table),
+            _ => ac_tables[slot] = Some(table),
+        }
+        payload = &payload[17 + total..];
+    }
+    Ok(())
+}
+
+impl HuffmanTable {
+    // Code lengths from symbol counts, limited to 16 bits and with no
+    // all-ones code, as in ITU T.81 Annex K.2
+    fn optimal(frequencies: &[u32; 256]) -> Self {
+        let mut freq = [0u64; 257];
+        for (i, &n) in frequencies.iter().enumerate() {
+            freq[i] = n as u64;
+        }
+        // Reserves the all-ones code
+        freq[256] = 1;
+        let mut code_size = [0usize; 257];
+        let mut others = [usize::MAX; 257];
+
+        loop {
+            let smallest = |skip: Option<usize>| {
+                let mut found: Option<usize> = None;
+                for i in 0..257 {
+                    if freq[i] > 0 && Some(i) != skip && found.is_none_or(|f| freq[i] <= freq[f]) {
+                        found = Some(i);
+                    }
+                }
+                found
+            };
+            let Some(mut c1) = smallest(None) else { break };
+            let Some(mut c2) = smallest(Some(c1)) else { break };
+
+            freq[c1] += freq[c2];
+            freq[c2] = 0;
+            code_size[c1] += 1;
+            while others[c1] != usize::MAX {
+                c1 = others[c1];
+                code_size[c1] += 1;
+            }
+            others[c1] = c2;
+            code_size[c2] += 1;
+            while others[c2] != usize::MAX {
+                c2 = others[c2];
+                code_size[c2] += 1;
+            }
+        }
+
+        let mut bits = [0usize; 33];
+        for &size in code_size.iter().filter(|&&size| size > 0) {
+            bits[size.min(32)] += 1;
+        }
+        for i in (17..=32).rev() {
+            while bits[i] > 0 {
+                let mut j = i - 2;
+                while bits[j] == 0 {
+                    j -= 1;
+                }
+                bits[i] -= 2;
+                bits[i - 1] += 1;
+                bits[j + 1] += 2;
+                bits[j] -= 1;
+            }
+        }
+        // Takes the reserved code back out
+        let mut longest = 16;
+        while bits[longest] == 0 {
+            longest -= 1;
+        }
+        bits[longest] -= 1;
+
+        let mut symbols = Vec::new();
+        for size in 1..=32 {
+            for (symbol, _) in code_size[..256].iter().enumerate().filter(|(_, &s)| s == size) {
+                symbols.push(symbol as u8);
+            }
+        }
+        let mut counts = [0u8; 16];
+        for (i, count) in counts.iter_mut().enumerate() {
+            *count = bits[i + 1] as u8;
+        }
+        Self { counts, symbols }
+    }
+
+    // Canonical (code, length) per symbol
+    fn codes(&self) -> [(u16, u8); 256] {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0u16;
+        let mut symbols = self.symbols.iter();
+        for (i, &count) in self.counts.iter().enumerate() {
+            for _ in 0..count {
+                if let Some(&symbol) = symbols.next() {
+                    codes[symbol as usize] = (code, i as u8 + 1);
+                }
+                code += 1;
+            }
+            code <<= 1;
+        }
+        codes
+    }
+}
+
+// Lookup for decoding one table, per ITU T.81 Annex F.2.2.3
+struct Decoding<'a> {
+    max_code: [i32; 17],
+    min_code: [i32; 17],
+    first_index: [usize; 17],
+    symbols: &'a [u8],
+}
+
+impl<'a> Decoding<'a> {
+    fn new(table: &'a HuffmanTable) -> Self {
+        let mut decoding = Self { max_code: [-1; 17], min_code: [0; 17], first_index: [0; 17], symbols: &table.symbols };
+        let (mut code, mut index) = (0i32, 0usize);
+        for length in 1..=16 {
+            let count = table.counts[length - 1] as usize;
+            if count > 0 {
+                decoding.first_index[length] = index;
+                decoding.min_code[length] = code;
+                code += count as i32;
+                index += count;
+                decoding.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        decoding
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | reader.bit()? as i32;
+            if code <= self.max_code[length] {
+                let index = self.first_index[length] + (code - self.min_code[length]) as usize;
+                return self.symbols.get(index).copied().ok_or_else(|| corrupt("bad Huffman code"));
+            }
+        }
+        Err(corrupt("bad Huffman code"))
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    byte: u8,
+    left: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, byte: 0, left: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u8> {
+        if self.left == 0 {
+            self.byte = self.next_byte()?;
+            self.left = 8;
+        }
+        self.left -= 1;
+        Ok((self.byte >> self.left) & 1)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<i32> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()? as i32;
+        }
+        Ok(value)
+    }
+
+    // Stuffed 0xFF 0x00 pairs are one 0xFF byte; at a marker the scan ran
+    // short, which decoders fill with zero bits
+    fn next_byte(&mut self) -> Result<u8> {
+        let byte = *self.data.get(self.pos).ok_or_else(|| corrupt("scan data runs past the end"))?;
+        if byte != 0xFF {
+            self.pos += 1;
+            return Ok(byte);
+        }
+        match self.data.get(self.pos + 1) {
+            Some(0x00) => {
+                self.pos += 2;
+                Ok(0xFF)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn restart(&mut self) -> Result<()> {
+        self.left = 0;
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([0xFF, marker]) if (0xD0..=0xD7).contains(marker) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(corrupt("missing restart marker")),
+        }
+    }
+}
+
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn new(output: Vec<u8>) -> Self {
+        Self { output, buffer: 0, count: 0 }
+    }
+
+    fn write(&mut self, bits: u32, count: u8) {
+        for i in (0..count).rev() {
+            self.buffer = (self.buffer << 1) | ((bits >> i) & 1);
+            self.count += 1;
+            if self.count == 8 {
+                self.push_byte();
+            }
+        }
+    }
+
+    // Pads the last byte with one bits
+    fn flush(&mut self) {
+        while self.count != 0 {
+            self.buffer = (self.buffer << 1) | 1;
+            self.count += 1;
+            if self.count == 8 {
+                self.push_byte();
+            }
+        }
+    }
+
+    fn push_byte(&mut self) {
+        let byte = self.buffer as u8;
+        self.output.push(byte);
+        if byte == 0xFF {
+            self.output.push(0x00);
+        }
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// Size category and extra bits of a coefficient, per ITU T.81 F.1.2.1
+fn magnitude(value: i32) -> (u8, u16) {
+    let size = (32 - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 { value - 1 } else { value };
+    (size, (bits & ((1 << size) - 1)) as u16)
+}
+
+fn extend(value: i32, size: u8) -> i32 {
+    if size == 0 {
+        0
+    } else if value < 1 << (size - 1) {
+        value - (1 << size) + 1
+    } else {
+        value
+    }
+}
+
+fn push_segment(output: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    output.extend_from_slice(&[0xFF, marker]);
+    output.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+    output.extend_from_slice(payload);
+}
+
+fn corrupt(reason: &str) -> ImageToolError {
+    ImageToolError::ProcessingError(format!("Lossless JPEG transform: {}", reason))
+}
@@ -0,0 +1,108 @@
+// pixie/src/processors/lqip.rs
+use crate::core::processor::ImageProcessor;
+use crate::core::{ProcessConfig, Result};
+use crate::processors::{Compressor, Loader};
+use crate::utils::{base64_encode, format_extension};
+use image::ImageFormat;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Lqip {
+    pub width: u32,
+    pub height: u32,
+    pub format: ImageFormat,
+    pub path: PathBuf,
+    /// The encoded file, for inlining
+    pub data: Vec<u8>,
+}
+
+impl Lqip {
+    pub fn data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.format.to_mime_type(), base64_encode(&self.data))
+    }
+
+    /// A rule showing the placeholder as a stretched background until the
+    /// real image loads on top of it
+    pub fn to_css(&self, selector: &str) -> String {
+        format!(
+            "{} {{\n  background-image: url(\"{}\");\n  background-size: cover;\n  aspect-ratio: {} / {};\n}}\n",
+            selector,
+            self.data_uri(),
+            self.width,
+            self.height
+        )
+    }
+}
+
+/// Renders a tiny, blurred, heavily compressed low-quality image placeholder
+/// through the same resize path as srcset generation
+pub struct LqipGenerator {
+    config: ProcessConfig,
+    width: u32,
+    blur: f32,
+    format: ImageFormat,
+}
+
+impl LqipGenerator {
+    pub fn new(config: ProcessConfig) -> Self {
+        Self {
+            config,
+            width: 20,
+            blur: 1.0,
+            format: ImageFormat::Jpeg,
+        }
+    }
+
+    pub fn with_width(mut self, width: u32) -> Self {
+        self.width = width.max(1);
+        self
+    }
+
+    /// Gaussian blur sigma in placeholder pixels; 0 disables it
+    pub fn with_blur(mut self, sigma: f32) -> Self {
+        self.blur = sigma.max(0.0);
+        self
+    }
+
+    pub fn with_format(mut self, format: ImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Writes `{stem}-lqip.{ext}` into `output_dir`
+    pub fn generate(&self, input_path: &Path, output_dir: &Path) -> Result<Lqip> {
+        std::fs::create_dir_all(output_dir)?;
+
+        let processor = ImageProcessor::new(self.config.clone());
+        let image = processor.orient(Loader::new().load(input_path)?, input_path);
+        let image = processor.redact(processor.tone_map(image))?;
+
+        // Inputs narrower than the placeholder are only blurred
+        let width = self.width.min(image.width());
+        let (_, small) = processor
+            .render_sizes(&image, &[width])?
+            .pop()
+            .expect("one size was requested");
+        let small = if self.blur > 0.0 { small.blur(self.blur) } else { small };
+
+        let stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("image");
+        let path = output_dir.join(format!("{}-lqip.{}", stem, format_extension(self.format)));
+        Compressor::new(self.config.quality)
+            .with_speed(self.config.speed)
+            .save_with_format(&small, &path, self.format)?;
+
+        let data = std::fs::read(&path)?;
+        log::debug!("LQIP {}x{}: {} bytes", small.width(), small.height(), data.len());
+
+        Ok(Lqip {
+            width: small.width(),
+            height: small.height(),
+            format: self.format,
+            path,
+            data,
+        })
+    }
+}
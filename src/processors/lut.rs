@@ -0,0 +1,126 @@
+// pixie/src/processors/lut.rs
+use crate::core::{ImageToolError, Result};
+use crate::utils::into_color;
+use image::DynamicImage;
+use std::path::Path;
+
+/// A 3D color lookup table in the Adobe/Resolve `.cube` format, applied
+/// with trilinear interpolation.
+#[derive(Debug, Clone)]
+pub struct Lut3d {
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    /// Red varies fastest, then green, then blue
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut3d {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| match e {
+            ImageToolError::InvalidParameter(message) => {
+                ImageToolError::InvalidParameter(format!("{}: {}", path.display(), message))
+            }
+            other => other,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = |message: String| ImageToolError::InvalidParameter(message);
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let keyword = fields.next().unwrap_or_default();
+            let triple = |fields: std::str::SplitWhitespace| -> Result<[f32; 3]> {
+                let values: Vec<f32> = fields
+                    .map(|v| v.parse().map_err(|_| invalid(format!("bad number on line {}", number + 1))))
+                    .collect::<Result<_>>()?;
+                values
+                    .try_into()
+                    .map_err(|_| invalid(format!("expected three values on line {}", number + 1)))
+            };
+
+            match keyword {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let n: usize = fields
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|n| (2..=256).contains(n))
+                        .ok_or_else(|| invalid("LUT_3D_SIZE must be between 2 and 256".to_string()))?;
+                    size = Some(n);
+                    table.reserve(n * n * n);
+                }
+                "LUT_1D_SIZE" => return Err(invalid("1D LUTs are not supported".to_string())),
+                "DOMAIN_MIN" => domain_min = triple(fields)?,
+                "DOMAIN_MAX" => domain_max = triple(fields)?,
+                _ if keyword.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') => {
+                    table.push(triple(line.split_whitespace())?);
+                }
+                // Vendor extensions such as LUT_3D_INPUT_RANGE
+                _ => log::debug!("Ignoring .cube keyword {}", keyword),
+            }
+        }
+
+        let size = size.ok_or_else(|| invalid("missing LUT_3D_SIZE".to_string()))?;
+        if table.len() != size * size * size {
+            return Err(invalid(format!(
+                "expected {} entries for a {}-point LUT, found {}",
+                size * size * size,
+                size,
+                table.len()
+            )));
+        }
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err(invalid("DOMAIN_MAX must be above DOMAIN_MIN".to_string()));
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let mut working = image.to_rgba32f();
+        for pixel in working.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let [r, g, b] = self.lookup([r, g, b]);
+            pixel.0 = [r, g, b, a];
+        }
+        into_color(DynamicImage::ImageRgba32F(working), image.color())
+    }
+
+    fn lookup(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        // Grid position and the fraction towards the next grid point, per axis
+        let axes: [(usize, usize, f32); 3] = std::array::from_fn(|c| {
+            let t = (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]);
+            let position = t.clamp(0.0, 1.0) * last;
+            let lower = (position.floor() as usize).min(self.size - 2);
+            (lower, lower + 1, position - lower as f32)
+        });
+
+        let at = |r: usize, g: usize, b: usize| self.table[(b * self.size + g) * self.size + r];
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| -> [f32; 3] { std::array::from_fn(|c| a[c] + (b[c] - a[c]) * t) };
+
+        let [(r0, r1, tr), (g0, g1, tg), (b0, b1, tb)] = axes;
+        let c00 = lerp(at(r0, g0, b0), at(r1, g0, b0), tr);
+        let c10 = lerp(at(r0, g1, b0), at(r1, g1, b0), tr);
+        let c01 = lerp(at(r0, g0, b1), at(r1, g0, b1), tr);
+        let c11 = lerp(at(r0, g1, b1), at(r1, g1, b1), tr);
+        lerp(lerp(c00, c10, tg), lerp(c01, c11, tg), tb)
+    }
+}
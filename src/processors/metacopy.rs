@@ -0,0 +1,396 @@
+// pixie/src/processors/metacopy.rs
+use crate::core::{ImageToolError, Result};
+use crate::processors::dpi::write_png_chunk;
+use crate::processors::MetadataProcessor;
+use exif::{In, Tag, Value};
+use flate2::write::ZlibEncoder;
+use image::{ImageDecoder, ImageFormat, ImageReader};
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+// Largest APPn payload once the marker and length are taken off
+const MAX_SEGMENT: usize = u16::MAX as usize - 2;
+
+/// Which kinds of metadata `MetadataCopier::copy` carried over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyReport {
+    pub exif: bool,
+    pub xmp: bool,
+    pub icc: bool,
+}
+
+impl CopyReport {
+    pub fn is_empty(&self) -> bool {
+        !(self.exif || self.xmp || self.icc)
+    }
+}
+
+/// Transplants EXIF, XMP and the ICC profile from one image into another,
+/// e.g. back onto an output of a tool that dropped them. The source can
+/// be anything `image` decodes; the destination is rewritten in place
+/// without re-encoding and must be a JPEG, PNG or WebP.
+pub struct MetadataCopier {
+    exif: bool,
+    xmp: bool,
+    icc: bool,
+    metadata: MetadataProcessor,
+}
+
+#[derive(Default)]
+struct Metadata {
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+}
+
+impl MetadataCopier {
+    pub fn new() -> Self {
+        Self { exif: true, xmp: true, icc: true, metadata: MetadataProcessor::new() }
+    }
+
+    pub fn with_exif(mut self, exif: bool) -> Self {
+        self.exif = exif;
+        self
+    }
+
+    pub fn with_xmp(mut self, xmp: bool) -> Self {
+        self.xmp = xmp;
+        self
+    }
+
+    pub fn with_icc(mut self, icc: bool) -> Self {
+        self.icc = icc;
+        self
+    }
+
+    /// Kinds the source doesn't have are left as they are in `destination`
+    pub fn copy(&self, source: &Path, destination: &Path) -> Result<CopyReport> {
+        let found = self.read(source)?;
+        let metadata = Metadata {
+            exif: found.exif.filter(|_| self.exif),
+            xmp: found.xmp.filter(|_| self.xmp),
+            icc: found.icc.filter(|_| self.icc),
+        };
+        let report = CopyReport {
+            exif: metadata.exif.is_some(),
+            xmp: metadata.xmp.is_some(),
+            icc: metadata.icc.is_some(),
+        };
+        if report.is_empty() {
+            return Ok(report);
+        }
+
+        let data = std::fs::read(destination)?;
+        let written = match image::guess_format(&data) {
+            Ok(ImageFormat::Jpeg) => self.write_jpeg(&data, &metadata)?,
+            Ok(ImageFormat::Png) => write_png(&data, &metadata)?,
+            Ok(ImageFormat::WebP) => write_webp(&data, &metadata)?,
+            Ok(format) => {
+                return Err(ImageToolError::UnsupportedFormat(format!(
+                    "Copying metadata into {:?} is not supported, only JPEG, PNG and WebP",
+                    format
+                )))
+            }
+            Err(_) => {
+                return Err(ImageToolError::UnsupportedFormat(format!(
+                    "{} is not a recognized image",
+                    destination.display()
+                )))
+            }
+        };
+
+        let file_name = destination.file_name().unwrap_or_default().to_string_lossy();
+        let part = destination.with_file_name(format!(".pixie-part-{}", file_name));
+        let replaced = std::fs::write(&part, &written)
+            .and_then(|()| std::fs::set_permissions(&part, std::fs::metadata(destination)?.permissions()))
+            .and_then(|()| std::fs::rename(&part, destination));
+        if let Err(e) = replaced {
+            let _ = std::fs::remove_file(&part);
+            return Err(e.into());
+        }
+        Ok(report)
+    }
+
+    fn read(&self, source: &Path) -> Result<Metadata> {
+        let mut decoder = ImageReader::open(source)?.with_guessed_format()?.into_decoder()?;
+        let icc = decoder.icc_profile()?;
+        let xmp = decoder.xmp_metadata()?;
+        let exif = match decoder.exif_metadata()? {
+            Some(exif) => Some(exif.strip_prefix(EXIF_HEADER).map(<[u8]>::to_vec).unwrap_or(exif)),
+            // TIFF keeps EXIF among its own tags; take the descriptive ones
+            None => match self.metadata.read_metadata(source) {
+                Ok(Some(exif)) => self.descriptive_exif(&exif)?,
+                _ => None,
+            },
+        };
+        Ok(Metadata { exif, xmp, icc })
+    }
+
+    // Re-encodes the primary IFD without the tags describing the source's
+    // own pixel data, which would be wrong for any other file
+    fn descriptive_exif(&self, exif: &exif::Exif) -> Result<Option<Vec<u8>>> {
+        use exif::experimental::Writer;
+
+        let mut writer = Writer::new();
+        let mut kept = 0;
+        for field in exif.fields() {
+            let structural = matches!(
+                field.tag,
+                Tag::ImageWidth
+                    | Tag::ImageLength
+                    | Tag::BitsPerSample
+                    | Tag::Compression
+                    | Tag::PhotometricInterpretation
+                    | Tag::StripOffsets
+                    | Tag::SamplesPerPixel
+                    | Tag::RowsPerStrip
+                    | Tag::StripByteCounts
+                    | Tag::PlanarConfiguration
+                    | Tag::JPEGInterchangeFormat
+                    | Tag::JPEGInterchangeFormatLength
+            );
+            let known = field.tag.description().is_some();
+            if field.ifd_num != In::PRIMARY || structural || !known || matches!(field.value, Value::Unknown(..)) {
+                continue;
+            }
+            if !matches!(field.tag, Tag::ExifIFDPointer | Tag::GPSInfoIFDPointer | Tag::InteropIFDPointer) {
+                kept += 1;
+            }
+            writer.push_field(field);
+        }
+        if kept == 0 {
+            return Ok(None);
+        }
+
+        let mut buffer = Cursor::new(Vec::new());
+        writer
+            .write(&mut buffer, exif.little_endian())
+            .map_err(|e| ImageToolError::ProcessingError(format!("EXIF write error: {}", e)))?;
+        Ok(Some(buffer.into_inner()))
+    }
+
+    fn write_jpeg(&self, jpeg: &[u8], metadata: &Metadata) -> Result<Vec<u8>> {
+        let jpeg = match &metadata.exif {
+            Some(tiff) => self.metadata.embed_exif_jpeg(jpeg, tiff)?,
+            None => jpeg.to_vec(),
+        };
+
+        let mut segments = Vec::new();
+        if let Some(xmp) = &metadata.xmp {
+            if XMP_HEADER.len() + xmp.len() > MAX_SEGMENT {
+                return Err(ImageToolError::ProcessingError(format!(
+                    "XMP packet of {} bytes does not fit in a JPEG APP1 segment",
+                    xmp.len()
+                )));
+            }
+            segments.push(jpeg_segment(0xE1, &[XMP_HEADER, xmp]));
+        }
+        if let Some(icc) = &metadata.icc {
+            // Split over numbered APP2 segments
+            let chunks: Vec<&[u8]> = icc.chunks(MAX_SEGMENT - ICC_HEADER.len() - 2).collect();
+            if chunks.len() > u8::MAX as usize {
+                return Err(ImageToolError::ProcessingError("ICC profile too large for a JPEG".to_string()));
+            }
+            for (i, chunk) in chunks.iter().enumerate() {
+                segments.push(jpeg_segment(0xE2, &[ICC_HEADER, &[i as u8 + 1, chunks.len() as u8], chunk]));
+            }
+        }
+
+        let replaced = |marker: u8, payload: &[u8]| {
+            (marker == 0xE1 && metadata.xmp.is_some() && payload.starts_with(XMP_HEADER))
+                || (marker == 0xE2 && metadata.icc.is_some() && payload.starts_with(ICC_HEADER))
+        };
+        splice_jpeg(&jpeg, replaced, &segments)
+    }
+}
+
+impl Default for MetadataCopier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn jpeg_segment(marker: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let len: usize = parts.iter().map(|part| part.len()).sum::<usize>() + 2;
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&(len as u16).to_be_bytes());
+    for part in parts {
+        segment.extend_from_slice(part);
+    }
+    segment
+}
+
+// Drops the leading APPn segments `replaced` picks out and puts `segments`
+// after JFIF and Exif, which readers expect first
+fn splice_jpeg(jpeg: &[u8], replaced: impl Fn(u8, &[u8]) -> bool, segments: &[Vec<u8>]) -> Result<Vec<u8>> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err(ImageToolError::ProcessingError("Not a JPEG stream".to_string()));
+    }
+
+    let mut output = Vec::with_capacity(jpeg.len() + segments.iter().map(Vec::len).sum::<usize>());
+    output.extend_from_slice(&jpeg[..2]);
+    let mut inserted = false;
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        let marker = jpeg[pos + 1];
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let end = (pos + 2 + len).min(jpeg.len());
+        let payload = &jpeg[(pos + 4).min(end)..end];
+
+        let leading = marker == 0xE0 || (marker == 0xE1 && payload.starts_with(EXIF_HEADER));
+        if !leading && !inserted {
+            segments.iter().for_each(|segment| output.extend_from_slice(segment));
+            inserted = true;
+        }
+        if !replaced(marker, payload) {
+            output.extend_from_slice(&jpeg[pos..end]);
+        }
+        pos = end;
+    }
+    if !inserted {
+        segments.iter().for_each(|segment| output.extend_from_slice(segment));
+    }
+    output.extend_from_slice(&jpeg[pos..]);
+    Ok(output)
+}
+
+// New chunks go right after IHDR, ahead of PLTE and IDAT as iCCP requires
+fn write_png(data: &[u8], metadata: &Metadata) -> Result<Vec<u8>> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Err(ImageToolError::ProcessingError("Not a PNG stream".to_string()));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(PNG_SIGNATURE);
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let end = pos + 12 + length;
+        if end > data.len() {
+            return Err(ImageToolError::ProcessingError("Truncated PNG chunk".to_string()));
+        }
+        let chunk_data = &data[pos + 8..pos + 8 + length];
+
+        let replaced = match chunk_type {
+            b"eXIf" => metadata.exif.is_some(),
+            // A profile overrides these, and PNG allows only one of them
+            b"iCCP" | b"sRGB" => metadata.icc.is_some(),
+            b"iTXt" | b"tEXt" | b"zTXt" => {
+                metadata.xmp.is_some() && chunk_data.split(|&b| b == 0).next() == Some(XMP_KEYWORD)
+            }
+            _ => false,
+        };
+        if !replaced {
+            output.extend_from_slice(&data[pos..end]);
+        }
+
+        if chunk_type == b"IHDR" {
+            if let Some(icc) = &metadata.icc {
+                let mut iccp = b"ICC profile\0\0".to_vec();
+                let mut encoder = ZlibEncoder::new(&mut iccp, flate2::Compression::default());
+                encoder.write_all(icc)?;
+                encoder.finish()?;
+                write_png_chunk(&mut output, b"iCCP", &iccp);
+            }
+            if let Some(exif) = &metadata.exif {
+                write_png_chunk(&mut output, b"eXIf", exif);
+            }
+            if let Some(xmp) = &metadata.xmp {
+                // Keyword, no compression, no language or translated keyword
+                let itxt = [XMP_KEYWORD, b"\0\0\0\0\0", xmp].concat();
+                write_png_chunk(&mut output, b"iTXt", &itxt);
+            }
+        }
+        pos = end;
+    }
+    Ok(output)
+}
+
+// Metadata needs the extended (VP8X) layout: VP8X, ICCP, the image
+// chunks, then EXIF and XMP. Simple files are converted to it.
+fn write_webp(data: &[u8], metadata: &Metadata) -> Result<Vec<u8>> {
+    const ICC_FLAG: u8 = 0x20;
+    const ALPHA_FLAG: u8 = 0x10;
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(ImageToolError::ProcessingError("Not a WebP stream".to_string()));
+    }
+
+    let mut chunks: Vec<([u8; 4], &[u8])> = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let fourcc: [u8; 4] = data[pos..pos + 4].try_into().unwrap_or_default();
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        let end = pos + 8 + size;
+        if end > data.len() {
+            return Err(ImageToolError::ProcessingError("Truncated WebP chunk".to_string()));
+        }
+        chunks.push((fourcc, &data[pos + 8..end]));
+        pos = end + size % 2;
+    }
+
+    let mut vp8x = match chunks.first() {
+        Some((fourcc, header)) if fourcc == b"VP8X" && header.len() >= 10 => header[..10].to_vec(),
+        _ => {
+            let decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(data))?;
+            let (width, height) = decoder.dimensions();
+            let mut header = vec![0; 10];
+            if decoder.color_type().has_alpha() {
+                header[0] |= ALPHA_FLAG;
+            }
+            header[4..7].copy_from_slice(&(width - 1).to_le_bytes()[..3]);
+            header[7..10].copy_from_slice(&(height - 1).to_le_bytes()[..3]);
+            header
+        }
+    };
+
+    let find = |fourcc: &[u8; 4]| chunks.iter().find(|(f, _)| f == fourcc).map(|(_, data)| *data);
+    let icc = metadata.icc.as_deref().or_else(|| find(b"ICCP"));
+    let exif = metadata.exif.as_deref().or_else(|| find(b"EXIF"));
+    let xmp = metadata.xmp.as_deref().or_else(|| find(b"XMP "));
+    for (flag, present) in [(ICC_FLAG, icc.is_some()), (EXIF_FLAG, exif.is_some()), (XMP_FLAG, xmp.is_some())] {
+        if present {
+            vp8x[0] |= flag;
+        }
+    }
+
+    let mut body = Vec::with_capacity(data.len());
+    let mut push = |fourcc: &[u8; 4], chunk: &[u8]| {
+        body.extend_from_slice(fourcc);
+        body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        body.extend_from_slice(chunk);
+        if chunk.len() % 2 == 1 {
+            body.push(0);
+        }
+    };
+    push(b"VP8X", &vp8x);
+    if let Some(icc) = icc {
+        push(b"ICCP", icc);
+    }
+    for (fourcc, chunk) in &chunks {
+        if !matches!(fourcc, b"VP8X" | b"ICCP" | b"EXIF" | b"XMP ") {
+            push(fourcc, chunk);
+        }
+    }
+    if let Some(exif) = exif {
+        push(b"EXIF", exif);
+    }
+    if let Some(xmp) = xmp {
+        push(b"XMP ", xmp);
+    }
+
+    let mut output = Vec::with_capacity(body.len() + 12);
+    output.extend_from_slice(b"RIFF");
+    output.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+    output.extend_from_slice(b"WEBP");
+    output.extend_from_slice(&body);
+    Ok(output)
+}
@@ -8,6 +8,44 @@ use std::path::Path;
 
 pub struct MetadataProcessor;
 
+/// Kinds of metadata that can identify the photographer or where a picture
+/// was taken, as found by `MetadataProcessor::privacy_risks`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivacyRisk {
+    Location,
+    SerialNumber,
+    OwnerName,
+    /// May still show what was cropped or edited out of the image
+    Thumbnail,
+}
+
+impl PrivacyRisk {
+    fn of(field: &exif::Field) -> Option<Self> {
+        if field.ifd_num == In::THUMBNAIL {
+            return Some(Self::Thumbnail);
+        }
+        if field.tag.context() == exif::Context::Gps {
+            return Some(Self::Location);
+        }
+        match field.tag {
+            Tag::BodySerialNumber | Tag::LensSerialNumber => Some(Self::SerialNumber),
+            Tag::CameraOwnerName | Tag::Artist => Some(Self::OwnerName),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PrivacyRisk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Location => "GPS location",
+            Self::SerialNumber => "Serial number",
+            Self::OwnerName => "Owner name",
+            Self::Thumbnail => "Embedded thumbnail",
+        })
+    }
+}
+
 impl MetadataProcessor {
     pub fn new() -> Self {
         Self
@@ -28,25 +66,244 @@ impl MetadataProcessor {
 
     pub fn read_metadata(&self, path: &Path) -> Result<Option<Exif>> {
         let file = File::open(path)?;
-        let mut bufreader = BufReader::new(&file);
+        read_exif(&mut BufReader::new(&file), &path.display().to_string())
+    }
+
+    pub fn read_metadata_from_bytes(&self, data: &[u8]) -> Result<Option<Exif>> {
+        read_exif(&mut std::io::Cursor::new(data), "input bytes")
+    }
+
+    /// The EXIF Orientation value, 1 to 8, if the tag is there
+    pub fn orientation(&self, exif: &Exif) -> Option<u8> {
+        let field = exif.get_field(Tag::Orientation, In::PRIMARY)?;
+        field.value.get_uint(0).and_then(|value| u8::try_from(value).ok())
+    }
+
+    // Re-encodes EXIF as a TIFF blob for the processed image: pixel
+    // dimensions are updated and the IFD1 thumbnail is replaced with
+    // `thumbnail` (a JPEG), or dropped entirely when it is `None`. When
+    // `upright` the pixels were already turned, so Orientation becomes 1.
+    pub fn rebuild_exif(
+        &self,
+        exif: &Exif,
+        image: &DynamicImage,
+        thumbnail: Option<&[u8]>,
+        upright: bool,
+    ) -> Result<Vec<u8>> {
+        use exif::experimental::Writer;
+        use exif::{Field, Value};
+
+        let dimensions = [
+            Field {
+                tag: Tag::PixelXDimension,
+                ifd_num: In::PRIMARY,
+                value: Value::Long(vec![image.width()]),
+            },
+            Field {
+                tag: Tag::PixelYDimension,
+                ifd_num: In::PRIMARY,
+                value: Value::Long(vec![image.height()]),
+            },
+        ];
+        let orientation = Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![1]),
+        };
+        let compression = Field {
+            tag: Tag::Compression,
+            ifd_num: In::THUMBNAIL,
+            value: Value::Short(vec![6]),
+        };
+
+        let mut writer = Writer::new();
+        let mut has_thumbnail_compression = false;
+
+        for field in exif.fields() {
+            if field.ifd_num == In::THUMBNAIL {
+                if thumbnail.is_none() {
+                    continue;
+                }
+                has_thumbnail_compression |= field.tag == Tag::Compression;
+            }
+            if field.ifd_num == In::PRIMARY
+                && (field.tag == Tag::PixelXDimension || field.tag == Tag::PixelYDimension)
+            {
+                continue;
+            }
+            if upright && field.ifd_num == In::PRIMARY && field.tag == Tag::Orientation {
+                writer.push_field(&orientation);
+                continue;
+            }
+            writer.push_field(field);
+        }
+
+        if exif.get_field(Tag::ExifVersion, In::PRIMARY).is_some() {
+            for field in &dimensions {
+                writer.push_field(field);
+            }
+        }
+
+        if let Some(jpeg) = thumbnail {
+            if !has_thumbnail_compression {
+                writer.push_field(&compression);
+            }
+            writer.set_jpeg(jpeg, In::THUMBNAIL);
+        }
+
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        writer
+            .write(&mut buffer, exif.little_endian())
+            .map_err(|e| ImageToolError::ProcessingError(format!("EXIF write error: {}", e)))?;
+
+        Ok(buffer.into_inner())
+    }
+
+    /// Each privacy-sensitive item in `exif`, with what it holds
+    pub fn privacy_risks(&self, exif: &Exif) -> Vec<(PrivacyRisk, String)> {
+        let mut risks = Vec::new();
+        let mut has_location = false;
+        let mut has_thumbnail = false;
+        for field in exif.fields() {
+            match PrivacyRisk::of(field) {
+                Some(PrivacyRisk::Location) => has_location = true,
+                Some(PrivacyRisk::Thumbnail) => has_thumbnail = true,
+                Some(risk) => {
+                    let value = field.display_value().to_string();
+                    risks.push((risk, format!("{} {}", field.tag, value.trim_matches('"'))));
+                }
+                None => {}
+            }
+        }
+
+        if has_location {
+            let detail = match self.extract_gps_coordinates(exif) {
+                Some((latitude, longitude, _)) => format!("{:.5}, {:.5}", latitude, longitude),
+                None => "GPS tags without coordinates".to_string(),
+            };
+            risks.insert(0, (PrivacyRisk::Location, detail));
+        }
+        if has_thumbnail {
+            let detail = match exif.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL) {
+                Some(field) => format!("{} bytes", field.display_value()),
+                None => "in IFD1".to_string(),
+            };
+            risks.push((PrivacyRisk::Thumbnail, detail));
+        }
+        risks
+    }
+
+    /// Re-encodes `exif` without anything `privacy_risks` reports, or None
+    /// when nothing else is left
+    pub fn strip_private_exif(&self, exif: &Exif) -> Result<Option<Vec<u8>>> {
+        use exif::experimental::Writer;
+
+        let mut writer = Writer::new();
+        let mut kept = 0;
+        for field in exif.fields() {
+            if PrivacyRisk::of(field).is_some() {
+                continue;
+            }
+            // Pointers are written by the writer as needed
+            if !matches!(field.tag, Tag::ExifIFDPointer | Tag::GPSInfoIFDPointer | Tag::InteropIFDPointer) {
+                kept += 1;
+            }
+            writer.push_field(field);
+        }
+        if kept == 0 {
+            return Ok(None);
+        }
+
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        writer
+            .write(&mut buffer, exif.little_endian())
+            .map_err(|e| ImageToolError::ProcessingError(format!("EXIF write error: {}", e)))?;
+        Ok(Some(buffer.into_inner()))
+    }
+
+    pub fn make_thumbnail(&self, image: &DynamicImage) -> Result<Vec<u8>> {
+        use image::codecs::jpeg::JpegEncoder;
+
+        let thumbnail = image.thumbnail(160, 120).to_rgb8();
+        let mut buffer = Vec::new();
+        JpegEncoder::new_with_quality(&mut buffer, 75).encode_image(&thumbnail)?;
+
+        Ok(buffer)
+    }
+
+    // Inserts an APP1 Exif segment after SOI (and after a JFIF APP0 if
+    // present), replacing any Exif segment already in the stream.
+    pub fn embed_exif_jpeg(&self, jpeg: &[u8], tiff: &[u8]) -> Result<Vec<u8>> {
+        self.splice_exif_jpeg(jpeg, Some(tiff))
+    }
+
+    /// Drops every Exif segment from a JPEG stream without re-encoding it
+    pub fn remove_exif_jpeg(&self, jpeg: &[u8]) -> Result<Vec<u8>> {
+        self.splice_exif_jpeg(jpeg, None)
+    }
+
+    fn splice_exif_jpeg(&self, jpeg: &[u8], tiff: Option<&[u8]>) -> Result<Vec<u8>> {
+        const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+        if !jpeg.starts_with(&[0xFF, 0xD8]) {
+            return Err(ImageToolError::ProcessingError("Not a JPEG stream".to_string()));
+        }
 
-        match Reader::new().read_from_container(&mut bufreader) {
-            Ok(exif) => {
-                log::debug!("Found EXIF data in {}", path.display());
-                Ok(Some(exif))
+        let segment_len = 2 + EXIF_HEADER.len() + tiff.map_or(0, |tiff| tiff.len());
+        if segment_len > u16::MAX as usize {
+            return Err(ImageToolError::ProcessingError(
+                format!("EXIF block of {} bytes does not fit in a JPEG APP1 segment", segment_len)
+            ));
+        }
+
+        let mut output = Vec::with_capacity(jpeg.len() + segment_len + 2);
+        output.extend_from_slice(&jpeg[..2]);
+
+        let mut pos = 2;
+        let mut inserted = false;
+        while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+            let marker = jpeg[pos + 1];
+            if !(0xE0..=0xEF).contains(&marker) {
+                break;
             }
-            Err(exif::Error::NotFound(_)) => {
-                log::debug!("No EXIF data found in {}", path.display());
-                Ok(None)
+            let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+            let end = (pos + 2 + len).min(jpeg.len());
+            let segment = &jpeg[pos..end];
+
+            let is_exif = marker == 0xE1 && segment.get(4..10) == Some(EXIF_HEADER);
+            if let (Some(tiff), false, false) = (tiff, marker == 0xE0, inserted) {
+                self.push_app1(&mut output, EXIF_HEADER, tiff);
+                inserted = true;
             }
-            Err(e) => {
-                log::warn!("Failed to read EXIF from {}: {}", path.display(), e);
-                Err(ImageToolError::ProcessingError(format!("EXIF read error: {}", e)))
+            if !is_exif {
+                output.extend_from_slice(segment);
             }
+            pos = end;
         }
+
+        if let (Some(tiff), false) = (tiff, inserted) {
+            self.push_app1(&mut output, EXIF_HEADER, tiff);
+        }
+        output.extend_from_slice(&jpeg[pos..]);
+
+        Ok(output)
     }
 
+    fn push_app1(&self, output: &mut Vec<u8>, header: &[u8], tiff: &[u8]) {
+        let len = (2 + header.len() + tiff.len()) as u16;
+        output.extend_from_slice(&[0xFF, 0xE1]);
+        output.extend_from_slice(&len.to_be_bytes());
+        output.extend_from_slice(header);
+        output.extend_from_slice(tiff);
+    }
+
+    // A TIFF is its own EXIF container and the EXIF reader loads it whole,
+    // so for TIFF only the first IFD is read and checked for EXIF or GPS
+    // sub-directories
     pub fn has_metadata(&self, path: &Path) -> Result<bool> {
+        if is_tiff(path)? {
+            return tiff_has_exif(path);
+        }
         Ok(self.read_metadata(path)?.is_some())
     }
 
@@ -196,6 +453,57 @@ impl MetadataProcessor {
         }
     }
 
+    /// When the picture was taken, from DateTimeOriginal and its
+    /// OffsetTimeOriginal if there is one
+    pub fn date_taken(&self, exif: &Exif) -> Option<exif::DateTime> {
+        let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+        let mut date = match &field.value {
+            exif::Value::Ascii(ascii) if !ascii.is_empty() => exif::DateTime::from_ascii(&ascii[0]).ok()?,
+            _ => return None,
+        };
+        if let Some(exif::Value::Ascii(offset)) = exif.get_field(Tag::OffsetTimeOriginal, In::PRIMARY).map(|f| &f.value) {
+            if let Some(offset) = offset.first() {
+                let _ = date.parse_offset(offset);
+            }
+        }
+        Some(date)
+    }
+
+    /// `date_taken` as a point in time. EXIF dates without an offset are
+    /// in the camera's local time, which is unknown; they are read as UTC.
+    pub fn capture_time(&self, exif: &Exif) -> Option<std::time::SystemTime> {
+        let date = self.date_taken(exif)?;
+        if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+            return None;
+        }
+
+        // Days since 1970-01-01 in the proleptic Gregorian calendar
+        let (month, day) = (date.month as i64, date.day as i64);
+        let year = date.year as i64 - i64::from(month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        let seconds = days * 86_400
+            + date.hour as i64 * 3_600
+            + date.minute as i64 * 60
+            + date.second as i64
+            - date.offset.unwrap_or(0) as i64 * 60;
+        let seconds = u64::try_from(seconds).ok()?;
+        Some(std::time::UNIX_EPOCH + std::time::Duration::new(seconds, date.nanosecond.unwrap_or(0)))
+    }
+
+    /// The value of the primary IFD field named `name` (e.g. "Artist"),
+    /// formatted for display with its unit
+    pub fn field_text(&self, exif: &Exif, name: &str) -> Option<String> {
+        let field = exif
+            .fields()
+            .find(|f| f.ifd_num == In::PRIMARY && f.tag.to_string() == name)?;
+        Some(display_field(field, exif)).filter(|t| !t.is_empty())
+    }
+
     pub fn get_camera_info(&self, exif: &Exif) -> Option<(String, String)> {
         let make = exif.get_field(Tag::Make, In::PRIMARY)
             .and_then(|f| {
@@ -257,4 +565,52 @@ impl Default for MetadataProcessor {
     fn default() -> Self {
         Self::new()
     }
+}
+
+fn is_tiff(path: &Path) -> Result<bool> {
+    use std::io::Read;
+
+    let mut magic = [0u8; 4];
+    let read = File::open(path)?.read(&mut magic)?;
+    Ok(read == 4 && (magic == *b"II*\0" || magic == *b"MM\0*"))
+}
+
+fn tiff_has_exif(path: &Path) -> Result<bool> {
+    use tiff::decoder::Decoder;
+    use tiff::tags::Tag as TiffTag;
+
+    let tiff_error = |e: tiff::TiffError| ImageToolError::ProcessingError(format!("EXIF read error: {}", e));
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?)).map_err(tiff_error)?;
+    for tag in [TiffTag::ExifDirectory, TiffTag::GpsDirectory] {
+        if decoder.find_tag(tag).map_err(tiff_error)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// A field's value with its unit, and without quotes around text
+pub(crate) fn display_field(field: &exif::Field, exif: &Exif) -> String {
+    let text = field.display_value().with_unit(exif).to_string();
+    match &field.value {
+        exif::Value::Ascii(_) => text.trim_matches('"').trim().to_string(),
+        _ => text,
+    }
+}
+
+fn read_exif<R: std::io::BufRead + std::io::Seek>(reader: &mut R, source: &str) -> Result<Option<Exif>> {
+    match Reader::new().read_from_container(reader) {
+        Ok(exif) => {
+            log::debug!("Found EXIF data in {}", source);
+            Ok(Some(exif))
+        }
+        Err(exif::Error::NotFound(_)) => {
+            log::debug!("No EXIF data found in {}", source);
+            Ok(None)
+        }
+        Err(e) => {
+            log::warn!("Failed to read EXIF from {}: {}", source, e);
+            Err(ImageToolError::ProcessingError(format!("EXIF read error: {}", e)))
+        }
+    }
 }
\ No newline at end of file
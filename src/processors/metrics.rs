@@ -0,0 +1,188 @@
+// pixie/src/processors/metrics.rs
+use crate::core::{ImageToolError, QualityScore, Result};
+use image::{DynamicImage, GrayImage};
+
+// Standard SSIM window: an 11-tap Gaussian with sigma 1.5
+const WINDOW_RADIUS: usize = 5;
+const WINDOW_SIGMA: f64 = 1.5;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Scores how closely an encoded image matches the image it was encoded
+/// from. Both must be the same size; alpha is ignored.
+pub struct QualityMeter;
+
+impl QualityMeter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn measure(&self, reference: &DynamicImage, distorted: &DynamicImage) -> Result<QualityScore> {
+        if reference.width() != distorted.width() || reference.height() != distorted.height() {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "Cannot compare a {}x{} image with a {}x{} one",
+                reference.width(),
+                reference.height(),
+                distorted.width(),
+                distorted.height()
+            )));
+        }
+
+        Ok(QualityScore {
+            ssim: ssim(&reference.to_luma8(), &distorted.to_luma8()),
+            psnr: psnr(reference, distorted),
+        })
+    }
+}
+
+impl Default for QualityMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Finds the lowest encoder quality whose decoded output still reaches a
+/// target SSIM against the source, as jpeg-archive does. Assumes SSIM
+/// rises with quality, so it takes a handful of trial encodes.
+pub struct QualitySearch {
+    target: f64,
+    min: u8,
+    max: u8,
+}
+
+impl QualitySearch {
+    pub fn new(target_ssim: f64) -> Self {
+        Self {
+            target: target_ssim,
+            min: 40,
+            max: 95,
+        }
+    }
+
+    /// Qualities tried, inclusive; the maximum is used when nothing reaches
+    /// the target
+    pub fn with_range(mut self, min: u8, max: u8) -> Self {
+        self.max = max.clamp(1, 100);
+        self.min = min.clamp(1, self.max);
+        self
+    }
+
+    /// `encode` writes the image at a given quality; returns the chosen
+    /// quality with its encoded bytes
+    pub fn search<F>(&self, image: &DynamicImage, encode: F) -> Result<(u8, Vec<u8>)>
+    where
+        F: Fn(u8) -> Result<Vec<u8>>,
+    {
+        let meter = QualityMeter::new();
+        let (mut low, mut high) = (self.min, self.max);
+        let mut best = None;
+
+        while low <= high {
+            let quality = low + (high - low) / 2;
+            let encoded = encode(quality)?;
+            let ssim = meter.measure(image, &image::load_from_memory(&encoded)?)?.ssim;
+            log::debug!("Quality {}: SSIM {:.5}", quality, ssim);
+
+            if ssim >= self.target {
+                best = Some((quality, encoded));
+                if quality == self.min {
+                    break;
+                }
+                high = quality - 1;
+            } else {
+                low = quality + 1;
+            }
+        }
+
+        match best {
+            Some(best) => Ok(best),
+            None => Ok((self.max, encode(self.max)?)),
+        }
+    }
+}
+
+// Mean of the SSIM map, computed on luma. The window sums are built from a
+// ring of horizontally blurred rows, so memory stays at 11 rows however
+// large the image is.
+fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    let (width, height) = (a.width() as usize, a.height() as usize);
+    let kernel = gaussian_kernel();
+    let taps = kernel.len();
+    let clamp = |i: isize, len: usize| i.clamp(0, len as isize - 1) as usize;
+
+    // x, y, x², y² and xy of one row, blurred horizontally
+    let blur_row = |row: usize| {
+        let xs = &a.as_raw()[row * width..(row + 1) * width];
+        let ys = &b.as_raw()[row * width..(row + 1) * width];
+        let mut sums = vec![[0.0f64; 5]; width];
+        for (x, sum) in sums.iter_mut().enumerate() {
+            for (k, w) in kernel.iter().enumerate() {
+                let i = clamp(x as isize + k as isize - WINDOW_RADIUS as isize, width);
+                let (p, q) = (xs[i] as f64, ys[i] as f64);
+                sum[0] += w * p;
+                sum[1] += w * q;
+                sum[2] += w * p * p;
+                sum[3] += w * q * q;
+                sum[4] += w * p * q;
+            }
+        }
+        sums
+    };
+
+    // The rows one window needs are consecutive, so they never share a slot
+    let mut ring: Vec<Option<(usize, Vec<[f64; 5]>)>> = vec![None; taps];
+    let mut total = 0.0;
+    for y in 0..height {
+        let rows: Vec<usize> = (0..taps)
+            .map(|k| clamp(y as isize + k as isize - WINDOW_RADIUS as isize, height))
+            .collect();
+        for &row in &rows {
+            if ring[row % taps].as_ref().map(|(cached, _)| *cached) != Some(row) {
+                ring[row % taps] = Some((row, blur_row(row)));
+            }
+        }
+
+        for x in 0..width {
+            let mut s = [0.0f64; 5];
+            for (k, &row) in rows.iter().enumerate() {
+                let (_, sums) = ring[row % taps].as_ref().expect("filled above");
+                for c in 0..5 {
+                    s[c] += kernel[k] * sums[x][c];
+                }
+            }
+            let [mx, my, xx, yy, xy] = s;
+            let (var_x, var_y, cov) = (xx - mx * mx, yy - my * my, xy - mx * my);
+            total += ((2.0 * mx * my + C1) * (2.0 * cov + C2)) / ((mx * mx + my * my + C1) * (var_x + var_y + C2));
+        }
+    }
+    total / (width * height) as f64
+}
+
+// Peak signal-to-noise ratio over the RGB channels, in dB; infinite for
+// identical images
+fn psnr(a: &DynamicImage, b: &DynamicImage) -> f64 {
+    let (a, b) = (a.to_rgb8(), b.to_rgb8());
+    let squared: f64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&p, &q)| (p as f64 - q as f64).powi(2))
+        .sum();
+    let mse = squared / a.as_raw().len() as f64;
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+fn gaussian_kernel() -> Vec<f64> {
+    let weights: Vec<f64> = (0..=2 * WINDOW_RADIUS)
+        .map(|i| {
+            let d = i as f64 - WINDOW_RADIUS as f64;
+            (-d * d / (2.0 * WINDOW_SIGMA * WINDOW_SIGMA)).exp()
+        })
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / sum).collect()
+}
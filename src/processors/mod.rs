@@ -1,16 +1,115 @@
 // pixie/src/processors/mod.rs
+mod alpha;
+mod archive;
+#[cfg(not(target_arch = "wasm32"))]
+mod audit;
+mod blurhash;
+mod cache;
+mod caption;
+mod channels;
+mod checkpoint;
+mod cmyk;
+mod collage;
+mod comparison;
 mod compressor;
+mod cropper;
+mod denoise;
+mod dpi;
+mod faces;
+mod frames;
+mod join;
+mod journal;
+mod levels;
 mod loader;
+mod lossless;
+mod lock;
+mod lqip;
+mod lut;
+mod metacopy;
 mod metadata;
+mod metrics;
+#[cfg(not(target_arch = "wasm32"))]
+mod orient;
+mod overrides;
+mod pipeline;
+mod posterize;
+mod pyramid;
+mod redact;
+mod remote;
+#[cfg(not(target_arch = "wasm32"))]
+mod renamer;
 mod resizer;
+mod rules;
+mod seam;
+mod shadow;
+mod sidecar;
+mod slicer;
+#[cfg(not(target_arch = "wasm32"))]
 mod batch;
+mod srcset;
+mod stages;
+mod storage;
+mod texture;
+mod thumbhash;
+mod tiled;
+mod tonemap;
+mod upscaler;
+mod validate;
+mod video;
 
+pub use alpha::{AlphaMasker, CornerRounder, EllipseMasker, Flattener};
+#[cfg(not(target_arch = "wasm32"))]
+pub use audit::{FileAudit, PrivacyAuditor};
+pub use archive::{ArchiveFormat, ArchiveWriter, TarEntry, TarReader, ZipEntry, ZipReader};
+pub use blurhash::BlurHasher;
+pub use cache::ProcessCache;
+pub use caption::Captioner;
+pub use channels::ChannelOps;
+pub use checkpoint::Checkpoint;
+pub use cmyk::{decode_cmyk_jpeg, scan_jpeg, write_cmyk_tiff, JpegColorInfo};
+pub use collage::{Collage, GridLayout};
+pub use comparison::Comparison;
 pub use compressor::Compressor;
+pub use cropper::Cropper;
+pub use denoise::Denoiser;
+pub use faces::FaceDetector;
+pub use frames::FrameExtractor;
+pub use join::{JoinAlign, JoinDirection, Joiner};
+pub use journal::{rollback_journal, Journal, RollbackReport};
+pub use levels::LevelStretcher;
 pub use loader::Loader;
-pub use metadata::MetadataProcessor;
+pub use lock::OutputLock;
+pub use lqip::{Lqip, LqipGenerator};
+pub use lut::Lut3d;
+pub use metacopy::{CopyReport, MetadataCopier};
+pub use metadata::{MetadataProcessor, PrivacyRisk};
+pub use metrics::{QualityMeter, QualitySearch};
+#[cfg(not(target_arch = "wasm32"))]
+pub use orient::{OrientationFix, OrientationFixer};
+pub use overrides::FileOverrides;
+pub use pipeline::Pipeline;
+pub use posterize::{DuotoneMapper, Posterizer, Quantizer, Thresholder};
+pub(crate) use posterize::count_colors;
+pub use pyramid::{PyramidGenerator, PyramidLayout, PyramidSummary};
+pub use redact::Redactor;
+pub use remote::RemoteFetcher;
+#[cfg(not(target_arch = "wasm32"))]
+pub use renamer::{RenameReport, Renamer};
 pub use resizer::{Resizer, ResizeMode};
+pub use rules::{ImageFacts, Rule};
+pub use seam::SeamCarver;
+pub use shadow::Canvas;
+pub use sidecar::MetadataExporter;
+pub use slicer::{TileBounds, TileGrid, TileSlicer};
+#[cfg(not(target_arch = "wasm32"))]
 pub use batch::BatchProcessor;
-
-pub mod prelude {
-    pub use super::{Compressor, Loader, MetadataProcessor, Resizer, BatchProcessor};
-}
\ No newline at end of file
+pub use srcset::{Srcset, SrcsetEntry, SrcsetGenerator};
+pub(crate) use stages::builtin_stages;
+pub use storage::{CloudBatch, StorageLocation};
+pub use texture::{TextureBaker, TextureCompression, TextureContainer};
+pub use thumbhash::ThumbHash;
+pub use tiled::TiledLoader;
+pub use tonemap::ToneMapper;
+pub use upscaler::ExternalUpscaler;
+pub use validate::{Defect, FileCheck, ImageValidator};
+pub use video::VideoFrameGrabber;
@@ -0,0 +1,167 @@
+// pixie/src/processors/orient.rs
+use crate::core::{ImageProcessor, ImageToolError, ProcessConfig, Result};
+use crate::processors::batch::collect_image_paths;
+use crate::processors::lossless::transform_jpeg;
+use crate::processors::{MetadataCopier, MetadataProcessor};
+use image::ImageFormat;
+use std::path::{Path, PathBuf};
+
+/// Turns images upright in place per their EXIF Orientation tag and sets
+/// the tag to 1. Files that are already upright, or have no tag, are left
+/// untouched. Baseline JPEGs that are a whole number of MCUs are turned
+/// losslessly; everything else is re-encoded, keeping its ICC profile and
+/// XMP.
+pub struct OrientationFixer {
+    metadata: MetadataProcessor,
+    recursive: bool,
+    quality: u8,
+}
+
+#[derive(Debug)]
+pub struct OrientationFix {
+    pub path: PathBuf,
+    /// The EXIF Orientation value found, 1 when the file had none
+    pub orientation: u8,
+    /// Whether the file was rewritten upright
+    pub fixed: bool,
+    /// Whether fixing it needed a lossy re-encode
+    pub reencoded: bool,
+    /// Why the file couldn't be read or fixed
+    pub error: Option<String>,
+}
+
+impl OrientationFix {
+    /// The file is upright now, whether or not it had to be rewritten
+    pub fn is_upright(&self) -> bool {
+        self.error.is_none() && (self.orientation == 1 || self.fixed)
+    }
+}
+
+impl OrientationFixer {
+    pub fn new() -> Self {
+        Self {
+            metadata: MetadataProcessor::new(),
+            recursive: false,
+            quality: 95,
+        }
+    }
+
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Quality for images that can't be turned losslessly
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Fixes one image, or every image in a directory
+    pub fn fix(&self, path: &Path) -> Result<Vec<OrientationFix>> {
+        if path.is_dir() {
+            let mut paths = collect_image_paths(path, self.recursive);
+            paths.sort();
+            Ok(paths.iter().map(|path| self.fix_file(path)).collect())
+        } else if path.is_file() {
+            Ok(vec![self.fix_file(path)])
+        } else {
+            Err(ImageToolError::InvalidParameter(format!(
+                "Input does not exist: {}",
+                path.display()
+            )))
+        }
+    }
+
+    pub fn fix_file(&self, path: &Path) -> OrientationFix {
+        let mut fix = OrientationFix {
+            path: path.to_path_buf(),
+            orientation: 1,
+            fixed: false,
+            reencoded: false,
+            error: None,
+        };
+
+        match self.metadata.read_metadata(path) {
+            Ok(exif) => {
+                fix.orientation = exif.and_then(|exif| self.metadata.orientation(&exif)).unwrap_or(1);
+            }
+            Err(e) => {
+                fix.error = Some(e.to_string());
+                return fix;
+            }
+        }
+        if fix.orientation == 1 {
+            return fix;
+        }
+
+        match self.rewrite(path, fix.orientation) {
+            Ok(reencoded) => {
+                log::info!("Turned {} upright (orientation {})", path.display(), fix.orientation);
+                fix.fixed = true;
+                fix.reencoded = reencoded;
+            }
+            Err(e) => fix.error = Some(e.to_string()),
+        }
+        fix
+    }
+
+    // Written next to the file and renamed over it, so a failed write
+    // never leaves a truncated image. Returns whether it was re-encoded.
+    fn rewrite(&self, path: &Path, orientation: u8) -> Result<bool> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let part = path.with_file_name(format!(".pixie-part-{}", file_name));
+        let written = self.write_upright(path, &part, orientation).and_then(|reencoded| {
+            std::fs::set_permissions(&part, std::fs::metadata(path)?.permissions())?;
+            std::fs::rename(&part, path)?;
+            Ok(reencoded)
+        });
+        if written.is_err() {
+            let _ = std::fs::remove_file(&part);
+        }
+        written
+    }
+
+    fn write_upright(&self, path: &Path, part: &Path, orientation: u8) -> Result<bool> {
+        let data = std::fs::read(path)?;
+        let format = image::guess_format(&data).ok();
+        if format == Some(ImageFormat::Jpeg) {
+            if let Some(turned) = transform_jpeg(&data, orientation)? {
+                // Decoding it checks the result and gives the new
+                // dimensions and thumbnail for the EXIF block
+                let image = image::load_from_memory_with_format(&turned, ImageFormat::Jpeg)?;
+                let turned = match self.metadata.read_metadata_from_bytes(&data)? {
+                    Some(exif) => {
+                        let thumbnail = self.metadata.make_thumbnail(&image)?;
+                        let tiff = self.metadata.rebuild_exif(&exif, &image, Some(&thumbnail), true)?;
+                        self.metadata.embed_exif_jpeg(&turned, &tiff)?
+                    }
+                    None => turned,
+                };
+                std::fs::write(part, turned)?;
+                return Ok(false);
+            }
+        }
+
+        log::warn!(
+            "{} can't be turned losslessly, re-encoding it at quality {}",
+            path.display(),
+            self.quality
+        );
+        let config = ProcessConfig {
+            quality: self.quality,
+            ..Default::default()
+        };
+        ImageProcessor::new(config).process(path, part)?;
+        if matches!(format, Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)) {
+            MetadataCopier::new().with_exif(false).copy(path, part)?;
+        }
+        Ok(true)
+    }
+}
+
+impl Default for OrientationFixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,84 @@
+// pixie/src/processors/overrides.rs
+use crate::core::options::OptionValue;
+use crate::core::{ImageToolError, ProcessConfig, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Settings for one input of a batch, read from a `photo.jpg.pixie.toml`
+/// next to it and laid over the batch config for that file alone.
+///
+/// The file holds flat `key = value` lines. `skip = true` leaves the input
+/// out of the run, `crop` takes an aspect ratio such as `"4:5"` (or
+/// `"none"` to keep the whole frame), and any other key is a processing
+/// option as the server takes it, e.g. `quality = 95` or
+/// `gravity = "north"`.
+#[derive(Debug, Clone, Default)]
+pub struct FileOverrides {
+    pub skip: bool,
+    values: HashMap<String, String>,
+}
+
+impl FileOverrides {
+    pub const SUFFIX: &'static str = ".pixie.toml";
+
+    /// Where the overrides for `input` live
+    pub fn path_for(input: &Path) -> PathBuf {
+        let mut name = input.file_name().unwrap_or_default().to_os_string();
+        name.push(Self::SUFFIX);
+        input.with_file_name(name)
+    }
+
+    /// The overrides for `input`, or None when it has no file
+    pub fn load(input: &Path) -> Result<Option<Self>> {
+        let path = Self::path_for(input);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Self::parse(&text).map(Some).map_err(|e| {
+            ImageToolError::InvalidParameter(format!("{}: {}", path.display(), e))
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: OverridesFile =
+            toml::from_str(text).map_err(|e| ImageToolError::InvalidParameter(e.to_string()))?;
+        let mut values: HashMap<String, String> =
+            file.options.into_iter().map(|(key, value)| (key, value.to_string())).collect();
+        if let Some(crop) = file.crop {
+            values.insert("aspect".to_string(), crop);
+        }
+        let overrides = Self { skip: file.skip, values };
+
+        // Checked here, so a bad value is reported before the run starts
+        overrides.apply(&ProcessConfig::default())?;
+        Ok(overrides)
+    }
+
+    /// `base` with these settings laid over it
+    pub fn apply(&self, base: &ProcessConfig) -> Result<ProcessConfig> {
+        let mut values = self.values.clone();
+        let uncropped = values.get("aspect").is_some_and(|aspect| aspect == "none");
+        if uncropped {
+            values.remove("aspect");
+        }
+        let mut config = base.apply_options(&values)?;
+        if uncropped {
+            config.aspect = None;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+// The file as written; `crop` is the `aspect` option under another name
+#[derive(Deserialize)]
+struct OverridesFile {
+    #[serde(default)]
+    skip: bool,
+    crop: Option<String>,
+    #[serde(flatten)]
+    options: HashMap<String, OptionValue>,
+}
@@ -0,0 +1,357 @@
+// pixie/src/processors/pipeline.rs
+use crate::core::options::OptionValue;
+use crate::core::{ImageProcessor, ImageToolError, ProcessConfig, ProcessingStage, Result, StageContext};
+use crate::processors::{Captioner, Loader};
+use image::{imageops, DynamicImage, GenericImageView};
+use serde::de::{Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+// Steps that set up the encoder rather than change pixels; wherever they
+// appear in a recipe they apply to the written file
+const ENCODE_KEYS: &[&str] = &["format", "quality", "strip-metadata", "speed", "bit-depth", "dpi"];
+
+/// An ordered recipe of processing steps, read from a TOML or YAML file,
+/// run in one pass over the decoded image without intermediate files.
+///
+/// Each step is an option name as used by the CLI and server, with its
+/// value and optionally further options for the same step. `auto-orient`
+/// turns the image upright on decode, `resize` takes `W`, `WxH` or `N%`,
+/// `sharpen` an unsharp-mask sigma and `watermark` an image path; encoder
+/// settings such as `format` and `quality` apply to the output.
+///
+/// TOML recipes are a list of `[[step]]` tables, YAML ones a `steps:` list:
+///
+/// ```yaml
+/// steps:
+///   - auto-orient
+///   - trim
+///   - resize: 1200
+///   - sharpen: 1.5
+///   - watermark: logo.png
+///     opacity: 0.5
+///   - format: webp
+///   - quality: 80
+/// ```
+pub struct Pipeline {
+    config: ProcessConfig,
+    stages: Vec<Arc<dyn ProcessingStage>>,
+}
+
+impl Pipeline {
+    /// Reads a `.toml`, `.yaml` or `.yml` recipe
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "toml" => Self::from_toml(&text),
+            "yaml" | "yml" => Self::from_yaml(&text),
+            _ => Err(ImageToolError::UnsupportedFormat(format!(
+                "Pipeline recipe {} must be .toml, .yaml or .yml",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let recipe: Recipe = toml::from_str(text).map_err(invalid_recipe)?;
+        Self::from_steps(recipe.steps)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let recipe: Recipe = serde_yaml::from_str(text).map_err(invalid_recipe)?;
+        Self::from_steps(recipe.steps)
+    }
+
+    fn from_steps(steps: Vec<RecipeStep>) -> Result<Self> {
+        if steps.is_empty() {
+            return Err(ImageToolError::InvalidParameter("Recipe has no steps".to_string()));
+        }
+        let mut config = ProcessConfig {
+            auto_orient: false,
+            ..Default::default()
+        };
+        let mut stages: Vec<Arc<dyn ProcessingStage>> = Vec::new();
+
+        for step in steps {
+            let (op, value, options) = step.split()?;
+            match op.as_str() {
+                // Turned upright on decode, which is before any other step
+                "auto-orient" => config.auto_orient = parse_flag(&op, &value)?,
+                key if ENCODE_KEYS.contains(&key) => {
+                    let mut query = options;
+                    query.insert(op.clone(), value);
+                    config = config.apply_options(&query)?;
+                }
+                "sharpen" => stages.push(Arc::new(Sharpen::new(&value)?)),
+                "watermark" => stages.push(Arc::new(Watermark::new(&value, &options)?)),
+                "resize" => {
+                    let mut query = options;
+                    resize_query(&value, &mut query)?;
+                    stages.push(Arc::new(ConfigStep::new(op, &query)?));
+                }
+                _ => {
+                    let mut query = options;
+                    query.insert(op.clone(), value);
+                    stages.push(Arc::new(ConfigStep::new(op, &query)?));
+                }
+            }
+        }
+
+        config.validate()?;
+        Ok(Self { config, stages })
+    }
+
+    /// Encoder and decode settings the recipe ends up with
+    pub fn config(&self) -> &ProcessConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut ProcessConfig {
+        &mut self.config
+    }
+
+    /// Names of the steps that change pixels, in order
+    pub fn step_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    /// A processor running the recipe on each image it is given
+    pub fn processor(&self) -> ImageProcessor {
+        self.stages
+            .iter()
+            .cloned()
+            .fold(ImageProcessor::new(self.config.clone()), ImageProcessor::with_stage)
+    }
+
+    /// A batch processor running the recipe on every image
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn batch(&self, max_threads: usize) -> Result<crate::processors::BatchProcessor> {
+        let batch = crate::processors::BatchProcessor::new(self.config.clone(), max_threads)?;
+        Ok(self.stages.iter().cloned().fold(batch, |batch, stage| batch.with_stage(stage)))
+    }
+}
+
+// Flags may be given bare or as true/false
+fn parse_flag(op: &str, value: &str) -> Result<bool> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(ImageToolError::InvalidParameter(format!("Step {} takes no value, got '{}'", op, value))),
+    }
+}
+
+// W, WxH or N%, as width/height/scale query values
+pub(crate) fn resize_query(value: &str, query: &mut HashMap<String, String>) -> Result<()> {
+    let invalid = || ImageToolError::InvalidParameter(format!("Invalid resize '{}', expected W, WxH or N%", value));
+    if let Some(percent) = value.strip_suffix('%') {
+        query.insert("scale".to_string(), percent.trim().to_string());
+    } else if let Some((width, height)) = value.split_once('x') {
+        query.insert("width".to_string(), width.trim().to_string());
+        query.insert("height".to_string(), height.trim().to_string());
+    } else if value.parse::<u32>().is_ok() {
+        query.insert("width".to_string(), value.to_string());
+    } else {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// A step made of processing options, run as its own pass over the image
+struct ConfigStep {
+    name: String,
+    processor: ImageProcessor,
+    caption: Option<(String, Captioner)>,
+}
+
+impl ConfigStep {
+    fn new(name: String, query: &HashMap<String, String>) -> Result<Self> {
+        let base = ProcessConfig {
+            auto_orient: false,
+            ..Default::default()
+        };
+        let config = base.apply_options(query)?;
+        config.validate()?;
+        // The processor only captions whole files, so the step draws it itself
+        let caption = match &config.caption {
+            Some(template) => Some((
+                template.clone(),
+                Captioner::new(config.caption_font.as_deref())?
+                    .with_size(config.caption_size)
+                    .with_gravity(config.caption_position),
+            )),
+            None => None,
+        };
+        Ok(Self {
+            name,
+            processor: ImageProcessor::new(config),
+            caption,
+        })
+    }
+}
+
+impl ProcessingStage for ConfigStep {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        let image = self.processor.process_image(image)?;
+        Ok(match &self.caption {
+            Some((template, captioner)) => captioner.apply(&image, &Captioner::text(template, None)),
+            None => image,
+        })
+    }
+}
+
+/// Unsharp mask; larger sigmas sharpen coarser detail
+struct Sharpen {
+    sigma: f32,
+}
+
+impl Sharpen {
+    fn new(value: &str) -> Result<Self> {
+        let sigma = match value {
+            "true" => 1.0,
+            value => value
+                .parse::<f32>()
+                .ok()
+                .filter(|sigma| sigma.is_finite() && *sigma > 0.0)
+                .ok_or_else(|| ImageToolError::InvalidParameter(format!("Invalid sharpen sigma '{}'", value)))?,
+        };
+        Ok(Self { sigma })
+    }
+}
+
+impl ProcessingStage for Sharpen {
+    fn name(&self) -> &str {
+        "sharpen"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(image.unsharpen(self.sigma, 1))
+    }
+}
+
+/// An image, such as a logo, laid over the bottom-right corner; shrunk to
+/// a quarter of the width at most
+struct Watermark {
+    mark: DynamicImage,
+    opacity: f32,
+}
+
+impl Watermark {
+    fn new(path: &str, options: &HashMap<String, String>) -> Result<Self> {
+        let opacity = match options.get("opacity") {
+            Some(value) => value
+                .parse::<f32>()
+                .ok()
+                .filter(|opacity| (0.0..=1.0).contains(opacity))
+                .ok_or_else(|| ImageToolError::InvalidParameter(format!("Invalid watermark opacity '{}'", value)))?,
+            None => 1.0,
+        };
+        Ok(Self {
+            mark: Loader::new().load(Path::new(path))?,
+            opacity,
+        })
+    }
+}
+
+impl ProcessingStage for Watermark {
+    fn name(&self) -> &str {
+        "watermark"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        let (width, height) = image.dimensions();
+        let max_width = (width / 4).max(1);
+        let mark = if self.mark.width() > max_width {
+            self.mark.resize(max_width, height, imageops::FilterType::Lanczos3)
+        } else {
+            self.mark.clone()
+        };
+        let mut mark = mark.to_rgba8();
+        for pixel in mark.pixels_mut() {
+            pixel.0[3] = (pixel.0[3] as f32 * self.opacity).round() as u8;
+        }
+
+        let margin = width.min(height) / 40;
+        let x = width.saturating_sub(mark.width() + margin) as i64;
+        let y = height.saturating_sub(mark.height() + margin) as i64;
+        let mut output = image.to_rgba8();
+        imageops::overlay(&mut output, &mark, x, y);
+        Ok(crate::utils::into_color(DynamicImage::ImageRgba8(output), image.color()))
+    }
+}
+
+// The steps of a recipe, which TOML writes as `[[step]]` tables
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Recipe {
+    #[serde(alias = "step")]
+    steps: Vec<RecipeStep>,
+}
+
+// A bare option name, or a mapping whose first key names the step and
+// whose further keys are options for it
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RecipeStep {
+    Flag(String),
+    Options(StepOptions),
+}
+
+impl RecipeStep {
+    // The step's option name and value, and the options that go with it
+    fn split(self) -> Result<(String, String, HashMap<String, String>)> {
+        match self {
+            RecipeStep::Flag(name) => Ok((normalize_key(&name), "true".to_string(), HashMap::new())),
+            RecipeStep::Options(StepOptions(mut options)) => {
+                if options.is_empty() {
+                    return Err(ImageToolError::InvalidParameter("Recipe has an empty step".to_string()));
+                }
+                let (op, value) = options.remove(0);
+                Ok((op, value, options.into_iter().collect()))
+            }
+        }
+    }
+}
+
+// The first key names the step, so entries are kept in the order written
+// rather than collected into a map
+struct StepOptions(Vec<(String, String)>);
+
+impl<'de> Deserialize<'de> for StepOptions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct OptionsVisitor;
+
+        impl<'de> Visitor<'de> for OptionsVisitor {
+            type Value = StepOptions;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a step name or a mapping of step options")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<StepOptions, A::Error> {
+                let mut options = Vec::new();
+                while let Some((key, value)) = map.next_entry::<String, OptionValue>()? {
+                    options.push((normalize_key(&key), value.to_string()));
+                }
+                Ok(StepOptions(options))
+            }
+        }
+
+        deserializer.deserialize_map(OptionsVisitor)
+    }
+}
+
+fn invalid_recipe(error: impl fmt::Display) -> ImageToolError {
+    ImageToolError::InvalidParameter(format!("Invalid recipe: {}", error))
+}
+
+fn normalize_key(key: &str) -> String {
+    key.trim().replace('_', "-")
+}
@@ -0,0 +1,140 @@
+// pixie/src/processors/posterize.rs
+use crate::core::{Color, Duotone};
+use crate::utils::into_color;
+use color_quant::NeuQuant;
+use image::{ColorType, DynamicImage};
+use std::collections::HashSet;
+
+/// Reduces each color channel to a fixed number of evenly spaced tones
+pub struct Posterizer {
+    levels: u8,
+}
+
+impl Posterizer {
+    pub fn new(levels: u8) -> Self {
+        Self {
+            levels: levels.max(2),
+        }
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let steps = (self.levels - 1) as f32;
+        let mut working = image.to_rgba32f();
+        for pixel in working.pixels_mut() {
+            for value in &mut pixel.0[..3] {
+                *value = (value.clamp(0.0, 1.0) * steps).round() / steps;
+            }
+        }
+        into_color(DynamicImage::ImageRgba32F(working), image.color())
+    }
+}
+
+/// Turns the image black and white: pixels at or above the cutoff
+/// brightness become white, the rest black. Alpha is kept, so transparent
+/// stencils stay transparent.
+pub struct Thresholder {
+    cutoff: u8,
+}
+
+impl Thresholder {
+    pub fn new(cutoff: u8) -> Self {
+        Self { cutoff }
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let cutoff = self.cutoff as f32 / 255.0;
+        let mut working = image.to_rgba32f();
+        for pixel in working.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            let value = if luma >= cutoff { 1.0 } else { 0.0 };
+            pixel.0 = [value, value, value, a];
+        }
+        into_color(DynamicImage::ImageRgba32F(working), image.color())
+    }
+}
+
+/// Recolors the image along a two-color gradient by brightness, shadows
+/// taking the dark color and highlights the light one. Alpha is kept.
+pub struct DuotoneMapper {
+    dark: Color,
+    light: Color,
+}
+
+impl DuotoneMapper {
+    pub fn new(duotone: Duotone) -> Self {
+        Self {
+            dark: duotone.dark,
+            light: duotone.light,
+        }
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let channel = |color: Color| [color.r, color.g, color.b].map(|value| value as f32 / 255.0);
+        let (dark, light) = (channel(self.dark), channel(self.light));
+        let mut working = image.to_rgba32f();
+        for pixel in working.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let luma = (0.2126 * r + 0.7152 * g + 0.0722 * b).clamp(0.0, 1.0);
+            let tone = |i: usize| dark[i] + (light[i] - dark[i]) * luma;
+            pixel.0 = [tone(0), tone(1), tone(2), a];
+        }
+        // Gray inputs come out in color
+        let color = match image.color() {
+            ColorType::L8 => ColorType::Rgb8,
+            ColorType::La8 => ColorType::Rgba8,
+            ColorType::L16 => ColorType::Rgb16,
+            ColorType::La16 => ColorType::Rgba16,
+            color => color,
+        };
+        into_color(DynamicImage::ImageRgba32F(working), color)
+    }
+}
+
+/// Reduces the image to a palette of at most `colors` colors, alpha
+/// included; PNG output of a quantized image is written indexed. Images
+/// that already fit are left as they are.
+pub struct Quantizer {
+    colors: u16,
+}
+
+impl Quantizer {
+    pub fn new(colors: u16) -> Self {
+        Self {
+            colors: colors.clamp(2, 256),
+        }
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let colors = self.colors as usize;
+        if count_colors(image, colors) <= colors {
+            return image.clone();
+        }
+        let mut working = image.to_rgba8();
+        // Sampling every 10th pixel is the usual speed/quality trade-off
+        let quantizer = NeuQuant::new(10, colors, working.as_raw());
+        for pixel in working.pixels_mut() {
+            quantizer.map_pixel(&mut pixel.0);
+        }
+        // A palette holds 8-bit samples
+        let color = match image.color() {
+            ColorType::L16 => ColorType::L8,
+            ColorType::La16 => ColorType::La8,
+            ColorType::Rgb16 | ColorType::Rgb32F => ColorType::Rgb8,
+            ColorType::Rgba16 | ColorType::Rgba32F => ColorType::Rgba8,
+            color => color,
+        };
+        into_color(DynamicImage::ImageRgba8(working), color)
+    }
+}
+
+/// Distinct colors in `image` at 8 bits, counted no further than `limit + 1`
+pub(crate) fn count_colors(image: &DynamicImage, limit: usize) -> usize {
+    let mut seen = HashSet::new();
+    for pixel in image.to_rgba8().pixels() {
+        if seen.insert(pixel.0) && seen.len() > limit {
+            break;
+        }
+    }
+    seen.len()
+}
@@ -0,0 +1,465 @@
+// pixie/src/processors/pyramid.rs
+use crate::core::{Color, ImageToolError, Result};
+use crate::processors::tiled::{samples_to_image, RowLayout, RowSink};
+use crate::processors::{Compressor, Flattener, Loader, TiledLoader};
+use crate::utils::{format_extension, json_escape};
+use image::{DynamicImage, ImageFormat};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PyramidLayout {
+    /// Deep Zoom: `name.dzi` plus `name_files/{level}/{col}_{row}.ext`
+    #[default]
+    Dzi,
+    /// IIIF Image API 3.0 level 0: `name/info.json` plus static tiles
+    Iiif,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyramidSummary {
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    pub tiles: usize,
+    /// The .dzi descriptor or IIIF info.json
+    pub descriptor: PathBuf,
+}
+
+/// Writes a multi-resolution tile pyramid for deep-zoom viewers. TIFF and
+/// PNG sources are streamed through the strip decoder, and every level is
+/// built from the one above it while rows arrive, so only a band of rows
+/// per level is ever held in memory.
+pub struct PyramidGenerator {
+    layout: PyramidLayout,
+    tile_size: u32,
+    overlap: u32,
+    format: ImageFormat,
+    quality: u8,
+    base_url: Option<String>,
+}
+
+impl PyramidGenerator {
+    /// Tiles default to 254 pixels plus a 1 pixel overlap for DZI (256 on
+    /// the wire), and 256 for IIIF
+    pub fn new(layout: PyramidLayout) -> Self {
+        Self {
+            layout,
+            tile_size: match layout {
+                PyramidLayout::Dzi => 254,
+                PyramidLayout::Iiif => 256,
+            },
+            overlap: 1,
+            format: ImageFormat::Jpeg,
+            quality: 85,
+            base_url: None,
+        }
+    }
+
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Pixels shared with neighbouring tiles; DZI only, IIIF tiles never overlap
+    pub fn with_overlap(mut self, overlap: u32) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    pub fn with_format(mut self, format: ImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Public URL the IIIF tiles will be served from, recorded as the `id`
+    pub fn with_base_url(mut self, base_url: Option<String>) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    pub fn generate(&self, input_path: &Path, output_dir: &Path) -> Result<PyramidSummary> {
+        if self.tile_size == 0 {
+            return Err(ImageToolError::InvalidParameter(
+                "Tile size must be greater than zero".to_string(),
+            ));
+        }
+
+        let stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("image");
+        let root = match self.layout {
+            PyramidLayout::Dzi => output_dir.join(format!("{}_files", stem)),
+            PyramidLayout::Iiif => output_dir.join(stem),
+        };
+        std::fs::create_dir_all(&root)?;
+
+        let mut builder = PyramidBuilder {
+            settings: self,
+            root,
+            levels: Vec::new(),
+            width: 0,
+            height: 0,
+            tiles: 0,
+        };
+
+        let loader = Loader::new();
+        let format = loader.detect_format(input_path)?;
+        let tiled = TiledLoader::new();
+        if tiled.supports(format) {
+            log::info!("Streaming {} into a tile pyramid", input_path.display());
+            tiled.stream_rows(input_path, format, &mut builder)?;
+        } else {
+            let image = loader.load(input_path)?;
+            let (layout, samples) = image_samples(&image);
+            builder.begin(layout)?;
+            let row_len = layout.width as usize * layout.channels;
+            for row in samples.chunks_exact(row_len.max(1)) {
+                builder.push_row(row)?;
+            }
+        }
+        builder.finish()?;
+
+        let descriptor = match self.layout {
+            PyramidLayout::Dzi => self.write_dzi(output_dir, stem, builder.width, builder.height)?,
+            PyramidLayout::Iiif => self.write_info_json(&builder.root, stem, builder.width, builder.height, builder.levels.len())?,
+        };
+
+        log::info!(
+            "Wrote {} tiles over {} levels to {}",
+            builder.tiles,
+            builder.levels.len(),
+            output_dir.display()
+        );
+
+        Ok(PyramidSummary {
+            width: builder.width,
+            height: builder.height,
+            levels: builder.levels.len() as u32,
+            tiles: builder.tiles,
+            descriptor,
+        })
+    }
+
+    fn overlap(&self) -> u32 {
+        match self.layout {
+            PyramidLayout::Dzi => self.overlap,
+            PyramidLayout::Iiif => 0,
+        }
+    }
+
+    fn write_dzi(&self, output_dir: &Path, stem: &str, width: u32, height: u32) -> Result<PathBuf> {
+        let path = output_dir.join(format!("{}.dzi", stem));
+        let xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"{}\" Overlap=\"{}\" TileSize=\"{}\">\n  \
+             <Size Width=\"{}\" Height=\"{}\"/>\n\
+             </Image>\n",
+            format_extension(self.format),
+            self.overlap(),
+            self.tile_size,
+            width,
+            height
+        );
+        std::fs::write(&path, xml)?;
+        Ok(path)
+    }
+
+    fn write_info_json(&self, root: &Path, stem: &str, width: u32, height: u32, levels: usize) -> Result<PathBuf> {
+        let path = root.join("info.json");
+        let id = self.base_url.clone().unwrap_or_else(|| stem.to_string());
+        let scale_factors: Vec<String> = (0..levels).map(|level| (1u32 << level).to_string()).collect();
+        let json = format!(
+            "{{\n  \"@context\": \"http://iiif.io/api/image/3/context.json\",\n  \
+             \"id\": \"{}\",\n  \"type\": \"ImageService3\",\n  \
+             \"protocol\": \"http://iiif.io/api/image\",\n  \"profile\": \"level0\",\n  \
+             \"width\": {},\n  \"height\": {},\n  \
+             \"tiles\": [{{ \"width\": {}, \"height\": {}, \"scaleFactors\": [{}] }}],\n  \
+             \"preferredFormats\": [\"{}\"]\n}}\n",
+            json_escape(id.trim_end_matches('/')),
+            width,
+            height,
+            self.tile_size,
+            self.tile_size,
+            scale_factors.join(", "),
+            format_extension(self.format)
+        );
+        std::fs::write(&path, json)?;
+        Ok(path)
+    }
+}
+
+struct PyramidBuilder<'a> {
+    settings: &'a PyramidGenerator,
+    root: PathBuf,
+    /// Full resolution first
+    levels: Vec<LevelWriter>,
+    width: u32,
+    height: u32,
+    tiles: usize,
+}
+
+impl RowSink for PyramidBuilder<'_> {
+    fn begin(&mut self, layout: RowLayout) -> Result<()> {
+        self.width = layout.width;
+        self.height = layout.height;
+        let tile_size = self.settings.tile_size;
+
+        // DZI goes all the way down to 1x1; IIIF stops once a level fits in one tile
+        let (mut width, mut height, mut scale) = (layout.width, layout.height, 1u32);
+        loop {
+            self.levels.push(LevelWriter::new(width, height, scale, layout));
+            let done = match self.settings.layout {
+                PyramidLayout::Dzi => width == 1 && height == 1,
+                PyramidLayout::Iiif => width <= tile_size && height <= tile_size,
+            };
+            if done {
+                break;
+            }
+            width = width.div_ceil(2);
+            height = height.div_ceil(2);
+            scale *= 2;
+        }
+
+        log::debug!(
+            "Pyramid for {}x{}: {} levels of {}px tiles",
+            layout.width,
+            layout.height,
+            self.levels.len(),
+            tile_size
+        );
+        Ok(())
+    }
+
+    fn push_row(&mut self, row: &[u16]) -> Result<()> {
+        self.push_at(0, row.to_vec())
+    }
+}
+
+impl PyramidBuilder<'_> {
+    // Stores the row for its own tiles and pairs it up for the level below
+    fn push_at(&mut self, index: usize, row: Vec<u16>) -> Result<()> {
+        let Some(level) = self.levels.get_mut(index) else {
+            return Ok(());
+        };
+
+        let reduced = match level.pending.take() {
+            Some(above) => Some(level.reduce(&above, Some(&row))),
+            None => {
+                level.pending = Some(row.clone());
+                None
+            }
+        };
+        level.rows.push_back(row);
+        level.received += 1;
+
+        self.write_ready_bands(index, false)?;
+        if let Some(reduced) = reduced {
+            self.push_at(index + 1, reduced)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for index in 0..self.levels.len() {
+            let level = &mut self.levels[index];
+            if level.received < level.height {
+                return Err(ImageToolError::ProcessingError(format!(
+                    "Image data ended after {} of {} rows",
+                    level.received, level.height
+                )));
+            }
+            // An odd last row is halved on its own
+            if let Some(last) = level.pending.take() {
+                let reduced = level.reduce(&last, None);
+                self.push_at(index + 1, reduced)?;
+            }
+            self.write_ready_bands(index, true)?;
+        }
+        Ok(())
+    }
+
+    fn write_ready_bands(&mut self, index: usize, at_end: bool) -> Result<()> {
+        let tile_size = self.settings.tile_size;
+        let overlap = self.settings.overlap();
+        let level_number = self.levels.len() - 1 - index;
+
+        loop {
+            let level = &mut self.levels[index];
+            let band_top = level.next_band * tile_size;
+            if band_top >= level.height {
+                return Ok(());
+            }
+            let top = band_top.saturating_sub(overlap);
+            let bottom = (band_top + tile_size + overlap).min(level.height);
+            if level.received < bottom && !at_end {
+                return Ok(());
+            }
+
+            let band = level.next_band;
+            let mut tiles = Vec::new();
+            for column in 0..level.width.div_ceil(tile_size) {
+                let left = (column * tile_size).saturating_sub(overlap);
+                let right = ((column + 1) * tile_size + overlap).min(level.width);
+                let mut samples = Vec::with_capacity(((right - left) * (bottom - top)) as usize * level.layout.channels);
+                for y in top..bottom {
+                    let row = &level.rows[(y - level.first_row) as usize];
+                    samples.extend_from_slice(&row[left as usize * level.layout.channels..right as usize * level.layout.channels]);
+                }
+                let tile = samples_to_image(right - left, bottom - top, level.layout.channels, level.layout.sixteen_bit, samples)?;
+                tiles.push((column, tile));
+            }
+
+            // Rows still needed by the next band's overlap stay
+            let keep_from = ((band + 1) * tile_size).saturating_sub(overlap);
+            while level.first_row < keep_from && !level.rows.is_empty() {
+                level.rows.pop_front();
+                level.first_row += 1;
+            }
+            level.next_band += 1;
+            let scale = level.scale;
+
+            for (column, tile) in tiles {
+                let path = self.tile_path(level_number, scale, column, band, &tile);
+                self.save_tile(&tile, &path)?;
+            }
+        }
+    }
+
+    fn tile_path(&self, level: usize, scale: u32, column: u32, row: u32, tile: &DynamicImage) -> PathBuf {
+        let extension = format_extension(self.settings.format);
+        match self.settings.layout {
+            PyramidLayout::Dzi => self
+                .root
+                .join(level.to_string())
+                .join(format!("{}_{}.{}", column, row, extension)),
+            PyramidLayout::Iiif => {
+                // Canonical IIIF 3.0 URIs: region in full-size pixels, then the output size
+                let span = self.settings.tile_size * scale;
+                let (x, y) = (column * span, row * span);
+                let (w, h) = (span.min(self.width - x), span.min(self.height - y));
+                let full = (w, h) == (self.width, self.height);
+                let region = if full { "full".to_string() } else { format!("{},{},{},{}", x, y, w, h) };
+                let size = if full && scale == 1 {
+                    "max".to_string()
+                } else {
+                    format!("{},{}", tile.width(), tile.height())
+                };
+                self.root
+                    .join(region)
+                    .join(size)
+                    .join("0")
+                    .join(format!("default.{}", extension))
+            }
+        }
+    }
+
+    fn save_tile(&mut self, tile: &DynamicImage, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let compressor = Compressor::new(self.settings.quality);
+        if self.settings.format == ImageFormat::Jpeg && tile.color().has_alpha() {
+            compressor.save_with_format(&Flattener::new(Color::WHITE).apply(tile), path, self.settings.format)?;
+        } else {
+            compressor.save_with_format(tile, path, self.settings.format)?;
+        }
+        self.tiles += 1;
+        Ok(())
+    }
+}
+
+struct LevelWriter {
+    width: u32,
+    height: u32,
+    /// Full-resolution pixels per pixel of this level
+    scale: u32,
+    layout: RowLayout,
+    /// Rows of the band being collected, starting at `first_row`
+    rows: VecDeque<Vec<u16>>,
+    first_row: u32,
+    received: u32,
+    next_band: u32,
+    /// Row waiting for its partner to be halved into the next level
+    pending: Option<Vec<u16>>,
+}
+
+impl LevelWriter {
+    fn new(width: u32, height: u32, scale: u32, layout: RowLayout) -> Self {
+        Self {
+            width,
+            height,
+            scale,
+            layout,
+            rows: VecDeque::new(),
+            first_row: 0,
+            received: 0,
+            next_band: 0,
+            pending: None,
+        }
+    }
+
+    // 2x2 box average into one row of the next level. Color is weighted by
+    // alpha so transparent pixels don't bleed into their neighbours.
+    fn reduce(&self, upper: &[u16], lower: Option<&[u16]>) -> Vec<u16> {
+        let channels = self.layout.channels;
+        let alpha = matches!(channels, 2 | 4).then(|| channels - 1);
+        let out_width = self.width.div_ceil(2) as usize;
+        let mut reduced = Vec::with_capacity(out_width * channels);
+
+        for x in 0..out_width {
+            let columns = [2 * x, (2 * x + 1).min(self.width as usize - 1)];
+            let rows = [Some(upper), lower];
+            let pixels: Vec<&[u16]> = rows
+                .iter()
+                .flatten()
+                .flat_map(|row| columns.iter().map(move |&c| &row[c * channels..(c + 1) * channels]))
+                .collect();
+
+            let alpha_sum: f32 = alpha.map_or(0.0, |a| pixels.iter().map(|p| p[a] as f32).sum());
+            for c in 0..channels {
+                let value = match alpha {
+                    Some(a) if c != a && alpha_sum > 0.0 => {
+                        pixels.iter().map(|p| p[c] as f32 * p[a] as f32).sum::<f32>() / alpha_sum
+                    }
+                    _ => pixels.iter().map(|p| p[c] as f32).sum::<f32>() / pixels.len() as f32,
+                };
+                reduced.push(value.round() as u16);
+            }
+        }
+        reduced
+    }
+}
+
+// Non-streamable formats are decoded whole and replayed row by row
+fn image_samples(image: &DynamicImage) -> (RowLayout, Vec<u16>) {
+    use image::ColorType;
+
+    // Float images come out as 16-bit
+    let color = image.color();
+    let sixteen_bit = color.bytes_per_pixel() / color.channel_count() > 1;
+    let (channels, samples): (usize, Vec<u16>) = match (color, sixteen_bit) {
+        (ColorType::L8, _) => (1, image.to_luma8().into_raw().into_iter().map(u16::from).collect()),
+        (ColorType::La8, _) => (2, image.to_luma_alpha8().into_raw().into_iter().map(u16::from).collect()),
+        (ColorType::L16, _) => (1, image.to_luma16().into_raw()),
+        (ColorType::La16, _) => (2, image.to_luma_alpha16().into_raw()),
+        (_, true) if color.has_alpha() => (4, image.to_rgba16().into_raw()),
+        (_, true) => (3, image.to_rgb16().into_raw()),
+        (_, false) if color.has_alpha() => (4, image.to_rgba8().into_raw().into_iter().map(u16::from).collect()),
+        (_, false) => (3, image.to_rgb8().into_raw().into_iter().map(u16::from).collect()),
+    };
+
+    let layout = RowLayout {
+        width: image.width(),
+        height: image.height(),
+        channels,
+        sixteen_bit,
+    };
+    (layout, samples)
+}
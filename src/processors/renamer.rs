@@ -0,0 +1,234 @@
+// pixie/src/processors/renamer.rs
+use crate::core::{ImageToolError, Result};
+use crate::processors::batch::collect_image_paths;
+use crate::processors::MetadataProcessor;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d_%H%M%S";
+
+enum Part {
+    Text(String),
+    Date(String),
+    Stem,
+    Ext,
+}
+
+/// Renames images after the date they were taken, read from EXIF
+/// DateTimeOriginal. Patterns combine `{date}` or `{date:FORMAT}` with
+/// `{stem}` and `{ext}` from the current name; a `/` in the pattern moves
+/// files into subdirectories of the directory being renamed.
+pub struct Renamer {
+    parts: Vec<Part>,
+    has_ext: bool,
+    recursive: bool,
+    dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RenameReport {
+    /// Old and new paths, in the order they were renamed
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    /// Files whose name already matched the pattern
+    pub unchanged: usize,
+    /// Files left alone, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+impl Renamer {
+    pub const DEFAULT_PATTERN: &'static str = "{date:%Y-%m-%d_%H%M%S}_{stem}";
+
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let close = rest[open..].find('}').map(|close| open + close).ok_or_else(|| {
+                ImageToolError::InvalidParameter(format!("Unclosed {{ in rename pattern: {}", pattern))
+            })?;
+            parts.push(match &rest[open + 1..close] {
+                "date" => Part::Date(DEFAULT_DATE_FORMAT.to_string()),
+                "stem" => Part::Stem,
+                "ext" => Part::Ext,
+                placeholder => match placeholder.strip_prefix("date:") {
+                    Some(format) => Part::Date(format.to_string()),
+                    None => {
+                        return Err(ImageToolError::InvalidParameter(format!(
+                            "Unknown placeholder {{{}}} in rename pattern, expected {{date}}, {{date:FORMAT}}, {{stem}} or {{ext}}",
+                            placeholder
+                        )))
+                    }
+                },
+            });
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if !parts.iter().any(|part| matches!(part, Part::Date(_))) {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "Rename pattern has no {{date}}: {}",
+                pattern
+            )));
+        }
+
+        let has_ext = parts.iter().any(|part| matches!(part, Part::Ext));
+        Ok(Self {
+            parts,
+            has_ext,
+            recursive: false,
+            dry_run: false,
+        })
+    }
+
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Work out the new names without renaming anything
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// New names are relative to `dir`, also for files in its
+    /// subdirectories. A name already in use gets `_1`, `_2` and so on
+    /// added to its stem.
+    pub fn rename_directory(&self, dir: &Path) -> Result<RenameReport> {
+        if !dir.is_dir() {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "Not a directory: {}",
+                dir.display()
+            )));
+        }
+
+        let metadata = MetadataProcessor::new();
+        let mut paths = collect_image_paths(dir, self.recursive);
+        paths.sort();
+
+        let mut report = RenameReport::default();
+        let mut taken = HashSet::new();
+        for path in paths {
+            let date = match metadata.read_metadata(&path) {
+                Ok(exif) => exif.and_then(|exif| metadata.date_taken(&exif)),
+                Err(e) => {
+                    report.skipped.push((path, e.to_string()));
+                    continue;
+                }
+            };
+            let Some(date) = date else {
+                report.skipped.push((path, "no DateTimeOriginal in EXIF".to_string()));
+                continue;
+            };
+
+            let target = free_name(dir.join(self.render(&path, &date)?), &path, &taken);
+            if target == path {
+                report.unchanged += 1;
+                taken.insert(target);
+                continue;
+            }
+
+            if !self.dry_run {
+                let renamed = match target.parent() {
+                    Some(parent) => std::fs::create_dir_all(parent),
+                    None => Ok(()),
+                }
+                .and_then(|()| std::fs::rename(&path, &target));
+                if let Err(e) = renamed {
+                    report.skipped.push((path, e.to_string()));
+                    continue;
+                }
+            }
+            log::info!("{} -> {}", path.display(), target.display());
+            taken.insert(target.clone());
+            report.renamed.push((path, target));
+        }
+
+        Ok(report)
+    }
+
+    // The new path relative to the directory being renamed
+    fn render(&self, path: &Path, date: &exif::DateTime) -> Result<PathBuf> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().map(|ext| ext.to_string_lossy());
+
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => name.push_str(text),
+                Part::Date(format) => name.push_str(&format_date(date, format)),
+                Part::Stem => name.push_str(&stem),
+                Part::Ext => name.push_str(ext.as_deref().unwrap_or_default()),
+            }
+        }
+        if let (false, Some(ext)) = (self.has_ext, &ext) {
+            name.push('.');
+            name.push_str(ext);
+        }
+
+        let relative = PathBuf::from(&name);
+        let valid = !name.ends_with('/')
+            && relative.components().all(|component| matches!(component, Component::Normal(_)));
+        if !valid {
+            return Err(ImageToolError::SecurityError(format!(
+                "Rename pattern gives a path outside the directory: {}",
+                name
+            )));
+        }
+        Ok(relative)
+    }
+}
+
+// `candidate`, or the first of candidate_1, candidate_2... that neither
+// exists nor was given to an earlier file
+fn free_name(candidate: PathBuf, source: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    let is_free = |path: &Path| path == source || (!path.exists() && !taken.contains(path));
+    if is_free(&candidate) {
+        return candidate;
+    }
+
+    let stem = candidate.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let ext = candidate.extension().map(|ext| ext.to_string_lossy().into_owned());
+    (1..)
+        .map(|n| {
+            let name = match &ext {
+                Some(ext) => format!("{}_{}.{}", stem, n, ext),
+                None => format!("{}_{}", stem, n),
+            };
+            candidate.with_file_name(name)
+        })
+        .find(|path| is_free(path))
+        .expect("unbounded")
+}
+
+// The strftime fields that make sense for a date without a time zone;
+// anything else is copied as is
+fn format_date(date: &exif::DateTime, format: &str) -> String {
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted.push_str(&format!("{:04}", date.year)),
+            Some('y') => formatted.push_str(&format!("{:02}", date.year % 100)),
+            Some('m') => formatted.push_str(&format!("{:02}", date.month)),
+            Some('d') => formatted.push_str(&format!("{:02}", date.day)),
+            Some('H') => formatted.push_str(&format!("{:02}", date.hour)),
+            Some('M') => formatted.push_str(&format!("{:02}", date.minute)),
+            Some('S') => formatted.push_str(&format!("{:02}", date.second)),
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    formatted
+}
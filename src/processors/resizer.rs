@@ -1,6 +1,8 @@
 // pixie/src/processors/resizer.rs
 use crate::core::{ImageToolError, ResizeAlgorithm, Result};
-use image::{DynamicImage, imageops::FilterType, GenericImageView};
+use crate::processors::SeamCarver;
+use crate::utils::{into_color, linear_to_srgb, srgb_to_linear};
+use image::{ColorType, DynamicImage, imageops::FilterType, GenericImageView, Rgba, Rgba32FImage};
 
 #[derive(Debug, Clone, Copy)]
 pub enum ResizeMode {
@@ -8,16 +10,57 @@ pub enum ResizeMode {
     Scale(f32),
     Width(u32),
     Height(u32),
+    MaxDimension(u32),
+    /// Exact size; the aspect change is made by carving seams
+    SeamCarve(u32, u32),
 }
 
+// Output rows resampled together; one band is one unit of parallel work
+const BAND_ROWS: usize = 64;
+
+// Sources at least this large are resized in parallel bands even when the
+// image crate has the filter, so one huge image doesn't leave cores idle
+#[cfg(not(target_arch = "wasm32"))]
+const PARALLEL_MIN_PIXELS: u64 = 4_000_000;
+
+type Kernel = Box<dyn Fn(f32) -> f32 + Send + Sync>;
+
 pub struct Resizer {
     algorithm: ResizeAlgorithm,
     keep_aspect: bool,
+    simd: bool,
+    no_upscale: bool,
+    premultiply: bool,
+    linear: bool,
 }
 
 impl Resizer {
     pub fn new(algorithm: ResizeAlgorithm, keep_aspect: bool) -> Self {
-        Self { algorithm, keep_aspect }
+        Self { algorithm, keep_aspect, simd: true, no_upscale: false, premultiply: true, linear: false }
+    }
+
+    pub fn with_no_upscale(mut self, no_upscale: bool) -> Self {
+        self.no_upscale = no_upscale;
+        self
+    }
+
+    /// Resample images with an alpha channel in premultiplied form so
+    /// transparent pixels don't darken the edges next to them
+    pub fn with_premultiply(mut self, premultiply: bool) -> Self {
+        self.premultiply = premultiply;
+        self
+    }
+
+    /// Resample in linear light instead of on sRGB-encoded values
+    pub fn with_linear(mut self, linear: bool) -> Self {
+        self.linear = linear;
+        self
+    }
+
+    // Only has an effect when built with the `simd` feature
+    pub fn with_simd(mut self, simd: bool) -> Self {
+        self.simd = simd;
+        self
     }
 
     pub fn resize(&self, image: &DynamicImage, mode: ResizeMode) -> DynamicImage {
@@ -36,13 +79,15 @@ impl Resizer {
             height
         );
 
-        let filter = self.get_filter_type();
+        if let ResizeMode::SeamCarve(..) = mode {
+            return self.seam_carve(image, width, height);
+        }
 
-        if self.keep_aspect {
-            image.resize(width, height, filter)
-        } else {
-            image.resize_exact(width, height, filter)
+        if let Some(resized) = self.resize_simd(image, width, height) {
+            return resized;
         }
+
+        self.resize_fallback(image, width, height, self.keep_aspect)
     }
 
     pub fn resize_exact(&self, image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
@@ -50,13 +95,136 @@ impl Resizer {
             return image.clone();
         }
 
-        let filter = self.get_filter_type();
-        image.resize_exact(width, height, filter)
+        if let Some(resized) = self.resize_simd(image, width, height) {
+            return resized;
+        }
+
+        self.resize_fallback(image, width, height, false)
+    }
+
+    // Scales uniformly until one side fits, then carves or inserts seams
+    // along the other. Shrinking covers the target and removes seams;
+    // growing fits inside it and inserts them.
+    fn seam_carve(&self, image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let scale_x = width as f32 / image.width() as f32;
+        let scale_y = height as f32 / image.height() as f32;
+        let grow = width as u64 * height as u64 > image.width() as u64 * image.height() as u64;
+        let scale = if grow { scale_x.min(scale_y) } else { scale_x.max(scale_y) };
+
+        let scaled_width = ((image.width() as f32 * scale).round() as u32).max(1);
+        let scaled_height = ((image.height() as f32 * scale).round() as u32).max(1);
+        let scaled = self.resize_exact(image, scaled_width, scaled_height);
+
+        let carved = SeamCarver::new().carve(&scaled, width, height);
+        into_color(DynamicImage::ImageRgba8(carved), image.color())
+    }
+
+    fn resize_fallback(&self, image: &DynamicImage, width: u32, height: u32, fit: bool) -> DynamicImage {
+        let resize = |image: &DynamicImage| match (self.get_filter_type(), self.convolution_kernel(image)) {
+            (_, Some((kernel, support))) => {
+                let resized = convolve(image, width, height, &kernel, support);
+                into_color(DynamicImage::ImageRgba32F(resized), image.color())
+            }
+            (Some(filter), None) if fit => image.resize(width, height, filter),
+            (Some(filter), None) => image.resize_exact(width, height, filter),
+            (None, None) => unreachable!("every algorithm has a filter or a kernel"),
+        };
+
+        let color = image.color();
+        let premultiply = self.premultiply && color.has_alpha();
+        // Float images are already linear
+        let linear = self.linear && !matches!(color, ColorType::Rgb32F | ColorType::Rgba32F);
+
+        // Nearest never mixes pixels, so there is nothing to weight
+        if !(premultiply || linear) || matches!(self.algorithm, ResizeAlgorithm::Nearest) {
+            return resize(image);
+        }
+
+        let mut working = image.to_rgba32f();
+        for pixel in working.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let rgb = if linear { [r, g, b].map(srgb_to_linear) } else { [r, g, b] };
+            let weight = if premultiply { a } else { 1.0 };
+            let [r, g, b] = rgb.map(|c| c * weight);
+            pixel.0 = [r, g, b, a];
+        }
+
+        let mut resized: Rgba32FImage = resize(&DynamicImage::ImageRgba32F(working)).into_rgba32f();
+        for pixel in resized.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let a = a.clamp(0.0, 1.0);
+            let rgb = match (premultiply, a > 0.0) {
+                (true, true) => [r / a, g / a, b / a],
+                (true, false) => [0.0; 3],
+                (false, _) => [r, g, b],
+            };
+            let [r, g, b] = if linear { rgb.map(|c| linear_to_srgb(c.clamp(0.0, 1.0))) } else { rgb };
+            pixel.0 = [r, g, b, a];
+        }
+
+        into_color(DynamicImage::ImageRgba32F(resized), color)
+    }
+
+    // fast_image_resize picks SSE4.1/AVX2/NEON kernels at runtime; pixel
+    // layouts it can't handle fall back to the image crate.
+    #[cfg(feature = "simd")]
+    fn resize_simd(&self, image: &DynamicImage, width: u32, height: u32) -> Option<DynamicImage> {
+        use fast_image_resize::{FilterType as FirFilter, ResizeAlg, ResizeOptions};
+
+        // The SIMD kernels work on the encoded values
+        if !self.simd || self.linear {
+            return None;
+        }
+
+        let algorithm = match self.algorithm {
+            ResizeAlgorithm::Nearest => ResizeAlg::Nearest,
+            ResizeAlgorithm::Box => ResizeAlg::Convolution(FirFilter::Box),
+            ResizeAlgorithm::Bilinear | ResizeAlgorithm::Triangle => ResizeAlg::Convolution(FirFilter::Bilinear),
+            ResizeAlgorithm::Bicubic | ResizeAlgorithm::CatmullRom => ResizeAlg::Convolution(FirFilter::CatmullRom),
+            ResizeAlgorithm::Mitchell => ResizeAlg::Convolution(FirFilter::Mitchell),
+            ResizeAlgorithm::Lanczos3 | ResizeAlgorithm::Lanczos(3) | ResizeAlgorithm::SeamCarve => {
+                ResizeAlg::Convolution(FirFilter::Lanczos3)
+            }
+            ResizeAlgorithm::Lanczos(_) => return None,
+        };
+
+        let mut resized = DynamicImage::new(width, height, image.color());
+        let options = ResizeOptions::new().resize_alg(algorithm).use_alpha(self.premultiply);
+
+        match fast_image_resize::Resizer::new().resize(image, &mut resized, &options) {
+            Ok(()) => Some(resized),
+            Err(e) => {
+                log::debug!("SIMD resize unavailable ({}), using fallback", e);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "simd"))]
+    fn resize_simd(&self, _image: &DynamicImage, _width: u32, _height: u32) -> Option<DynamicImage> {
+        None
     }
 
     fn calculate_dimensions(&self, image: &DynamicImage, mode: ResizeMode) -> (u32, u32) {
         let (orig_width, orig_height) = image.dimensions();
-        
+        self.target_dimensions(orig_width, orig_height, mode)
+    }
+
+    pub fn target_dimensions(&self, orig_width: u32, orig_height: u32, mode: ResizeMode) -> (u32, u32) {
+        let (width, height) = self.requested_dimensions(orig_width, orig_height, mode);
+
+        if self.no_upscale && (width > orig_width || height > orig_height) {
+            log::debug!(
+                "Requested {}x{} exceeds source {}x{}, not upscaling",
+                width, height, orig_width, orig_height
+            );
+            return (orig_width, orig_height);
+        }
+
+        (width, height)
+    }
+
+    fn requested_dimensions(&self, orig_width: u32, orig_height: u32, mode: ResizeMode) -> (u32, u32) {
         match mode {
             ResizeMode::Absolute(w, h) => {
                 if w == 0 && h == 0 {
@@ -94,6 +262,17 @@ impl Resizer {
                 let width = (orig_width as f32 * ratio).round() as u32;
                 (width.max(1), height)
             }
+            ResizeMode::SeamCarve(w, h) => (w.max(1), h.max(1)),
+            ResizeMode::MaxDimension(max) => {
+                let longest = orig_width.max(orig_height);
+                if max == 0 || longest <= max {
+                    return (orig_width, orig_height);
+                }
+                let ratio = max as f32 / longest as f32;
+                let new_width = (orig_width as f32 * ratio).round() as u32;
+                let new_height = (orig_height as f32 * ratio).round() as u32;
+                (new_width.clamp(1, max), new_height.clamp(1, max))
+            }
         }
     }
 
@@ -124,12 +303,57 @@ impl Resizer {
         (new_w.max(1), new_h.max(1))
     }
 
-    fn get_filter_type(&self) -> FilterType {
+    fn get_filter_type(&self) -> Option<FilterType> {
+        match self.algorithm {
+            ResizeAlgorithm::Nearest => Some(FilterType::Nearest),
+            ResizeAlgorithm::Bilinear | ResizeAlgorithm::Triangle => Some(FilterType::Triangle),
+            ResizeAlgorithm::Bicubic | ResizeAlgorithm::CatmullRom => Some(FilterType::CatmullRom),
+            // The uniform part of a seam-carving resize
+            ResizeAlgorithm::Lanczos3 | ResizeAlgorithm::Lanczos(3) | ResizeAlgorithm::SeamCarve => {
+                Some(FilterType::Lanczos3)
+            }
+            ResizeAlgorithm::Box | ResizeAlgorithm::Mitchell | ResizeAlgorithm::Lanczos(_) => None,
+        }
+    }
+
+    // Kernels resized through `convolve`: the ones the image crate lacks,
+    // plus its own filters for sources worth splitting across threads.
+    // The target size is already aspect-corrected, so fitting isn't needed.
+    fn convolution_kernel(&self, image: &DynamicImage) -> Option<(Kernel, f32)> {
+        if let Some(custom) = self.custom_kernel() {
+            return Some(custom);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if image.width() as u64 * image.height() as u64 >= PARALLEL_MIN_PIXELS && rayon::current_num_threads() > 1 {
+            let kernel: Option<(Kernel, f32)> = match self.get_filter_type()? {
+                FilterType::Triangle => Some((Box::new(|x: f32| (1.0 - x.abs()).max(0.0)), 1.0)),
+                FilterType::CatmullRom => Some((Box::new(catmull_rom), 2.0)),
+                FilterType::Lanczos3 => Some((Box::new(|x: f32| lanczos(x, 3.0)), 3.0)),
+                // Nearest is a copy per pixel, not worth the bands
+                _ => None,
+            };
+            if kernel.is_some() {
+                log::debug!("Resizing in bands of {} rows across {} threads", BAND_ROWS, rayon::current_num_threads());
+            }
+            return kernel;
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        let _ = image;
+        None
+    }
+
+    /// Kernels the image crate doesn't offer, with their support radius
+    fn custom_kernel(&self) -> Option<(Kernel, f32)> {
         match self.algorithm {
-            ResizeAlgorithm::Nearest => FilterType::Nearest,
-            ResizeAlgorithm::Bilinear => FilterType::Triangle,
-            ResizeAlgorithm::Bicubic => FilterType::CatmullRom,
-            ResizeAlgorithm::Lanczos3 => FilterType::Lanczos3,
+            ResizeAlgorithm::Box => Some((Box::new(|x: f32| if x > -0.5 && x <= 0.5 { 1.0 } else { 0.0 }), 0.5)),
+            ResizeAlgorithm::Mitchell => Some((Box::new(mitchell), 2.0)),
+            ResizeAlgorithm::Lanczos(window) => {
+                let window = window as f32;
+                Some((Box::new(move |x: f32| lanczos(x, window)), window))
+            }
+            _ => None,
         }
     }
 
@@ -146,4 +370,123 @@ impl Resizer {
             ResizeMode::Absolute(0, 0)
         }
     }
+}
+
+// Separable resampling in two passes, rows then columns. The output is
+// built in bands of rows, in parallel where threads exist; each band only
+// converts and filters the source rows its kernel reaches.
+fn convolve(image: &DynamicImage, width: u32, height: u32, kernel: &Kernel, support: f32) -> Rgba32FImage {
+    let columns = kernel_weights(image.width(), width, kernel, support);
+    let rows = kernel_weights(image.height(), height, kernel, support);
+    let mut output = Rgba32FImage::new(width, height);
+    let row_len = width as usize * 4;
+
+    let resample_band = |(band, out): (usize, &mut [f32])| {
+        let band_rows = &rows[band * BAND_ROWS..][..out.len() / row_len];
+        let first = band_rows[0].0;
+        let last = band_rows
+            .iter()
+            .map(|(start, taps)| start + taps.len() as u32)
+            .max()
+            .expect("bands are never empty");
+        let source = image.crop_imm(0, first, image.width(), last - first).to_rgba32f();
+
+        let mut horizontal = Rgba32FImage::new(width, source.height());
+        for y in 0..source.height() {
+            for (x, (start, taps)) in columns.iter().enumerate() {
+                let pixel = taps.iter().enumerate().fold([0.0; 4], |mut sum, (i, weight)| {
+                    let source = source.get_pixel(start + i as u32, y).0;
+                    (0..4).for_each(|c| sum[c] += source[c] * weight);
+                    sum
+                });
+                horizontal.put_pixel(x as u32, y, Rgba(pixel));
+            }
+        }
+
+        for ((start, taps), out_row) in band_rows.iter().zip(out.chunks_exact_mut(row_len)) {
+            for (x, out_pixel) in out_row.chunks_exact_mut(4).enumerate() {
+                let pixel = taps.iter().enumerate().fold([0.0; 4], |mut sum, (i, weight)| {
+                    let source = horizontal.get_pixel(x as u32, start - first + i as u32).0;
+                    (0..4).for_each(|c| sum[c] += source[c] * weight);
+                    sum
+                });
+                out_pixel.copy_from_slice(&pixel);
+            }
+        }
+    };
+
+    let band_len = (BAND_ROWS * row_len).max(1);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use rayon::prelude::*;
+        output.par_chunks_mut(band_len).enumerate().for_each(resample_band);
+    }
+    #[cfg(target_arch = "wasm32")]
+    output.chunks_mut(band_len).enumerate().for_each(resample_band);
+
+    output
+}
+
+/// First source index and normalized weights for every destination index.
+/// The kernel is stretched when downscaling so it covers every source pixel.
+fn kernel_weights(src: u32, dst: u32, kernel: &dyn Fn(f32) -> f32, support: f32) -> Vec<(u32, Vec<f32>)> {
+    let ratio = src as f32 / dst as f32;
+    let scale = ratio.max(1.0);
+    let radius = support * scale;
+
+    (0..dst)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * ratio;
+            let start = (center - radius).floor().max(0.0) as u32;
+            let end = ((center + radius).ceil() as u32).clamp(start + 1, src);
+            let mut taps: Vec<f32> = (start..end)
+                .map(|j| kernel((j as f32 + 0.5 - center) / scale))
+                .collect();
+            let total: f32 = taps.iter().sum();
+            if total != 0.0 {
+                taps.iter_mut().for_each(|w| *w /= total);
+            }
+            (start, taps)
+        })
+        .collect()
+}
+
+// Keys cubic with a = -0.5, as the image crate's CatmullRom
+fn catmull_rom(x: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        1.5 * x * x * x - 2.5 * x * x + 1.0
+    } else if x < 2.0 {
+        -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+    } else {
+        0.0
+    }
+}
+
+// Mitchell-Netravali with B = C = 1/3
+fn mitchell(x: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        (7.0 * x * x * x - 12.0 * x * x + 16.0 / 3.0) / 6.0
+    } else if x < 2.0 {
+        (-7.0 / 3.0 * x * x * x + 12.0 * x * x - 20.0 * x + 32.0 / 3.0) / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn lanczos(x: f32, window: f32) -> f32 {
+    if x.abs() >= window {
+        return 0.0;
+    }
+    sinc(x) * sinc(x / window)
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
 }
\ No newline at end of file
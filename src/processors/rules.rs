@@ -0,0 +1,259 @@
+// pixie/src/processors/rules.rs
+use crate::core::{ImageToolError, ProcessConfig, Result};
+use crate::processors::pipeline::resize_query;
+use crate::processors::{count_colors, Loader};
+use crate::utils::unquote;
+use image::{ImageDecoder, ImageFormat, ImageReader};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Processing options for the batch inputs a condition holds for, as in
+/// `--if "width>1600" --then "resize:1600"`.
+///
+/// Conditions compare `width`, `height`, `megapixels`, `size` (file bytes,
+/// or with a K or M suffix), `colors`, `alpha` and `format` using `==`,
+/// `!=`, `<`, `<=`, `>` and `>=`, joined with `&&` and `||` (`&&` binds
+/// tighter). A bare `alpha` means `alpha==true`. Actions are
+/// comma-separated options as the server takes them, `name` or
+/// `name:value`; `resize` takes `W`, `WxH` or `N%`.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    // Holds when all comparisons of any one group hold
+    condition: Vec<Vec<Comparison>>,
+    action: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Width,
+    Height,
+    Megapixels,
+    Size,
+    Colors,
+    Alpha,
+    Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+    Format(ImageFormat),
+}
+
+#[derive(Debug, Clone)]
+struct Comparison {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+/// What rule conditions are checked against
+#[derive(Debug, Clone, Default)]
+pub struct ImageFacts {
+    pub width: u32,
+    pub height: u32,
+    pub format: Option<ImageFormat>,
+    /// File size in bytes
+    pub size: u64,
+    pub alpha: bool,
+    /// Distinct colors, when counted; see `ImageFacts::read`
+    pub colors: Option<usize>,
+}
+
+impl ImageFacts {
+    /// Reads the facts from the file header. Counting colors decodes the
+    /// whole image, so it is only done given a limit, past which counting
+    /// stops at `limit + 1`.
+    pub fn read(path: &Path, colors_limit: Option<usize>) -> Result<Self> {
+        let size = std::fs::metadata(path)?.len();
+        let reader = ImageReader::open(path)?.with_guessed_format()?;
+        let format = reader.format();
+        let decoder = reader.into_decoder()?;
+        let (width, height) = decoder.dimensions();
+        let alpha = decoder.color_type().has_alpha();
+        drop(decoder);
+
+        let colors = match colors_limit {
+            Some(limit) => Some(count_colors(&Loader::new().load(path)?, limit)),
+            None => None,
+        };
+        Ok(Self {
+            width,
+            height,
+            format,
+            size,
+            alpha,
+            colors,
+        })
+    }
+}
+
+impl Rule {
+    pub fn new(condition: &str, action: &str) -> Result<Self> {
+        let condition = condition
+            .split("||")
+            .map(|group| group.split("&&").map(parse_comparison).collect::<Result<Vec<_>>>())
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut query = HashMap::new();
+        for item in action.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (name, value) = match item.split_once(':') {
+                Some((name, value)) => (name.trim().replace('_', "-"), unquote(value)),
+                None => (item.replace('_', "-"), "true".to_string()),
+            };
+            match name.as_str() {
+                "resize" => resize_query(&value, &mut query)?,
+                _ => {
+                    query.insert(name, value);
+                }
+            }
+        }
+        if query.is_empty() {
+            return Err(ImageToolError::InvalidParameter(format!("Rule action '{}' is empty", action)));
+        }
+
+        let rule = Self { condition, action: query };
+        // Checked now, so a bad action fails before any input is read
+        rule.apply(&ProcessConfig::default())?;
+        Ok(rule)
+    }
+
+    pub fn matches(&self, facts: &ImageFacts) -> bool {
+        self.condition
+            .iter()
+            .any(|group| group.iter().all(|comparison| comparison.holds(facts)))
+    }
+
+    /// `config` with the rule's options laid over it
+    pub fn apply(&self, config: &ProcessConfig) -> Result<ProcessConfig> {
+        let config = config.apply_options(&self.action)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The color count to count up to for this rule, when it asks about colors
+    pub fn colors_limit(&self) -> Option<usize> {
+        self.condition
+            .iter()
+            .flatten()
+            .filter_map(|comparison| match (comparison.field, &comparison.value) {
+                (Field::Colors, Value::Number(n)) => Some(n.max(0.0).ceil() as usize),
+                _ => None,
+            })
+            .max()
+    }
+}
+
+fn parse_comparison(text: &str) -> Result<Comparison> {
+    let invalid = |reason: &str| {
+        ImageToolError::InvalidParameter(format!("Invalid rule condition '{}': {}", text.trim(), reason))
+    };
+    let text = text.trim();
+
+    let (name, op, value) = match text.find(['=', '!', '<', '>']) {
+        Some(start) => {
+            let rest = &text[start..];
+            let (op, len) = if rest.starts_with("==") {
+                (Op::Eq, 2)
+            } else if rest.starts_with("!=") {
+                (Op::Ne, 2)
+            } else if rest.starts_with("<=") {
+                (Op::Le, 2)
+            } else if rest.starts_with(">=") {
+                (Op::Ge, 2)
+            } else if rest.starts_with('<') {
+                (Op::Lt, 1)
+            } else if rest.starts_with('>') {
+                (Op::Gt, 1)
+            } else {
+                return Err(invalid("expected ==, !=, <, <=, > or >="));
+            };
+            (text[..start].trim(), op, unquote(&rest[len..]).to_lowercase())
+        }
+        // A bare flag
+        None => (text, Op::Eq, "true".to_string()),
+    };
+
+    let field = match name.to_lowercase().as_str() {
+        "width" => Field::Width,
+        "height" => Field::Height,
+        "megapixels" => Field::Megapixels,
+        "size" => Field::Size,
+        "colors" => Field::Colors,
+        "alpha" => Field::Alpha,
+        "format" => Field::Format,
+        _ => return Err(invalid("unknown field")),
+    };
+
+    let value = match field {
+        Field::Alpha => Value::Bool(match value.as_str() {
+            "true" => true,
+            "false" => false,
+            _ => return Err(invalid("alpha is true or false")),
+        }),
+        Field::Format => Value::Format(
+            ImageFormat::from_extension(&value).ok_or_else(|| invalid("unknown format"))?,
+        ),
+        Field::Size => {
+            let (number, unit) = match value.trim_end_matches('b') {
+                v if v.ends_with('k') => (&v[..v.len() - 1], 1024.0),
+                v if v.ends_with('m') => (&v[..v.len() - 1], 1024.0 * 1024.0),
+                _ => (value.as_str(), 1.0),
+            };
+            Value::Number(number.trim().parse::<f64>().map_err(|_| invalid("expected a size"))? * unit)
+        }
+        _ => Value::Number(value.parse().map_err(|_| invalid("expected a number"))?),
+    };
+    if matches!(value, Value::Bool(_) | Value::Format(_)) && !matches!(op, Op::Eq | Op::Ne) {
+        return Err(invalid("only == and != apply here"));
+    }
+
+    Ok(Comparison { field, op, value })
+}
+
+impl Comparison {
+    fn holds(&self, facts: &ImageFacts) -> bool {
+        let actual = match self.field {
+            Field::Width => facts.width as f64,
+            Field::Height => facts.height as f64,
+            Field::Megapixels => facts.width as f64 * facts.height as f64 / 1_000_000.0,
+            Field::Size => facts.size as f64,
+            Field::Colors => match facts.colors {
+                Some(colors) => colors as f64,
+                None => return false,
+            },
+            Field::Alpha => return self.equal(matches!(self.value, Value::Bool(alpha) if alpha == facts.alpha)),
+            Field::Format => return self.equal(matches!(self.value, Value::Format(format) if Some(format) == facts.format)),
+        };
+        let Value::Number(expected) = self.value else {
+            return false;
+        };
+        match self.op {
+            Op::Eq => actual == expected,
+            Op::Ne => actual != expected,
+            Op::Lt => actual < expected,
+            Op::Le => actual <= expected,
+            Op::Gt => actual > expected,
+            Op::Ge => actual >= expected,
+        }
+    }
+
+    fn equal(&self, same: bool) -> bool {
+        if self.op == Op::Ne {
+            !same
+        } else {
+            same
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// pixie/src/processors/seam.rs
+use image::{DynamicImage, RgbaImage};
+
+/// Content-aware retargeting: removes or duplicates the connected paths of
+/// pixels with the least detail, so subjects keep their proportions while
+/// the canvas changes shape.
+pub struct SeamCarver;
+
+impl SeamCarver {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Carves `image` to exactly `width` x `height`. Meant for modest
+    /// changes; scale uniformly first so only the aspect change is carved.
+    pub fn carve(&self, image: &DynamicImage, width: u32, height: u32) -> RgbaImage {
+        let mut grid = Grid::from_image(&image.to_rgba8());
+
+        log::debug!(
+            "Seam carving {}x{} to {}x{}",
+            grid.width,
+            grid.height,
+            width,
+            height
+        );
+
+        grid = grid.retarget_width(width.max(1) as usize);
+        grid = grid.transpose().retarget_width(height.max(1) as usize).transpose();
+        grid.into_image()
+    }
+}
+
+impl Default for SeamCarver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
+struct Grid {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 4]>,
+}
+
+impl Grid {
+    fn from_image(image: &RgbaImage) -> Self {
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image.pixels().map(|p| p.0).collect(),
+        }
+    }
+
+    fn into_image(self) -> RgbaImage {
+        let raw = self.pixels.into_iter().flatten().collect();
+        RgbaImage::from_raw(self.width as u32, self.height as u32, raw)
+            .expect("grid size matches its pixel count")
+    }
+
+    // Horizontal seams are vertical seams of the transposed grid
+    fn transpose(&self) -> Self {
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        for x in 0..self.width {
+            for y in 0..self.height {
+                pixels.push(self.pixels[y * self.width + x]);
+            }
+        }
+        Self {
+            width: self.height,
+            height: self.width,
+            pixels,
+        }
+    }
+
+    fn retarget_width(mut self, target: usize) -> Self {
+        while self.width > target {
+            let seam = self.find_seam();
+            self.remove_seam(&seam);
+        }
+        while self.width < target {
+            // Duplicating the same seam over and over would just stretch it,
+            // so each pass inserts at most half the current width
+            let count = (target - self.width).min(self.width.div_ceil(2));
+            self = self.insert_seams(count);
+        }
+        self
+    }
+
+    fn luma(&self) -> Vec<f32> {
+        self.pixels
+            .iter()
+            .map(|p| (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) * p[3] as f32 / 255.0)
+            .collect()
+    }
+
+    // Gradient magnitude with clamped borders
+    fn energy(&self) -> Vec<f32> {
+        let luma = self.luma();
+        let (w, h) = (self.width, self.height);
+        let mut energy = vec![0.0; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let at = |x: usize, y: usize| luma[y * w + x];
+                let dx = at((x + 1).min(w - 1), y) - at(x.saturating_sub(1), y);
+                let dy = at(x, (y + 1).min(h - 1)) - at(x, y.saturating_sub(1));
+                energy[y * w + x] = dx.abs() + dy.abs();
+            }
+        }
+        energy
+    }
+
+    /// Column of the lowest-energy 8-connected top-to-bottom path, per row
+    fn find_seam(&self) -> Vec<usize> {
+        let (w, h) = (self.width, self.height);
+        let mut cost = self.energy();
+        for y in 1..h {
+            for x in 0..w {
+                let above = &cost[(y - 1) * w..y * w];
+                let best = above[x.saturating_sub(1)..(x + 2).min(w)]
+                    .iter()
+                    .copied()
+                    .fold(f32::INFINITY, f32::min);
+                cost[y * w + x] += best;
+            }
+        }
+
+        let last = &cost[(h - 1) * w..];
+        let mut x = (0..w).min_by(|&a, &b| last[a].total_cmp(&last[b])).unwrap_or(0);
+        let mut seam = vec![0; h];
+        seam[h - 1] = x;
+        for y in (0..h - 1).rev() {
+            let row = &cost[y * w..(y + 1) * w];
+            x = (x.saturating_sub(1)..(x + 2).min(w))
+                .min_by(|&a, &b| row[a].total_cmp(&row[b]))
+                .unwrap_or(x);
+            seam[y] = x;
+        }
+        seam
+    }
+
+    fn remove_seam(&mut self, seam: &[usize]) {
+        let w = self.width;
+        let mut pixels = Vec::with_capacity((w - 1) * self.height);
+        for (y, &skip) in seam.iter().enumerate() {
+            let row = &self.pixels[y * w..(y + 1) * w];
+            pixels.extend_from_slice(&row[..skip]);
+            pixels.extend_from_slice(&row[skip + 1..]);
+        }
+        self.pixels = pixels;
+        self.width -= 1;
+    }
+
+    /// Finds the `count` cheapest seams on a shrinking copy, then widens the
+    /// original by blending each of them with its right-hand neighbour
+    fn insert_seams(self, count: usize) -> Self {
+        let (w, h) = (self.width, self.height);
+        let mut scratch = self.clone();
+        // Original column of every scratch pixel
+        let mut origin: Vec<usize> = (0..h).flat_map(|_| 0..w).collect();
+        let mut duplicated = vec![Vec::with_capacity(count); h];
+
+        for _ in 0..count {
+            let seam = scratch.find_seam();
+            let sw = scratch.width;
+            let mut kept = Vec::with_capacity((sw - 1) * h);
+            for (y, &x) in seam.iter().enumerate() {
+                duplicated[y].push(origin[y * sw + x]);
+                kept.extend_from_slice(&origin[y * sw..y * sw + x]);
+                kept.extend_from_slice(&origin[y * sw + x + 1..(y + 1) * sw]);
+            }
+            origin = kept;
+            scratch.remove_seam(&seam);
+        }
+
+        let mut pixels = Vec::with_capacity((w + count) * h);
+        for (y, columns) in duplicated.iter_mut().enumerate() {
+            columns.sort_unstable();
+            let row = &self.pixels[y * w..(y + 1) * w];
+            let mut next = columns.iter().peekable();
+            for x in 0..w {
+                pixels.push(row[x]);
+                while next.next_if(|&&c| c == x).is_some() {
+                    let right = row[(x + 1).min(w - 1)];
+                    pixels.push(std::array::from_fn(|c| ((row[x][c] as u16 + right[c] as u16) / 2) as u8));
+                }
+            }
+        }
+
+        Self {
+            width: w + count,
+            height: h,
+            pixels,
+        }
+    }
+}
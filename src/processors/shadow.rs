@@ -0,0 +1,71 @@
+// pixie/src/processors/shadow.rs
+use crate::core::Shadow;
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+/// How dark the shadow is where the image is fully opaque
+const SHADOW_OPACITY: f32 = 0.5;
+
+/// Places the image on a larger transparent canvas, optionally with a
+/// soft drop shadow beneath it
+pub struct Canvas {
+    padding: Option<u32>,
+    shadow: Option<Shadow>,
+}
+
+impl Canvas {
+    /// `padding` pixels of canvas on every side; when unset, just enough
+    /// for the shadow to fit
+    pub fn new(padding: Option<u32>) -> Self {
+        Self { padding, shadow: None }
+    }
+
+    pub fn with_shadow(mut self, shadow: Option<Shadow>) -> Self {
+        self.shadow = shadow;
+        self
+    }
+
+    /// Canvas added on each side of the image
+    pub fn margin(&self) -> u32 {
+        self.padding.unwrap_or_else(|| self.shadow.map_or(0, |shadow| reach(&shadow)))
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let margin = self.margin();
+        let source = image.to_rgba8();
+        let (width, height) = (source.width() + 2 * margin, source.height() + 2 * margin);
+
+        let mut canvas = match self.shadow {
+            Some(shadow) => shadow_layer(&source, &shadow, margin, width, height),
+            None => RgbaImage::new(width, height),
+        };
+        imageops::overlay(&mut canvas, &source, margin as i64, margin as i64);
+        DynamicImage::ImageRgba8(canvas)
+    }
+}
+
+// The blur fades out by three sigmas, and the offset pushes one side further
+fn reach(shadow: &Shadow) -> u32 {
+    let offset = shadow.offset_x.unsigned_abs().max(shadow.offset_y.unsigned_abs());
+    (shadow.blur * 3.0).ceil() as u32 + offset
+}
+
+fn shadow_layer(source: &RgbaImage, shadow: &Shadow, margin: u32, width: u32, height: u32) -> RgbaImage {
+    // Transparent pixels carry the shadow color too, so the blur doesn't
+    // darken a light shadow toward black at its edges
+    let color = shadow.color;
+    let mut layer = RgbaImage::from_pixel(width, height, Rgba([color.r, color.g, color.b, 0]));
+    for (x, y, pixel) in source.enumerate_pixels() {
+        let sx = (margin + x) as i64 + shadow.offset_x as i64;
+        let sy = (margin + y) as i64 + shadow.offset_y as i64;
+        if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+            continue;
+        }
+        let alpha = (pixel.0[3] as f32 * SHADOW_OPACITY).round() as u8;
+        layer.put_pixel(sx as u32, sy as u32, Rgba([color.r, color.g, color.b, alpha]));
+    }
+    if shadow.blur > 0.0 {
+        imageops::fast_blur(&layer, shadow.blur)
+    } else {
+        layer
+    }
+}
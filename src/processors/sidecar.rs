@@ -0,0 +1,207 @@
+// pixie/src/processors/sidecar.rs
+use crate::core::{Result, SidecarFormat};
+use crate::processors::metadata::display_field;
+use crate::processors::MetadataProcessor;
+use crate::utils::json_escape;
+use exif::{Exif, Field, In, Tag, Value};
+use std::path::{Path, PathBuf};
+
+const TIFF_NS: &str = "http://ns.adobe.com/tiff/1.0/";
+const EXIF_NS: &str = "http://ns.adobe.com/exif/1.0/";
+
+/// Dumps an image's EXIF and XMP into a sidecar file, so the metadata
+/// survives outputs that have it stripped.
+pub struct MetadataExporter {
+    format: SidecarFormat,
+    metadata: MetadataProcessor,
+}
+
+impl MetadataExporter {
+    pub fn new(format: SidecarFormat) -> Self {
+        Self { format, metadata: MetadataProcessor::new() }
+    }
+
+    /// The output's file name with `.json` or `.xmp` appended, so that
+    /// `photo.jpg` and `photo.png` get separate sidecars
+    pub fn sidecar_path(&self, output_path: &Path) -> PathBuf {
+        let extension = match self.format {
+            SidecarFormat::Json => "json",
+            SidecarFormat::Xmp => "xmp",
+        };
+        let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(extension);
+        output_path.with_file_name(name)
+    }
+
+    /// The sidecar for an image file's contents, or None when it has
+    /// neither EXIF nor XMP
+    pub fn export(&self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let exif = self.metadata.read_metadata_from_bytes(data)?;
+        let xmp = xmp_packet(data);
+        if exif.is_none() && xmp.is_none() {
+            return Ok(None);
+        }
+
+        let sidecar = match self.format {
+            SidecarFormat::Json => self.json(exif.as_ref(), xmp),
+            SidecarFormat::Xmp => self.xmp(exif.as_ref(), xmp),
+        };
+        Ok(Some(sidecar.into_bytes()))
+    }
+
+    fn json(&self, exif: Option<&Exif>, xmp: Option<&str>) -> String {
+        let mut ifds: Vec<(In, Vec<String>)> = Vec::new();
+        if let Some(exif) = exif {
+            for field in exif.fields() {
+                let entry = format!(
+                    "\"{}\": \"{}\"",
+                    json_escape(&field.tag.to_string()),
+                    json_escape(&display_field(field, exif))
+                );
+                match ifds.iter_mut().find(|(ifd, _)| *ifd == field.ifd_num) {
+                    Some((_, entries)) => entries.push(entry),
+                    None => ifds.push((field.ifd_num, vec![entry])),
+                }
+            }
+        }
+
+        let ifds: Vec<String> = ifds
+            .iter()
+            .map(|(ifd, entries)| {
+                let name = match *ifd {
+                    In::PRIMARY => "primary".to_string(),
+                    In::THUMBNAIL => "thumbnail".to_string(),
+                    other => format!("ifd{}", other.index()),
+                };
+                format!("    \"{}\": {{\n      {}\n    }}", name, entries.join(",\n      "))
+            })
+            .collect();
+        let exif = if ifds.is_empty() { "{}".to_string() } else { format!("{{\n{}\n  }}", ifds.join(",\n")) };
+        let xmp = xmp.map_or("null".to_string(), |xmp| format!("\"{}\"", json_escape(xmp)));
+        format!("{{\n  \"exif\": {},\n  \"xmp\": {}\n}}\n", exif, xmp)
+    }
+
+    // EXIF fields go in a description of their own; descriptions and
+    // namespaces from the source's XMP are carried over as they are
+    fn xmp(&self, exif: Option<&Exif>, xmp: Option<&str>) -> String {
+        let (source_namespaces, source_body) = xmp.and_then(rdf_parts).unwrap_or_default();
+
+        let mut properties = String::new();
+        let fields = exif.map(|exif| exif.fields()).into_iter().flatten();
+        for field in fields.filter(|field| field.ifd_num == In::PRIMARY && field.tag != Tag::MakerNote) {
+            let name = field.tag.to_string();
+            if !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+                continue;
+            }
+            let prefix = match field.tag.context() {
+                exif::Context::Tiff => "tiff",
+                _ => "exif",
+            };
+            // The source's XMP has the final say on properties it already sets
+            let property = format!("{}:{}", prefix, name);
+            if source_body.contains(&format!("<{}", property)) || source_body.contains(&format!(" {}=", property)) {
+                continue;
+            }
+
+            let values = xmp_values(field);
+            let value = match values.as_slice() {
+                [] => continue,
+                [value] => xml_escape(value),
+                values => {
+                    let items: String = values
+                        .iter()
+                        .map(|value| format!("<rdf:li>{}</rdf:li>", xml_escape(value)))
+                        .collect();
+                    format!("<rdf:Seq>{}</rdf:Seq>", items)
+                }
+            };
+            properties.push_str(&format!("   <{0}>{1}</{0}>\n", property, value));
+        }
+
+        let mut packet = String::from("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+        packet.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+        packet.push_str(&format!(
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\"{}>\n",
+            source_namespaces
+        ));
+        if !properties.is_empty() {
+            packet.push_str(&format!(
+                "  <rdf:Description rdf:about=\"\" xmlns:tiff=\"{}\" xmlns:exif=\"{}\">\n{}  </rdf:Description>\n",
+                TIFF_NS, EXIF_NS, properties
+            ));
+        }
+        if !source_body.trim().is_empty() {
+            packet.push_str(&format!("  {}\n", source_body.trim()));
+        }
+        packet.push_str(" </rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>\n");
+        packet
+    }
+}
+
+// The XMP packet embedded in an image. Every container keeps it as plain
+// text, so it is found by scanning rather than by parsing each format.
+fn xmp_packet(data: &[u8]) -> Option<&str> {
+    let start = find(data, b"<x:xmpmeta")?;
+    let end = start + find(&data[start..], b"</x:xmpmeta>")? + b"</x:xmpmeta>".len();
+    std::str::from_utf8(&data[start..end]).ok()
+}
+
+// The namespace declarations on `<rdf:RDF>` other than rdf's own, and
+// everything inside it
+fn rdf_parts(xmp: &str) -> Option<(String, String)> {
+    let start = xmp.find("<rdf:RDF")?;
+    let open_end = start + xmp[start..].find('>')?;
+    let close = xmp.find("</rdf:RDF>")?;
+    if close < open_end {
+        return None;
+    }
+
+    let attributes = xmp[start + "<rdf:RDF".len()..open_end].trim_end_matches('/');
+    let namespaces: String = attributes
+        .split_whitespace()
+        .filter(|attribute| attribute.starts_with("xmlns:") && !attribute.starts_with("xmlns:rdf="))
+        .map(|attribute| format!(" {}", attribute))
+        .collect();
+    Some((namespaces, xmp[open_end + 1..close].to_string()))
+}
+
+// XMP writes rationals as fractions and dates in ISO 8601
+fn xmp_values(field: &Field) -> Vec<String> {
+    match &field.value {
+        Value::Ascii(strings) => strings
+            .iter()
+            .map(|bytes| match exif::DateTime::from_ascii(bytes) {
+                Ok(date) if matches!(field.tag, Tag::DateTime | Tag::DateTimeOriginal | Tag::DateTimeDigitized) => format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                    date.year, date.month, date.day, date.hour, date.minute, date.second
+                ),
+                _ => String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string(),
+            })
+            .collect(),
+        Value::Rational(values) => values.iter().map(|r| format!("{}/{}", r.num, r.denom)).collect(),
+        Value::SRational(values) => values.iter().map(|r| format!("{}/{}", r.num, r.denom)).collect(),
+        Value::Byte(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::Short(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::Long(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::SByte(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::SShort(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::SLong(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::Float(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::Double(values) => values.iter().map(|v| v.to_string()).collect(),
+        Value::Undefined(..) => vec![field.display_value().to_string()],
+        Value::Unknown(..) => Vec::new(),
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
@@ -0,0 +1,151 @@
+// pixie/src/processors/slicer.rs
+use crate::core::{ImageToolError, Result};
+use crate::processors::{Compressor, Loader};
+use crate::utils::format_extension;
+use image::{DynamicImage, ImageFormat};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileGrid {
+    /// Split into this many columns and rows of (nearly) equal size
+    Count { columns: u32, rows: u32 },
+    /// Tiles of this size from the top left; the last row and column may be smaller
+    Size { width: u32, height: u32 },
+}
+
+/// Where one tile sits in the source image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileBounds {
+    pub row: u32,
+    pub column: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Slices an image into a grid of numbered tiles, e.g. for web maps or
+/// printing a poster across several sheets.
+pub struct TileSlicer {
+    grid: TileGrid,
+    format: Option<ImageFormat>,
+    quality: u8,
+}
+
+impl TileSlicer {
+    pub fn new(grid: TileGrid) -> Self {
+        Self {
+            grid,
+            format: None,
+            quality: 85,
+        }
+    }
+
+    /// Tile format; defaults to the input's own format
+    pub fn with_format(mut self, format: Option<ImageFormat>) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn bounds(&self, width: u32, height: u32) -> Result<Vec<TileBounds>> {
+        let (xs, ys) = match self.grid {
+            TileGrid::Count { columns, rows } => {
+                if columns == 0 || rows == 0 || columns > width || rows > height {
+                    return Err(ImageToolError::InvalidParameter(format!(
+                        "Cannot split {}x{} into {} columns and {} rows",
+                        width, height, columns, rows
+                    )));
+                }
+                (even_edges(width, columns), even_edges(height, rows))
+            }
+            TileGrid::Size { width: tile_w, height: tile_h } => {
+                if tile_w == 0 || tile_h == 0 {
+                    return Err(ImageToolError::InvalidParameter(
+                        "Tile size must be greater than zero".to_string(),
+                    ));
+                }
+                (fixed_edges(width, tile_w), fixed_edges(height, tile_h))
+            }
+        };
+
+        let mut bounds = Vec::with_capacity((xs.len() - 1) * (ys.len() - 1));
+        for (row, y) in ys.windows(2).enumerate() {
+            for (column, x) in xs.windows(2).enumerate() {
+                bounds.push(TileBounds {
+                    row: row as u32,
+                    column: column as u32,
+                    x: x[0],
+                    y: y[0],
+                    width: x[1] - x[0],
+                    height: y[1] - y[0],
+                });
+            }
+        }
+        Ok(bounds)
+    }
+
+    pub fn split(&self, image: &DynamicImage) -> Result<Vec<(TileBounds, DynamicImage)>> {
+        Ok(self
+            .bounds(image.width(), image.height())?
+            .into_iter()
+            .map(|b| (b, image.crop_imm(b.x, b.y, b.width, b.height)))
+            .collect())
+    }
+
+    /// Writes `{stem}_{row}_{column}.{ext}` into `output_dir`, both numbers
+    /// zero-padded so the files sort in reading order
+    pub fn write(&self, input_path: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
+        let loader = Loader::new();
+        let image = loader.load(input_path)?;
+        let format = match self.format {
+            Some(format) => format,
+            None => loader.detect_format(input_path)?,
+        };
+
+        std::fs::create_dir_all(output_dir)?;
+
+        let stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("tile");
+        let tiles = self.split(&image)?;
+        let last = tiles.last().map_or(0, |(b, _)| b.row.max(b.column));
+        let digits = last.to_string().len();
+
+        let compressor = Compressor::new(self.quality);
+        let mut written = Vec::with_capacity(tiles.len());
+        for (bounds, tile) in tiles {
+            let path = output_dir.join(format!(
+                "{}_{:0width$}_{:0width$}.{}",
+                stem,
+                bounds.row,
+                bounds.column,
+                format_extension(format),
+                width = digits
+            ));
+            compressor.save_with_format(&tile, &path, format)?;
+            written.push(path);
+        }
+
+        log::info!("Wrote {} tile(s) to {}", written.len(), output_dir.display());
+        Ok(written)
+    }
+}
+
+// Cut positions splitting `length` into `parts` pieces that differ by at most a pixel
+fn even_edges(length: u32, parts: u32) -> Vec<u32> {
+    (0..=parts)
+        .map(|i| (length as u64 * i as u64 / parts as u64) as u32)
+        .collect()
+}
+
+fn fixed_edges(length: u32, size: u32) -> Vec<u32> {
+    let mut edges: Vec<u32> = (0..length).step_by(size as usize).collect();
+    edges.push(length);
+    edges
+}
@@ -0,0 +1,188 @@
+// pixie/src/processors/srcset.rs
+use crate::core::processor::ImageProcessor;
+use crate::core::{ImageToolError, ProcessConfig, Result};
+use crate::processors::{Compressor, Loader};
+use crate::utils::{format_extension, json_escape};
+use image::ImageFormat;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct SrcsetEntry {
+    pub width: u32,
+    pub height: u32,
+    pub format: ImageFormat,
+    pub path: PathBuf,
+    pub file_size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Srcset {
+    pub source: PathBuf,
+    pub formats: Vec<ImageFormat>,
+    pub entries: Vec<SrcsetEntry>,
+}
+
+pub struct SrcsetGenerator {
+    config: ProcessConfig,
+    sizes_attr: String,
+}
+
+impl SrcsetGenerator {
+    pub fn new(config: ProcessConfig) -> Self {
+        Self {
+            config,
+            sizes_attr: "100vw".to_string(),
+        }
+    }
+
+    pub fn with_sizes_attr(mut self, sizes: &str) -> Self {
+        self.sizes_attr = sizes.to_string();
+        self
+    }
+
+    pub fn generate(
+        &self,
+        input_path: &Path,
+        output_dir: &Path,
+        widths: &[u32],
+        formats: &[ImageFormat],
+    ) -> Result<Srcset> {
+        if formats.is_empty() {
+            return Err(ImageToolError::InvalidParameter(
+                "At least one output format is required".to_string(),
+            ));
+        }
+
+        std::fs::create_dir_all(output_dir)?;
+
+        let processor = ImageProcessor::new(self.config.clone());
+        let compressor = Compressor::new(self.config.quality)
+            .with_speed(self.config.speed)
+            .with_bit_depth(self.config.bit_depth)
+            .with_cmyk(self.config.cmyk, self.config.cmyk_profile.clone());
+        let image = processor.orient(Loader::new().load(input_path)?, input_path);
+        let image = processor.redact(processor.tone_map(image))?;
+
+        let stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("image");
+
+        let mut entries = Vec::new();
+        for (width, resized) in processor.render_sizes(&image, widths)? {
+            for &format in formats {
+                let extension = format_extension(format);
+                let path = output_dir.join(format!("{}-{}.{}", stem, width, extension));
+                compressor.save_with_format(&resized, &path, format)?;
+                if self.config.verify {
+                    processor.verify_output(&path, resized.width(), resized.height())?;
+                }
+
+                entries.push(SrcsetEntry {
+                    width,
+                    height: resized.height(),
+                    format,
+                    file_size: std::fs::metadata(&path)?.len(),
+                    path,
+                });
+            }
+        }
+
+        // Smallest first reads naturally in srcset attributes
+        entries.sort_by_key(|e| e.width);
+
+        Ok(Srcset {
+            source: input_path.to_path_buf(),
+            formats: formats.to_vec(),
+            entries,
+        })
+    }
+
+    pub fn write_snippets(&self, srcset: &Srcset, output_dir: &Path) -> Result<(PathBuf, PathBuf)> {
+        let stem = srcset
+            .source
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("image");
+
+        let html_path = output_dir.join(format!("{}.html", stem));
+        let json_path = output_dir.join(format!("{}.json", stem));
+        std::fs::write(&html_path, srcset.to_html(&self.sizes_attr, ""))?;
+        std::fs::write(&json_path, srcset.to_json())?;
+
+        Ok((html_path, json_path))
+    }
+}
+
+impl Srcset {
+    fn srcset_attr(&self, format: ImageFormat) -> String {
+        self.entries
+            .iter()
+            .filter(|e| e.format == format)
+            .map(|e| format!("{} {}w", file_name(&e.path), e.width))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // Every format but the last becomes a <source>; the last one listed is
+    // used for the <img> fallback, so put the most compatible format last.
+    pub fn to_html(&self, sizes: &str, alt: &str) -> String {
+        let Some(&fallback) = self.formats.last() else {
+            return String::new();
+        };
+
+        let mut html = String::from("<picture>\n");
+        for &format in &self.formats[..self.formats.len() - 1] {
+            html.push_str(&format!(
+                "  <source type=\"{}\" srcset=\"{}\" sizes=\"{}\">\n",
+                format.to_mime_type(),
+                self.srcset_attr(format),
+                sizes
+            ));
+        }
+
+        if let Some(largest) = self.entries.iter().rfind(|e| e.format == fallback) {
+            html.push_str(&format!(
+                "  <img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"{}\" loading=\"lazy\" decoding=\"async\">\n",
+                file_name(&largest.path),
+                self.srcset_attr(fallback),
+                sizes,
+                largest.width,
+                largest.height,
+                alt
+            ));
+        }
+
+        html.push_str("</picture>\n");
+        html
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                format!(
+                    "    {{\"file\": \"{}\", \"width\": {}, \"height\": {}, \"type\": \"{}\", \"bytes\": {}}}",
+                    json_escape(&file_name(&e.path)),
+                    e.width,
+                    e.height,
+                    e.format.to_mime_type(),
+                    e.file_size
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n  \"source\": \"{}\",\n  \"images\": [\n{}\n  ]\n}}\n",
+            json_escape(&self.source.display().to_string()),
+            entries.join(",\n")
+        )
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
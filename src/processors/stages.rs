@@ -0,0 +1,208 @@
+// pixie/src/processors/stages.rs
+use crate::core::{AspectRatio, CropGravity, MaskShape, ProcessConfig, ProcessingStage, Result, StageContext};
+use crate::processors::{
+    AlphaMasker, Canvas, ChannelOps, CornerRounder, Cropper, Denoiser, DuotoneMapper, EllipseMasker, Flattener,
+    LevelStretcher, Lut3d, Posterizer, Quantizer, Thresholder,
+};
+use image::DynamicImage;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The filter stages `config` asks for, in the order they run
+pub(crate) fn builtin_stages(config: &ProcessConfig) -> Vec<Arc<dyn ProcessingStage>> {
+    let mut stages: Vec<Arc<dyn ProcessingStage>> = Vec::new();
+    if let Some(level) = config.denoise {
+        stages.push(Arc::new(Denoiser::new(config.denoise_method, level)));
+    }
+    // After denoising, so stray noisy samples don't set the end points
+    if let Some(mode) = config.auto_level {
+        stages.push(Arc::new(LevelStretcher::new(mode).with_clip(config.level_clip)));
+    }
+    // Grading comes after correction so it sees the corrected image
+    if let Some(path) = &config.lut {
+        stages.push(Arc::new(LutStage(path.clone())));
+    }
+    // Tone reduction goes last
+    if let Some(levels) = config.posterize {
+        stages.push(Arc::new(Posterizer::new(levels)));
+    }
+    if let Some(cutoff) = config.threshold {
+        stages.push(Arc::new(Thresholder::new(cutoff)));
+    }
+    if let Some(duotone) = config.duotone {
+        stages.push(Arc::new(DuotoneMapper::new(duotone)));
+    }
+    if let Some(colors) = config.quantize {
+        stages.push(Arc::new(Quantizer::new(colors)));
+    }
+    let channels = ChannelOps::new()
+        .with_order(config.channel_order)
+        .with_extract(config.channel)
+        .with_invert(config.invert);
+    if !channels.is_noop() {
+        stages.push(Arc::new(channels));
+    }
+
+    if let Some(source) = &config.add_alpha {
+        stages.push(Arc::new(AlphaMasker::new(source.clone()).with_fuzz(config.fuzz)));
+    }
+    if let Some(radius) = config.corner_radius {
+        stages.push(Arc::new(CornerRounder::new(radius)));
+    }
+    if let Some(shape) = config.mask {
+        stages.push(Arc::new(MaskStage { shape, gravity: config.gravity }));
+    }
+    // The shadow follows the outline left by the masks
+    if config.shadow.is_some() || config.padding.is_some() {
+        stages.push(Arc::new(Canvas::new(config.padding).with_shadow(config.shadow)));
+    }
+    if let Some(background) = config.remove_alpha {
+        stages.push(Arc::new(Flattener::new(background)));
+    }
+    stages
+}
+
+impl ProcessingStage for Denoiser {
+    fn name(&self) -> &str {
+        "denoise"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(Denoiser::apply(self, &image))
+    }
+}
+
+impl ProcessingStage for LevelStretcher {
+    fn name(&self) -> &str {
+        "auto-level"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(LevelStretcher::apply(self, &image))
+    }
+}
+
+// The LUT file is read when the stage runs, so a bad file fails the image
+// rather than building the processor
+struct LutStage(PathBuf);
+
+impl ProcessingStage for LutStage {
+    fn name(&self) -> &str {
+        "lut"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(Lut3d::load(&self.0)?.apply(&image))
+    }
+}
+
+impl ProcessingStage for Posterizer {
+    fn name(&self) -> &str {
+        "posterize"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(Posterizer::apply(self, &image))
+    }
+}
+
+impl ProcessingStage for Thresholder {
+    fn name(&self) -> &str {
+        "threshold"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(Thresholder::apply(self, &image))
+    }
+}
+
+impl ProcessingStage for DuotoneMapper {
+    fn name(&self) -> &str {
+        "duotone"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(DuotoneMapper::apply(self, &image))
+    }
+}
+
+impl ProcessingStage for Quantizer {
+    fn name(&self) -> &str {
+        "quantize"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(Quantizer::apply(self, &image))
+    }
+}
+
+impl ProcessingStage for ChannelOps {
+    fn name(&self) -> &str {
+        "channels"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(ChannelOps::apply(self, &image))
+    }
+}
+
+impl ProcessingStage for AlphaMasker {
+    fn name(&self) -> &str {
+        "add-alpha"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        AlphaMasker::apply(self, &image)
+    }
+}
+
+impl ProcessingStage for CornerRounder {
+    fn name(&self) -> &str {
+        "corner-radius"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(CornerRounder::apply(self, &image))
+    }
+}
+
+struct MaskStage {
+    shape: MaskShape,
+    gravity: CropGravity,
+}
+
+impl ProcessingStage for MaskStage {
+    fn name(&self) -> &str {
+        "mask"
+    }
+
+    // A non-square size was asked for, so the circle comes out of its middle
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(match self.shape {
+            MaskShape::Circle if image.width() != image.height() => {
+                EllipseMasker.apply(&Cropper::new(self.gravity).crop_to_aspect(&image, AspectRatio::new(1, 1)))
+            }
+            _ => EllipseMasker.apply(&image),
+        })
+    }
+}
+
+impl ProcessingStage for Canvas {
+    fn name(&self) -> &str {
+        "canvas"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(Canvas::apply(self, &image))
+    }
+}
+
+impl ProcessingStage for Flattener {
+    fn name(&self) -> &str {
+        "remove-alpha"
+    }
+
+    fn apply(&self, image: DynamicImage, _ctx: &StageContext) -> Result<DynamicImage> {
+        Ok(Flattener::apply(self, &image))
+    }
+}
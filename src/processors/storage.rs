@@ -0,0 +1,338 @@
+// pixie/src/processors/storage.rs
+use crate::core::{ImageToolError, ProcessConfig, ProcessingStats, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Where a batch reads from or writes to: a local directory or a bucket prefix.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageLocation {
+    Local(PathBuf),
+    S3 { bucket: String, prefix: String },
+    Gcs { bucket: String, prefix: String },
+}
+
+impl StorageLocation {
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, StorageLocation::Local(_))
+    }
+}
+
+impl FromStr for StorageLocation {
+    type Err = ImageToolError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let split = |rest: &str| -> Result<(String, String)> {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                return Err(ImageToolError::InvalidParameter(format!("Missing bucket name in {}", s)));
+            }
+            Ok((bucket.to_string(), prefix.trim_matches('/').to_string()))
+        };
+
+        if let Some(rest) = s.strip_prefix("s3://") {
+            let (bucket, prefix) = split(rest)?;
+            Ok(StorageLocation::S3 { bucket, prefix })
+        } else if let Some(rest) = s.strip_prefix("gs://") {
+            let (bucket, prefix) = split(rest)?;
+            Ok(StorageLocation::Gcs { bucket, prefix })
+        } else if s.contains("://") {
+            Err(ImageToolError::UnsupportedFormat(format!("Unsupported storage URI: {}", s)))
+        } else {
+            Ok(StorageLocation::Local(PathBuf::from(s)))
+        }
+    }
+}
+
+impl fmt::Display for StorageLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageLocation::Local(path) => write!(f, "{}", path.display()),
+            StorageLocation::S3 { bucket, prefix } => write!(f, "s3://{}/{}", bucket, prefix),
+            StorageLocation::Gcs { bucket, prefix } => write!(f, "gs://{}/{}", bucket, prefix),
+        }
+    }
+}
+
+/// Batch processing where either side may be a cloud bucket. Objects are
+/// streamed through memory rather than staged on disk, and at most
+/// `concurrency` of them are in flight at once.
+#[cfg_attr(not(feature = "cloud"), allow(dead_code))]
+pub struct CloudBatch {
+    config: ProcessConfig,
+    concurrency: usize,
+}
+
+impl CloudBatch {
+    pub fn new(config: ProcessConfig, concurrency: usize) -> Self {
+        Self {
+            config,
+            concurrency: if concurrency == 0 { 8 } else { concurrency },
+        }
+    }
+}
+
+#[cfg(not(feature = "cloud"))]
+impl CloudBatch {
+    pub fn run(
+        &self,
+        input: &StorageLocation,
+        output: &StorageLocation,
+        _recursive: bool,
+    ) -> Result<ProcessingStats> {
+        Err(ImageToolError::UnsupportedFormat(format!(
+            "Cannot process {} -> {}: pixie was built without the `cloud` feature",
+            input, output
+        )))
+    }
+}
+
+#[cfg(feature = "cloud")]
+impl CloudBatch {
+    pub fn run(
+        &self,
+        input: &StorageLocation,
+        output: &StorageLocation,
+        recursive: bool,
+    ) -> Result<ProcessingStats> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(self.run_async(input, output, recursive))
+    }
+
+    async fn run_async(
+        &self,
+        input: &StorageLocation,
+        output: &StorageLocation,
+        recursive: bool,
+    ) -> Result<ProcessingStats> {
+        use futures::StreamExt;
+
+        let source = cloud::Endpoint::open(input)?;
+        let target = cloud::Endpoint::open(output)?;
+
+        let keys: Vec<String> = source
+            .list(recursive)
+            .await?
+            .into_iter()
+            .filter(|key| crate::utils::is_supported_format(std::path::Path::new(key)))
+            .collect();
+
+        if keys.is_empty() {
+            log::warn!("No image files found in {}", input);
+            return Ok(ProcessingStats::default());
+        }
+
+        log::info!(
+            "Processing {} images from {} ({} at a time)",
+            keys.len(),
+            input,
+            self.concurrency
+        );
+
+        let results: Vec<(String, Result<(u64, u64)>)> = futures::stream::iter(keys)
+            .map(|key| {
+                let (source, target) = (&source, &target);
+                async move {
+                    let result = self.process_object(source, target, &key).await;
+                    (key, result)
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let mut stats = ProcessingStats::default();
+        for (key, result) in results {
+            match result {
+                Ok((before, after)) => {
+                    stats.processed_count += 1;
+                    stats.total_size_before += before;
+                    stats.total_size_after += after;
+                }
+                Err(e) => {
+                    log::warn!("Failed to process {}: {}", key, e);
+                    stats.errors.push((PathBuf::from(key), e));
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    async fn process_object(
+        &self,
+        source: &cloud::Endpoint,
+        target: &cloud::Endpoint,
+        key: &str,
+    ) -> Result<(u64, u64)> {
+        let data = source.read(key).await?;
+        let size_before = data.len() as u64;
+
+        // Decoding and encoding are CPU bound, keep them off the I/O threads
+        let config = self.config.clone();
+        let output = tokio::task::spawn_blocking(move || {
+            crate::core::processor::ImageProcessor::new(config).process_bytes(&data)
+        })
+        .await
+        .map_err(|e| ImageToolError::ProcessingError(format!("Processing task failed: {}", e)))??;
+
+        let size_after = output.len() as u64;
+        target.write(&self.output_key(key), output).await?;
+
+        Ok((size_before, size_after))
+    }
+
+    fn output_key(&self, key: &str) -> String {
+        let extension = self
+            .config
+            .format
+            .and_then(|f| f.image_format())
+            .map(crate::utils::format_extension);
+
+        match (extension, key.rsplit_once('.')) {
+            (Some(extension), Some((stem, _))) => format!("{}.{}", stem, extension),
+            _ => key.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "cloud")]
+mod cloud {
+    use super::StorageLocation;
+    use crate::core::{ImageToolError, Result};
+    use futures::TryStreamExt;
+    use object_store::aws::AmazonS3Builder;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+    use object_store::path::Path as ObjectPath;
+    use object_store::ObjectStore;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use walkdir::WalkDir;
+
+    pub(super) enum Endpoint {
+        Local(PathBuf),
+        Remote {
+            store: Arc<dyn ObjectStore>,
+            prefix: String,
+        },
+    }
+
+    impl Endpoint {
+        // Credentials and region come from the usual AWS_* / GOOGLE_* environment
+        pub(super) fn open(location: &StorageLocation) -> Result<Self> {
+            let (store, prefix): (Arc<dyn ObjectStore>, &String) = match location {
+                StorageLocation::Local(path) => return Ok(Endpoint::Local(path.clone())),
+                StorageLocation::S3 { bucket, prefix } => (
+                    Arc::new(
+                        AmazonS3Builder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()
+                            .map_err(store_error)?,
+                    ),
+                    prefix,
+                ),
+                StorageLocation::Gcs { bucket, prefix } => (
+                    Arc::new(
+                        GoogleCloudStorageBuilder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()
+                            .map_err(store_error)?,
+                    ),
+                    prefix,
+                ),
+            };
+
+            Ok(Endpoint::Remote {
+                store,
+                prefix: prefix.clone(),
+            })
+        }
+
+        /// Keys relative to the endpoint root, always `/`-separated
+        pub(super) async fn list(&self, recursive: bool) -> Result<Vec<String>> {
+            match self {
+                Endpoint::Local(root) => {
+                    let walker = if recursive {
+                        WalkDir::new(root)
+                    } else {
+                        WalkDir::new(root).max_depth(1)
+                    };
+
+                    Ok(walker
+                        .into_iter()
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| entry.file_type().is_file())
+                        .filter_map(|entry| {
+                            let relative = entry.path().strip_prefix(root).ok()?;
+                            let parts: Vec<_> = relative.iter().map(|p| p.to_string_lossy()).collect();
+                            Some(parts.join("/"))
+                        })
+                        .collect())
+                }
+                Endpoint::Remote { store, prefix } => {
+                    let root = (!prefix.is_empty()).then(|| ObjectPath::from(prefix.as_str()));
+                    let objects = if recursive {
+                        store.list(root.as_ref()).try_collect::<Vec<_>>().await.map_err(store_error)?
+                    } else {
+                        store.list_with_delimiter(root.as_ref()).await.map_err(store_error)?.objects
+                    };
+
+                    Ok(objects
+                        .into_iter()
+                        .map(|meta| {
+                            let location = meta.location.to_string();
+                            location
+                                .strip_prefix(prefix.as_str())
+                                .unwrap_or(&location)
+                                .trim_start_matches('/')
+                                .to_string()
+                        })
+                        .collect())
+                }
+            }
+        }
+
+        pub(super) async fn read(&self, key: &str) -> Result<Vec<u8>> {
+            match self {
+                Endpoint::Local(root) => Ok(tokio::fs::read(root.join(key)).await?),
+                Endpoint::Remote { store, prefix } => {
+                    let object = store.get(&object_path(prefix, key)).await.map_err(store_error)?;
+                    Ok(object.bytes().await.map_err(store_error)?.to_vec())
+                }
+            }
+        }
+
+        pub(super) async fn write(&self, key: &str, data: Vec<u8>) -> Result<()> {
+            match self {
+                Endpoint::Local(root) => {
+                    let path = root.join(key);
+                    if let Some(parent) = path.parent() {
+                        tokio::fs::create_dir_all(parent).await?;
+                    }
+                    Ok(tokio::fs::write(path, data).await?)
+                }
+                Endpoint::Remote { store, prefix } => {
+                    store
+                        .put(&object_path(prefix, key), data.into())
+                        .await
+                        .map_err(store_error)?;
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn object_path(prefix: &str, key: &str) -> ObjectPath {
+        if prefix.is_empty() {
+            ObjectPath::from(key)
+        } else {
+            ObjectPath::from(format!("{}/{}", prefix, key))
+        }
+    }
+
+    fn store_error(e: object_store::Error) -> ImageToolError {
+        ImageToolError::ProcessingError(format!("Storage error: {}", e))
+    }
+}
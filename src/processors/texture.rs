@@ -0,0 +1,422 @@
+// pixie/src/processors/texture.rs
+use crate::core::{ImageToolError, ResizeAlgorithm, Result};
+use crate::processors::Resizer;
+use image::{DynamicImage, RgbaImage};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureContainer {
+    Dds,
+    Ktx2,
+}
+
+impl TextureContainer {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "dds" => Some(TextureContainer::Dds),
+            "ktx2" => Some(TextureContainer::Ktx2),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureCompression {
+    /// Uncompressed RGBA8
+    None,
+    /// BC1 (DXT1): RGB, 4 bits per pixel, alpha is dropped
+    Bc1,
+    /// BC3 (DXT5): RGBA, 8 bits per pixel
+    Bc3,
+}
+
+impl TextureCompression {
+    // Bytes per 4x4 block, or per pixel when uncompressed
+    fn block_bytes(&self) -> usize {
+        match self {
+            TextureCompression::None => 4,
+            TextureCompression::Bc1 => 8,
+            TextureCompression::Bc3 => 16,
+        }
+    }
+
+    fn is_block_compressed(&self) -> bool {
+        *self != TextureCompression::None
+    }
+}
+
+/// Bakes an image into a GPU texture file with an optional mip chain.
+pub struct TextureBaker {
+    container: TextureContainer,
+    compression: TextureCompression,
+    mipmaps: bool,
+    algorithm: ResizeAlgorithm,
+}
+
+struct MipLevel {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+// A data format descriptor sample: bit offset, bit length, channel type, upper
+type DfdSample = (u32, u32, u32, u32);
+
+impl TextureBaker {
+    pub fn new(container: TextureContainer) -> Self {
+        Self {
+            container,
+            compression: TextureCompression::None,
+            mipmaps: true,
+            algorithm: ResizeAlgorithm::Lanczos3,
+        }
+    }
+
+    pub fn with_compression(mut self, compression: TextureCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub fn with_algorithm(mut self, algorithm: ResizeAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn bake(&self, image: &DynamicImage, output_path: &Path) -> Result<()> {
+        let data = self.bake_to_bytes(image)?;
+        std::fs::write(output_path, &data)?;
+        log::info!("Saved texture: {} ({} bytes)", output_path.display(), data.len());
+        Ok(())
+    }
+
+    pub fn bake_to_bytes(&self, image: &DynamicImage) -> Result<Vec<u8>> {
+        if image.width() == 0 || image.height() == 0 {
+            return Err(ImageToolError::InvalidParameter(
+                "Cannot bake an empty image".to_string(),
+            ));
+        }
+
+        let levels: Vec<MipLevel> = self
+            .mip_chain(image)
+            .into_iter()
+            .map(|rgba| MipLevel {
+                width: rgba.width(),
+                height: rgba.height(),
+                data: self.encode_level(&rgba),
+            })
+            .collect();
+
+        log::debug!(
+            "Baking {}x{} texture with {} mip level(s), {:?}",
+            image.width(),
+            image.height(),
+            levels.len(),
+            self.compression
+        );
+
+        Ok(match self.container {
+            TextureContainer::Dds => self.write_dds(&levels),
+            TextureContainer::Ktx2 => self.write_ktx2(&levels),
+        })
+    }
+
+    // Each level halves the previous one, down to 1x1
+    fn mip_chain(&self, image: &DynamicImage) -> Vec<RgbaImage> {
+        let resizer = Resizer::new(self.algorithm, false);
+        let mut levels = vec![image.to_rgba8()];
+        if !self.mipmaps {
+            return levels;
+        }
+
+        let (mut width, mut height) = (image.width(), image.height());
+        while width > 1 || height > 1 {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            // Resample from the full image rather than the previous level to
+            // avoid compounding filter blur
+            levels.push(resizer.resize_exact(image, width, height).to_rgba8());
+        }
+        levels
+    }
+
+    fn encode_level(&self, image: &RgbaImage) -> Vec<u8> {
+        match self.compression {
+            TextureCompression::None => image.as_raw().clone(),
+            TextureCompression::Bc1 | TextureCompression::Bc3 => {
+                let blocks_x = image.width().div_ceil(4);
+                let blocks_y = image.height().div_ceil(4);
+                let mut out = Vec::with_capacity((blocks_x * blocks_y) as usize * self.compression.block_bytes());
+
+                for by in 0..blocks_y {
+                    for bx in 0..blocks_x {
+                        let block = read_block(image, bx * 4, by * 4);
+                        if self.compression == TextureCompression::Bc3 {
+                            encode_bc3_alpha(&block, &mut out);
+                        }
+                        encode_bc1_color(&block, &mut out);
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    fn write_dds(&self, levels: &[MipLevel]) -> Vec<u8> {
+        const CAPS: u32 = 0x1;
+        const HEIGHT: u32 = 0x2;
+        const WIDTH: u32 = 0x4;
+        const PITCH: u32 = 0x8;
+        const PIXELFORMAT: u32 = 0x1000;
+        const MIPMAPCOUNT: u32 = 0x20000;
+        const LINEARSIZE: u32 = 0x80000;
+        const DDPF_ALPHAPIXELS: u32 = 0x1;
+        const DDPF_FOURCC: u32 = 0x4;
+        const DDPF_RGB: u32 = 0x40;
+        const CAPS_COMPLEX: u32 = 0x8;
+        const CAPS_TEXTURE: u32 = 0x1000;
+        const CAPS_MIPMAP: u32 = 0x400000;
+
+        let base = &levels[0];
+        let compressed = self.compression.is_block_compressed();
+        let mut flags = CAPS | HEIGHT | WIDTH | PIXELFORMAT;
+        flags |= if compressed { LINEARSIZE } else { PITCH };
+        let mut caps = CAPS_TEXTURE;
+        if levels.len() > 1 {
+            flags |= MIPMAPCOUNT;
+            caps |= CAPS_COMPLEX | CAPS_MIPMAP;
+        }
+        let pitch_or_size = if compressed {
+            base.data.len() as u32
+        } else {
+            base.width * 4
+        };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"DDS ");
+        for value in [124, flags, base.height, base.width, pitch_or_size, 0, levels.len() as u32] {
+            push_u32(&mut out, value);
+        }
+        out.extend_from_slice(&[0u8; 44]);
+
+        // DDS_PIXELFORMAT
+        push_u32(&mut out, 32);
+        match self.compression {
+            TextureCompression::None => {
+                for value in [DDPF_RGB | DDPF_ALPHAPIXELS, 0, 32, 0x0000_00ff, 0x0000_ff00, 0x00ff_0000, 0xff00_0000] {
+                    push_u32(&mut out, value);
+                }
+            }
+            TextureCompression::Bc1 | TextureCompression::Bc3 => {
+                push_u32(&mut out, DDPF_FOURCC);
+                out.extend_from_slice(if self.compression == TextureCompression::Bc1 { b"DXT1" } else { b"DXT5" });
+                out.extend_from_slice(&[0u8; 20]);
+            }
+        }
+
+        for value in [caps, 0, 0, 0, 0] {
+            push_u32(&mut out, value);
+        }
+
+        for level in levels {
+            out.extend_from_slice(&level.data);
+        }
+        out
+    }
+
+    fn write_ktx2(&self, levels: &[MipLevel]) -> Vec<u8> {
+        const IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+        // VkFormat values; source images are sRGB encoded
+        let vk_format: u32 = match self.compression {
+            TextureCompression::None => 43,  // R8G8B8A8_SRGB
+            TextureCompression::Bc1 => 132,  // BC1_RGB_SRGB_BLOCK
+            TextureCompression::Bc3 => 138,  // BC3_SRGB_BLOCK
+        };
+
+        let dfd = self.data_format_descriptor();
+        let level_index_offset = 12 + 36 + 32;
+        let dfd_offset = level_index_offset + 24 * levels.len();
+        // Level data must be aligned to lcm(texel block size, 4)
+        let alignment = self.compression.block_bytes().max(4);
+        let data_start = (dfd_offset + dfd.len()).next_multiple_of(alignment);
+
+        // Levels are stored smallest first so streaming readers can show a
+        // low resolution version early
+        let mut offsets = vec![0usize; levels.len()];
+        let mut cursor = data_start;
+        for (index, level) in levels.iter().enumerate().rev() {
+            cursor = cursor.next_multiple_of(alignment);
+            offsets[index] = cursor;
+            cursor += level.data.len();
+        }
+
+        let mut out = Vec::with_capacity(cursor);
+        out.extend_from_slice(&IDENTIFIER);
+        let base = &levels[0];
+        // typeSize is 1 for every 8-bit and block-compressed format
+        for value in [vk_format, 1, base.width, base.height, 0, 0, 1, levels.len() as u32, 0] {
+            push_u32(&mut out, value);
+        }
+
+        // Index: DFD, then empty key/value and supercompression sections
+        push_u32(&mut out, dfd_offset as u32);
+        push_u32(&mut out, dfd.len() as u32);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 0);
+        out.extend_from_slice(&0u64.to_le_bytes());
+        out.extend_from_slice(&0u64.to_le_bytes());
+
+        for (level, &offset) in levels.iter().zip(&offsets) {
+            let length = level.data.len() as u64;
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            out.extend_from_slice(&length.to_le_bytes());
+            out.extend_from_slice(&length.to_le_bytes());
+        }
+
+        out.extend_from_slice(&dfd);
+        for (index, level) in levels.iter().enumerate().rev() {
+            out.resize(offsets[index], 0);
+            out.extend_from_slice(&level.data);
+        }
+        out
+    }
+
+    // Basic data format descriptor block (Khronos Data Format 1.3)
+    fn data_format_descriptor(&self) -> Vec<u8> {
+        const SRGB: u32 = 2;
+        const BT709: u32 = 1;
+        const LINEAR: u32 = 0x10;
+        const ALPHA: u32 = 15;
+
+        let (color_model, block_dim, samples): (u32, u32, Vec<DfdSample>) = match self.compression {
+            TextureCompression::None => (
+                1, // RGBSDA
+                0,
+                vec![(0, 8, 0, 255), (8, 8, 1, 255), (16, 8, 2, 255), (24, 8, ALPHA | LINEAR, 255)],
+            ),
+            TextureCompression::Bc1 => (128, 0x0303, vec![(0, 64, 0, u32::MAX)]),
+            TextureCompression::Bc3 => (
+                130,
+                0x0303,
+                vec![(0, 64, ALPHA | LINEAR, u32::MAX), (64, 64, 0, u32::MAX)],
+            ),
+        };
+
+        let block_size = 24 + 16 * samples.len() as u32;
+        let mut out = Vec::new();
+        push_u32(&mut out, 4 + block_size);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 2 | (block_size << 16));
+        push_u32(&mut out, color_model | (BT709 << 8) | (SRGB << 16));
+        push_u32(&mut out, block_dim);
+        push_u32(&mut out, self.compression.block_bytes() as u32);
+        push_u32(&mut out, 0);
+
+        for (offset, length, channel, upper) in samples {
+            push_u32(&mut out, offset | ((length - 1) << 16) | (channel << 24));
+            push_u32(&mut out, 0);
+            push_u32(&mut out, 0);
+            push_u32(&mut out, upper);
+        }
+        out
+    }
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+// 4x4 block of RGBA pixels; edges repeat the last row/column
+fn read_block(image: &RgbaImage, x: u32, y: u32) -> [[u8; 4]; 16] {
+    let mut block = [[0u8; 4]; 16];
+    for (i, pixel) in block.iter_mut().enumerate() {
+        let px = (x + i as u32 % 4).min(image.width() - 1);
+        let py = (y + i as u32 / 4).min(image.height() - 1);
+        *pixel = image.get_pixel(px, py).0;
+    }
+    block
+}
+
+fn to_565(c: [u8; 3]) -> u16 {
+    ((c[0] as u16 >> 3) << 11) | ((c[1] as u16 >> 2) << 5) | (c[2] as u16 >> 3)
+}
+
+fn from_565(c: u16) -> [i32; 3] {
+    let r = ((c >> 11) & 0x1f) as i32;
+    let g = ((c >> 5) & 0x3f) as i32;
+    let b = (c & 0x1f) as i32;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+// Endpoints are the corners of the block's RGB bounding box, always in
+// four-color mode (first endpoint greater than the second)
+fn encode_bc1_color(block: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+    let mut min = [255u8; 3];
+    let mut max = [0u8; 3];
+    for pixel in block {
+        for c in 0..3 {
+            min[c] = min[c].min(pixel[c]);
+            max[c] = max[c].max(pixel[c]);
+        }
+    }
+
+    let (mut c0, mut c1) = (to_565(max), to_565(min));
+    if c0 < c1 {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+
+    let mut indices = 0u32;
+    if c0 != c1 {
+        let (e0, e1) = (from_565(c0), from_565(c1));
+        let palette = [
+            e0,
+            e1,
+            [0, 1, 2].map(|c| (2 * e0[c] + e1[c]) / 3),
+            [0, 1, 2].map(|c| (e0[c] + 2 * e1[c]) / 3),
+        ];
+        for (i, pixel) in block.iter().enumerate() {
+            let best = (0..4)
+                .min_by_key(|&p| {
+                    (0..3)
+                        .map(|c| (palette[p][c] - pixel[c] as i32).pow(2))
+                        .sum::<i32>()
+                })
+                .unwrap_or(0);
+            indices |= (best as u32) << (2 * i);
+        }
+    }
+
+    out.extend_from_slice(&c0.to_le_bytes());
+    out.extend_from_slice(&c1.to_le_bytes());
+    out.extend_from_slice(&indices.to_le_bytes());
+}
+
+// Eight-value alpha ramp between the block's min and max alpha
+fn encode_bc3_alpha(block: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+    let a0 = block.iter().map(|p| p[3]).max().unwrap_or(255);
+    let a1 = block.iter().map(|p| p[3]).min().unwrap_or(255);
+
+    let mut indices = 0u64;
+    if a0 != a1 {
+        let (a0i, a1i) = (a0 as i32, a1 as i32);
+        let mut palette = [a0i, a1i, 0, 0, 0, 0, 0, 0];
+        for (step, value) in palette.iter_mut().enumerate().skip(2) {
+            let step = step as i32 - 1;
+            *value = ((7 - step) * a0i + step * a1i) / 7;
+        }
+        for (i, pixel) in block.iter().enumerate() {
+            let best = (0..8)
+                .min_by_key(|&p| (palette[p] - pixel[3] as i32).abs())
+                .unwrap_or(0);
+            indices |= (best as u64) << (3 * i);
+        }
+    }
+
+    out.push(a0);
+    out.push(a1);
+    out.extend_from_slice(&indices.to_le_bytes()[..6]);
+}
@@ -0,0 +1,269 @@
+// pixie/src/processors/thumbhash.rs
+use crate::core::{ImageToolError, Result};
+use crate::utils::base64_encode;
+use image::{imageops, DynamicImage, RgbaImage};
+use std::f64::consts::PI;
+
+// ThumbHash is defined on images of at most 100x100
+const MAX_SIZE: u32 = 100;
+
+/// A ThumbHash (https://evanw.github.io/thumbhash/) placeholder: a ~25 byte
+/// hash that keeps the aspect ratio and alpha channel, unlike BlurHash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThumbHash(Vec<u8>);
+
+impl ThumbHash {
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let rgba = if image.width() > MAX_SIZE || image.height() > MAX_SIZE {
+            image.resize(MAX_SIZE, MAX_SIZE, imageops::FilterType::Triangle).to_rgba8()
+        } else {
+            image.to_rgba8()
+        };
+        Self(encode(&rgba))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        if bytes.len() < 5 || (bytes[2] & 0x80 != 0 && bytes.len() < 6) {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "ThumbHash too short ({} bytes)",
+                bytes.len()
+            )));
+        }
+        Ok(Self(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The usual text form, for data attributes and JSON
+    pub fn to_base64(&self) -> String {
+        base64_encode(&self.0)
+    }
+
+    /// Width over height, as far as the hash remembers it
+    pub fn aspect_ratio(&self) -> f32 {
+        let header = self.0[3];
+        let has_alpha = self.0[2] & 0x80 != 0;
+        let landscape = self.0[4] & 0x80 != 0;
+        let lx = if landscape { if has_alpha { 5 } else { 7 } } else { header & 7 };
+        let ly = if landscape { header & 7 } else if has_alpha { 5 } else { 7 };
+        lx as f32 / ly as f32
+    }
+
+    /// Renders the placeholder, at most 32 pixels on its long side
+    pub fn to_image(&self) -> DynamicImage {
+        let hash = &self.0;
+        let header24 = hash[0] as u32 | (hash[1] as u32) << 8 | (hash[2] as u32) << 16;
+        let header16 = hash[3] as u32 | (hash[4] as u32) << 8;
+        let l_dc = (header24 & 63) as f64 / 63.0;
+        let p_dc = ((header24 >> 6) & 63) as f64 / 31.5 - 1.0;
+        let q_dc = ((header24 >> 12) & 63) as f64 / 31.5 - 1.0;
+        let l_scale = ((header24 >> 18) & 31) as f64 / 31.0;
+        let has_alpha = header24 >> 23 != 0;
+        let p_scale = ((header16 >> 3) & 63) as f64 / 63.0;
+        let q_scale = ((header16 >> 9) & 63) as f64 / 63.0;
+        let landscape = header16 >> 15 != 0;
+        let l_limit = if has_alpha { 5 } else { 7 };
+        let lx = (if landscape { l_limit } else { header16 & 7 }).max(3) as usize;
+        let ly = (if landscape { header16 & 7 } else { l_limit }).max(3) as usize;
+        let (a_dc, a_scale) = if has_alpha {
+            ((hash[5] & 15) as f64 / 15.0, (hash[5] >> 4) as f64 / 15.0)
+        } else {
+            (1.0, 0.0)
+        };
+
+        // Saturation is boosted by 1.25x to make up for quantization
+        let mut reader = Nibbles::new(hash, if has_alpha { 6 } else { 5 });
+        let l_ac = reader.channel(lx, ly, l_scale);
+        let p_ac = reader.channel(3, 3, p_scale * 1.25);
+        let q_ac = reader.channel(3, 3, q_scale * 1.25);
+        let a_ac = if has_alpha { reader.channel(5, 5, a_scale) } else { Vec::new() };
+
+        let ratio = self.aspect_ratio() as f64;
+        let width = (if ratio > 1.0 { 32.0 } else { 32.0 * ratio }).round() as u32;
+        let height = (if ratio > 1.0 { 32.0 / ratio } else { 32.0 }).round() as u32;
+        let n = lx.max(ly).max(if has_alpha { 5 } else { 3 });
+
+        RgbaImage::from_fn(width, height, |x, y| {
+            let fx: Vec<f64> = (0..n).map(|cx| (PI / width as f64 * (x as f64 + 0.5) * cx as f64).cos()).collect();
+            let fy: Vec<f64> = (0..n).map(|cy| (PI / height as f64 * (y as f64 + 0.5) * cy as f64).cos()).collect();
+            let decode = |dc: f64, ac: &[f64], nx: usize, ny: usize| {
+                let mut value = dc;
+                let mut j = 0;
+                for (cy, fy) in fy.iter().enumerate().take(ny) {
+                    let fy2 = fy * 2.0;
+                    let mut cx = if cy > 0 { 0 } else { 1 };
+                    while cx * ny < nx * (ny - cy) {
+                        value += ac[j] * fx[cx] * fy2;
+                        j += 1;
+                        cx += 1;
+                    }
+                }
+                value
+            };
+
+            let l = decode(l_dc, &l_ac, lx, ly);
+            let p = decode(p_dc, &p_ac, 3, 3);
+            let q = decode(q_dc, &q_ac, 3, 3);
+            let a = if has_alpha { decode(a_dc, &a_ac, 5, 5) } else { a_dc };
+
+            let b = l - 2.0 / 3.0 * p;
+            let r = (3.0 * l - b + q) / 2.0;
+            let g = r - q;
+            image::Rgba([r, g, b, a].map(|v| (v.clamp(0.0, 1.0) * 255.0) as u8))
+        })
+        .into()
+    }
+}
+
+impl std::fmt::Display for ThumbHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_base64())
+    }
+}
+
+fn encode(rgba: &RgbaImage) -> Vec<u8> {
+    let (w, h) = (rgba.width() as usize, rgba.height() as usize);
+
+    // Average color, weighted by alpha
+    let (mut avg_r, mut avg_g, mut avg_b, mut avg_a) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for pixel in rgba.pixels() {
+        let alpha = pixel.0[3] as f64 / 255.0;
+        avg_r += alpha / 255.0 * pixel.0[0] as f64;
+        avg_g += alpha / 255.0 * pixel.0[1] as f64;
+        avg_b += alpha / 255.0 * pixel.0[2] as f64;
+        avg_a += alpha;
+    }
+    if avg_a > 0.0 {
+        avg_r /= avg_a;
+        avg_g /= avg_a;
+        avg_b /= avg_a;
+    }
+
+    // Fewer luminance components are kept when there's alpha to store
+    let has_alpha = avg_a < (w * h) as f64;
+    let l_limit = if has_alpha { 5.0 } else { 7.0 };
+    let longest = w.max(h) as f64;
+    let lx = ((l_limit * w as f64 / longest).round() as usize).max(1);
+    let ly = ((l_limit * h as f64 / longest).round() as usize).max(1);
+
+    // Convert to luminance, yellow-blue, red-green and alpha planes,
+    // composited over the average color
+    let mut l = Vec::with_capacity(w * h);
+    let mut p = Vec::with_capacity(w * h);
+    let mut q = Vec::with_capacity(w * h);
+    let mut a = Vec::with_capacity(w * h);
+    for pixel in rgba.pixels() {
+        let alpha = pixel.0[3] as f64 / 255.0;
+        let r = avg_r * (1.0 - alpha) + alpha / 255.0 * pixel.0[0] as f64;
+        let g = avg_g * (1.0 - alpha) + alpha / 255.0 * pixel.0[1] as f64;
+        let b = avg_b * (1.0 - alpha) + alpha / 255.0 * pixel.0[2] as f64;
+        l.push((r + g + b) / 3.0);
+        p.push((r + g) / 2.0 - b);
+        q.push(r - g);
+        a.push(alpha);
+    }
+
+    let encode_channel = |channel: &[f64], nx: usize, ny: usize| {
+        let mut dc = 0.0;
+        let mut ac = Vec::new();
+        let mut scale = 0.0f64;
+        for cy in 0..ny {
+            let mut cx = 0;
+            while cx * ny < nx * (ny - cy) {
+                let fx: Vec<f64> = (0..w).map(|x| (PI / w as f64 * cx as f64 * (x as f64 + 0.5)).cos()).collect();
+                let mut f = 0.0;
+                for y in 0..h {
+                    let fy = (PI / h as f64 * cy as f64 * (y as f64 + 0.5)).cos();
+                    for x in 0..w {
+                        f += channel[x + y * w] * fx[x] * fy;
+                    }
+                }
+                f /= (w * h) as f64;
+                if cx > 0 || cy > 0 {
+                    ac.push(f);
+                    scale = scale.max(f.abs());
+                } else {
+                    dc = f;
+                }
+                cx += 1;
+            }
+        }
+        if scale > 0.0 {
+            for value in &mut ac {
+                *value = 0.5 + 0.5 / scale * *value;
+            }
+        }
+        (dc, ac, scale)
+    };
+
+    let (l_dc, l_ac, l_scale) = encode_channel(&l, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode_channel(&p, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode_channel(&q, 3, 3);
+
+    let landscape = w > h;
+    let header24 = (63.0 * l_dc).round() as u32
+        | ((31.5 + 31.5 * p_dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q_dc).round() as u32) << 12
+        | ((31.0 * l_scale).round() as u32) << 18
+        | (has_alpha as u32) << 23;
+    let header16 = (if landscape { ly } else { lx }) as u32
+        | ((63.0 * p_scale).round() as u32) << 3
+        | ((63.0 * q_scale).round() as u32) << 9
+        | (landscape as u32) << 15;
+    let mut hash = vec![
+        header24 as u8,
+        (header24 >> 8) as u8,
+        (header24 >> 16) as u8,
+        header16 as u8,
+        (header16 >> 8) as u8,
+    ];
+
+    let mut factors = vec![l_ac, p_ac, q_ac];
+    if has_alpha {
+        let (a_dc, a_ac, a_scale) = encode_channel(&a, 5, 5);
+        hash.push((15.0 * a_dc).round() as u8 | ((15.0 * a_scale).round() as u8) << 4);
+        factors.push(a_ac);
+    }
+
+    // Two 4-bit factors per byte, low nibble first
+    for (index, factor) in factors.iter().flatten().enumerate() {
+        let nibble = (15.0 * factor).round() as u8;
+        if index % 2 == 0 {
+            hash.push(nibble);
+        } else {
+            *hash.last_mut().expect("pushed above") |= nibble << 4;
+        }
+    }
+    hash
+}
+
+// Reads the 4-bit AC factors back in the order `encode` wrote them
+struct Nibbles<'a> {
+    hash: &'a [u8],
+    start: usize,
+    index: usize,
+}
+
+impl<'a> Nibbles<'a> {
+    fn new(hash: &'a [u8], start: usize) -> Self {
+        Self { hash, start, index: 0 }
+    }
+
+    fn channel(&mut self, nx: usize, ny: usize, scale: f64) -> Vec<f64> {
+        let mut ac = Vec::new();
+        for cy in 0..ny {
+            let mut cx = if cy > 0 { 0 } else { 1 };
+            while cx * ny < nx * (ny - cy) {
+                // Missing bytes read as zero, as in the reference decoder
+                let byte = self.hash.get(self.start + self.index / 2).copied().unwrap_or(0);
+                let nibble = (byte >> ((self.index & 1) * 4)) & 15;
+                ac.push((nibble as f64 / 7.5 - 1.0) * scale);
+                self.index += 1;
+                cx += 1;
+            }
+        }
+        ac
+    }
+}
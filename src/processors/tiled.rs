@@ -0,0 +1,407 @@
+// pixie/src/processors/tiled.rs
+use crate::core::{ImageToolError, Result};
+use crate::utils::{linear_to_srgb, srgb_to_linear};
+use image::{DynamicImage, ImageBuffer, ImageFormat};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Shape of the rows a streaming decode produces. Samples are widened to
+/// u16 whatever the source depth.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RowLayout {
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    pub sixteen_bit: bool,
+}
+
+/// Receives a decoded image one row at a time, top to bottom
+pub(crate) trait RowSink {
+    fn begin(&mut self, layout: RowLayout) -> Result<()>;
+    fn push_row(&mut self, row: &[u16]) -> Result<()>;
+}
+
+/// Decodes large images strip by strip and box-downscales each strip as it
+/// arrives, so the full-resolution raster never has to be held in memory.
+pub struct TiledLoader {
+    linear: bool,
+}
+
+impl TiledLoader {
+    pub fn new() -> Self {
+        Self { linear: false }
+    }
+
+    /// Average in linear light instead of on sRGB-encoded values
+    pub fn with_linear(mut self, linear: bool) -> Self {
+        self.linear = linear;
+        self
+    }
+
+    pub fn supports(&self, format: ImageFormat) -> bool {
+        matches!(format, ImageFormat::Tiff | ImageFormat::Png)
+    }
+
+    pub fn load_downscaled(
+        &self,
+        path: &Path,
+        format: ImageFormat,
+        target_width: u32,
+        target_height: u32,
+    ) -> Result<DynamicImage> {
+        log::debug!(
+            "Streaming decode of {} down to {}x{}",
+            path.display(),
+            target_width,
+            target_height
+        );
+
+        let mut downscaler = Downscaler {
+            target_width,
+            target_height,
+            linear: self.linear,
+            accumulator: None,
+        };
+        self.stream_rows(path, format, &mut downscaler)?;
+        downscaler
+            .accumulator
+            .ok_or_else(|| ImageToolError::ProcessingError("Decoder produced no image".to_string()))?
+            .finish()
+    }
+
+    /// Feeds the decoded rows of a TIFF or PNG to `sink` without holding
+    /// more than one strip in memory
+    pub(crate) fn stream_rows(&self, path: &Path, format: ImageFormat, sink: &mut dyn RowSink) -> Result<()> {
+        match format {
+            ImageFormat::Tiff => self.stream_tiff(path, sink),
+            ImageFormat::Png => self.stream_png(path, sink),
+            _ => Err(ImageToolError::UnsupportedFormat(format!(
+                "Tiled decoding is not available for {:?}",
+                format
+            ))),
+        }
+    }
+
+    fn stream_tiff(&self, path: &Path, sink: &mut dyn RowSink) -> Result<()> {
+        use tiff::decoder::{Decoder, DecodingResult, Limits};
+        use tiff::ColorType;
+
+        let file = BufReader::new(File::open(path)?);
+        let mut decoder = Decoder::new(file)
+            .map_err(tiff_error)?
+            .with_limits(Limits::unlimited());
+
+        let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+        let color_type = decoder.colortype().map_err(tiff_error)?;
+        let channels = match color_type {
+            ColorType::Gray(8 | 16) => 1,
+            ColorType::GrayA(8 | 16) => 2,
+            ColorType::RGB(8 | 16) => 3,
+            ColorType::RGBA(8 | 16) => 4,
+            other => {
+                return Err(ImageToolError::UnsupportedFormat(format!(
+                    "Tiled decoding does not support TIFF color type {:?}",
+                    other
+                )))
+            }
+        };
+
+        sink.begin(RowLayout {
+            width,
+            height,
+            channels,
+            sixteen_bit: color_type.bit_depth() == 16,
+        })?;
+
+        let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+        let chunks_across = width.div_ceil(chunk_width);
+        let chunks_down = height.div_ceil(chunk_height);
+        let row_len = width as usize * channels;
+
+        for band in 0..chunks_down {
+            let band_height = chunk_height.min(height - band * chunk_height) as usize;
+            let mut band_buffer = vec![0u16; row_len * band_height];
+
+            for column in 0..chunks_across {
+                let index = band * chunks_across + column;
+                let (data_width, data_height) = decoder.chunk_data_dimensions(index);
+                let samples = match decoder.read_chunk(index).map_err(tiff_error)? {
+                    DecodingResult::U8(data) => data.into_iter().map(u16::from).collect(),
+                    DecodingResult::U16(data) => data,
+                    _ => {
+                        return Err(ImageToolError::UnsupportedFormat(
+                            "Tiled decoding only supports 8 and 16 bit TIFF samples".to_string(),
+                        ))
+                    }
+                };
+
+                let chunk_row_len = data_width as usize * channels;
+                let x_offset = (column * chunk_width) as usize * channels;
+                for row in 0..(data_height as usize).min(band_height) {
+                    let src = &samples[row * chunk_row_len..(row + 1) * chunk_row_len];
+                    let dst_start = row * row_len + x_offset;
+                    band_buffer[dst_start..dst_start + chunk_row_len].copy_from_slice(src);
+                }
+            }
+
+            for row in band_buffer.chunks_exact(row_len) {
+                sink.push_row(row)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stream_png(&self, path: &Path, sink: &mut dyn RowSink) -> Result<()> {
+        let file = BufReader::new(File::open(path)?);
+        let mut decoder = png::Decoder::new(file);
+        // Expand palettes and low bit depths but keep 16-bit samples
+        decoder.set_transformations(png::Transformations::EXPAND);
+
+        let mut reader = decoder.read_info().map_err(png_error)?;
+        if reader.info().interlaced {
+            return Err(ImageToolError::UnsupportedFormat(
+                "Tiled decoding does not support interlaced PNG".to_string(),
+            ));
+        }
+
+        let (width, height) = (reader.info().width, reader.info().height);
+        let channels = match reader.output_color_type().0 {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => {
+                return Err(ImageToolError::UnsupportedFormat(
+                    "Unexpanded indexed PNG".to_string(),
+                ))
+            }
+        };
+
+        let sixteen_bit = reader.output_color_type().1 == png::BitDepth::Sixteen;
+        sink.begin(RowLayout {
+            width,
+            height,
+            channels,
+            sixteen_bit,
+        })?;
+
+        let mut samples = Vec::new();
+        while let Some(row) = reader.next_row().map_err(png_error)? {
+            samples.clear();
+            if sixteen_bit {
+                samples.extend(row.data().chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])));
+            } else {
+                samples.extend(row.data().iter().map(|&b| u16::from(b)));
+            }
+            sink.push_row(&samples)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for TiledLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Downscaler {
+    target_width: u32,
+    target_height: u32,
+    linear: bool,
+    accumulator: Option<BoxAccumulator>,
+}
+
+impl RowSink for Downscaler {
+    fn begin(&mut self, layout: RowLayout) -> Result<()> {
+        self.accumulator = Some(BoxAccumulator::new(
+            layout.width,
+            layout.height,
+            self.target_width,
+            self.target_height,
+            layout.channels,
+            layout.sixteen_bit,
+            self.linear,
+        ));
+        Ok(())
+    }
+
+    fn push_row(&mut self, row: &[u16]) -> Result<()> {
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.push_row(row);
+        }
+        Ok(())
+    }
+}
+
+struct BoxAccumulator {
+    src_height: u32,
+    dst_width: u32,
+    dst_height: u32,
+    channels: usize,
+    sixteen_bit: bool,
+    /// Sample value to linear light, when averaging linearly
+    to_linear: Option<Vec<f32>>,
+    x_map: Vec<usize>,
+    sums: Vec<f32>,
+    counts: Vec<u32>,
+    src_row: u32,
+    dst_row: u32,
+    output: Vec<u16>,
+}
+
+impl BoxAccumulator {
+    fn new(
+        src_width: u32,
+        src_height: u32,
+        dst_width: u32,
+        dst_height: u32,
+        channels: usize,
+        sixteen_bit: bool,
+        linear: bool,
+    ) -> Self {
+        let dst_width = dst_width.clamp(1, src_width);
+        let dst_height = dst_height.clamp(1, src_height);
+        let x_map = (0..src_width as u64)
+            .map(|x| (x * dst_width as u64 / src_width as u64) as usize)
+            .collect();
+
+        Self {
+            src_height,
+            dst_width,
+            dst_height,
+            channels,
+            sixteen_bit,
+            to_linear: linear.then(|| {
+                let max = if sixteen_bit { 65535 } else { 255 };
+                (0..=max).map(|v| srgb_to_linear(v as f32 / max as f32) * max as f32).collect()
+            }),
+            x_map,
+            sums: vec![0.0; dst_width as usize * channels],
+            counts: vec![0; dst_width as usize],
+            src_row: 0,
+            dst_row: 0,
+            output: Vec::with_capacity(dst_width as usize * dst_height as usize * channels),
+        }
+    }
+
+    fn push_row(&mut self, row: &[u16]) {
+        if self.src_row >= self.src_height {
+            return;
+        }
+
+        let dst_row = (self.src_row as u64 * self.dst_height as u64 / self.src_height as u64) as u32;
+        if dst_row != self.dst_row {
+            self.flush_row();
+            self.dst_row = dst_row;
+        }
+
+        let alpha = self.alpha_channel();
+        for (x, pixel) in row.chunks_exact(self.channels).enumerate() {
+            let dst_x = self.x_map[x];
+            let offset = dst_x * self.channels;
+            // Color is weighted by coverage (premultiplied) so transparent
+            // pixels don't darken the edges they border
+            let weight = alpha.map_or(1.0, |a| pixel[a] as f32);
+            for (c, &sample) in pixel.iter().enumerate() {
+                if Some(c) == alpha {
+                    self.sums[offset + c] += sample as f32;
+                    continue;
+                }
+                let value = match &self.to_linear {
+                    Some(lut) => lut[sample as usize],
+                    None => sample as f32,
+                };
+                self.sums[offset + c] += value * weight;
+            }
+            self.counts[dst_x] += 1;
+        }
+
+        self.src_row += 1;
+    }
+
+    fn flush_row(&mut self) {
+        let max = if self.sixteen_bit { 65535.0 } else { 255.0 };
+        let alpha = self.alpha_channel();
+        let linear = self.to_linear.is_some();
+        for (x, count) in self.counts.iter_mut().enumerate() {
+            let count_f = (*count).max(1) as f32;
+            let pixel = &mut self.sums[x * self.channels..(x + 1) * self.channels];
+            let alpha_sum = alpha.map(|a| pixel[a]);
+            for (c, sum) in pixel.iter_mut().enumerate() {
+                let value = match alpha_sum {
+                    Some(alpha_sum) if Some(c) != alpha => {
+                        if alpha_sum > 0.0 { *sum / alpha_sum } else { 0.0 }
+                    }
+                    _ => *sum / count_f,
+                };
+                let value = if linear && Some(c) != alpha {
+                    linear_to_srgb((value / max).clamp(0.0, 1.0)) * max
+                } else {
+                    value
+                };
+                self.output.push(value.round().clamp(0.0, max) as u16);
+                *sum = 0.0;
+            }
+            *count = 0;
+        }
+    }
+
+    fn alpha_channel(&self) -> Option<usize> {
+        matches!(self.channels, 2 | 4).then(|| self.channels - 1)
+    }
+
+    fn finish(mut self) -> Result<DynamicImage> {
+        if self.src_row < self.src_height {
+            return Err(ImageToolError::ProcessingError(format!(
+                "Image data ended after {} of {} rows",
+                self.src_row, self.src_height
+            )));
+        }
+        self.flush_row();
+
+        samples_to_image(self.dst_width, self.dst_height, self.channels, self.sixteen_bit, self.output)
+    }
+}
+
+/// Packs interleaved u16 samples (holding 8-bit values unless `sixteen_bit`)
+/// into an image
+pub(crate) fn samples_to_image(
+    width: u32,
+    height: u32,
+    channels: usize,
+    sixteen_bit: bool,
+    samples: Vec<u16>,
+) -> Result<DynamicImage> {
+    let (w, h) = (width, height);
+    let invalid = || ImageToolError::ProcessingError("Tiled output buffer size mismatch".to_string());
+    if sixteen_bit {
+        return Ok(match channels {
+            1 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+            2 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+            3 => DynamicImage::ImageRgb16(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+            _ => DynamicImage::ImageRgba16(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+        });
+    }
+
+    let samples: Vec<u8> = samples.into_iter().map(|v| v as u8).collect();
+    let image = match channels {
+        1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+        2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+        3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+        _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(w, h, samples).ok_or_else(invalid)?),
+    };
+
+    Ok(image)
+}
+
+fn tiff_error(e: tiff::TiffError) -> ImageToolError {
+    ImageToolError::ProcessingError(format!("TIFF decode error: {}", e))
+}
+
+fn png_error(e: png::DecodingError) -> ImageToolError {
+    ImageToolError::ProcessingError(format!("PNG decode error: {}", e))
+}
@@ -0,0 +1,80 @@
+// pixie/src/processors/tonemap.rs
+use crate::core::ToneMapOperator;
+use crate::utils::linear_to_srgb;
+use image::{DynamicImage, Rgb32FImage, RgbImage, RgbaImage};
+
+/// Maps linear floating point (HDR/EXR) images into 8-bit sRGB.
+pub struct ToneMapper {
+    operator: ToneMapOperator,
+    exposure: f32,
+}
+
+impl ToneMapper {
+    pub fn new(operator: ToneMapOperator) -> Self {
+        Self {
+            operator,
+            exposure: 0.0,
+        }
+    }
+
+    /// Exposure adjustment in stops applied before the curve
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn is_hdr(image: &DynamicImage) -> bool {
+        matches!(image, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_))
+    }
+
+    /// Returns the image unchanged unless it holds float samples
+    pub fn map(&self, image: DynamicImage) -> DynamicImage {
+        if !Self::is_hdr(&image) {
+            return image;
+        }
+
+        let scale = 2f32.powf(self.exposure);
+        log::debug!(
+            "Tone mapping {}x{} image with {:?}, exposure {:+.2} EV",
+            image.width(),
+            image.height(),
+            self.operator,
+            self.exposure
+        );
+
+        match image {
+            DynamicImage::ImageRgb32F(hdr) => DynamicImage::ImageRgb8(self.map_rgb(&hdr, scale)),
+            DynamicImage::ImageRgba32F(hdr) => {
+                let mut out = RgbaImage::new(hdr.width(), hdr.height());
+                for (src, dst) in hdr.pixels().zip(out.pixels_mut()) {
+                    let [r, g, b, a] = src.0;
+                    let [r, g, b] = self.map_pixel([r, g, b], scale);
+                    dst.0 = [r, g, b, (a.clamp(0.0, 1.0) * 255.0).round() as u8];
+                }
+                DynamicImage::ImageRgba8(out)
+            }
+            other => other,
+        }
+    }
+
+    fn map_rgb(&self, hdr: &Rgb32FImage, scale: f32) -> RgbImage {
+        let mut out = RgbImage::new(hdr.width(), hdr.height());
+        for (src, dst) in hdr.pixels().zip(out.pixels_mut()) {
+            dst.0 = self.map_pixel(src.0, scale);
+        }
+        out
+    }
+
+    fn map_pixel(&self, rgb: [f32; 3], scale: f32) -> [u8; 3] {
+        rgb.map(|c| {
+            let c = (c * scale).max(0.0);
+            let mapped = match self.operator {
+                ToneMapOperator::Reinhard => c / (1.0 + c),
+                // Narkowicz's fit of the ACES filmic curve
+                ToneMapOperator::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+                ToneMapOperator::Clamp => c,
+            };
+            (linear_to_srgb(mapped.clamp(0.0, 1.0)) * 255.0).round() as u8
+        })
+    }
+}
@@ -0,0 +1,190 @@
+// pixie/src/processors/upscaler.rs
+use crate::core::{ImageToolError, Result, Upscaler};
+use image::{imageops, DynamicImage, GenericImageView, ImageFormat};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Source tile edge handed to the external tool, which keeps its (usually
+/// GPU) memory use bounded on large inputs
+const DEFAULT_TILE_SIZE: u32 = 512;
+/// Extra context around each tile so seams don't show after stitching
+const TILE_OVERLAP: u32 = 16;
+
+static SCRATCH_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Delegates enlargement to an external super-resolution program. The image
+/// is cut into overlapping tiles, each tile is round-tripped through PNG
+/// files and the results are stitched back together.
+pub struct ExternalUpscaler {
+    upscaler: Upscaler,
+    tile_size: u32,
+}
+
+impl ExternalUpscaler {
+    pub fn new(upscaler: Upscaler) -> Self {
+        Self {
+            upscaler,
+            tile_size: DEFAULT_TILE_SIZE,
+        }
+    }
+
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    /// Whole factor the tool is asked for. Super-resolution models only come
+    /// in 2x to 4x, so the caller resamples the result to the exact size.
+    pub fn factor_for(scale: f32) -> u32 {
+        (scale.ceil() as u32).clamp(2, 4)
+    }
+
+    pub fn upscale(&self, image: &DynamicImage, scale: f32) -> Result<DynamicImage> {
+        let factor = Self::factor_for(scale);
+        let source = if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        };
+        let (width, height) = source.dimensions();
+
+        log::info!(
+            "Upscaling {}x{} by {}x with {}",
+            width,
+            height,
+            factor,
+            self.program().display()
+        );
+
+        let scratch = ScratchDir::new("upscale")?;
+        let mut output = DynamicImage::new(width * factor, height * factor, source.color());
+
+        for y in (0..height).step_by(self.tile_size as usize) {
+            for x in (0..width).step_by(self.tile_size as usize) {
+                let tile_width = self.tile_size.min(width - x);
+                let tile_height = self.tile_size.min(height - y);
+                let left = x.saturating_sub(TILE_OVERLAP);
+                let top = y.saturating_sub(TILE_OVERLAP);
+                let right = (x + tile_width + TILE_OVERLAP).min(width);
+                let bottom = (y + tile_height + TILE_OVERLAP).min(height);
+
+                let padded = source.crop_imm(left, top, right - left, bottom - top);
+                let upscaled = self.run(&padded, factor, scratch.path())?;
+                if upscaled.dimensions() != (padded.width() * factor, padded.height() * factor) {
+                    return Err(ImageToolError::ProcessingError(format!(
+                        "Upscaler returned {}x{} for a {}x{} tile at {}x",
+                        upscaled.width(),
+                        upscaled.height(),
+                        padded.width(),
+                        padded.height(),
+                        factor
+                    )));
+                }
+
+                let inner = upscaled.crop_imm(
+                    (x - left) * factor,
+                    (y - top) * factor,
+                    tile_width * factor,
+                    tile_height * factor,
+                );
+                imageops::replace(&mut output, &inner, (x * factor) as i64, (y * factor) as i64);
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn run(&self, tile: &DynamicImage, factor: u32, dir: &Path) -> Result<DynamicImage> {
+        let input = dir.join("tile.png");
+        let output = dir.join("tile_upscaled.png");
+        tile.save_with_format(&input, ImageFormat::Png)?;
+        // A stale result from the previous tile must not be mistaken for this one
+        let _ = std::fs::remove_file(&output);
+
+        let mut command = match &self.upscaler {
+            Upscaler::RealEsrganNcnn(binary) => {
+                let mut command = Command::new(binary);
+                command
+                    .arg("-i")
+                    .arg(&input)
+                    .arg("-o")
+                    .arg(&output)
+                    .args(["-s", &factor.to_string(), "-f", "png"]);
+                command
+            }
+            Upscaler::Command(template) => {
+                // Split before substituting so paths with spaces stay one argument
+                let mut args = template.split_whitespace().map(|part| {
+                    part.replace("{input}", &input.to_string_lossy())
+                        .replace("{output}", &output.to_string_lossy())
+                        .replace("{scale}", &factor.to_string())
+                });
+                let program = args.next().ok_or_else(|| {
+                    ImageToolError::InvalidParameter("Upscaler command is empty".to_string())
+                })?;
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+
+        let result = command
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => ImageToolError::UnsupportedFormat(format!(
+                    "{} not found; install it or pass --upscaler-cmd",
+                    self.program().display()
+                )),
+                _ => ImageToolError::Io(e),
+            })?;
+
+        if !result.status.success() {
+            return Err(ImageToolError::ProcessingError(format!(
+                "Upscaler failed: {}",
+                String::from_utf8_lossy(&result.stderr).trim()
+            )));
+        }
+
+        image::open(&output).map_err(|e| {
+            ImageToolError::ProcessingError(format!("Upscaler output could not be read: {}", e))
+        })
+    }
+
+    fn program(&self) -> PathBuf {
+        match &self.upscaler {
+            Upscaler::RealEsrganNcnn(binary) => binary.clone(),
+            Upscaler::Command(template) => {
+                PathBuf::from(template.split_whitespace().next().unwrap_or_default())
+            }
+        }
+    }
+}
+
+// Files handed to external tools, removed again when the work finishes
+// or fails
+pub(crate) struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub(crate) fn new(purpose: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "pixie-{}-{}-{}",
+            purpose,
+            std::process::id(),
+            SCRATCH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
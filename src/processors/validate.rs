@@ -0,0 +1,290 @@
+// pixie/src/processors/validate.rs
+use crate::core::Result;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::processors::batch::collect_image_paths;
+use image::{ImageFormat, ImageReader};
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Checks that images are intact: walks the container structure of
+/// JPEG, PNG, WebP and GIF files for truncation, bad chunk CRCs and stray
+/// markers, then fully decodes every file.
+pub struct ImageValidator {
+    recursive: bool,
+}
+
+#[derive(Debug)]
+pub struct FileCheck {
+    pub path: PathBuf,
+    pub format: Option<ImageFormat>,
+    pub defects: Vec<Defect>,
+}
+
+#[derive(Debug)]
+pub struct Defect {
+    /// Where in the file it was found, when the structure says
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+impl FileCheck {
+    pub fn is_valid(&self) -> bool {
+        self.defects.is_empty()
+    }
+}
+
+impl std::fmt::Display for Defect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "at byte {}: {}", offset, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl ImageValidator {
+    pub fn new() -> Self {
+        Self { recursive: false }
+    }
+
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Checks each file, and every image in each directory, in order
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn validate(&self, paths: &[PathBuf]) -> Result<Vec<FileCheck>> {
+        let mut files = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                let mut found = collect_image_paths(path, self.recursive);
+                found.sort();
+                files.extend(found);
+            } else {
+                files.push(path.clone());
+            }
+        }
+        Ok(files.par_iter().map(|path| self.check_file(path)).collect())
+    }
+
+    pub fn check_file(&self, path: &Path) -> FileCheck {
+        let mut check = FileCheck {
+            path: path.to_path_buf(),
+            format: None,
+            defects: Vec::new(),
+        };
+        match std::fs::read(path) {
+            Ok(data) => {
+                check.format = image::guess_format(&data).ok();
+                check.defects = self.check_bytes(&data);
+            }
+            Err(e) => check.defects.push(Defect { offset: None, message: e.to_string() }),
+        }
+        check
+    }
+
+    /// Structural defects first, in file order, then any decoding error
+    pub fn check_bytes(&self, data: &[u8]) -> Vec<Defect> {
+        let mut defects = Vec::new();
+        if data.is_empty() {
+            defects.push(Defect { offset: None, message: "the file is empty".to_string() });
+            return defects;
+        }
+
+        match image::guess_format(data) {
+            Ok(ImageFormat::Jpeg) => check_jpeg(data, &mut defects),
+            Ok(ImageFormat::Png) => check_png(data, &mut defects),
+            Ok(ImageFormat::WebP) => check_riff(data, &mut defects),
+            Ok(ImageFormat::Gif) if data.last() != Some(&0x3B) => defects.push(Defect {
+                offset: Some(data.len() as u64),
+                message: "truncated: the GIF trailer is missing".to_string(),
+            }),
+            _ => {}
+        }
+
+        let decoded = ImageReader::new(std::io::Cursor::new(data))
+            .with_guessed_format()
+            .map_err(image::ImageError::from)
+            .and_then(|reader| reader.decode());
+        if let Err(e) = decoded {
+            defects.push(Defect { offset: None, message: format!("decoding failed: {}", e) });
+        }
+        defects
+    }
+}
+
+impl Default for ImageValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where a JPEG's data breaks off, found before decoding it
+pub(crate) struct JpegDamage {
+    pub defect: Defect,
+    /// Rows in each band of blocks, the unit the decoder fills in
+    pub mcu_height: u32,
+}
+
+/// None for intact JPEGs and anything else. Files ending in the
+/// end-of-image marker are taken as intact without walking them.
+pub(crate) fn jpeg_damage(data: &[u8]) -> Option<JpegDamage> {
+    if !data.starts_with(&[0xFF, 0xD8]) || data.ends_with(&[0xFF, 0xD9]) {
+        return None;
+    }
+    let mut defects = Vec::new();
+    check_jpeg(data, &mut defects);
+    let defect = defects.into_iter().next()?;
+    Some(JpegDamage { defect, mcu_height: mcu_height(data) })
+}
+
+// 8 rows, times the largest vertical sampling factor when the scan
+// interleaves several components
+fn mcu_height(data: &[u8]) -> u32 {
+    let mut pos = 2;
+    while let Some(&[0xFF, marker, high, low]) = data.get(pos..pos + 4) {
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let components = data.get(pos + 9).copied().unwrap_or(1) as usize;
+            let max_vertical = (0..components)
+                .filter_map(|i| data.get(pos + 11 + 3 * i))
+                .map(|sampling| (sampling & 0x0F).max(1))
+                .max()
+                .unwrap_or(1);
+            return if components > 1 { 8 * max_vertical as u32 } else { 8 };
+        }
+        if marker == 0xDA {
+            break;
+        }
+        pos += 2 + u16::from_be_bytes([high, low]) as usize;
+    }
+    8
+}
+
+fn defect(defects: &mut Vec<Defect>, offset: usize, message: String) {
+    defects.push(Defect { offset: Some(offset as u64), message });
+}
+
+// Segment by segment from SOI to EOI, skipping over the entropy-coded
+// data after each SOS up to the next marker
+fn check_jpeg(data: &[u8], defects: &mut Vec<Defect>) {
+    let mut pos = 2;
+    loop {
+        if pos >= data.len() {
+            defect(defects, data.len(), "truncated: the end-of-image marker is missing".to_string());
+            return;
+        }
+        if data[pos] != 0xFF {
+            defect(defects, pos, format!("expected a marker, found 0x{:02X}", data[pos]));
+            return;
+        }
+        let start = pos;
+        // Any number of 0xFF may pad a marker
+        while pos < data.len() && data[pos] == 0xFF {
+            pos += 1;
+        }
+        let Some(&marker) = data.get(pos) else { continue };
+        pos += 1;
+
+        match marker {
+            0xD9 => return,
+            0x01 | 0xD0..=0xD7 => continue,
+            0x00 | 0xD8 => {
+                defect(defects, start, format!("unexpected marker 0xFF{:02X}", marker));
+                return;
+            }
+            _ => {}
+        }
+
+        let Some(length) = data.get(pos..pos + 2).map(|len| u16::from_be_bytes([len[0], len[1]]) as usize) else {
+            defect(defects, start, format!("truncated in the header of segment 0xFF{:02X}", marker));
+            return;
+        };
+        if length < 2 {
+            defect(defects, start, format!("segment 0xFF{:02X} has an invalid length of {}", marker, length));
+            return;
+        }
+        let end = pos + length;
+        if end > data.len() {
+            defect(
+                defects,
+                start,
+                format!("truncated: segment 0xFF{:02X} of {} bytes runs past the end of the file", marker, length),
+            );
+            return;
+        }
+        pos = end;
+
+        if marker == 0xDA {
+            // Image data runs to the first marker other than a stuffed 0xFF00 or a restart
+            let next = data[pos..]
+                .windows(2)
+                .position(|pair| pair[0] == 0xFF && pair[1] != 0x00 && !(0xD0..=0xD7).contains(&pair[1]));
+            match next {
+                Some(offset) => pos += offset,
+                None => {
+                    defect(defects, data.len(), "truncated in the image data: the end-of-image marker is missing".to_string());
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// Chunk by chunk up to IEND, checking each CRC
+fn check_png(data: &[u8], defects: &mut Vec<Defect>) {
+    let mut pos = PNG_SIGNATURE.len();
+    loop {
+        if pos == data.len() {
+            defect(defects, pos, "truncated: the IEND chunk is missing".to_string());
+            return;
+        }
+        let Some(header) = data.get(pos..pos + 8) else {
+            defect(defects, pos, "truncated in a chunk header".to_string());
+            return;
+        };
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let name = header[4..8].escape_ascii().to_string();
+        let Some(stored) = data.get(pos + 8 + length..pos + 12 + length) else {
+            defect(
+                defects,
+                pos,
+                format!("truncated: {} chunk of {} bytes runs past the end of the file", name, length),
+            );
+            return;
+        };
+
+        let stored = u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]]);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&data[pos + 4..pos + 8 + length]);
+        let computed = hasher.finalize();
+        if stored != computed {
+            defect(
+                defects,
+                pos,
+                format!("{} chunk CRC mismatch (stored 0x{:08X}, computed 0x{:08X})", name, stored, computed),
+            );
+        }
+
+        if name == "IEND" {
+            return;
+        }
+        pos += 12 + length;
+    }
+}
+
+// The RIFF header records the size of everything after it
+fn check_riff(data: &[u8], defects: &mut Vec<Defect>) {
+    let Some(size) = data.get(4..8) else { return };
+    let expected = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize + 8;
+    if expected > data.len() {
+        defect(
+            defects,
+            data.len(),
+            format!("truncated: the RIFF header declares {} bytes but the file has {}", expected, data.len()),
+        );
+    }
+}
@@ -0,0 +1,80 @@
+// pixie/src/processors/video.rs
+use crate::core::{ImageToolError, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Pulls a single frame out of a video by shelling out to ffmpeg, which has
+/// to be installed separately.
+pub struct VideoFrameGrabber {
+    ffmpeg: PathBuf,
+}
+
+impl VideoFrameGrabber {
+    pub fn new() -> Self {
+        Self {
+            ffmpeg: PathBuf::from("ffmpeg"),
+        }
+    }
+
+    pub fn with_ffmpeg(mut self, ffmpeg: PathBuf) -> Self {
+        self.ffmpeg = ffmpeg;
+        self
+    }
+
+    /// Returns the frame at `timestamp` (`HH:MM:SS[.ms]` or seconds) as PNG bytes
+    pub fn grab_frame(&self, input_path: &Path, timestamp: &str) -> Result<Vec<u8>> {
+        if !input_path.exists() {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "Input file does not exist: {}",
+                input_path.display()
+            )));
+        }
+
+        if timestamp.is_empty() || !timestamp.chars().all(|c| c.is_ascii_digit() || c == ':' || c == '.') {
+            return Err(ImageToolError::InvalidParameter(format!(
+                "Invalid timestamp: {} (expected HH:MM:SS or seconds)",
+                timestamp
+            )));
+        }
+
+        log::debug!("Grabbing frame at {} from {}", timestamp, input_path.display());
+
+        // -ss before -i seeks on keyframes first, which is much faster on long videos
+        let output = Command::new(&self.ffmpeg)
+            .args(["-v", "error", "-ss", timestamp, "-i"])
+            .arg(input_path)
+            .args(["-frames:v", "1", "-f", "image2pipe", "-vcodec", "png", "-"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => ImageToolError::UnsupportedFormat(format!(
+                    "{} not found; install ffmpeg or pass --ffmpeg",
+                    self.ffmpeg.display()
+                )),
+                _ => ImageToolError::Io(e),
+            })?;
+
+        if !output.status.success() {
+            return Err(ImageToolError::ProcessingError(format!(
+                "ffmpeg failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        if output.stdout.is_empty() {
+            return Err(ImageToolError::ProcessingError(format!(
+                "No frame at {} in {}",
+                timestamp,
+                input_path.display()
+            )));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+impl Default for VideoFrameGrabber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,112 @@
+// pixie/src/server/metrics.rs
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counters behind the server's `/metrics` endpoint, rendered in the
+/// Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    processed: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    bytes_saved: AtomicU64,
+    rejected: AtomicU64,
+    errors: Mutex<BTreeMap<u16, u64>>,
+    // One count per bucket, not cumulative; the last is +Inf
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    latency_count: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+impl ServerMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_processed(&self, bytes_in: usize, bytes_out: usize, elapsed: Duration) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes_in as u64, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes_out as u64, Ordering::Relaxed);
+        // Outputs larger than their input save nothing rather than going negative
+        self.bytes_saved
+            .fetch_add(bytes_in.saturating_sub(bytes_out) as u64, Ordering::Relaxed);
+        self.observe_latency(elapsed);
+    }
+
+    /// A failed processing request, by the HTTP status it was answered with
+    pub fn record_error(&self, status: u16, elapsed: Duration) {
+        *self.errors.lock().unwrap_or_else(|e| e.into_inner()).entry(status).or_insert(0) += 1;
+        self.observe_latency(elapsed);
+    }
+
+    /// A connection turned away with 503 because the worker queue was full
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn observe_latency(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.latency_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut out = String::new();
+
+        let mut counter = |name: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        counter("pixie_images_processed_total", "Images processed successfully.", load(&self.processed));
+        counter("pixie_input_bytes_total", "Bytes received in successful requests.", load(&self.bytes_in));
+        counter("pixie_output_bytes_total", "Bytes returned by successful requests.", load(&self.bytes_out));
+        counter(
+            "pixie_bytes_saved_total",
+            "Input bytes minus output bytes, counting only outputs that shrank.",
+            load(&self.bytes_saved),
+        );
+        counter(
+            "pixie_requests_rejected_total",
+            "Connections answered with 503 because the worker queue was full.",
+            load(&self.rejected),
+        );
+
+        let _ = writeln!(out, "# HELP pixie_errors_total Processing requests that failed, by HTTP status.");
+        let _ = writeln!(out, "# TYPE pixie_errors_total counter");
+        for (status, count) in self.errors.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            let _ = writeln!(out, "pixie_errors_total{{status=\"{}\"}} {}", status, count);
+        }
+
+        let _ = writeln!(out, "# HELP pixie_processing_seconds Time to process a request, failed ones included.");
+        let _ = writeln!(out, "# TYPE pixie_processing_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += load(bucket);
+            let _ = writeln!(out, "pixie_processing_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        cumulative += load(&self.latency_buckets[LATENCY_BUCKETS.len()]);
+        let _ = writeln!(out, "pixie_processing_seconds_bucket{{le=\"+Inf\"}} {}", cumulative);
+        let _ = writeln!(
+            out,
+            "pixie_processing_seconds_sum {}",
+            load(&self.latency_micros) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "pixie_processing_seconds_count {}", load(&self.latency_count));
+
+        out
+    }
+}
@@ -0,0 +1,383 @@
+// pixie/src/server/mod.rs
+use crate::core::processor::ImageProcessor;
+use crate::core::{ImageToolError, ProcessConfig, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod metrics;
+
+// Limits on the request head, which is read before anything else is checked
+const MAX_HEADER_BYTES: u64 = 8 * 1024;
+const MAX_HEADER_LINES: usize = 100;
+
+pub use metrics::ServerMetrics;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listen: String,
+    pub workers: usize,
+    pub queue_size: usize,
+    pub max_body_size: u64,
+    /// Largest input or output, in pixels, a request may decode or produce
+    pub max_pixels: u64,
+    pub base_config: ProcessConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:8080".to_string(),
+            workers: 0,
+            queue_size: 64,
+            max_body_size: 50 * 1024 * 1024,
+            max_pixels: 100_000_000,
+            base_config: ProcessConfig::default(),
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn from_error(error: &ImageToolError) -> Self {
+        let status = match error {
+            ImageToolError::InvalidParameter(_) => 400,
+            ImageToolError::UnsupportedFormat(_) => 415,
+            ImageToolError::MemoryLimitExceeded(_) => 413,
+            ImageToolError::Image(_) | ImageToolError::ProcessingError(_) => 422,
+            _ => 500,
+        };
+        Self::text(status, &format!("{}\n", error))
+    }
+}
+
+pub struct Server {
+    config: ServerConfig,
+    metrics: Arc<ServerMetrics>,
+}
+
+impl Server {
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            metrics: Arc::new(ServerMetrics::new()),
+        }
+    }
+
+    /// What `/metrics` reports, for embedders exporting it some other way
+    pub fn metrics(&self) -> Arc<ServerMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    // Connections are handed to a fixed pool of workers through a bounded
+    // queue; when the queue is full new clients get 503 straight away
+    // instead of piling up decode jobs.
+    pub fn run(&self) -> Result<()> {
+        let listener = TcpListener::bind(&self.config.listen)?;
+        let workers = match self.config.workers {
+            0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            n => n,
+        };
+
+        log::info!(
+            "Listening on {} with {} workers",
+            self.config.listen, workers
+        );
+
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(self.config.queue_size.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let config = Arc::new(self.config.clone());
+
+        for id in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let config = Arc::clone(&config);
+            let metrics = Arc::clone(&self.metrics);
+            std::thread::Builder::new()
+                .name(format!("pixie-worker-{}", id))
+                .spawn(move || worker_loop(receiver, config, metrics))?;
+        }
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+
+            match sender.try_send(stream) {
+                Ok(()) => {}
+                Err(TrySendError::Full(mut stream)) => {
+                    log::warn!("Worker queue full, rejecting connection");
+                    self.metrics.record_rejected();
+                    let _ = write_response(&mut stream, &Response::text(503, "Server busy\n"));
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    return Err(ImageToolError::ProcessingError("All workers have stopped".to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn worker_loop(receiver: Arc<Mutex<Receiver<TcpStream>>>, config: Arc<ServerConfig>, metrics: Arc<ServerMetrics>) {
+    loop {
+        let stream = match receiver.lock() {
+            Ok(receiver) => match receiver.recv() {
+                Ok(stream) => stream,
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
+
+        if let Err(e) = handle_connection(stream, &config, &metrics) {
+            log::debug!("Connection error: {}", e);
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, config: &ServerConfig, metrics: &ServerMetrics) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+
+    let response = match read_request(&stream, config.max_body_size) {
+        Ok(request) => route(&request, config, metrics),
+        Err(e) => Response::from_error(&e),
+    };
+
+    write_response(&mut stream, &response)?;
+    Ok(())
+}
+
+fn route(request: &Request, config: &ServerConfig, metrics: &ServerMetrics) -> Response {
+    log::debug!("{} {}", request.method, request.path);
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => Response::text(200, "ok\n"),
+        ("GET", "/metrics") => Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: metrics.render().into_bytes(),
+        },
+        ("POST", "/process") => {
+            let start = Instant::now();
+            match process_request(request, config) {
+                Ok(response) => {
+                    metrics.record_processed(request.body.len(), response.body.len(), start.elapsed());
+                    response
+                }
+                Err(e) => {
+                    let response = Response::from_error(&e);
+                    metrics.record_error(response.status, start.elapsed());
+                    response
+                }
+            }
+        }
+        (_, "/process") | (_, "/health") | (_, "/metrics") => Response::text(405, "Method not allowed\n"),
+        _ => Response::text(404, "Not found\n"),
+    }
+}
+
+fn process_request(request: &Request, config: &ServerConfig) -> Result<Response> {
+    if request.body.is_empty() {
+        return Err(ImageToolError::InvalidParameter("Request body is empty".to_string()));
+    }
+
+    let process_config = config_from_query(&config.base_config, &request.query)?;
+    process_config.validate()?;
+
+    let processor = ImageProcessor::new(process_config);
+    check_size(&processor, &request.body, config)?;
+    let (body, output_format) = processor.process_bytes_with_format(&request.body)?;
+
+    Ok(Response {
+        status: 200,
+        content_type: output_format.to_mime_type(),
+        body,
+    })
+}
+
+// Refuses bodies whose input or output would be too large, going by the
+// header alone so nothing is decoded first
+fn check_size(processor: &ImageProcessor, body: &[u8], config: &ServerConfig) -> Result<()> {
+    let (width, height) = image::ImageReader::new(std::io::Cursor::new(body))
+        .with_guessed_format()?
+        .into_dimensions()?;
+    let (out_width, out_height) = processor.output_dimensions(width, height);
+
+    for (what, w, h) in [("Input", width, height), ("Output", out_width, out_height)] {
+        let pixels = w as u64 * h as u64;
+        if pixels > config.max_pixels {
+            return Err(ImageToolError::MemoryLimitExceeded(format!(
+                "{} of {}x{} exceeds the {} pixel limit", what, w, h, config.max_pixels
+            )));
+        }
+        // RGBA8; wider decodes are caught by the loader's own estimate
+        if let Some(max_memory) = config.base_config.max_memory.filter(|&max| pixels * 4 > max) {
+            return Err(ImageToolError::MemoryLimitExceeded(format!(
+                "{} of {}x{} needs more than the {} byte memory limit", what, w, h, max_memory
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Query parameters use the same names as the CLI flags
+pub fn config_from_query(base: &ProcessConfig, query: &HashMap<String, String>) -> Result<ProcessConfig> {
+    base.apply_options(query)
+}
+
+fn read_request(stream: &TcpStream, max_body_size: u64) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let bad_request = |msg: &str| ImageToolError::InvalidParameter(msg.to_string());
+
+    let request_line = read_header_line(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().ok_or_else(|| bad_request("Malformed request line"))?.to_string();
+    let target = parts.next().ok_or_else(|| bad_request("Malformed request line"))?;
+
+    let (path, query_string) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut content_length: u64 = 0;
+    let mut header_lines = 0;
+    loop {
+        let line = read_header_line(&mut reader)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        header_lines += 1;
+        if header_lines > MAX_HEADER_LINES {
+            return Err(bad_request("Too many header lines"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| bad_request("Invalid Content-Length"))?;
+            }
+        }
+    }
+
+    if content_length > max_body_size {
+        return Err(ImageToolError::MemoryLimitExceeded(format!(
+            "Request body of {} bytes exceeds limit {}",
+            content_length, max_body_size
+        )));
+    }
+
+    let mut body = Vec::with_capacity(content_length as usize);
+    reader.take(content_length).read_to_end(&mut body)?;
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query: parse_query(query_string),
+        body,
+    })
+}
+
+// One line of the request head, empty at end of stream. Lines longer than
+// `MAX_HEADER_BYTES` are refused rather than buffered.
+fn read_header_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_HEADER_BYTES).read_line(&mut line)?;
+    if line.len() as u64 == MAX_HEADER_BYTES && !line.ends_with('\n') {
+        return Err(ImageToolError::InvalidParameter("Header line too long".to_string()));
+    }
+    Ok(line)
+}
+
+// A repeated `redact` adds a region rather than replacing the last one;
+// other repeated keys keep the last value
+fn parse_query(query: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (key, value) = (percent_decode(key), percent_decode(value));
+        match params.get_mut(&key) {
+            Some(regions) if key == "redact" => {
+                *regions = format!("{};{}", regions, value);
+            }
+            _ => {
+                params.insert(key, value);
+            }
+        }
+    }
+    params
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Entity",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
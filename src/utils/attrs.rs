@@ -0,0 +1,99 @@
+// pixie/src/utils/attrs.rs
+use crate::core::Result;
+use std::path::Path;
+
+/// Gives `to` the permissions of `from`, and on Unix its owner, group and
+/// extended attributes as far as this process may set them. Ownership
+/// and xattrs that can't be copied are skipped with a debug log, since an
+/// unprivileged user can only hand files to groups they belong to.
+pub(crate) fn copy_file_attrs(from: &Path, to: &Path) -> Result<()> {
+    let metadata = std::fs::metadata(from)?;
+
+    // Before the mode, which may leave the output read-only
+    #[cfg(target_os = "linux")]
+    copy_xattrs(from, to);
+    #[cfg(unix)]
+    copy_owner(&metadata, to);
+
+    std::fs::set_permissions(to, metadata.permissions())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_owner(metadata: &std::fs::Metadata, to: &Path) {
+    use std::os::unix::fs::MetadataExt;
+
+    let current = match std::fs::metadata(to) {
+        Ok(current) => current,
+        Err(_) => return,
+    };
+    if current.uid() == metadata.uid() && current.gid() == metadata.gid() {
+        return;
+    }
+    if let Err(e) = std::os::unix::fs::chown(to, Some(metadata.uid()), Some(metadata.gid())) {
+        // The group alone often works where the owner doesn't
+        let group = std::os::unix::fs::chown(to, None, Some(metadata.gid()));
+        log::debug!(
+            "Could not give {} owner {}: {}{}",
+            to.display(),
+            metadata.uid(),
+            e,
+            if group.is_ok() { " (group copied)" } else { "" }
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn copy_xattrs(from: &Path, to: &Path) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let (Ok(from_c), Ok(to_c)) = (
+        CString::new(from.as_os_str().as_bytes()),
+        CString::new(to.as_os_str().as_bytes()),
+    ) else {
+        return;
+    };
+
+    let Some(names) = read_xattr(|buf, len| unsafe { libc::listxattr(from_c.as_ptr(), buf, len) }) else {
+        return;
+    };
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let Ok(name) = CString::new(name) else { continue };
+        let Some(value) =
+            read_xattr(|buf, len| unsafe { libc::getxattr(from_c.as_ptr(), name.as_ptr(), buf.cast(), len) })
+        else {
+            continue;
+        };
+        let set = unsafe { libc::setxattr(to_c.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+        if set != 0 {
+            log::debug!(
+                "Could not copy {} to {}: {}",
+                name.to_string_lossy(),
+                to.display(),
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+// Calls `read` once for the size and again for the contents, retrying if
+// the attribute grew in between
+#[cfg(target_os = "linux")]
+fn read_xattr(read: impl Fn(*mut libc::c_char, usize) -> libc::ssize_t) -> Option<Vec<u8>> {
+    loop {
+        let size = read(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return None;
+        }
+        let mut buf = vec![0u8; size as usize];
+        let len = read(buf.as_mut_ptr().cast(), buf.len());
+        if len >= 0 {
+            buf.truncate(len as usize);
+            return Some(buf);
+        }
+        if std::io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE) {
+            return None;
+        }
+    }
+}
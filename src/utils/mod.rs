@@ -1,13 +1,30 @@
 // pixie/src/utils/mod.rs
-use crate::core::{ImageToolError, Result};
+use crate::core::{ConflictPolicy, ImageToolError, Result};
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+mod attrs;
+mod log_file;
+
+pub(crate) use attrs::copy_file_attrs;
+pub use log_file::RotatingFile;
+
 pub fn generate_output_path(
     input_path: &Path,
     output: Option<&Path>,
     suffix: &str,
 ) -> PathBuf {
+    // Remote inputs are written next to the current directory, named after
+    // the last path segment of the URL
+    let remote_name;
+    let input_path = match input_path.to_str().filter(|s| is_remote_url(s)) {
+        Some(url) => {
+            remote_name = PathBuf::from(url_file_name(url));
+            remote_name.as_path()
+        }
+        None => input_path,
+    };
+
     match output {
         Some(path) => path.to_path_buf(),
         None => {
@@ -15,28 +32,47 @@ pub fn generate_output_path(
                 .file_stem()
                 .and_then(|s| s.to_str())
                 .unwrap_or("image");
+            // Inputs without an extension get the one of their content
             let extension = input_path
                 .extension()
                 .and_then(|ext| ext.to_str())
+                .or_else(|| sniff_format(input_path).map(format_extension))
                 .unwrap_or("jpg");
 
-            let timestamp = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-
-            let mut new_filename = format!("{}_{}_{}.{}", stem, suffix, timestamp, extension);
-            let mut counter = 1;
+            // An existing file by this name is left to the conflict policy
+            input_path.with_file_name(format!("{}_{}.{}", stem, suffix, extension))
+        }
+    }
+}
 
-            // Ensure we don't overwrite existing files
-            while Path::new(&new_filename).exists() {
-                new_filename = format!("{}_{}_{}_{}.{}", stem, suffix, timestamp, counter, extension);
-                counter += 1;
-            }
+/// Where an output goes under `policy` if a file is already there; None
+/// when the input should be skipped
+pub fn resolve_conflict(path: &Path, policy: ConflictPolicy) -> Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(Some(path.to_path_buf()));
+    }
+    match policy {
+        ConflictPolicy::Overwrite => Ok(Some(path.to_path_buf())),
+        ConflictPolicy::Skip => Ok(None),
+        ConflictPolicy::Rename => Ok(Some(renamed_path(path, Path::exists))),
+        ConflictPolicy::Error => Err(ImageToolError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("Output already exists: {}", path.display()),
+        ))),
+    }
+}
 
-            input_path.with_file_name(new_filename)
-        }
+/// `path`, or the first `stem_N.ext` next to it that isn't `taken`
+pub(crate) fn renamed_path(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    if !taken(path) {
+        return path.to_path_buf();
     }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{}_{}{}", stem, n, extension)))
+        .find(|candidate| !taken(candidate))
+        .expect("some suffix is free")
 }
 
 pub fn format_file_size(bytes: u64) -> String {
@@ -113,6 +149,7 @@ pub fn get_image_info(path: &Path) -> Result<(u32, u32, String)> {
 pub fn is_supported_format(path: &Path) -> bool {
     let extensions = [
         "jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "webp",
+        "tga", "ppm", "pgm", "pnm", "qoi", "hdr", "exr",
     ];
 
     path.extension()
@@ -121,6 +158,32 @@ pub fn is_supported_format(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
+/// Format given by the file's magic bytes, whatever its extension says,
+/// when it's one that can be decoded
+pub fn sniff_format(path: &Path) -> Option<image::ImageFormat> {
+    use std::io::Read;
+
+    let mut head = Vec::with_capacity(32);
+    std::fs::File::open(path).ok()?.take(32).read_to_end(&mut head).ok()?;
+    image::guess_format(&head).ok().filter(|format| format.reading_enabled())
+}
+
+pub fn is_remote_url(input: &str) -> bool {
+    let lower = input.to_ascii_lowercase();
+    lower.starts_with("https://") || lower.starts_with("http://")
+}
+
+fn url_file_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let path = path.splitn(4, '/').nth(3).unwrap_or("");
+    let name = path.rsplit('/').next().unwrap_or("");
+    if name.is_empty() {
+        "image".to_string()
+    } else {
+        sanitize_filename(name)
+    }
+}
+
 pub fn sanitize_filename(filename: &str) -> String {
     let invalid_chars = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
     filename
@@ -135,6 +198,50 @@ pub fn get_file_extension(path: &Path) -> Option<String> {
         .map(|s| s.to_lowercase())
 }
 
+/// File extension used for generated output names
+pub fn format_extension(format: image::ImageFormat) -> &'static str {
+    match format {
+        // PPM or PGM is picked per image, so use the umbrella extension
+        image::ImageFormat::Pnm => "pnm",
+        _ => format.extensions_str().first().copied().unwrap_or("img"),
+    }
+}
+
+/// sRGB transfer function, decoding a 0-1 value to linear light
+pub fn srgb_to_linear(encoded: f32) -> f32 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts `image` back to the pixel layout a processing step started from
+pub(crate) fn into_color(image: image::DynamicImage, color: image::ColorType) -> image::DynamicImage {
+    use image::{ColorType, DynamicImage};
+
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(image.into_luma8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(image.into_luma16()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(image.into_rgb8()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(image.into_rgb16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(image.into_rgb32f()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(image.into_luma_alpha8()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(image.into_luma_alpha16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(image.into_rgba16()),
+        ColorType::Rgba32F => DynamicImage::ImageRgba32F(image.into_rgba32f()),
+        _ => DynamicImage::ImageRgba8(image.into_rgba8()),
+    }
+}
+
 pub fn image_format_to_string(format: image::ImageFormat) -> String {
     match format {
         image::ImageFormat::Jpeg => "JPEG",
@@ -155,4 +262,184 @@ pub fn image_format_to_string(format: image::ImageFormat) -> String {
         _ => "Unknown",
     }
     .to_string()
-}
\ No newline at end of file
+}
+
+/// Hash of a byte slice, equal to `hash_file` of a file with that content
+pub(crate) fn hash_bytes(data: &[u8]) -> u64 {
+    use std::hash::Hasher;
+
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Streamed 64-bit FNV-1a hash of a file's bytes. The algorithm is fixed,
+/// unlike the standard library's, so hashes written to disk stay valid
+/// across builds.
+pub(crate) fn hash_file(path: &Path) -> Result<u64> {
+    use std::hash::Hasher;
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = fnv::FnvHasher::default();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Sets both the modification and access time of a file
+pub(crate) fn set_file_time(path: &Path, time: SystemTime) -> Result<()> {
+    let times = std::fs::FileTimes::new().set_modified(time).set_accessed(time);
+    std::fs::File::options().write(true).open(path)?.set_times(times)?;
+    Ok(())
+}
+
+/// Standard base64 with padding
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (chunk.get(1).copied().unwrap_or(0) as u32) << 8
+            | chunk.get(2).copied().unwrap_or(0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Parses a single-level JSON object such as `{"width": 800, "format": "png"}`
+// into string values. Nested objects and arrays are rejected; null values
+// are dropped.
+pub fn parse_flat_json(input: &str) -> Result<std::collections::HashMap<String, String>> {
+    let invalid = |msg: &str| ImageToolError::InvalidParameter(format!("Invalid config JSON: {}", msg));
+
+    let mut chars = input.trim().chars().peekable();
+    let mut values = std::collections::HashMap::new();
+
+    fn skip_ws(chars: &mut std::iter::Peekable<std::str::Chars>) {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+        if chars.next()? != '"' {
+            return None;
+        }
+        let mut value = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(value),
+                '\\' => match chars.next()? {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'r' => value.push('\r'),
+                    'u' => {
+                        let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+                        value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                    }
+                    c => value.push(c),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    if chars.next() != Some('{') {
+        return Err(invalid("expected an object"));
+    }
+
+    loop {
+        skip_ws(&mut chars);
+        match chars.peek() {
+            Some('}') => {
+                chars.next();
+                break;
+            }
+            Some('"') => {}
+            _ => return Err(invalid("expected a key")),
+        }
+
+        let key = parse_string(&mut chars).ok_or_else(|| invalid("unterminated key"))?;
+        skip_ws(&mut chars);
+        if chars.next() != Some(':') {
+            return Err(invalid("expected ':'"));
+        }
+        skip_ws(&mut chars);
+
+        let value = match chars.peek() {
+            Some('"') => Some(parse_string(&mut chars).ok_or_else(|| invalid("unterminated string"))?),
+            Some('{') | Some('[') => return Err(invalid("nested values are not supported")),
+            Some(_) => {
+                let mut literal = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == ',' || c == '}' || c.is_whitespace() {
+                        break;
+                    }
+                    literal.push(c);
+                    chars.next();
+                }
+                (literal != "null").then_some(literal)
+            }
+            None => return Err(invalid("unexpected end of input")),
+        };
+
+        if let Some(value) = value {
+            values.insert(key, value);
+        }
+
+        skip_ws(&mut chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some('}') => break,
+            _ => return Err(invalid("expected ',' or '}'")),
+        }
+    }
+
+    skip_ws(&mut chars);
+    if chars.next().is_some() {
+        return Err(invalid("trailing characters"));
+    }
+
+    Ok(values)
+}
+
+// Removes one pair of matching single or double quotes around a value
+pub(crate) fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
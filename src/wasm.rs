@@ -0,0 +1,21 @@
+// pixie/src/wasm.rs
+use crate::core::{ImageProcessor, ProcessConfig};
+use crate::utils::parse_flat_json;
+use wasm_bindgen::prelude::*;
+
+/// Processes an encoded image entirely in memory and returns the encoded
+/// result. `config_json` is a flat object with the same keys the HTTP
+/// server accepts as query parameters, e.g. `{"width": 800, "format": "png"}`.
+#[wasm_bindgen]
+pub fn process(bytes: &[u8], config_json: &str) -> Result<Vec<u8>, JsError> {
+    let params = if config_json.trim().is_empty() {
+        Default::default()
+    } else {
+        parse_flat_json(config_json)?
+    };
+
+    let config = ProcessConfig::default().apply_options(&params)?;
+    config.validate()?;
+
+    Ok(ImageProcessor::new(config).process_bytes(bytes)?)
+}
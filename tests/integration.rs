@@ -2,7 +2,7 @@
 mod tests {
     use assert_fs::prelude::*;
     use assert_fs::TempDir;
-    use image_tool_rs::{ImageProcessor, ProcessConfig, ResizeAlgorithm};
+    use pixie::{config_from_query, Dimensions, ImageProcessor, OutputFormat, ProcessConfig, ResizeAlgorithm, StorageLocation};
     use std::fs;
 
     #[test]
@@ -24,6 +24,7 @@ mod tests {
             keep_aspect: true,
             strip_metadata: false,
             algorithm: ResizeAlgorithm::Lanczos3,
+            ..Default::default()
         };
         
         let processor = ImageProcessor::new(config);
@@ -42,4 +43,2991 @@ mod tests {
         
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_config_builder() {
+        let config = ProcessConfig::builder()
+            .dimensions(Dimensions::width(640))
+            .quality(80)
+            .build()
+            .unwrap();
+        
+        assert_eq!(config.width, 640);
+        assert_eq!(config.height, 0);
+        assert_eq!(config.quality, 80);
+        
+        let result = ProcessConfig::builder()
+            .width(640)
+            .scale(50.0)
+            .build();
+        
+        assert!(result.is_err());
+    }
+    
+    #[test]
+    fn test_process_bytes() {
+        let img = image::RgbImage::new(40, 20);
+        let mut input = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut input, image::ImageFormat::Png).unwrap();
+        
+        let config = ProcessConfig {
+            width: 20,
+            ..Default::default()
+        };
+        
+        let processor = ImageProcessor::new(config);
+        let output = processor.process_bytes(input.get_ref()).unwrap();
+        
+        let decoded = image::load_from_memory(&output).unwrap();
+        assert_eq!(decoded.width(), 20);
+        assert_eq!(decoded.height(), 10);
+
+        // HDR can't be written back, so its output format differs from the input's
+        let mut hdr = std::io::Cursor::new(Vec::new());
+        image::Rgb32FImage::from_pixel(8, 8, image::Rgb([0.5, 0.5, 0.5]))
+            .write_to(&mut hdr, image::ImageFormat::Hdr)
+            .unwrap();
+        let config = ProcessConfig { verify: true, ..Default::default() };
+        let (output, format) = ImageProcessor::new(config).process_bytes_with_format(hdr.get_ref()).unwrap();
+        assert_eq!(format, image::ImageFormat::Jpeg);
+        assert_eq!(image::guess_format(&output).unwrap(), image::ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_jpeg_quality() {
+        use pixie::Compressor;
+
+        let noise = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 37 + y * 11) as u8, (x * y) as u8, (x ^ y) as u8 * 4])
+        }));
+        let low = Compressor::new(20).compress_to_bytes(&noise, image::ImageFormat::Jpeg).unwrap();
+        let high = Compressor::new(95).compress_to_bytes(&noise, image::ImageFormat::Jpeg).unwrap();
+        assert!(low.len() * 2 < high.len(), "{} vs {} bytes", low.len(), high.len());
+    }
+    
+    #[test]
+    fn test_no_upscale() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.child("small.png");
+        image::RgbImage::new(50, 40).save(input_path.path()).unwrap();
+        
+        let output_path = temp_dir.child("output.png");
+        
+        let config = ProcessConfig {
+            width: 200,
+            no_upscale: true,
+            ..Default::default()
+        };
+        
+        let processor = ImageProcessor::new(config);
+        processor.process(input_path.path(), output_path.path()).unwrap();
+        
+        let output = image::open(output_path.path()).unwrap();
+        assert_eq!((output.width(), output.height()), (50, 40));
+    }
+    
+    #[test]
+    fn test_rotating_log_file() {
+        use pixie::RotatingFile;
+        use std::io::Write;
+        
+        let dir = TempDir::new().unwrap();
+        let log = dir.child("run.log");
+        let mut file = RotatingFile::open(log.path(), 10).unwrap().with_keep(2);
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+        
+        // Each line pushes the file past 10 bytes, so every write rotates
+        // and only the two most recent old copies are kept
+        assert_eq!(std::fs::read_to_string(log.path()).unwrap(), "fourth\n");
+        assert_eq!(std::fs::read_to_string(dir.child("run.log.1").path()).unwrap(), "third\n");
+        assert_eq!(std::fs::read_to_string(dir.child("run.log.2").path()).unwrap(), "second\n");
+        assert!(!dir.child("run.log.3").path().exists());
+        
+        // Reopening appends to what is there
+        let mut file = RotatingFile::open(log.path(), 0).unwrap();
+        file.write_all(b"fifth\n").unwrap();
+        file.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(log.path()).unwrap(), "fourth\nfifth\n");
+    }
+    
+    #[test]
+    fn test_server_metrics() {
+        use pixie::ServerMetrics;
+        use std::time::Duration;
+        
+        let metrics = ServerMetrics::new();
+        metrics.record_processed(1000, 400, Duration::from_millis(30));
+        metrics.record_processed(100, 150, Duration::from_secs(20));
+        metrics.record_error(415, Duration::from_millis(1));
+        metrics.record_rejected();
+        
+        let text = metrics.render();
+        assert!(text.contains("pixie_images_processed_total 2\n"));
+        assert!(text.contains("pixie_bytes_saved_total 600\n"));
+        assert!(text.contains("pixie_requests_rejected_total 1\n"));
+        assert!(text.contains("pixie_errors_total{status=\"415\"} 1\n"));
+        
+        // Buckets are cumulative; the 20s request only lands in +Inf
+        assert!(text.contains("pixie_processing_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("pixie_processing_seconds_bucket{le=\"0.05\"} 2\n"));
+        assert!(text.contains("pixie_processing_seconds_bucket{le=\"10\"} 2\n"));
+        assert!(text.contains("pixie_processing_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("pixie_processing_seconds_count 3\n"));
+    }
+    
+    #[test]
+    fn test_server_redact_regions() {
+        use pixie::{Server, ServerConfig};
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let listen = format!("127.0.0.1:{}", port);
+        let server = Server::new(ServerConfig { listen: listen.clone(), workers: 1, ..Default::default() });
+        std::thread::spawn(move || server.run());
+
+        let mut body = Vec::new();
+        image::RgbImage::from_pixel(64, 64, image::Rgb([255, 255, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut body), image::ImageFormat::Png)
+            .unwrap();
+        // Repeated keys and a ';' list both add regions
+        for query in ["redact=0,0,8,8:black&redact=32,32,8,8:black", "redact=0,0,8,8:black%3B32,32,8,8:black"] {
+            let mut stream = (0..50)
+                .find_map(|_| {
+                    TcpStream::connect(&listen).ok().or_else(|| {
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        None
+                    })
+                })
+                .unwrap();
+            write!(stream, "POST /process?{}&format=png HTTP/1.1\r\nContent-Length: {}\r\n\r\n", query, body.len()).unwrap();
+            stream.write_all(&body).unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+
+            let start = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+            assert!(response.starts_with(b"HTTP/1.1 200"), "{}", String::from_utf8_lossy(&response[..start]));
+            let output = image::load_from_memory(&response[start..]).unwrap().to_rgb8();
+            assert_eq!(output.get_pixel(4, 4).0, [0, 0, 0]);
+            assert_eq!(output.get_pixel(36, 36).0, [0, 0, 0]);
+            assert_eq!(output.get_pixel(20, 20).0, [255, 255, 255]);
+        }
+    }
+    
+    #[test]
+    fn test_config_from_query() {
+        let mut query = std::collections::HashMap::new();
+        query.insert("width".to_string(), "320".to_string());
+        query.insert("no-upscale".to_string(), "true".to_string());
+        
+        let config = config_from_query(&ProcessConfig::default(), &query).unwrap();
+        assert_eq!(config.width, 320);
+        assert!(config.no_upscale);
+        
+        query.insert("bogus".to_string(), "1".to_string());
+        assert!(config_from_query(&ProcessConfig::default(), &query).is_err());
+
+        // Keys that set the same field can't be combined
+        let options: std::collections::HashMap<String, String> =
+            [("auto-level", "true"), ("auto_contrast", "true")].map(|(k, v)| (k.to_string(), v.to_string())).into();
+        assert!(ProcessConfig::default().apply_options(&options).is_err());
+        let options: std::collections::HashMap<String, String> =
+            [("add_alpha", "#ffffff"), ("transparent-color", "#000000")].map(|(k, v)| (k.to_string(), v.to_string())).into();
+        assert!(ProcessConfig::default().apply_options(&options).is_err());
+        for key in ["add_alpha", "transparent-color"] {
+            let single = options.iter().filter(|(k, _)| *k == key).map(|(k, v)| (k.clone(), v.clone())).collect();
+            assert!(ProcessConfig::default().apply_options(&single).is_ok());
+        }
+    }
+    
+    #[test]
+    fn test_storage_location_parse() {
+        let location: StorageLocation = "s3://media/uploads/2024/".parse().unwrap();
+        assert_eq!(location, StorageLocation::S3 {
+            bucket: "media".to_string(),
+            prefix: "uploads/2024".to_string(),
+        });
+        assert!(location.is_remote());
+        
+        let local: StorageLocation = "photos/out".parse().unwrap();
+        assert!(!local.is_remote());
+        
+        assert!("ftp://host/dir".parse::<StorageLocation>().is_err());
+    }
+    
+    #[test]
+    fn test_remote_output_path() {
+        use pixie::{generate_output_path, is_remote_url};
+        
+        let url = "https://cdn.example.com/photos/cat.jpg?w=800";
+        assert!(is_remote_url(url));
+        
+        let output = generate_output_path(std::path::Path::new(url), None, "resized");
+        assert_eq!(output, std::path::Path::new("cat_resized.jpg"));
+    }
+
+    #[test]
+    fn test_on_conflict() {
+        use pixie::{resolve_conflict, BatchProcessor, ConflictPolicy};
+
+        let temp_dir = TempDir::new().unwrap();
+        let existing = temp_dir.child("photo.png");
+        image::RgbImage::new(16, 16).save(existing.path()).unwrap();
+        temp_dir.child("photo_1.png").write_binary(b"taken").unwrap();
+
+        let resolve = |policy| resolve_conflict(existing.path(), policy);
+        assert_eq!(resolve(ConflictPolicy::Overwrite).unwrap().as_deref(), Some(existing.path()));
+        assert_eq!(resolve(ConflictPolicy::Skip).unwrap(), None);
+        assert_eq!(resolve(ConflictPolicy::Rename).unwrap(), Some(temp_dir.child("photo_2.png").path().to_path_buf()));
+        assert!(resolve(ConflictPolicy::Error).is_err());
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(16, 16).save(input_dir.child("a.png").path()).unwrap();
+        image::RgbImage::new(16, 16).save(input_dir.child("b.png").path()).unwrap();
+        output_dir.child("a.png").write_binary(b"keep").unwrap();
+        let batch = |policy| {
+            let config = ProcessConfig { width: 8, on_conflict: Some(policy), ..Default::default() };
+            BatchProcessor::new(config, 2)
+                .unwrap()
+                .with_progress(false)
+                .process_directory(input_dir.path(), output_dir.path(), false)
+                .unwrap()
+        };
+
+        let stats = batch(ConflictPolicy::Skip);
+        assert_eq!((stats.processed_count, stats.existing_count), (1, 1));
+        assert_eq!(fs::read(output_dir.child("a.png").path()).unwrap(), b"keep");
+
+        let stats = batch(ConflictPolicy::Error);
+        assert_eq!((stats.processed_count, stats.failed_count()), (0, 2));
+
+        let stats = batch(ConflictPolicy::Rename);
+        assert_eq!(stats.processed_count, 2);
+        assert!(output_dir.child("a_1.png").path().exists());
+        assert!(output_dir.child("b_1.png").path().exists());
+    }
+    
+    #[test]
+    fn test_parse_flat_json() {
+        use pixie::parse_flat_json;
+        
+        let params = parse_flat_json(r#"{"width": 800, "format": "png", "no_upscale": true, "dpi": null}"#).unwrap();
+        assert_eq!(params.get("width").map(String::as_str), Some("800"));
+        assert_eq!(params.get("format").map(String::as_str), Some("png"));
+        assert_eq!(params.get("no_upscale").map(String::as_str), Some("true"));
+        assert!(!params.contains_key("dpi"));
+        
+        assert!(parse_flat_json(r#"{"crop": {"x": 1}}"#).is_err());
+        assert!(parse_flat_json("[1, 2]").is_err());
+    }
+    
+    #[test]
+    fn test_error_exit_codes() {
+        use pixie::{exit_code, ImageToolError};
+        
+        let err = ImageToolError::InvalidParameter("width".to_string());
+        assert_eq!(err.exit_code(), exit_code::INVALID_ARGS);
+        
+        let err = ImageToolError::UnsupportedFormat("xyz".to_string());
+        assert_eq!(err.exit_code(), exit_code::UNSUPPORTED_FORMAT);
+        
+        let err = ImageToolError::from(std::io::Error::new(std::io::ErrorKind::NotFound, "missing"));
+        assert_eq!(err.exit_code(), exit_code::IO_ERROR);
+    }
+    
+    #[test]
+    fn test_batch_collects_failures() {
+        use pixie::BatchProcessor;
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("good.png").path()).unwrap();
+        input_dir.child("broken.png").write_binary(b"not a png").unwrap();
+        
+        let config = ProcessConfig {
+            width: 10,
+            ..Default::default()
+        };
+        
+        let processor = BatchProcessor::new(config, 1).unwrap().with_progress(false);
+        let stats = processor
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        
+        assert_eq!(stats.processed_count, 1);
+        assert_eq!(stats.failed_count(), 1);
+        assert_eq!(stats.errors[0].0.file_name().unwrap(), "broken.png");
+    }
+    
+    #[test]
+    fn test_batch_processors_in_one_process() {
+        use pixie::BatchProcessor;
+        
+        let input_dir = TempDir::new().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("one.png").path()).unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("two.png").path()).unwrap();
+        
+        let config = ProcessConfig {
+            width: 10,
+            ..Default::default()
+        };
+        
+        // Each processor owns its pool, so repeated and overlapping runs work
+        let processors: Vec<BatchProcessor> = [0, 2, 2]
+            .iter()
+            .map(|&threads| BatchProcessor::new(config.clone(), threads).unwrap().with_progress(false))
+            .collect();
+        std::thread::scope(|scope| {
+            for processor in &processors {
+                let input = input_dir.path();
+                scope.spawn(move || {
+                    let output_dir = TempDir::new().unwrap();
+                    let stats = processor.process_directory(input, output_dir.path(), false).unwrap();
+                    assert_eq!(stats.processed_count, 2);
+                });
+            }
+        });
+    }
+    
+    #[test]
+    fn test_batch_matches_single_file_processing() {
+        use pixie::BatchProcessor;
+        
+        let input_dir = TempDir::new().unwrap();
+        let batch_dir = TempDir::new().unwrap();
+        let single_dir = TempDir::new().unwrap();
+        let gradient = image::RgbImage::from_fn(64, 48, |x, y| image::Rgb([x as u8 * 4, y as u8 * 5, 128]));
+        for name in ["one.png", "two.jpg", "three.bmp", "four.png"] {
+            gradient.save(input_dir.child(name).path()).unwrap();
+        }
+        
+        let config = ProcessConfig {
+            width: 32,
+            ..Default::default()
+        };
+        
+        // Batch outputs are encoded in memory and written by a separate
+        // stage, but must come out the same as processing each file alone
+        let stats = BatchProcessor::new(config.clone(), 3)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), batch_dir.path(), false)
+            .unwrap();
+        assert_eq!(stats.processed_count, 4);
+        
+        let processor = ImageProcessor::new(config);
+        for name in ["one.png", "two.jpg", "three.bmp", "four.png"] {
+            let single = single_dir.child(name);
+            processor.process(input_dir.child(name).path(), single.path()).unwrap();
+            assert_eq!(
+                std::fs::read(batch_dir.child(name).path()).unwrap(),
+                std::fs::read(single.path()).unwrap(),
+                "{} differs",
+                name
+            );
+        }
+    }
+    
+    #[test]
+    fn test_stage_timings() {
+        use pixie::BatchProcessor;
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(200, 100).save(input_dir.child("one.png").path()).unwrap();
+        image::RgbImage::new(100, 200).save(input_dir.child("two.png").path()).unwrap();
+        
+        let config = ProcessConfig {
+            width: 50,
+            ..Default::default()
+        };
+        
+        let single = ImageProcessor::new(config.clone())
+            .process(input_dir.child("one.png").path(), output_dir.child("single.png").path())
+            .unwrap();
+        assert_eq!(single.timings.len(), 1);
+        assert_eq!(single.timings[0].0, output_dir.child("single.png").path());
+        
+        let batch_dir = output_dir.child("batch");
+        let stats = BatchProcessor::new(config, 2)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), batch_dir.path(), false)
+            .unwrap();
+        
+        // One entry per output
+        let mut outputs: Vec<_> = stats.timings.iter().map(|(path, _)| path.file_name().unwrap()).collect();
+        outputs.sort();
+        assert_eq!(outputs, ["one.png", "two.png"]);
+        let total = stats.total_timings();
+        assert_eq!(total.total(), stats.timings[0].1.total() + stats.timings[1].1.total());
+        assert!(total.decode > std::time::Duration::ZERO);
+    }
+    
+    #[test]
+    fn test_batch_cancel() {
+        use pixie::BatchProcessor;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        for i in 0..3 {
+            image::RgbImage::new(20, 20).save(input_dir.child(format!("img{}.png", i)).path()).unwrap();
+        }
+        // Left behind by a run that was killed mid-write
+        output_dir.child(".pixie-part-img0.png").write_binary(b"trunc").unwrap();
+        
+        let cancel = Arc::new(AtomicBool::new(true));
+        let processor = BatchProcessor::new(ProcessConfig { width: 10, ..Default::default() }, 1)
+            .unwrap()
+            .with_progress(false)
+            .with_cancel(cancel.clone());
+        let stats = processor.process_directory(input_dir.path(), output_dir.path(), false).unwrap();
+        
+        assert!(stats.was_cancelled());
+        assert_eq!(stats.cancelled.len(), 3);
+        assert_eq!(stats.processed_count, 0);
+        assert!(!output_dir.child(".pixie-part-img0.png").path().exists());
+        
+        cancel.store(false, Ordering::Relaxed);
+        let stats = processor.process_directory(input_dir.path(), output_dir.path(), false).unwrap();
+        assert!(!stats.was_cancelled());
+        assert_eq!(stats.processed_count, 3);
+        
+        // Outputs are renamed into place, so no part files remain
+        let leftovers = std::fs::read_dir(output_dir.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with(".pixie-part-"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+    
+    #[test]
+    fn test_batch_checkpoint() {
+        use pixie::{BatchProcessor, Checkpoint};
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        let checkpoint = output_dir.child("run.checkpoint");
+        image::RgbImage::new(20, 20).save(input_dir.child("a.png").path()).unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("b.png").path()).unwrap();
+        input_dir.child("c.png").write_binary(b"not a png").unwrap();
+        
+        let config = ProcessConfig { width: 10, ..Default::default() };
+        let processor = BatchProcessor::new(config.clone(), 1)
+            .unwrap()
+            .with_progress(false)
+            .with_checkpoint(Some(checkpoint.path().to_path_buf()));
+        let out = output_dir.child("out");
+        
+        // A failure leaves something to resume, so the checkpoint stays
+        let stats = processor.process_directory(input_dir.path(), out.path(), false).unwrap();
+        assert_eq!(stats.processed_count, 2);
+        assert_eq!(stats.failed_count(), 1);
+        assert!(checkpoint.path().exists());
+        
+        image::RgbImage::new(20, 20).save(input_dir.child("c.png").path()).unwrap();
+        let stats = processor.process_directory(input_dir.path(), out.path(), false).unwrap();
+        assert_eq!(stats.resumed_count, 2);
+        assert_eq!(stats.processed_count, 1);
+        assert!(!checkpoint.path().exists());
+        
+        // Entries only count for the settings that wrote them
+        let recorded = Checkpoint::open(checkpoint.path(), &config).unwrap();
+        recorded.record(input_dir.child("a.png").path()).unwrap();
+        drop(recorded);
+        assert!(Checkpoint::open(checkpoint.path(), &config).unwrap().is_done(input_dir.child("a.png").path()));
+        let other = ProcessConfig { width: 12, ..Default::default() };
+        assert!(!Checkpoint::open(checkpoint.path(), &other).unwrap().is_done(input_dir.child("a.png").path()));
+    }
+    
+    #[test]
+    fn test_batch_output_lock() {
+        use pixie::{exit_code, BatchProcessor, ImageToolError, OutputLock};
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("a.png").path()).unwrap();
+        
+        let config = ProcessConfig { width: 10, ..Default::default() };
+        let held = OutputLock::acquire(output_dir.path(), false).unwrap();
+        assert!(held.path().exists());
+        assert!(OutputLock::acquire(output_dir.path(), false).is_err());
+        
+        let locked = BatchProcessor::new(config.clone(), 1)
+            .unwrap()
+            .with_progress(false)
+            .with_output_lock(true, false)
+            .process_directory(input_dir.path(), output_dir.path(), false);
+        match locked {
+            Err(e @ ImageToolError::Locked(_)) => assert_eq!(e.exit_code(), exit_code::LOCKED),
+            other => panic!("expected a lock error, got {:?}", other.map(|s| s.processed_count)),
+        }
+        assert!(!output_dir.child("a.png").path().exists());
+        
+        // Forcing takes the lock over and releases it when done
+        let stats = BatchProcessor::new(config, 1)
+            .unwrap()
+            .with_progress(false)
+            .with_output_lock(true, true)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        assert_eq!(stats.processed_count, 1);
+        assert!(!held.path().exists());
+    }
+    
+    #[test]
+    fn test_batch_zip_input() {
+        use pixie::{BatchProcessor, ZipReader};
+        
+        // Stored entries only, which is all a reader has to undo
+        fn stored_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+            let mut zip = Vec::new();
+            let mut directory = Vec::new();
+            for (name, data) in files {
+                let offset = zip.len() as u32;
+                let mut header = Vec::new();
+                header.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+                header.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+                header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                header.extend_from_slice(&[0, 0]);
+                
+                zip.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+                zip.extend_from_slice(&header);
+                zip.extend_from_slice(name.as_bytes());
+                zip.extend_from_slice(data);
+                
+                directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+                directory.extend_from_slice(&[20, 0]);
+                directory.extend_from_slice(&header);
+                directory.extend_from_slice(&[0; 10]);
+                directory.extend_from_slice(&offset.to_le_bytes());
+                directory.extend_from_slice(name.as_bytes());
+            }
+            let directory_offset = zip.len() as u32;
+            zip.extend_from_slice(&directory);
+            zip.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+            zip.extend_from_slice(&[0; 4]);
+            zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&directory_offset.to_le_bytes());
+            zip.extend_from_slice(&[0, 0]);
+            zip
+        }
+        
+        let temp_dir = TempDir::new().unwrap();
+        let mut png = Vec::new();
+        image::RgbImage::new(20, 20)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let zip = stored_zip(&[
+            ("pages/", b""),
+            ("pages/01.png", &png),
+            ("pages/02.png", &png),
+            ("__MACOSX/pages/._01.png", b"junk"),
+            ("readme.txt", b"hello"),
+            ("broken.png", b"not a png"),
+        ]);
+        let archive = temp_dir.child("comic.cbz");
+        archive.write_binary(&zip).unwrap();
+        
+        assert!(ZipReader::is_zip(archive.path()));
+        let reader = ZipReader::open(archive.path()).unwrap();
+        assert_eq!(reader.entries().len(), 6);
+        assert_eq!(reader.read(reader.entry("pages/01.png").unwrap()).unwrap(), png);
+        
+        let output_dir = temp_dir.child("out");
+        let config = ProcessConfig { width: 10, ..Default::default() };
+        let stats = BatchProcessor::new(config, 1)
+            .unwrap()
+            .with_progress(false)
+            .process_archive(archive.path(), output_dir.path())
+            .unwrap();
+        assert_eq!(stats.processed_count, 2);
+        assert_eq!(stats.failed_count(), 1);
+        assert_eq!(image::image_dimensions(output_dir.child("01.png").path()).unwrap(), (10, 10));
+        assert!(!output_dir.child("readme.txt").path().exists());
+        
+        temp_dir.child("bad.zip").write_binary(b"not a zip").unwrap();
+        assert!(ZipReader::open(temp_dir.child("bad.zip").path()).is_err());
+    }
+    
+    #[test]
+    fn test_batch_archive_output() {
+        use pixie::{ArchiveFormat, ArchiveWriter, BatchProcessor, ZipReader};
+        
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.child("in");
+        input_dir.create_dir_all().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("a.png").path()).unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("b.png").path()).unwrap();
+        
+        assert_eq!(ArchiveFormat::from_path(temp_dir.child("proofs.TGZ").path()), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_path(temp_dir.child("proofs.rar").path()), None);
+        
+        let config = ProcessConfig { width: 10, ..Default::default() };
+        let archive = temp_dir.child("proofs.zip");
+        let stats = BatchProcessor::new(config.clone(), 1)
+            .unwrap()
+            .with_progress(false)
+            .with_archive_output(Some(archive.path().to_path_buf()))
+            .process_directory(input_dir.path(), temp_dir.path(), false)
+            .unwrap();
+        assert_eq!(stats.processed_count, 2);
+        assert!(!temp_dir.child("a.png").path().exists());
+        
+        let reader = ZipReader::open(archive.path()).unwrap();
+        let data = reader.read(reader.entry("a.png").unwrap()).unwrap();
+        assert_eq!(image::load_from_memory(&data).unwrap().width(), 10);
+        assert!(reader.entry("b.png").is_some());
+        
+        // Tar entries are a 512-byte header, then the data padded to 512
+        let mut writer = ArchiveWriter::new(ArchiveFormat::Tar, Vec::new());
+        writer.add("a.txt", b"hello").unwrap();
+        assert!(writer.add("a.txt", b"again").is_err());
+        let tar = writer.finish().unwrap();
+        assert_eq!(tar.len(), 512 * 4);
+        assert_eq!(&tar[512..517], b"hello");
+        
+        let unknown = BatchProcessor::new(config, 1)
+            .unwrap()
+            .with_progress(false)
+            .with_archive_output(Some(temp_dir.child("proofs.rar").path().to_path_buf()))
+            .process_directory(input_dir.path(), temp_dir.path(), false);
+        assert!(unknown.is_err());
+    }
+    
+    #[test]
+    fn test_batch_tar_stream() {
+        use pixie::{ArchiveFormat, ArchiveWriter, BatchProcessor, TarReader};
+        
+        let mut png = Vec::new();
+        image::RgbImage::new(20, 20)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let long_name = format!("shoot/{}.png", "a".repeat(120));
+        let mut writer = ArchiveWriter::new(ArchiveFormat::TarGz, Vec::new());
+        writer.add("shoot/01.png", &png).unwrap();
+        writer.add(&long_name, &png).unwrap();
+        writer.add("notes.txt", b"not an image").unwrap();
+        writer.add("broken.png", b"not a png").unwrap();
+        let input = writer.finish().unwrap();
+        
+        let config = ProcessConfig { width: 10, ..Default::default() };
+        let mut output = Vec::new();
+        let stats = BatchProcessor::new(config, 2)
+            .unwrap()
+            .with_progress(false)
+            .process_tar_stream(input.as_slice(), &mut output)
+            .unwrap();
+        assert_eq!(stats.processed_count, 2);
+        assert_eq!(stats.failed_count(), 1);
+        
+        let mut reader = TarReader::new(output.as_slice());
+        let mut names = Vec::new();
+        while let Some(entry) = reader.next_entry().unwrap() {
+            let data = reader.read_data().unwrap();
+            assert_eq!(image::load_from_memory(&data).unwrap().width(), 10);
+            names.push(entry.name);
+        }
+        names.sort();
+        assert_eq!(names, vec!["shoot/01.png".to_string(), long_name]);
+        
+        assert!(TarReader::new(&b"garbage"[..]).next_entry().is_err());
+    }
+    
+    #[test]
+    fn test_rename_by_date() {
+        use pixie::{MetadataProcessor, Renamer};
+        
+        // A small JPEG whose EXIF says it was taken at `date`
+        fn photo(date: &str) -> Vec<u8> {
+            let field = exif::Field {
+                tag: exif::Tag::DateTimeOriginal,
+                ifd_num: exif::In::PRIMARY,
+                value: exif::Value::Ascii(vec![date.as_bytes().to_vec()]),
+            };
+            let mut writer = exif::experimental::Writer::new();
+            writer.push_field(&field);
+            let mut tiff = std::io::Cursor::new(Vec::new());
+            writer.write(&mut tiff, false).unwrap();
+            
+            let mut jpeg = Vec::new();
+            image::RgbImage::new(8, 8)
+                .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+                .unwrap();
+            MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap()
+        }
+        
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("IMG_1.jpg").write_binary(&photo("2024:05:06 07:08:09")).unwrap();
+        temp_dir.child("IMG_2.jpg").write_binary(&photo("2024:05:06 07:08:09")).unwrap();
+        temp_dir.child("scan.jpg").write_binary(&photo("2023:12:31 23:59:58")).unwrap();
+        image::RgbImage::new(8, 8).save(temp_dir.child("plain.png").path()).unwrap();
+        
+        let renamer = Renamer::new("{date:%Y}/{date:%m-%d_%H%M%S}").unwrap();
+        let planned = renamer.with_dry_run(true).rename_directory(temp_dir.path()).unwrap();
+        assert_eq!(planned.renamed.len(), 3);
+        assert_eq!(planned.skipped.len(), 1);
+        assert!(temp_dir.child("IMG_1.jpg").path().exists());
+        
+        let report = Renamer::new(Renamer::DEFAULT_PATTERN)
+            .unwrap()
+            .rename_directory(temp_dir.path())
+            .unwrap();
+        assert_eq!(report.renamed.len(), 3);
+        assert!(temp_dir.child("2024-05-06_070809_IMG_1.jpg").path().exists());
+        assert!(temp_dir.child("2023-12-31_235958_scan.jpg").path().exists());
+        assert!(!temp_dir.child("IMG_1.jpg").path().exists());
+        
+        // Same date and a clashing name: the second file gets a suffix
+        let report = Renamer::new("{date}")
+            .unwrap()
+            .rename_directory(temp_dir.path())
+            .unwrap();
+        assert!(temp_dir.child("2024-05-06_070809.jpg").path().exists());
+        assert!(temp_dir.child("2024-05-06_070809_1.jpg").path().exists());
+        assert_eq!(report.skipped[0].0, temp_dir.child("plain.png").path());
+        
+        assert!(Renamer::new("{stem}").is_err());
+        assert!(Renamer::new("{date}_{size}").is_err());
+        let escaping = Renamer::new("../{date}").unwrap().rename_directory(temp_dir.path());
+        assert!(escaping.is_err());
+    }
+    
+    #[test]
+    fn test_privacy_audit() {
+        use exif::{Field, In, Rational, Tag, Value};
+        use pixie::{MetadataProcessor, PrivacyAuditor, PrivacyRisk};
+        
+        let ascii = |tag, text: &str| Field { tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![text.as_bytes().to_vec()]) };
+        let degrees = |tag, d| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![Rational::from((d, 1)), Rational::from((30, 1)), Rational::from((0, 1))]),
+        };
+        let fields = [
+            ascii(Tag::Make, "Pixie"),
+            ascii(Tag::BodySerialNumber, "SN12345"),
+            ascii(Tag::CameraOwnerName, "Jo Doe"),
+            ascii(Tag::GPSLatitudeRef, "N"),
+            degrees(Tag::GPSLatitude, 51),
+            ascii(Tag::GPSLongitudeRef, "W"),
+            degrees(Tag::GPSLongitude, 0),
+            Field { tag: Tag::Compression, ifd_num: In::THUMBNAIL, value: Value::Short(vec![6]) },
+        ];
+        
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_pixel(16, 16, image::Rgb([200, 100, 50]))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let mut writer = exif::experimental::Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        writer.set_jpeg(&jpeg, In::THUMBNAIL);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let photo = MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap();
+        
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.child("photo.jpg");
+        file.write_binary(&photo).unwrap();
+        image::RgbImage::new(8, 8).save(temp_dir.child("clean.png").path()).unwrap();
+        
+        let audits = PrivacyAuditor::new().audit(temp_dir.path()).unwrap();
+        assert_eq!(audits.len(), 2);
+        let audit = audits.iter().find(|audit| audit.path == file.path()).unwrap();
+        let risks: Vec<PrivacyRisk> = audit.risks.iter().map(|(risk, _)| *risk).collect();
+        assert_eq!(
+            risks,
+            vec![PrivacyRisk::Location, PrivacyRisk::OwnerName, PrivacyRisk::SerialNumber, PrivacyRisk::Thumbnail]
+        );
+        assert!(audit.risks[0].1.starts_with("51.50000, -0.50000"));
+        assert!(!audit.is_clean());
+        
+        let fixed = PrivacyAuditor::new().with_fix(true).audit_file(file.path());
+        assert!(fixed.fixed && fixed.is_clean());
+        let exif = MetadataProcessor::new().read_metadata(file.path()).unwrap().unwrap();
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+        assert!(MetadataProcessor::new().privacy_risks(&exif).is_empty());
+        assert_eq!(
+            image::open(file.path()).unwrap().to_rgb8(),
+            image::load_from_memory(&jpeg).unwrap().to_rgb8()
+        );
+    }
+
+    #[test]
+    fn test_auto_orient() {
+        use exif::{Field, In, Tag, Value};
+        use pixie::{MetadataProcessor, OrientationFixer};
+
+        // Stored sideways: red on the left turns into red on top
+        let stored = image::RgbImage::from_fn(40, 20, |x, _| {
+            if x < 20 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) }
+        });
+        let mut jpeg = Vec::new();
+        stored.write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg).unwrap();
+        let mut writer = exif::experimental::Writer::new();
+        let rotated = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) };
+        writer.push_field(&rotated);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let photo = MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("photo.jpg");
+        input.write_binary(&photo).unwrap();
+        let orientation = |path: &std::path::Path| {
+            let exif = MetadataProcessor::new().read_metadata(path).unwrap().unwrap();
+            MetadataProcessor::new().orientation(&exif)
+        };
+
+        let upright = temp_dir.child("upright.jpg");
+        ImageProcessor::new(ProcessConfig::default()).process(input.path(), upright.path()).unwrap();
+        let output = image::open(upright.path()).unwrap().to_rgb8();
+        assert_eq!(output.dimensions(), (20, 40));
+        assert!(output.get_pixel(10, 5).0[0] > 200 && output.get_pixel(10, 35).0[2] > 200);
+        assert_eq!(orientation(upright.path()), Some(1));
+
+        // Sizes apply to the upright image
+        let resized = temp_dir.child("resized.jpg");
+        let config = ProcessConfig { width: 10, ..Default::default() };
+        ImageProcessor::new(config).process(input.path(), resized.path()).unwrap();
+        assert_eq!(image::image_dimensions(resized.path()).unwrap(), (10, 20));
+
+        let stored_path = temp_dir.child("stored.jpg");
+        let config = ProcessConfig { auto_orient: false, ..Default::default() };
+        ImageProcessor::new(config).process(input.path(), stored_path.path()).unwrap();
+        assert_eq!(image::image_dimensions(stored_path.path()).unwrap(), (40, 20));
+        assert_eq!(orientation(stored_path.path()), Some(6));
+
+        // Only the sideways file is rewritten
+        std::fs::remove_file(upright.path()).unwrap();
+        std::fs::remove_file(resized.path()).unwrap();
+        std::fs::remove_file(stored_path.path()).unwrap();
+        image::RgbImage::new(8, 8).save(temp_dir.child("plain.png").path()).unwrap();
+        let fixes = OrientationFixer::new().fix(temp_dir.path()).unwrap();
+        assert_eq!(fixes.len(), 2);
+        assert!(fixes.iter().all(|fix| fix.is_upright()));
+        let fix = fixes.iter().find(|fix| fix.path == input.path()).unwrap();
+        assert!(fix.fixed && fix.orientation == 6);
+        assert_eq!(image::image_dimensions(input.path()).unwrap(), (20, 40));
+        assert_eq!(orientation(input.path()), Some(1));
+        assert!(OrientationFixer::new().fix(temp_dir.path()).unwrap().iter().all(|fix| !fix.fixed));
+    }
+
+    #[test]
+    fn test_lossless_orientation() {
+        use exif::{Field, In, Tag, Value};
+        use image::{GenericImageView, ImageDecoder, ImageEncoder};
+        use pixie::{MetadataProcessor, OrientationFixer};
+
+        let icc = moxcms::ColorProfile::new_display_p3().encode().unwrap();
+        let encode = |image: &image::DynamicImage, orientation: u16| {
+            let mut jpeg = Vec::new();
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90);
+            encoder.set_icc_profile(icc.clone()).unwrap();
+            encoder.write_image(image.as_bytes(), image.width(), image.height(), image.color().into()).unwrap();
+            let mut writer = exif::experimental::Writer::new();
+            let field = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![orientation]) };
+            writer.push_field(&field);
+            let mut tiff = std::io::Cursor::new(Vec::new());
+            writer.write(&mut tiff, false).unwrap();
+            MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap()
+        };
+        let icc_profile = |path: &std::path::Path| {
+            let mut decoder = image::ImageReader::open(path).unwrap().with_guessed_format().unwrap().into_decoder().unwrap();
+            decoder.icc_profile().unwrap()
+        };
+
+        let color = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(32, 16, |x, y| {
+            image::Rgb([x as u8 * 8, y as u8 * 16, if x < 8 && y < 4 { 255 } else { 40 }])
+        }));
+        let gray = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(24, 8, |x, y| image::Luma([(x * 10 + y * 3) as u8])));
+        let temp_dir = TempDir::new().unwrap();
+        for (name, image) in [("color", &color), ("gray", &gray)] {
+            for orientation in 2..=8u8 {
+                let data = encode(image, orientation as u16);
+                let file = temp_dir.child(format!("{}-{}.jpg", name, orientation));
+                file.write_binary(&data).unwrap();
+
+                let fix = OrientationFixer::new().fix_file(file.path());
+                assert!(fix.fixed && !fix.reencoded, "{} {}", name, orientation);
+
+                // Same pixels as turning the decoded original
+                let mut expected = image::load_from_memory(&data).unwrap();
+                expected.apply_orientation(image::metadata::Orientation::from_exif(orientation).unwrap());
+                let fixed = image::open(file.path()).unwrap();
+                assert_eq!(fixed.dimensions(), expected.dimensions(), "{} {}", name, orientation);
+                let (fixed, expected) = (fixed.to_rgb8(), expected.to_rgb8());
+                let worst = fixed.as_raw().iter().zip(expected.as_raw()).map(|(a, b)| a.abs_diff(*b)).max();
+                assert!(worst.unwrap() <= 2, "{} {}: off by {:?}", name, orientation, worst);
+
+                let exif = MetadataProcessor::new().read_metadata(file.path()).unwrap().unwrap();
+                assert_eq!(MetadataProcessor::new().orientation(&exif), Some(1));
+                assert_eq!(icc_profile(file.path()), Some(icc.clone()));
+            }
+        }
+
+        // Not a whole number of MCUs: re-encoded, keeping the ICC profile
+        let odd = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(30, 20, image::Rgb([200, 30, 30])));
+        let file = temp_dir.child("odd.jpg");
+        file.write_binary(&encode(&odd, 6)).unwrap();
+        let fix = OrientationFixer::new().fix_file(file.path());
+        assert!(fix.fixed && fix.reencoded);
+        assert_eq!(image::image_dimensions(file.path()).unwrap(), (20, 30));
+        assert_eq!(icc_profile(file.path()), Some(icc.clone()));
+    }
+
+    #[test]
+    fn test_caption_from_exif() {
+        use exif::{Field, In, Tag, Value};
+        use pixie::{Captioner, MetadataProcessor};
+
+        let ascii = |tag, text: &str| Field { tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![text.as_bytes().to_vec()]) };
+        let mut writer = exif::experimental::Writer::new();
+        let fields = [ascii(Tag::Artist, "Jo Doe"), ascii(Tag::DateTimeOriginal, "2024:05:06 07:08:09")];
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_pixel(16, 16, image::Rgb([0, 0, 0]))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let photo = MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap();
+        let exif = MetadataProcessor::new().read_metadata_from_bytes(&photo).unwrap();
+
+        assert_eq!(
+            Captioner::text("{Artist} — {DateTimeOriginal}", exif.as_ref()),
+            "Jo Doe — 2024-05-06 07:08:09"
+        );
+        assert_eq!(Captioner::text("© {Artist}{Copyright}", exif.as_ref()), "© Jo Doe");
+        assert_eq!(Captioner::text("{Make} {Artist}, {Make}", exif.as_ref()), "Jo Doe,");
+        assert_eq!(Captioner::text("{Artist}", None), "");
+
+        // Needs a system font to draw with
+        let Ok(captioner) = Captioner::new(None) else { return };
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::new(200, 100));
+        let captioned = captioner.with_size(Some(20.0)).apply(&image, "Jo Doe").to_rgb8();
+        assert_eq!(captioned.dimensions(), (200, 100));
+        // Bottom right by default
+        assert!(captioned.enumerate_pixels().any(|(x, y, p)| x > 100 && y > 50 && p[0] > 200));
+        assert!(captioned.enumerate_pixels().all(|(x, y, p)| (x > 100 && y > 50) || p[0] == 0));
+    }
+
+    #[test]
+    fn test_export_metadata_sidecar() {
+        use exif::{Field, In, Tag, Value};
+        use pixie::{MetadataProcessor, SidecarFormat};
+
+        let mut writer = exif::experimental::Writer::new();
+        let artist = Field { tag: Tag::Artist, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Jo & Co".to_vec()]) };
+        writer.push_field(&artist);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_pixel(16, 16, image::Rgb([90, 120, 150]))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap();
+        let xmp = concat!(
+            "http://ns.adobe.com/xap/1.0/\0<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">",
+            "<dc:subject><rdf:Bag><rdf:li>beach</rdf:li></rdf:Bag></dc:subject>",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>"
+        );
+        let segment = [&[0xFF, 0xE1][..], &((xmp.len() + 2) as u16).to_be_bytes(), xmp.as_bytes()].concat();
+        let photo = [&jpeg[..2], &segment, &jpeg[2..]].concat();
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("photo.jpg");
+        input.write_binary(&photo).unwrap();
+        let output = temp_dir.child("out/photo.jpg");
+
+        for (format, sidecar) in [(SidecarFormat::Json, "photo.jpg.json"), (SidecarFormat::Xmp, "photo.jpg.xmp")] {
+            let config = ProcessConfig {
+                width: 8,
+                strip_metadata: true,
+                export_metadata: Some(format),
+                ..Default::default()
+            };
+            ImageProcessor::new(config).process(input.path(), output.path()).unwrap();
+            assert!(MetadataProcessor::new().read_metadata(output.path()).unwrap().is_none());
+
+            let sidecar = fs::read_to_string(temp_dir.child("out").child(sidecar).path()).unwrap();
+            match format {
+                SidecarFormat::Json => {
+                    assert!(sidecar.contains("\"Artist\": \"Jo & Co\""));
+                    assert!(sidecar.contains("<rdf:li>beach</rdf:li>"));
+                }
+                SidecarFormat::Xmp => {
+                    assert!(sidecar.contains("<tiff:Artist>Jo &amp; Co</tiff:Artist>"));
+                    assert!(sidecar.contains("xmlns:dc=\"http://purl.org/dc/elements/1.1/\""));
+                    assert!(sidecar.contains("<dc:subject><rdf:Bag><rdf:li>beach</rdf:li></rdf:Bag></dc:subject>"));
+                }
+            }
+        }
+
+        // Nothing to save, no sidecar
+        let plain = temp_dir.child("plain.png");
+        image::RgbImage::new(8, 8).save(plain.path()).unwrap();
+        let config = ProcessConfig { export_metadata: Some(SidecarFormat::Json), ..Default::default() };
+        ImageProcessor::new(config).process(plain.path(), temp_dir.child("out/plain.png").path()).unwrap();
+        assert!(!temp_dir.child("out/plain.png.json").path().exists());
+    }
+
+    #[test]
+    fn test_validate() {
+        use pixie::ImageValidator;
+
+        let mut png = Vec::new();
+        image::RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 0]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 0]))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let validator = ImageValidator::new();
+        assert!(validator.check_bytes(&png).is_empty());
+        assert!(validator.check_bytes(&jpeg).is_empty());
+
+        // A byte just before the IEND chunk, inside the last IDAT
+        let mut corrupt = png.clone();
+        let last_data = corrupt.len() - 12 - 5;
+        corrupt[last_data] ^= 0xFF;
+        let defects = validator.check_bytes(&corrupt);
+        assert!(defects.iter().any(|defect| defect.message.contains("CRC mismatch")));
+
+        let defects = validator.check_bytes(&png[..png.len() - 12]);
+        assert_eq!(defects[0].offset, Some(png.len() as u64 - 12));
+        assert!(defects[0].message.contains("IEND"));
+
+        // Points at the segment it cuts short, or the end of the data
+        let defects = validator.check_bytes(&jpeg[..jpeg.len() / 2]);
+        assert!(defects[0].message.starts_with("truncated"));
+        assert!(defects[0].offset.unwrap() <= jpeg.len() as u64 / 2);
+
+        let temp_dir = TempDir::new().unwrap();
+        temp_dir.child("good.png").write_binary(&png).unwrap();
+        temp_dir.child("cut.jpg").write_binary(&jpeg[..jpeg.len() / 2]).unwrap();
+        let checks = validator.validate(&[temp_dir.path().to_path_buf()]).unwrap();
+        let broken: Vec<_> = checks.iter().filter(|check| !check.is_valid()).map(|check| check.path.clone()).collect();
+        assert_eq!(checks.len(), 2);
+        assert_eq!(broken, vec![temp_dir.child("cut.jpg").path().to_path_buf()]);
+    }
+
+    #[test]
+    fn test_salvage_truncated_jpeg() {
+        use pixie::{ImageToolError, SalvageMode};
+
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 200]))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("cut.jpg");
+        input.write_binary(&jpeg[..jpeg.len() * 3 / 5]).unwrap();
+
+        let result = ImageProcessor::new(ProcessConfig::default()).process(input.path(), temp_dir.child("refused.jpg").path());
+        assert!(matches!(result, Err(ImageToolError::ProcessingError(_))));
+
+        let salvage = |mode, name: &str| {
+            let config = ProcessConfig { salvage: Some(mode), ..Default::default() };
+            ImageProcessor::new(config).process(input.path(), temp_dir.child(name).path()).unwrap();
+            image::image_dimensions(temp_dir.child(name).path()).unwrap()
+        };
+        assert_eq!(salvage(SalvageMode::Pad, "padded.jpg"), (64, 64));
+        let (width, height) = salvage(SalvageMode::Crop, "cropped.jpg");
+        assert_eq!(width, 64);
+        assert!(height > 0 && height < 64 && height % 8 == 0);
+    }
+
+    #[test]
+    fn test_meta_copy() {
+        use exif::{Field, In, Tag, Value};
+        use image::{ImageDecoder, ImageEncoder};
+        use pixie::{MetadataCopier, MetadataProcessor};
+
+        let icc = moxcms::ColorProfile::new_display_p3().encode().unwrap();
+        let mut writer = exif::experimental::Writer::new();
+        let artist = Field { tag: Tag::Artist, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Jo Doe".to_vec()]) };
+        writer.push_field(&artist);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+
+        let pixels = image::RgbImage::from_fn(24, 16, |x, y| image::Rgb([x as u8 * 10, y as u8 * 15, 90]));
+        let mut jpeg = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg);
+        encoder.set_icc_profile(icc.clone()).unwrap();
+        encoder.write_image(&pixels, 24, 16, image::ExtendedColorType::Rgb8).unwrap();
+        let jpeg = MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap();
+        let xmp = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF/></x:xmpmeta>";
+        let payload = ["http://ns.adobe.com/xap/1.0/\0", xmp].concat();
+        let segment = [&[0xFF, 0xE1][..], &((payload.len() + 2) as u16).to_be_bytes(), payload.as_bytes()].concat();
+        let source_data = [&jpeg[..2], &segment, &jpeg[2..]].concat();
+
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.child("source.jpg");
+        source.write_binary(&source_data).unwrap();
+
+        for name in ["plain.png", "plain.webp", "plain.jpg"] {
+            let destination = temp_dir.child(name);
+            match name {
+                "plain.webp" => {
+                    let mut webp = Vec::new();
+                    image::codecs::webp::WebPEncoder::new_lossless(&mut webp)
+                        .write_image(&pixels, 24, 16, image::ExtendedColorType::Rgb8)
+                        .unwrap();
+                    destination.write_binary(&webp).unwrap();
+                }
+                _ => pixels.save(destination.path()).unwrap(),
+            }
+            let before = image::open(destination.path()).unwrap().to_rgb8();
+
+            let report = MetadataCopier::new().copy(source.path(), destination.path()).unwrap();
+            assert!(report.exif && report.xmp && report.icc, "{}", name);
+
+            let mut decoder = image::ImageReader::open(destination.path())
+                .unwrap()
+                .with_guessed_format()
+                .unwrap()
+                .into_decoder()
+                .unwrap();
+            assert_eq!(decoder.icc_profile().unwrap(), Some(icc.clone()), "{}", name);
+            assert_eq!(decoder.xmp_metadata().unwrap(), Some(xmp.as_bytes().to_vec()), "{}", name);
+            let exif = MetadataProcessor::new().read_metadata(destination.path()).unwrap().unwrap();
+            assert_eq!(MetadataProcessor::new().field_text(&exif, "Artist").as_deref(), Some("Jo Doe"), "{}", name);
+            assert_eq!(image::open(destination.path()).unwrap().to_rgb8(), before, "{}", name);
+        }
+
+        // Only what was asked for
+        let partial = temp_dir.child("partial.png");
+        pixels.save(partial.path()).unwrap();
+        let report = MetadataCopier::new().with_exif(false).with_icc(false).copy(source.path(), partial.path()).unwrap();
+        assert!(report.xmp && !report.exif && !report.icc);
+        assert!(MetadataProcessor::new().read_metadata(partial.path()).unwrap().is_none());
+
+        let bmp = temp_dir.child("plain.bmp");
+        pixels.save(bmp.path()).unwrap();
+        assert!(MetadataCopier::new().copy(source.path(), bmp.path()).is_err());
+    }
+
+    #[test]
+    fn test_preserve_times() {
+        use exif::{Field, In, Tag, Value};
+        use pixie::{BatchProcessor, MetadataProcessor, TimeSource};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let ascii = |tag, text: &str| Field { tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![text.as_bytes().to_vec()]) };
+        let mut writer = exif::experimental::Writer::new();
+        let fields = [ascii(Tag::DateTimeOriginal, "2024:05:06 07:08:09"), ascii(Tag::OffsetTimeOriginal, "+02:00")];
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let mut jpeg = Vec::new();
+        image::RgbImage::new(16, 16)
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let photo = MetadataProcessor::new().embed_exif_jpeg(&jpeg, tiff.get_ref()).unwrap();
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        input_dir.child("dated.jpg").write_binary(&photo).unwrap();
+        image::RgbImage::new(16, 16).save(input_dir.child("plain.png").path()).unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        for name in ["dated.jpg", "plain.png"] {
+            let file = fs::File::options().write(true).open(input_dir.child(name).path()).unwrap();
+            file.set_modified(modified).unwrap();
+        }
+        let captured = UNIX_EPOCH + Duration::from_secs(1_714_972_089);
+        let mtime = |name: &str| fs::metadata(output_dir.child(name).path()).unwrap().modified().unwrap();
+
+        let config = ProcessConfig {
+            width: 8,
+            preserve_times: Some(TimeSource::Captured),
+            ..Default::default()
+        };
+        ImageProcessor::new(config.clone())
+            .process(input_dir.child("dated.jpg").path(), output_dir.child("single.jpg").path())
+            .unwrap();
+        assert_eq!(mtime("single.jpg"), captured);
+
+        BatchProcessor::new(config.clone(), 2)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        assert_eq!(mtime("dated.jpg"), captured);
+        // No capture date, so the file's own time
+        assert_eq!(mtime("plain.png"), modified);
+
+        let config = ProcessConfig { preserve_times: Some(TimeSource::Modified), ..config };
+        BatchProcessor::new(config, 2)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        assert_eq!(mtime("dated.jpg"), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_attrs() {
+        use pixie::BatchProcessor;
+        use std::os::unix::fs::PermissionsExt;
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        let input = input_dir.child("served.png");
+        image::RgbImage::new(16, 16).save(input.path()).unwrap();
+        fs::set_permissions(input.path(), fs::Permissions::from_mode(0o640)).unwrap();
+        let mode = |name: &str| fs::metadata(output_dir.child(name).path()).unwrap().permissions().mode() & 0o777;
+
+        let config = ProcessConfig { width: 8, preserve_attrs: true, ..Default::default() };
+        ImageProcessor::new(config.clone()).process(input.path(), output_dir.child("single.png").path()).unwrap();
+        assert_eq!(mode("single.png"), 0o640);
+
+        BatchProcessor::new(config, 2)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        assert_eq!(mode("served.png"), 0o640);
+    }
+
+    #[test]
+    fn test_create_output_dirs() {
+        use pixie::ImageToolError;
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("input.png");
+        image::RgbImage::new(16, 16).save(input.path()).unwrap();
+
+        let nested = temp_dir.child("new/dir/out.png");
+        ImageProcessor::new(ProcessConfig::default()).process(input.path(), nested.path()).unwrap();
+        assert!(nested.path().exists());
+
+        let config = ProcessConfig { create_dirs: false, ..Default::default() };
+        let missing = temp_dir.child("missing/out.png");
+        let result = ImageProcessor::new(config).process(input.path(), missing.path());
+        assert!(matches!(result, Err(ImageToolError::InvalidParameter(_))));
+        assert!(!temp_dir.child("missing").path().exists());
+    }
+
+    #[test]
+    fn test_batch_sniffs_content() {
+        use pixie::BatchProcessor;
+        use image::ImageFormat;
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        let image = image::RgbImage::new(20, 20);
+        image.save_with_format(input_dir.child("photo.jpeg").path(), ImageFormat::Png).unwrap();
+        image.save_with_format(input_dir.child("scan.dat").path(), ImageFormat::Jpeg).unwrap();
+        std::fs::write(input_dir.child("notes.txt").path(), "not an image").unwrap();
+
+        let processor = BatchProcessor::new(ProcessConfig { width: 10, ..Default::default() }, 1)
+            .unwrap()
+            .with_progress(false);
+        let stats = processor.process_directory(input_dir.path(), output_dir.path(), false).unwrap();
+        assert_eq!(stats.processed_count, 2);
+        assert!(stats.errors.is_empty());
+
+        let photo = std::fs::read(output_dir.child("photo.jpeg").path()).unwrap();
+        assert_eq!(image::guess_format(&photo).unwrap(), ImageFormat::Png);
+        let scan = std::fs::read(output_dir.child("scan.dat").path()).unwrap();
+        assert_eq!(image::guess_format(&scan).unwrap(), ImageFormat::Jpeg);
+        assert!(!output_dir.child("notes.txt").path().exists());
+    }
+
+    #[test]
+    fn test_extensionless_input() {
+        use pixie::{BatchProcessor, OutputFormat};
+        use image::ImageFormat;
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        let input = input_dir.child("upload");
+        image::RgbImage::new(20, 20).save_with_format(input.path(), ImageFormat::Png).unwrap();
+
+        let processor = ImageProcessor::new(ProcessConfig { width: 10, ..Default::default() });
+        let output = processor.generated_output_path(input.path(), "resized");
+        assert_eq!(output, input_dir.child("upload_resized.png").path());
+        processor.process(input.path(), &output).unwrap();
+        assert_eq!(image::open(&output).unwrap().width(), 10);
+
+        let config = ProcessConfig { format: Some(OutputFormat::Jpeg), ..Default::default() };
+        let output = ImageProcessor::new(config.clone()).generated_output_path(input.path(), "converted");
+        assert_eq!(output, input_dir.child("upload_converted.jpg").path());
+
+        let batch = BatchProcessor::new(config, 1).unwrap().with_progress(false);
+        let stats = batch.process_directory(input_dir.path(), output_dir.path(), false).unwrap();
+        assert!(stats.errors.is_empty());
+        let written = std::fs::read(output_dir.child("upload.jpg").path()).unwrap();
+        assert_eq!(image::guess_format(&written).unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_redact() {
+        use pixie::{RedactStyle, Redaction};
+
+        let region: Redaction = "8,8,16,16:black".parse().unwrap();
+        assert_eq!(region.style, RedactStyle::Black);
+        assert_eq!("8,8,16,16".parse::<Redaction>().unwrap().style, RedactStyle::Pixelate);
+        assert!("8,8,0,16".parse::<Redaction>().is_err());
+        assert!("8,8,16:blur".parse::<Redaction>().is_err());
+
+        // Stripes one pixel wide, which no redaction style leaves intact
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, _| {
+            if x % 2 == 0 { image::Rgb([255, 255, 255]) } else { image::Rgb([0, 0, 0]) }
+        }));
+        let config = ProcessConfig {
+            redact: vec![region, "32,0,64,32:pixelate".parse().unwrap(), "0,40,16,16:blur".parse().unwrap()],
+            ..Default::default()
+        };
+        let output = ImageProcessor::new(config).process_image(image.clone()).unwrap().to_rgb8();
+
+        assert_eq!(output.get_pixel(10, 10).0, [0, 0, 0]);
+        // Clipped at the right edge, and flat across each block
+        assert_eq!(output.get_pixel(40, 4), output.get_pixel(41, 4));
+        assert_eq!(output.get_pixel(62, 4), output.get_pixel(63, 4));
+        assert_ne!(output.get_pixel(4, 48).0, [255, 255, 255]);
+        assert_ne!(output.get_pixel(5, 48).0, [0, 0, 0]);
+        assert_eq!(output.get_pixel(4, 60), image.to_rgb8().get_pixel(4, 60));
+        assert_eq!(output.get_pixel(40, 40), image.to_rgb8().get_pixel(40, 40));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_blur_faces() {
+        use pixie::RedactStyle;
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stands in for a real detector, reporting one face
+        let temp_dir = TempDir::new().unwrap();
+        let detector = temp_dir.child("detect.sh");
+        fs::write(detector.path(), "#!/bin/sh\ntest -f \"$1\" && echo '16 16 16 16'\n").unwrap();
+        fs::set_permissions(detector.path(), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(64, 64, image::Rgb([255, 255, 255])));
+        let config = ProcessConfig {
+            blur_faces: Some(RedactStyle::Black),
+            face_detector: Some(format!("{} {{input}}", detector.path().display())),
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        let output = ImageProcessor::new(config).process_image(image).unwrap().to_rgb8();
+        // Widened by a quarter of the box on each side
+        assert_eq!(output.get_pixel(12, 12).0, [0, 0, 0]);
+        assert_eq!(output.get_pixel(35, 35).0, [0, 0, 0]);
+        assert_eq!(output.get_pixel(11, 11).0, [255, 255, 255]);
+        assert_eq!(output.get_pixel(36, 36).0, [255, 255, 255]);
+
+        let config = ProcessConfig { face_detector: Some("detect".to_string()), ..Default::default() };
+        assert!(config.validate().is_err());
+        let config = ProcessConfig {
+            blur_faces: Some(RedactStyle::Blur),
+            face_detector: Some("/nonexistent/facedetect {input}".to_string()),
+            ..Default::default()
+        };
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 8));
+        assert!(ImageProcessor::new(config).process_image(image).is_err());
+    }
+
+    #[test]
+    fn test_corner_radius() {
+        use pixie::{Color, OutputFormat};
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("card.png");
+        image::RgbImage::from_pixel(64, 48, image::Rgb([0, 0, 255])).save(input.path()).unwrap();
+        let config = ProcessConfig { corner_radius: Some(16), ..Default::default() };
+
+        let png = temp_dir.child("card_round.png");
+        ImageProcessor::new(config.clone()).process(input.path(), png.path()).unwrap();
+        let output = image::open(png.path()).unwrap().to_rgba8();
+        assert_eq!(output.get_pixel(0, 0).0[3], 0);
+        assert_eq!(output.get_pixel(63, 47).0[3], 0);
+        assert_eq!(output.get_pixel(32, 0).0[3], 255);
+        assert_eq!(output.get_pixel(0, 24).0[3], 255);
+        // Anti-aliased along the arc
+        let edge = output.get_pixel(4, 4).0[3];
+        assert!(edge > 0 && edge < 255, "{}", edge);
+
+        let config = ProcessConfig {
+            mask_background: Color::new(255, 0, 0),
+            format: Some(OutputFormat::Jpeg),
+            ..config
+        };
+        let jpeg = temp_dir.child("card_round.jpg");
+        ImageProcessor::new(config).process(input.path(), jpeg.path()).unwrap();
+        let output = image::open(jpeg.path()).unwrap().to_rgb8();
+        let corner = output.get_pixel(0, 0).0;
+        assert!(corner[0] > 200 && corner[2] < 50, "{:?}", corner);
+        let center = output.get_pixel(32, 24).0;
+        assert!(center[2] > 200 && center[0] < 50, "{:?}", center);
+    }
+
+    #[test]
+    fn test_mask() {
+        use pixie::MaskShape;
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("avatar.png");
+        image::RgbImage::from_pixel(64, 48, image::Rgb([0, 0, 255])).save(input.path()).unwrap();
+
+        // A circle crops to a square first
+        let circle = temp_dir.child("circle.png");
+        let config = ProcessConfig { mask: Some(MaskShape::Circle), ..Default::default() };
+        ImageProcessor::new(config).process(input.path(), circle.path()).unwrap();
+        let output = image::open(circle.path()).unwrap().to_rgba8();
+        assert_eq!(output.dimensions(), (48, 48));
+        assert_eq!(output.get_pixel(0, 0).0[3], 0);
+        assert_eq!(output.get_pixel(47, 47).0[3], 0);
+        assert_eq!(output.get_pixel(24, 24).0[3], 255);
+        assert_eq!(output.get_pixel(24, 1).0[3], 255);
+
+        // An ellipse keeps the shape of the image
+        let ellipse = temp_dir.child("ellipse.png");
+        let config = ProcessConfig { mask: Some(MaskShape::Ellipse), ..Default::default() };
+        ImageProcessor::new(config).process(input.path(), ellipse.path()).unwrap();
+        let output = image::open(ellipse.path()).unwrap().to_rgba8();
+        assert_eq!(output.dimensions(), (64, 48));
+        assert_eq!(output.get_pixel(0, 0).0[3], 0);
+        assert_eq!(output.get_pixel(1, 24).0[3], 255);
+        assert_eq!(output.get_pixel(32, 24).0[3], 255);
+    }
+
+    #[test]
+    fn test_shadow() {
+        use pixie::{Color, Shadow};
+
+        assert!("8,0".parse::<Shadow>().is_err());
+        assert!("-1,0,4".parse::<Shadow>().is_err());
+        let shadow: Shadow = "4,0,6:#333333".parse().unwrap();
+        assert_eq!(shadow.color, Color::new(0x33, 0x33, 0x33));
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("product.png");
+        image::RgbImage::from_pixel(40, 30, image::Rgb([0, 0, 255])).save(input.path()).unwrap();
+
+        // The canvas grows by three sigmas plus the offset on every side
+        let output_path = temp_dir.child("product_shadow.png");
+        let config = ProcessConfig { shadow: Some(shadow), ..Default::default() };
+        ImageProcessor::new(config).process(input.path(), output_path.path()).unwrap();
+        let output = image::open(output_path.path()).unwrap().to_rgba8();
+        assert_eq!(output.dimensions(), (76, 66));
+        assert_eq!(output.get_pixel(0, 0).0[3], 0);
+        assert_eq!(output.get_pixel(38, 33).0, [0, 0, 255, 255]);
+        let below = output.get_pixel(38, 51).0;
+        assert!(below[3] > 0 && below[3] < 255 && below[0] == 0x33, "{:?}", below);
+        assert_eq!(output.get_pixel(38, 2).0[3], 0);
+
+        let output_path = temp_dir.child("product_padded.png");
+        let config = ProcessConfig { padding: Some(5), ..Default::default() };
+        ImageProcessor::new(config).process(input.path(), output_path.path()).unwrap();
+        let output = image::open(output_path.path()).unwrap().to_rgba8();
+        assert_eq!(output.dimensions(), (50, 40));
+        assert_eq!(output.get_pixel(2, 20).0[3], 0);
+        assert_eq!(output.get_pixel(5, 20).0[3], 255);
+    }
+
+    #[test]
+    fn test_output_dimensions() {
+        use pixie::{AspectRatio, Shadow};
+
+        let config = ProcessConfig { width: 400, padding: Some(10), ..Default::default() };
+        assert_eq!(ImageProcessor::new(config).output_dimensions(800, 600), (420, 320));
+        let config = ProcessConfig {
+            aspect: Some(AspectRatio::new(1, 1)),
+            shadow: Some("4,0,6".parse::<Shadow>().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(ImageProcessor::new(config).output_dimensions(800, 600), (636, 636));
+
+        // Sizes that would make the output enormous don't validate
+        assert!(ProcessConfig { scale: 5000.0, ..Default::default() }.validate().is_err());
+        assert!(ProcessConfig { scale: f32::NAN, ..Default::default() }.validate().is_err());
+        assert!(ProcessConfig { padding: Some(1_000_000), ..Default::default() }.validate().is_err());
+        let shadow = "2000,0,0".parse::<Shadow>().unwrap();
+        assert!(ProcessConfig { shadow: Some(shadow), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_duotone() {
+        use pixie::Duotone;
+
+        assert!("#1e3264".parse::<Duotone>().is_err());
+        let duotone: Duotone = "#1e3264,#f573a0".parse().unwrap();
+
+        // Grayscale input still comes out in the two colors
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("gray.png");
+        let mut gray = image::GrayImage::new(2, 1);
+        gray.put_pixel(1, 0, image::Luma([255]));
+        gray.save(input.path()).unwrap();
+
+        let output_path = temp_dir.child("duotone.png");
+        let config = ProcessConfig { duotone: Some(duotone), ..Default::default() };
+        ImageProcessor::new(config).process(input.path(), output_path.path()).unwrap();
+        let output = image::open(output_path.path()).unwrap().to_rgb8();
+        assert_eq!(output.get_pixel(0, 0).0, [0x1e, 0x32, 0x64]);
+        assert_eq!(output.get_pixel(1, 0).0, [0xf5, 0x73, 0xa0]);
+    }
+
+    #[test]
+    fn test_custom_stage() {
+        use image::GenericImage;
+        use pixie::{BatchProcessor, ProcessingStage, StageContext};
+        use std::sync::Arc;
+
+        // Marks the top-left pixel, as a watermark would
+        struct Stamp;
+        impl ProcessingStage for Stamp {
+            fn name(&self) -> &str {
+                "stamp"
+            }
+
+            fn apply(&self, mut image: image::DynamicImage, ctx: &StageContext) -> pixie::Result<image::DynamicImage> {
+                assert!(ctx.input_path.is_some());
+                image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+                Ok(image)
+            }
+        }
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        let input = input_dir.child("a.png");
+        image::RgbImage::new(8, 8).save(input.path()).unwrap();
+        let config = ProcessConfig { posterize: Some(4), ..Default::default() };
+
+        let processor = ImageProcessor::new(config.clone()).with_stage(Arc::new(Stamp));
+        assert_eq!(processor.stage_names(), vec!["posterize", "stamp"]);
+        let before = ImageProcessor::new(config.clone()).with_stage_before("posterize", Arc::new(Stamp));
+        assert_eq!(before.stage_names(), vec!["stamp", "posterize"]);
+
+        let output = output_dir.child("stamped.png");
+        processor.process(input.path(), output.path()).unwrap();
+        let stamped = image::open(output.path()).unwrap().to_rgb8();
+        assert_eq!(stamped.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(stamped.get_pixel(1, 0).0, [0, 0, 0]);
+
+        BatchProcessor::new(config, 1)
+            .unwrap()
+            .with_progress(false)
+            .with_stage(Arc::new(Stamp))
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        let stamped = image::open(output_dir.child("a.png").path()).unwrap().to_rgb8();
+        assert_eq!(stamped.get_pixel(0, 0).0, [255, 0, 0]);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        use pixie::Pipeline;
+
+        let temp = TempDir::new().unwrap();
+        let input = temp.child("in.png");
+        image::RgbImage::from_pixel(80, 60, image::Rgb([40, 40, 40])).save(input.path()).unwrap();
+        let logo = temp.child("logo.png");
+        image::RgbaImage::from_pixel(10, 10, image::Rgba([255, 255, 255, 255])).save(logo.path()).unwrap();
+
+        let yaml = format!(
+            "steps:\n  - auto-orient\n  - resize: 40  # px\n  - sharpen: 1.5\n  - watermark: \"{}\"\n    opacity: 0.5\n  - format: png\n",
+            logo.path().display()
+        );
+        let pipeline = Pipeline::from_yaml(&yaml).unwrap();
+        assert_eq!(pipeline.step_names(), vec!["resize", "sharpen", "watermark"]);
+        assert!(pipeline.config().auto_orient);
+
+        let output = temp.child("out.png");
+        pipeline.processor().process(input.path(), output.path()).unwrap();
+        let result = image::open(output.path()).unwrap().to_rgb8();
+        assert_eq!(result.dimensions(), (40, 30));
+        // Half-opaque white over the bottom-right corner
+        assert!(result.get_pixel(37, 27).0[0] > 120);
+        assert!(result.get_pixel(2, 2).0[0] < 60);
+
+        let toml = "[[step]]\nresize = \"50%\"\n\n[[step]]\nposterize = 2\n\n[[step]]\nformat = \"jpeg\"\nquality = 70\n";
+        let pipeline = Pipeline::from_toml(toml).unwrap();
+        assert_eq!(pipeline.step_names(), vec!["resize", "posterize"]);
+        assert!(!pipeline.config().auto_orient);
+        assert_eq!(pipeline.config().quality, 70);
+        let output = temp.child("out.jpg");
+        pipeline.processor().process(input.path(), output.path()).unwrap();
+        assert_eq!(image::open(output.path()).unwrap().width(), 40);
+
+        assert!(Pipeline::from_yaml("steps:\n").is_err());
+        assert!(Pipeline::from_toml("[[step]]\nresize = \"big\"\n").is_err());
+    }
+
+    #[test]
+    fn test_batch_overrides() {
+        use pixie::{BatchProcessor, FileOverrides};
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        for name in ["a.png", "b.png", "c.png", "d.png"] {
+            image::RgbImage::new(40, 20).save(input_dir.child(name).path()).unwrap();
+        }
+        fs::write(input_dir.child("a.png.pixie.toml").path(), "# square\ncrop = \"1:1\"\ngravity = \"west\"\nquality = 95\n").unwrap();
+        fs::write(input_dir.child("b.png.pixie.toml").path(), "skip = true\n").unwrap();
+        fs::write(input_dir.child("d.png.pixie.toml").path(), "quality = 0\n").unwrap();
+
+        let stats = BatchProcessor::new(ProcessConfig { width: 20, ..Default::default() }, 1)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+
+        assert_eq!(stats.processed_count, 2);
+        assert_eq!(stats.skipped_count, 1);
+        assert_eq!(stats.failed_count(), 1);
+        assert!(stats.errors[0].0.ends_with("d.png"));
+        assert_eq!(image::image_dimensions(output_dir.child("a.png").path()).unwrap(), (20, 20));
+        assert_eq!(image::image_dimensions(output_dir.child("c.png").path()).unwrap(), (20, 10));
+        assert!(!output_dir.child("b.png").path().exists());
+        assert!(!output_dir.child("a.png.pixie.toml").path().exists());
+
+        let overrides = FileOverrides::parse("crop = \"none\"\n").unwrap();
+        let base = ProcessConfig { aspect: Some("16:9".parse().unwrap()), ..Default::default() };
+        assert!(overrides.apply(&base).unwrap().aspect.is_none());
+        assert!(FileOverrides::parse("[crop]\n").is_err());
+    }
+
+    #[test]
+    fn test_batch_rules() {
+        use pixie::{BatchProcessor, ImageFacts, Rule};
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        let gradient = image::RgbImage::from_fn(64, 32, |x, y| image::Rgb([x as u8 * 4, y as u8 * 8, 128]));
+        gradient.save(input_dir.child("wide.png").path()).unwrap();
+        image::RgbImage::from_fn(16, 16, |x, _| image::Rgb([if x < 8 { 0 } else { 255 }, 0, 0]))
+            .save(input_dir.child("small.png").path())
+            .unwrap();
+
+        let rules = vec![
+            Rule::new("width>32", "resize:32").unwrap(),
+            Rule::new("format==png && colors>16", "quantize:16").unwrap(),
+        ];
+        let stats = BatchProcessor::new(ProcessConfig::default(), 1)
+            .unwrap()
+            .with_progress(false)
+            .with_rules(rules)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        assert_eq!(stats.processed_count, 2);
+
+        let wide = image::open(output_dir.child("wide.png").path()).unwrap().to_rgb8();
+        assert_eq!(wide.dimensions(), (32, 16));
+        let colors: std::collections::HashSet<_> = wide.pixels().map(|p| p.0).collect();
+        assert!(colors.len() <= 16);
+        // Color type 3 in the IHDR chunk: stored with a palette, not as RGB
+        assert_eq!(fs::read(output_dir.child("wide.png").path()).unwrap()[25], 3);
+        let small = image::open(output_dir.child("small.png").path()).unwrap().to_rgb8();
+        assert_eq!(small.dimensions(), (16, 16));
+        assert_eq!(small.get_pixel(15, 0).0, [255, 0, 0]);
+
+        let facts = ImageFacts { width: 100, height: 50, alpha: true, ..Default::default() };
+        assert!(Rule::new("alpha", "trim").unwrap().matches(&facts));
+        assert!(Rule::new("width<10 || height==50", "trim").unwrap().matches(&facts));
+        assert!(!Rule::new("megapixels>1 && alpha", "trim").unwrap().matches(&facts));
+        assert!(Rule::new("size>1MB", "trim").is_ok());
+        assert!(Rule::new("depth>8", "trim").is_err());
+        assert!(Rule::new("format>png", "trim").is_err());
+        assert!(Rule::new("width>10", "resize:big").is_err());
+    }
+
+    #[test]
+    fn test_batch_pause() {
+        use pixie::BatchProcessor;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("a.png").path()).unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("b.png").path()).unwrap();
+        
+        let pause = Arc::new(AtomicBool::new(true));
+        let processor = BatchProcessor::new(ProcessConfig { width: 10, ..Default::default() }, 1)
+            .unwrap()
+            .with_progress(false)
+            .with_pause(pause.clone());
+        
+        std::thread::scope(|scope| {
+            let run = scope.spawn(|| processor.process_directory(input_dir.path(), output_dir.path(), false));
+            
+            // Nothing starts while paused
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            assert!(!output_dir.child("a.png").path().exists());
+            
+            pause.store(false, Ordering::Relaxed);
+            assert_eq!(run.join().unwrap().unwrap().processed_count, 2);
+        });
+    }
+    
+    #[test]
+    fn test_batch_throughput() {
+        use pixie::BatchProcessor;
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(400, 400).save(input_dir.child("big.png").path()).unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("small.png").path()).unwrap();
+        
+        let stats = BatchProcessor::new(ProcessConfig { width: 10, ..Default::default() }, 1)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        
+        assert!(stats.elapsed > std::time::Duration::ZERO);
+        assert!(stats.images_per_sec() > 0.0);
+        assert!(stats.bytes_per_sec() > 0.0);
+        assert_eq!(stats.average_time(), stats.total_timings().total() / 2);
+        
+        let slowest = stats.slowest(10);
+        assert_eq!(slowest.len(), 2);
+        assert!(slowest[0].1.total() >= slowest[1].1.total());
+        assert_eq!(stats.slowest(1).len(), 1);
+        
+        // Nothing ran, so nothing divides by zero
+        let empty = pixie::ProcessingStats::default();
+        assert_eq!(empty.images_per_sec(), 0.0);
+        assert_eq!(empty.average_time(), std::time::Duration::ZERO);
+    }
+    
+    #[test]
+    fn test_progress_hook() {
+        use pixie::{BatchProcessor, Phase, Progress, ProgressHook};
+        use std::sync::{Arc, Mutex};
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(200, 100).save(input_dir.child("one.png").path()).unwrap();
+        image::RgbImage::new(100, 200).save(input_dir.child("two.png").path()).unwrap();
+        
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = events.clone();
+        let hook: ProgressHook = Arc::new(move |progress| recorder.lock().unwrap().push(progress));
+        let config = ProcessConfig {
+            width: 50,
+            ..Default::default()
+        };
+        
+        ImageProcessor::new(config.clone())
+            .with_progress_hook(hook.clone())
+            .process(input_dir.child("one.png").path(), output_dir.child("single.png").path())
+            .unwrap();
+        // The encoder writes the file itself; Write only shows up when EXIF is carried over
+        let phases: Vec<Progress> = events.lock().unwrap().drain(..).collect();
+        assert_eq!(phases, [Phase::Decode, Phase::Resize, Phase::Filter, Phase::Encode].map(Progress::Phase));
+        
+        BatchProcessor::new(config, 2)
+            .unwrap()
+            .with_progress_hook(hook)
+            .process_directory(input_dir.path(), output_dir.child("batch").path(), false)
+            .unwrap();
+        let events = events.lock().unwrap();
+        assert_eq!(events[0], Progress::Start { total: 2 });
+        assert_eq!(events.iter().filter(|e| **e == Progress::Advance).count(), 2);
+        assert!(matches!(events.last(), Some(Progress::Finish(message)) if message.starts_with("Processed 2 images")));
+    }
+    
+    #[test]
+    fn test_batch_memory_budget() {
+        use pixie::BatchProcessor;
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(200, 200).save(input_dir.child("big.png").path()).unwrap();
+        for i in 0..6 {
+            image::RgbImage::new(10, 10).save(input_dir.child(format!("small{}.png", i)).path()).unwrap();
+        }
+        input_dir.child("broken.png").write_binary(b"not a png").unwrap();
+        
+        let config = ProcessConfig {
+            width: 5,
+            ..Default::default()
+        };
+        
+        // The big image alone is over budget and the small ones share it;
+        // every file must still be processed without waiting forever
+        let processor = BatchProcessor::new(config, 4)
+            .unwrap()
+            .with_progress(false)
+            .with_memory_budget(Some(1000));
+        let stats = processor
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        
+        assert_eq!(stats.processed_count, 7);
+        assert_eq!(stats.failed_count(), 1);
+        assert!(output_dir.child("big.png").path().exists());
+    }
+    
+    #[test]
+    fn test_batch_cache() {
+        use pixie::{BatchProcessor, ProcessCache};
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("one.png").path()).unwrap();
+        image::RgbImage::new(30, 30).save(input_dir.child("two.png").path()).unwrap();
+        
+        let run = |width: u32| {
+            let config = ProcessConfig {
+                width,
+                ..Default::default()
+            };
+            BatchProcessor::new(config, 2)
+                .unwrap()
+                .with_progress(false)
+                .with_cache(Some(cache_dir.path().to_path_buf()))
+                .process_directory(input_dir.path(), output_dir.path(), false)
+                .unwrap()
+        };
+        
+        let first = run(10);
+        assert_eq!(first.processed_count, 2);
+        assert_eq!(first.cached_count, 0);
+        
+        let second = run(10);
+        assert_eq!(second.processed_count, 0);
+        assert_eq!(second.cached_count, 2);
+        
+        // A changed output no longer counts as up to date
+        output_dir.child("one.png").write_binary(b"edited").unwrap();
+        let third = run(10);
+        assert_eq!(third.processed_count, 1);
+        assert_eq!(third.cached_count, 1);
+        
+        // Neither do different settings
+        let fourth = run(5);
+        assert_eq!(fourth.processed_count, 2);
+        assert_eq!(fourth.cached_count, 0);
+
+        // Keys are a fixed hash of the content, so they hold across builds
+        let cache = ProcessCache::open(cache_dir.path(), &ProcessConfig::default()).unwrap();
+        let key = cache.key_for_bytes(b"hello");
+        assert!(key.starts_with("a430d84680aabd0b"));
+        let again = ProcessCache::open(cache_dir.path(), &ProcessConfig::default()).unwrap();
+        assert_eq!(again.key_for_bytes(b"hello"), key);
+        let other = ProcessCache::open(cache_dir.path(), &ProcessConfig { quality: 50, ..Default::default() }).unwrap();
+        assert_ne!(other.key_for_bytes(b"hello"), key);
+    }
+    
+    #[test]
+    fn test_batch_changed_only() {
+        use pixie::BatchProcessor;
+        use std::time::{Duration, SystemTime};
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("old.png").path()).unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("new.png").path()).unwrap();
+        
+        let run = || {
+            BatchProcessor::new(ProcessConfig::default(), 2)
+                .unwrap()
+                .with_progress(false)
+                .with_changed_only(true)
+                .process_directory(input_dir.path(), output_dir.path(), false)
+                .unwrap()
+        };
+        
+        let first = run();
+        assert_eq!(first.processed_count, 2);
+        assert_eq!(first.unchanged_count, 0);
+        
+        let touched = SystemTime::now() + Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(input_dir.child("new.png").path())
+            .unwrap()
+            .set_modified(touched)
+            .unwrap();
+        
+        let second = run();
+        assert_eq!(second.processed_count, 1);
+        assert_eq!(second.unchanged_count, 1);
+    }
+    
+    #[test]
+    fn test_batch_journal_rollback() {
+        use pixie::{rollback_journal, BatchProcessor};
+        
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("one.png").path()).unwrap();
+        image::RgbImage::new(20, 20).save(input_dir.child("two.png").path()).unwrap();
+        
+        let journal = output_dir.child("journal.txt");
+        let config = ProcessConfig {
+            width: 10,
+            ..Default::default()
+        };
+        
+        let processor = BatchProcessor::new(config, 1)
+            .unwrap()
+            .with_progress(false)
+            .with_journal(journal.path().to_path_buf());
+        processor
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+        assert!(output_dir.child("one.png").path().exists());
+        
+        let report = rollback_journal(journal.path()).unwrap();
+        assert_eq!(report.removed, 2);
+        assert!(!output_dir.child("one.png").path().exists());
+        assert!(!output_dir.child("two.png").path().exists());
+        assert!(!journal.path().exists());
+    }
+    
+    #[test]
+    fn test_frame_extraction_stride() {
+        use pixie::FrameExtractor;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.child("anim.gif");
+        {
+            let file = fs::File::create(input_path.path()).unwrap();
+            let mut encoder = image::codecs::gif::GifEncoder::new(file);
+            let frames = (0..5u8).map(|i| {
+                image::Frame::new(image::RgbaImage::from_pixel(8, 8, image::Rgba([i * 40, 0, 0, 255])))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        
+        let output_dir = temp_dir.child("frames");
+        let written = FrameExtractor::new()
+            .with_stride(2)
+            .extract(input_path.path(), output_dir.path())
+            .unwrap();
+        
+        assert_eq!(written.len(), 3);
+        assert!(output_dir.child("anim_0004.png").path().exists());
+    }
+    
+    #[test]
+    fn test_tga_pnm_qoi_output() {
+        let img = image::RgbaImage::new(8, 4);
+        let mut input = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut input, image::ImageFormat::Png).unwrap();
+        
+        for (format, expected) in [
+            (OutputFormat::Tga, image::ImageFormat::Tga),
+            (OutputFormat::Pnm, image::ImageFormat::Pnm),
+            (OutputFormat::Qoi, image::ImageFormat::Qoi),
+        ] {
+            let config = ProcessConfig {
+                format: Some(format),
+                ..Default::default()
+            };
+            let output = ImageProcessor::new(config).process_bytes(input.get_ref()).unwrap();
+            
+            let decoded = image::load_from_memory_with_format(&output, expected).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (8, 4));
+        }
+    }
+    
+    #[test]
+    fn test_texture_mip_chain() {
+        use pixie::{TextureBaker, TextureCompression, TextureContainer};
+        
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(16, 8));
+        let dds = TextureBaker::new(TextureContainer::Dds)
+            .with_compression(TextureCompression::Bc1)
+            .bake_to_bytes(&img)
+            .unwrap();
+        
+        assert_eq!(&dds[..4], b"DDS ");
+        assert_eq!(&dds[84..88], b"DXT1");
+        // 16x8, 8x4, 4x2, 2x1 and 1x1 levels: 8 + 2 + 1 + 1 + 1 blocks
+        assert_eq!(u32::from_le_bytes(dds[28..32].try_into().unwrap()), 5);
+        assert_eq!(dds.len(), 128 + 13 * 8);
+    }
+    
+    #[test]
+    fn test_tone_map_hdr() {
+        use pixie::{ToneMapOperator, ToneMapper};
+        
+        let hdr = image::Rgb32FImage::from_fn(3, 1, |x, _| image::Rgb([[0.0, 1.0, 100.0][x as usize]; 3]));
+        let mapped = ToneMapper::new(ToneMapOperator::Reinhard).map(image::DynamicImage::ImageRgb32F(hdr));
+        
+        let mapped = mapped.as_rgb8().expect("tone mapping produces 8-bit RGB");
+        let values: Vec<u8> = mapped.pixels().map(|p| p.0[0]).collect();
+        assert_eq!(values[0], 0);
+        // 1.0 maps to 0.5 linear, which is 188 in sRGB
+        assert_eq!(values[1], 188);
+        assert!(values[2] > 250);
+    }
+    
+    #[test]
+    fn test_16_bit_depth() {
+        let img = image::ImageBuffer::<image::Rgb<u16>, _>::from_fn(8, 8, |x, y| image::Rgb([x as u16 * 4001, y as u16 * 7, 1]));
+        let mut input = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut input, image::ImageFormat::Png).unwrap();
+        
+        let keep = ImageProcessor::new(ProcessConfig { width: 4, ..Default::default() });
+        let output = image::load_from_memory(&keep.process_bytes(input.get_ref()).unwrap()).unwrap();
+        assert_eq!(output.color(), image::ColorType::Rgb16);
+        
+        let reduce = ImageProcessor::new(ProcessConfig { width: 4, bit_depth: Some(8), ..Default::default() });
+        let output = image::load_from_memory(&reduce.process_bytes(input.get_ref()).unwrap()).unwrap();
+        assert_eq!(output.color(), image::ColorType::Rgb8);
+    }
+    
+    #[test]
+    fn test_cmyk_tiff_round_trip() {
+        use pixie::{scan_jpeg, write_cmyk_tiff};
+        
+        let img = image::RgbImage::from_pixel(4, 4, image::Rgb([255, 0, 0]));
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut jpeg, image::ImageFormat::Jpeg).unwrap();
+        let info = scan_jpeg(jpeg.get_ref().as_slice()).unwrap().expect("JPEG header is recognized");
+        assert!(!info.is_cmyk());
+        
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        write_cmyk_tiff(&image::DynamicImage::ImageRgb8(img), &mut tiff, None).unwrap();
+        let decoded = image::load_from_memory(tiff.get_ref()).unwrap().to_rgb8();
+        assert_eq!(decoded.get_pixel(0, 0).0, [255, 0, 0]);
+    }
+    
+    #[test]
+    fn test_premultiplied_alpha_resize() {
+        use pixie::Resizer;
+        
+        let logo = image::RgbaImage::from_fn(101, 8, |x, _| {
+            if x < 50 { image::Rgba([255, 255, 255, 255]) } else { image::Rgba([0, 0, 0, 0]) }
+        });
+        let logo = image::DynamicImage::ImageRgba8(logo);
+        
+        let edge = |resizer: Resizer| {
+            let resized = resizer.resize_exact(&logo, 13, 1).to_rgba8();
+            *resized.pixels().find(|p| p.0[3] > 0 && p.0[3] < 255).expect("a partially covered edge pixel")
+        };
+        
+        assert_eq!(edge(Resizer::new(ResizeAlgorithm::Bicubic, false)).0[0], 255);
+        assert!(edge(Resizer::new(ResizeAlgorithm::Bicubic, false).with_premultiply(false)).0[0] < 255);
+    }
+    
+    #[test]
+    fn test_linear_light_resize() {
+        use pixie::Resizer;
+        
+        let stripes = image::GrayImage::from_fn(64, 8, |x, _| image::Luma([if x % 2 == 0 { 0 } else { 255 }]));
+        let stripes = image::DynamicImage::ImageLuma8(stripes);
+        let center = |resizer: Resizer| resizer.resize_exact(&stripes, 8, 1).to_luma8().get_pixel(4, 0).0[0];
+        
+        let encoded = center(Resizer::new(ResizeAlgorithm::Bilinear, false));
+        let linear = center(Resizer::new(ResizeAlgorithm::Bilinear, false).with_linear(true));
+        // Half black, half white is 50% linear light, which encodes to 188
+        assert!((120..=135).contains(&encoded), "encoded average was {}", encoded);
+        assert!((183..=192).contains(&linear), "linear average was {}", linear);
+    }
+    
+    #[test]
+    fn test_additional_filters() {
+        use pixie::Resizer;
+        
+        let checker = image::GrayImage::from_fn(8, 8, |x, y| image::Luma([if (x + y) % 2 == 0 { 0 } else { 200 }]));
+        let checker = image::DynamicImage::ImageLuma8(checker);
+        
+        let boxed = Resizer::new(ResizeAlgorithm::Box, false).resize_exact(&checker, 4, 4).to_luma8();
+        assert!(boxed.pixels().all(|p| p.0[0] == 100));
+        
+        for algorithm in [ResizeAlgorithm::Mitchell, ResizeAlgorithm::CatmullRom, ResizeAlgorithm::Lanczos(5)] {
+            let resized = Resizer::new(algorithm, false).resize_exact(&checker, 3, 5);
+            assert_eq!((resized.width(), resized.height()), (3, 5));
+        }
+        
+        let config = ProcessConfig { algorithm: ResizeAlgorithm::Lanczos(0), ..Default::default() };
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_banded_resize_matches_single_pass() {
+        use image::imageops::FilterType;
+        use pixie::Resizer;
+        
+        // Large enough to be resized in parallel bands
+        let source = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(2100, 2000, |x, y| {
+            image::Rgb([((x * 7) ^ (y * 3)) as u8, ((x + y) % 256) as u8, if (x / 9 + y / 13) % 2 == 0 { 20 } else { 230 }])
+        }));
+        
+        for (algorithm, filter) in [
+            (ResizeAlgorithm::Lanczos3, FilterType::Lanczos3),
+            (ResizeAlgorithm::CatmullRom, FilterType::CatmullRom),
+            (ResizeAlgorithm::Triangle, FilterType::Triangle),
+        ] {
+            let banded = Resizer::new(algorithm, false).resize_exact(&source, 733, 517).to_rgb8();
+            let reference = source.resize_exact(733, 517, filter).to_rgb8();
+            let worst = banded
+                .as_raw()
+                .iter()
+                .zip(reference.as_raw())
+                .map(|(&a, &b)| (a as i32 - b as i32).abs())
+                .max()
+                .unwrap();
+            assert!(worst <= 1, "{:?} differs by {}", algorithm, worst);
+        }
+    }
+    
+    #[test]
+    fn test_external_upscaler_hook() {
+        use pixie::{ExternalUpscaler, Upscaler};
+        
+        assert_eq!(ExternalUpscaler::factor_for(1.2), 2);
+        assert_eq!(ExternalUpscaler::factor_for(2.5), 3);
+        assert_eq!(ExternalUpscaler::factor_for(8.0), 4);
+        
+        let config = ProcessConfig { upscaler: Some(Upscaler::Command("upscale-tool".to_string())), ..Default::default() };
+        assert!(config.validate().is_err());
+        
+        let missing = Upscaler::RealEsrganNcnn("/nonexistent/realesrgan-ncnn-vulkan".into());
+        let processor = ImageProcessor::new(ProcessConfig { width: 8, upscaler: Some(missing), ..Default::default() });
+        let small = image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 4));
+        assert!(processor.process_image(small).is_err());
+        // Downscales never reach the external tool
+        let large = image::DynamicImage::ImageRgb8(image::RgbImage::new(16, 16));
+        assert_eq!(processor.process_image(large).unwrap().width(), 8);
+    }
+    
+    #[test]
+    fn test_seam_carve_keeps_subjects() {
+        let squares = image::RgbImage::from_fn(60, 30, |x, y| {
+            let subject = (10..20).contains(&y) && ((5..15).contains(&x) || (40..50).contains(&x));
+            if subject { image::Rgb([255, 0, 0]) } else { image::Rgb([240, 240, 240]) }
+        });
+        let red_pixels = |image: &image::DynamicImage| image.to_rgb8().pixels().filter(|p| p.0 == [255, 0, 0]).count();
+        
+        for width in [40, 80] {
+            let config = ProcessConfig { width, height: 30, algorithm: ResizeAlgorithm::SeamCarve, ..Default::default() };
+            let carved = ImageProcessor::new(config).process_image(image::DynamicImage::ImageRgb8(squares.clone())).unwrap();
+            assert_eq!((carved.width(), carved.height()), (width, 30));
+            assert_eq!(red_pixels(&carved), 200);
+        }
+    }
+    
+    #[test]
+    fn test_denoise_stage() {
+        use pixie::DenoiseMethod;
+        
+        // Alternating speckles on a flat field, with a hard edge down the middle
+        let noisy = image::GrayImage::from_fn(32, 32, |x, y| {
+            let base: i16 = if x < 16 { 60 } else { 190 };
+            let noise = if (x * 7 + y * 13) % 5 == 0 { 40 } else { 0 };
+            image::Luma([(base + noise) as u8])
+        });
+        let spread = |image: &image::GrayImage| {
+            let flat: Vec<i32> = (2..14).flat_map(|x| (0..32).map(move |y| (x, y))).map(|(x, y)| image.get_pixel(x, y).0[0] as i32).collect();
+            flat.iter().max().unwrap() - flat.iter().min().unwrap()
+        };
+        
+        for method in [DenoiseMethod::Median, DenoiseMethod::Bilateral, DenoiseMethod::NlMeans] {
+            let config = ProcessConfig { denoise: Some(5), denoise_method: method, ..Default::default() };
+            let denoised = ImageProcessor::new(config).process_image(image::DynamicImage::ImageLuma8(noisy.clone())).unwrap().to_luma8();
+            assert!(spread(&denoised) < spread(&noisy), "{:?} left the noise in", method);
+            // The edge survives
+            assert!(denoised.get_pixel(8, 16).0[0] < 110 && denoised.get_pixel(24, 16).0[0] > 170);
+        }
+    }
+    
+    #[test]
+    fn test_auto_levels() {
+        use pixie::AutoLevel;
+        
+        let dull = image::RgbImage::from_fn(64, 4, |x, _| image::Rgb([100 + x as u8, 80 + x as u8, 50 + x as u8]));
+        let stretch = |mode| {
+            let config = ProcessConfig { auto_level: Some(mode), level_clip: 0.0, ..Default::default() };
+            ImageProcessor::new(config).process_image(image::DynamicImage::ImageRgb8(dull.clone())).unwrap().to_rgb8()
+        };
+        
+        let levels = stretch(AutoLevel::Levels);
+        assert_eq!(levels.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(levels.get_pixel(63, 0).0, [255, 255, 255]);
+        
+        // One curve for all channels keeps red above blue
+        let contrast = stretch(AutoLevel::Contrast);
+        assert_eq!(contrast.get_pixel(0, 0).0[2], 0);
+        assert_eq!(contrast.get_pixel(63, 0).0[0], 255);
+        assert!(contrast.get_pixel(32, 0).0[0] > contrast.get_pixel(32, 0).0[2]);
+    }
+
+    #[test]
+    fn test_lut_cube() {
+        use pixie::Lut3d;
+
+        let invert = "TITLE \"Invert\"\nLUT_3D_SIZE 2\n\
+            1 1 1\n0 1 1\n1 0 1\n0 0 1\n1 1 0\n0 1 0\n1 0 0\n0 0 0\n";
+        let lut = Lut3d::parse(invert).unwrap();
+
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(2, 2, image::Rgb([200, 100, 0])));
+        let graded = lut.apply(&image).to_rgb8();
+        assert_eq!(graded.get_pixel(0, 0).0, [55, 155, 255]);
+
+        // A truncated table is rejected rather than read out of bounds
+        assert!(Lut3d::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut3d::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+    }
+
+    #[test]
+    fn test_posterize_and_threshold() {
+        let ramp = image::RgbImage::from_fn(256, 1, |x, _| image::Rgb([x as u8, x as u8, x as u8]));
+        let run = |config: ProcessConfig| {
+            ImageProcessor::new(config).process_image(image::DynamicImage::ImageRgb8(ramp.clone())).unwrap().to_rgb8()
+        };
+
+        let posterized = run(ProcessConfig { posterize: Some(3), ..Default::default() });
+        let mut tones: Vec<u8> = posterized.pixels().map(|p| p.0[0]).collect();
+        tones.dedup();
+        assert_eq!(tones, vec![0, 128, 255]);
+
+        let stencil = run(ProcessConfig { threshold: Some(100), ..Default::default() });
+        assert_eq!(stencil.get_pixel(99, 0).0, [0, 0, 0]);
+        assert_eq!(stencil.get_pixel(100, 0).0, [255, 255, 255]);
+
+        assert!(ProcessConfig { posterize: Some(1), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_channel_operations() {
+        use pixie::{Channel, ChannelOrder};
+
+        let source = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, image::Rgba([200, 100, 50, 64])));
+        let run = |config: ProcessConfig| ImageProcessor::new(config).process_image(source.clone()).unwrap();
+
+        let order: ChannelOrder = "bgr".parse().unwrap();
+        let swapped = run(ProcessConfig { channel_order: Some(order), ..Default::default() }).to_rgba8();
+        assert_eq!(swapped.get_pixel(0, 0).0, [50, 100, 200, 64]);
+
+        let inverted = run(ProcessConfig { invert: true, ..Default::default() }).to_rgba8();
+        assert_eq!(inverted.get_pixel(0, 0).0, [55, 155, 205, 64]);
+
+        // Extracted channels come out as grayscale, and invert composes with them
+        let mask = run(ProcessConfig { channel: Some(Channel::Alpha), invert: true, ..Default::default() });
+        assert_eq!(mask.color(), image::ColorType::L8);
+        assert_eq!(mask.to_luma8().get_pixel(0, 0).0, [191]);
+
+        assert!("rgbx".parse::<ChannelOrder>().is_err());
+        assert!("rg".parse::<ChannelOrder>().is_err());
+    }
+
+    #[test]
+    fn test_alpha_add_and_remove() {
+        use pixie::{AlphaSource, Color};
+
+        let temp_dir = TempDir::new().unwrap();
+        let mask = temp_dir.child("mask.png");
+        image::GrayImage::from_fn(2, 1, |x, _| image::Luma([if x == 0 { 255 } else { 0 }])).save(mask.path()).unwrap();
+
+        let run = |config: ProcessConfig, image: image::DynamicImage| ImageProcessor::new(config).process_image(image).unwrap();
+        let rgb = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(4, 2, |x, _| {
+            if x < 2 { image::Rgb([255, 255, 255]) } else { image::Rgb([10, 20, 30]) }
+        }));
+
+        let keyed = run(ProcessConfig { add_alpha: Some("#ffffff".parse().unwrap()), ..Default::default() }, rgb.clone());
+        assert_eq!(keyed.color(), image::ColorType::Rgba8);
+        assert_eq!(keyed.to_rgba8().get_pixel(0, 0).0[3], 0);
+        assert_eq!(keyed.to_rgba8().get_pixel(3, 0).0[3], 255);
+
+        // The mask is stretched to the image size
+        let masked = run(ProcessConfig { add_alpha: Some(AlphaSource::Mask(mask.path().to_path_buf())), ..Default::default() }, rgb);
+        assert_eq!(masked.to_rgba8().get_pixel(0, 1).0[3], 255);
+        assert_eq!(masked.to_rgba8().get_pixel(3, 1).0[3], 0);
+
+        let flattened = run(ProcessConfig { remove_alpha: Some(Color::new(255, 0, 0)), ..Default::default() }, keyed);
+        assert_eq!(flattened.color(), image::ColorType::Rgb8);
+        assert_eq!(flattened.to_rgb8().get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(flattened.to_rgb8().get_pixel(3, 0).0, [10, 20, 30]);
+
+        let both = ProcessConfig { add_alpha: Some("#000".parse().unwrap()), remove_alpha: Some(Color::WHITE), ..Default::default() };
+        assert!(both.validate().is_err());
+    }
+
+    #[test]
+    fn test_transparent_color_fuzz() {
+        use pixie::Color;
+
+        // A white background with slightly off-white compression noise
+        let logo = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(3, 1, |x, _| match x {
+            0 => image::Rgb([255, 255, 255]),
+            1 => image::Rgb([250, 252, 247]),
+            _ => image::Rgb([200, 30, 30]),
+        }));
+        let alpha = |fuzz: f32| {
+            let config = ProcessConfig::builder().transparent_color(Color::WHITE, fuzz).build().unwrap();
+            let output = ImageProcessor::new(config).process_image(logo.clone()).unwrap().to_rgba8();
+            output.pixels().map(|p| p.0[3]).collect::<Vec<_>>()
+        };
+
+        assert_eq!(alpha(0.0), vec![0, 255, 255]);
+        assert_eq!(alpha(5.0), vec![0, 0, 255]);
+
+        let jpeg = ProcessConfig { add_alpha: Some("#fff".parse().unwrap()), format: Some(OutputFormat::Jpeg), ..Default::default() };
+        assert!(jpeg.validate().is_err());
+    }
+
+    #[test]
+    fn test_trim_borders() {
+        // A dark block on a white page, with one speck of scanner noise in the margin
+        let scan = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(20, 10, |x, y| {
+            if (5..11).contains(&x) && (3..7).contains(&y) {
+                image::Rgb([0, 0, 0])
+            } else if (x, y) == (15, 1) {
+                image::Rgb([245, 245, 245])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        }));
+        let trim = |fuzz: f32| {
+            let config = ProcessConfig::builder().trim(fuzz).build().unwrap();
+            let output = ImageProcessor::new(config).process_image(scan.clone()).unwrap();
+            (output.width(), output.height())
+        };
+
+        assert_eq!(trim(0.0), (11, 6));
+        assert_eq!(trim(5.0), (6, 4));
+
+        // Trimming happens before resizing
+        let config = ProcessConfig { trim: true, fuzz: 5.0, width: 12, ..Default::default() };
+        let output = ImageProcessor::new(config).process_image(scan).unwrap();
+        assert_eq!((output.width(), output.height()), (12, 8));
+    }
+
+    #[test]
+    fn test_collage_grid() {
+        use pixie::{Collage, Color, GridLayout};
+
+        let red = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(40, 20, image::Rgb([255, 0, 0])));
+        let blue = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(20, 20, image::Rgb([0, 0, 255])));
+
+        let layout: GridLayout = "2x2".parse().unwrap();
+        let collage = Collage::new()
+            .with_layout(Some(layout))
+            .with_gap(4)
+            .with_background(Color::BLACK)
+            .compose(&[red.clone(), blue.clone(), red.clone()])
+            .unwrap()
+            .to_rgb8();
+
+        // Cells take the largest input's size: 2 x 40 + 3 gaps, 2 x 20 + 3 gaps
+        assert_eq!(collage.dimensions(), (92, 52));
+        assert_eq!(collage.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(collage.get_pixel(4, 4).0, [255, 0, 0]);
+        // The square image is centered in its wider cell
+        assert_eq!(collage.get_pixel(48 + 5, 4).0, [0, 0, 0]);
+        assert_eq!(collage.get_pixel(48 + 10, 4).0, [0, 0, 255]);
+        // The fourth cell stays empty
+        assert_eq!(collage.get_pixel(70, 40).0, [0, 0, 0]);
+
+        assert!(Collage::new().with_layout(Some(GridLayout::new(1, 1))).compose(&[red, blue]).is_err());
+        assert!("3by2".parse::<GridLayout>().is_err());
+    }
+
+    #[test]
+    fn test_join_strip() {
+        use pixie::{Color, JoinAlign, JoinDirection, Joiner};
+
+        let tall = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(10, 30, image::Rgb([255, 0, 0])));
+        let short = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(20, 10, image::Rgb([0, 0, 255])));
+        let images = [tall, short];
+
+        let strip = Joiner::new(JoinDirection::Horizontal)
+            .with_spacing(5)
+            .with_align(JoinAlign::End)
+            .with_background(Color::BLACK)
+            .join(&images)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(strip.dimensions(), (35, 30));
+        assert_eq!(strip.get_pixel(12, 0).0, [0, 0, 0]);
+        assert_eq!(strip.get_pixel(15, 0).0, [0, 0, 0]);
+        assert_eq!(strip.get_pixel(15, 29).0, [0, 0, 255]);
+
+        let stack = Joiner::new(JoinDirection::Vertical).join(&images).unwrap();
+        assert_eq!((stack.width(), stack.height()), (20, 40));
+
+        // Equal heights: the tall image shrinks to the short one's height
+        let equal = Joiner::new(JoinDirection::Horizontal).with_same_size(true).join(&images).unwrap();
+        assert_eq!((equal.width(), equal.height()), (3 + 20, 10));
+    }
+
+    #[test]
+    fn test_tile_slicing() {
+        use pixie::{TileGrid, TileSlicer};
+
+        // Counts spread the remainder so tiles differ by at most a pixel
+        let slicer = TileSlicer::new(TileGrid::Count { columns: 3, rows: 2 });
+        let bounds = slicer.bounds(100, 50).unwrap();
+        assert_eq!(bounds.len(), 6);
+        assert_eq!(bounds.iter().map(|b| b.width).take(3).collect::<Vec<_>>(), vec![33, 33, 34]);
+        assert_eq!((bounds[5].row, bounds[5].column, bounds[5].x, bounds[5].y), (1, 2, 66, 25));
+
+        // Fixed sizes leave a smaller last row and column
+        let slicer = TileSlicer::new(TileGrid::Size { width: 256, height: 256 });
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::new(600, 300));
+        let tiles = slicer.split(&image).unwrap();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!((tiles[2].1.width(), tiles[2].1.height()), (88, 256));
+        assert_eq!((tiles[5].1.width(), tiles[5].1.height()), (88, 44));
+
+        assert!(TileSlicer::new(TileGrid::Count { columns: 0, rows: 1 }).bounds(10, 10).is_err());
+    }
+
+    #[test]
+    fn test_tile_pyramid() {
+        use pixie::{PyramidGenerator, PyramidLayout};
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("scan.png");
+        image::RgbImage::from_fn(600, 300, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 0]))
+            .save(input.path())
+            .unwrap();
+
+        // DZI goes all the way down to 1x1: ceil(log2(600)) + 1 levels
+        let output = temp_dir.child("dzi");
+        let summary = PyramidGenerator::new(PyramidLayout::Dzi)
+            .with_format(image::ImageFormat::Png)
+            .generate(input.path(), output.path())
+            .unwrap();
+        assert_eq!((summary.width, summary.height, summary.levels), (600, 300, 11));
+        assert!(std::fs::read_to_string(&summary.descriptor).unwrap().contains(r#"Width="600""#));
+
+        // Edge tiles carry the overlap on their inner side only
+        let files = output.child("scan_files");
+        let first = image::open(files.child("10/0_0.png").path()).unwrap();
+        assert_eq!((first.width(), first.height()), (255, 255));
+        let last = image::open(files.child("10/2_1.png").path()).unwrap();
+        assert_eq!((last.width(), last.height()), (93, 47));
+        let top = image::open(files.child("0/0_0.png").path()).unwrap();
+        assert_eq!((top.width(), top.height()), (1, 1));
+
+        // IIIF stops once the image fits a single tile
+        let output = temp_dir.child("iiif");
+        let summary = PyramidGenerator::new(PyramidLayout::Iiif)
+            .with_format(image::ImageFormat::Png)
+            .generate(input.path(), output.path())
+            .unwrap();
+        assert_eq!(summary.levels, 3);
+        assert!(output.child("scan/0,0,256,256/256,256/0/default.png").path().exists());
+        assert!(output.child("scan/full/150,75/0/default.png").path().exists());
+        let info = std::fs::read_to_string(&summary.descriptor).unwrap();
+        assert!(info.contains(r#""scaleFactors": [1, 2, 4]"#));
+    }
+
+    #[test]
+    fn test_blurhash() {
+        use pixie::BlurHasher;
+
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(40, 30, |x, y| {
+            image::Rgb([(x * 6 % 256) as u8, (y * 8 % 256) as u8, (x * y % 256) as u8])
+        }));
+
+        // Same strings as the reference encoder
+        assert_eq!(BlurHasher::new().encode(&image).unwrap(), "LqG91|2mwtX3l[WTjuf9gFfkfTfm");
+        assert_eq!(BlurHasher::new().with_components(1, 1).encode(&image).unwrap(), "00G91|");
+        assert!(BlurHasher::new().with_components(10, 3).encode(&image).is_err());
+    }
+
+    #[test]
+    fn test_thumbhash() {
+        use pixie::ThumbHash;
+
+        let opaque = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(40, 30, |x, y| {
+            image::Rgb([(x * 6 % 256) as u8, (y * 8 % 256) as u8, (x * y % 256) as u8])
+        }));
+        let hash = ThumbHash::from_image(&opaque);
+        assert_eq!(hash.to_base64(), "XAgOHZhgdndwh4h2iGiIh/GxD/eI");
+        let preview = hash.to_image();
+        assert_eq!((preview.width(), preview.height()), (32, 23));
+
+        // A red disc on a transparent square keeps its alpha
+        let disc = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(32, 32, |x, y| {
+            let inside = (x as i32 - 16).pow(2) + (y as i32 - 16).pow(2) < 100;
+            image::Rgba([255, 0, 0, if inside { 255 } else { 0 }])
+        }));
+        let hash = ThumbHash::from_image(&disc);
+        assert_eq!(hash.to_base64(), "1fuDBQA0eXh3iIgIh3eIcIcIB3iHeICLdw==");
+        let preview = hash.to_image().to_rgba8();
+        assert_eq!(preview.get_pixel(0, 0).0[3], 0);
+        assert_eq!(preview.get_pixel(16, 16).0[3], 255);
+
+        assert!(ThumbHash::from_bytes(vec![0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_lqip_placeholder() {
+        use pixie::LqipGenerator;
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("hero.png");
+        image::RgbImage::from_fn(200, 100, |x, _| image::Rgb([x as u8, 0, 0])).save(input.path()).unwrap();
+
+        let output = temp_dir.child("out");
+        let lqip = LqipGenerator::new(ProcessConfig::default())
+            .with_format(image::ImageFormat::Png)
+            .generate(input.path(), output.path())
+            .unwrap();
+        assert_eq!((lqip.width, lqip.height), (20, 10));
+        assert_eq!(lqip.path, output.child("hero-lqip.png").path());
+        assert!(lqip.data_uri().starts_with("data:image/png;base64,iVBORw0KGgo"));
+
+        let css = lqip.to_css(".hero");
+        assert!(css.starts_with(".hero {"));
+        assert!(css.contains("aspect-ratio: 20 / 10;"));
+    }
+
+    #[test]
+    fn test_save_comparison() {
+        use pixie::{Comparison, ComparisonLayout};
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("input.png");
+        image::RgbImage::from_pixel(80, 40, image::Rgb([200, 0, 0])).save(input.path()).unwrap();
+        let output = temp_dir.child("output.png");
+        let comparison = temp_dir.child("review/compare.png");
+
+        let config = ProcessConfig {
+            width: 40,
+            height: 20,
+            comparison: Some(comparison.path().to_path_buf()),
+            ..Default::default()
+        };
+        ImageProcessor::new(config).process(input.path(), output.path()).unwrap();
+
+        // The original is scaled down to the output size, with a gap between
+        let composite = image::open(comparison.path()).unwrap();
+        assert_eq!((composite.width(), composite.height()), (84, 20));
+
+        // Split keeps the output size, original on the left of a divider
+        let original = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(20, 10, image::Rgb([0, 0, 255])));
+        let processed = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(10, 5, image::Rgb([0, 255, 0])));
+        let split = Comparison::new(ComparisonLayout::Split).compose(&original, &processed).unwrap().to_rgb8();
+        assert_eq!(split.dimensions(), (10, 5));
+        assert_eq!(split.get_pixel(0, 2).0, [0, 0, 255]);
+        assert_eq!(split.get_pixel(5, 2).0, [255, 255, 255]);
+        assert_eq!(split.get_pixel(9, 2).0, [0, 255, 0]);
+    }
+
+    #[test]
+    fn test_measure_quality() {
+        use pixie::QualityMeter;
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("input.png");
+        image::RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, 128]))
+            .save(input.path())
+            .unwrap();
+        let output = temp_dir.child("output.png");
+
+        let config = ProcessConfig {
+            width: 32,
+            height: 24,
+            measure_quality: true,
+            ..Default::default()
+        };
+        let stats = ImageProcessor::new(config).process(input.path(), output.path()).unwrap();
+
+        // PNG is lossless, so the output matches what was encoded exactly
+        assert_eq!(stats.quality.len(), 1);
+        let (path, score) = &stats.quality[0];
+        assert_eq!(path, output.path());
+        assert!((score.ssim - 1.0).abs() < 1e-9);
+        assert!(score.psnr.is_infinite());
+
+        let reference = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(30, 20, |x, y| {
+            image::Luma([((x * 7 + y * 3) % 256) as u8])
+        }));
+        let mut distorted = reference.to_luma8();
+        for (x, y, pixel) in distorted.enumerate_pixels_mut() {
+            pixel.0[0] = (pixel.0[0] as i32 + ((x * y) % 11) as i32 - 5).clamp(0, 255) as u8;
+        }
+        let score = QualityMeter::new().measure(&reference, &image::DynamicImage::ImageLuma8(distorted)).unwrap();
+        assert!((score.ssim - 0.96704).abs() < 1e-5);
+        assert!((score.psnr - 37.515).abs() < 1e-3);
+
+        let smaller = reference.crop_imm(0, 0, 10, 10);
+        assert!(QualityMeter::new().measure(&reference, &smaller).is_err());
+    }
+
+    #[test]
+    fn test_auto_quality() {
+        use pixie::{Quality, QualityMeter, QualitySearch};
+        use std::io::Cursor;
+
+        assert_eq!("72".parse::<Quality>().unwrap(), Quality::Fixed(72));
+        assert_eq!("auto".parse::<Quality>().unwrap(), Quality::Auto(Quality::DEFAULT_TARGET_SSIM));
+        assert_eq!("auto:0.95".parse::<Quality>().unwrap().target_ssim(), Some(0.95));
+        assert!("auto:1.5".parse::<Quality>().is_err());
+        assert!("0".parse::<Quality>().is_err());
+
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 48, |x, y| {
+            image::Rgb([(x * 4) as u8, ((x * y) % 256) as u8, (y * 5) as u8])
+        }));
+
+        // Stands in for a lossy encoder: coarser steps at lower qualities
+        let encode = |quality: u8| -> pixie::Result<Vec<u8>> {
+            let step = (101 - quality as u32) / 3 + 1;
+            let mut coarse = image.to_rgb8();
+            coarse.pixels_mut().flat_map(|p| p.0.iter_mut()).for_each(|v| *v = (*v as u32 / step * step) as u8);
+            let mut buffer = Cursor::new(Vec::new());
+            coarse.write_to(&mut buffer, image::ImageFormat::Png)?;
+            Ok(buffer.into_inner())
+        };
+        let ssim = |quality: u8| {
+            let decoded = image::load_from_memory(&encode(quality).unwrap()).unwrap();
+            QualityMeter::new().measure(&image, &decoded).unwrap().ssim
+        };
+
+        let (quality, encoded) = QualitySearch::new(0.99).search(&image, encode).unwrap();
+        assert!(quality > 40 && quality < 95);
+        assert!(ssim(quality) >= 0.99);
+        assert!(ssim(quality - 1) < 0.99);
+        assert_eq!(encoded, encode(quality).unwrap());
+
+        // A target nothing reaches falls back to the top of the range
+        let (quality, _) = QualitySearch::new(1.0).with_range(50, 80).search(&image, encode).unwrap();
+        assert_eq!(quality, 80);
+
+        let config = ProcessConfig {
+            auto_quality: Some(1.5),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_header_only_metadata() {
+        use pixie::ImageMetadata;
+
+        let temp_dir = TempDir::new().unwrap();
+        let tiff = temp_dir.child("scan.tif");
+        image::RgbImage::new(300, 200).save(tiff.path()).unwrap();
+
+        // A plain TIFF has no EXIF or GPS directory in its first IFD
+        let metadata = ImageMetadata::read(tiff.path()).unwrap();
+        assert_eq!((metadata.width, metadata.height), (300, 200));
+        assert_eq!(metadata.format, "TIFF");
+        assert!(!metadata.has_exif);
+        assert_eq!(metadata.file_size, std::fs::metadata(tiff.path()).unwrap().len());
+
+        assert!(ImageMetadata::read(temp_dir.child("missing.tif").path()).is_err());
+    }
+
+    #[test]
+    fn test_tiled_downscale() {
+        use image::{imageops::FilterType, ImageFormat};
+        use pixie::{Loader, TiledLoader};
+
+        let temp_dir = TempDir::new().unwrap();
+        let gradient = image::RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([(x * 255 / 399) as u8, (y * 255 / 299) as u8, 128])
+        });
+        // 1200-byte rows make the encoder split the TIFF into many strips
+        let tiff = temp_dir.child("scan.tif");
+        gradient.save(tiff.path()).unwrap();
+        let png = temp_dir.child("scan.png");
+        gradient.save(png.path()).unwrap();
+
+        let loader = TiledLoader::new();
+        for (path, format) in [(tiff.path(), ImageFormat::Tiff), (png.path(), ImageFormat::Png)] {
+            let tiled = loader.load_downscaled(path, format, 100, 75).unwrap().to_rgb8();
+            let full = Loader::new().load(path).unwrap().resize_exact(100, 75, FilterType::Triangle).to_rgb8();
+            assert_eq!(tiled.dimensions(), (100, 75));
+
+            let diff: u64 = tiled
+                .as_raw()
+                .iter()
+                .zip(full.as_raw())
+                .map(|(a, b)| (*a as i32 - *b as i32).unsigned_abs() as u64)
+                .sum();
+            let mean = diff as f64 / tiled.as_raw().len() as f64;
+            assert!(mean < 2.0, "{:?} strip decode differs by {:.2} on average", format, mean);
+        }
+    }
+
+    #[test]
+    fn test_batch_skip_duplicates() {
+        use pixie::BatchProcessor;
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        for name in ["a.png", "b.png", "c.png"] {
+            image::RgbImage::new(40, 20).save(input_dir.child(name).path()).unwrap();
+        }
+        // Same size on disk as the white pair, but different bytes
+        image::RgbImage::from_pixel(40, 20, image::Rgb([255, 255, 255])).save(input_dir.child("d.bmp").path()).unwrap();
+        fs::copy(input_dir.child("d.bmp").path(), input_dir.child("e.bmp").path()).unwrap();
+        image::RgbImage::from_pixel(40, 20, image::Rgb([255, 0, 0])).save(input_dir.child("f.bmp").path()).unwrap();
+
+        let stats = BatchProcessor::new(ProcessConfig { width: 20, ..Default::default() }, 2)
+            .unwrap()
+            .with_progress(false)
+            .with_skip_duplicates(true)
+            .process_directory(input_dir.path(), output_dir.path(), false)
+            .unwrap();
+
+        // Groups follow the directory listing order, so compare them sorted
+        let mut names: Vec<Vec<String>> = stats
+            .duplicates
+            .iter()
+            .map(|group| {
+                let mut names: Vec<String> =
+                    group.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect();
+                names.sort();
+                names
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![vec!["a.png", "b.png", "c.png"], vec!["d.bmp", "e.bmp"]]);
+
+        // Only the first of each group is written
+        assert_eq!(stats.processed_count, 3);
+        for group in &stats.duplicates {
+            assert!(output_dir.child(group[0].file_name().unwrap()).path().exists());
+            assert!(!output_dir.child(group[1].file_name().unwrap()).path().exists());
+        }
+        assert!(output_dir.child("f.bmp").path().exists());
+    }
+
+    #[test]
+    fn test_exif_thumbnail() {
+        use exif::{Field, In, Tag, Value};
+        use pixie::{ExifThumbnail, MetadataProcessor};
+
+        let encode = |image: image::RgbImage| {
+            let mut jpeg = Vec::new();
+            image.write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg).unwrap();
+            jpeg
+        };
+        let stale = encode(image::RgbImage::new(16, 16));
+        let mut writer = exif::experimental::Writer::new();
+        let make = Field { tag: Tag::Make, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Pixie".to_vec()]) };
+        let compression = Field { tag: Tag::Compression, ifd_num: In::THUMBNAIL, value: Value::Short(vec![6]) };
+        writer.push_field(&make);
+        writer.push_field(&compression);
+        writer.set_jpeg(&stale, In::THUMBNAIL);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let photo = encode(image::RgbImage::from_pixel(640, 480, image::Rgb([30, 120, 200])));
+        let photo = MetadataProcessor::new().embed_exif_jpeg(&photo, tiff.get_ref()).unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("photo.jpg");
+        input.write_binary(&photo).unwrap();
+
+        let thumbnail = |path: &std::path::Path| {
+            let exif = MetadataProcessor::new().read_metadata(path).unwrap().unwrap();
+            assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+            let offset = exif.get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+            let length = exif.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+            let thumbnail = image::load_from_memory(&exif.buf()[offset..offset + length]).unwrap();
+            Some((thumbnail.width(), thumbnail.height()))
+        };
+        assert_eq!(thumbnail(input.path()), Some((16, 16)));
+
+        for (mode, expected) in [(ExifThumbnail::Regenerate, Some((160, 120))), (ExifThumbnail::Strip, None)] {
+            let output = temp_dir.child(format!("{:?}.jpg", mode));
+            let config = ProcessConfig { width: 320, exif_thumbnail: mode, ..Default::default() };
+            ImageProcessor::new(config).process(input.path(), output.path()).unwrap();
+            assert_eq!(thumbnail(output.path()), expected);
+        }
+    }
+
+    #[test]
+    fn test_process_sizes() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("photo.png");
+        image::RgbImage::from_fn(400, 300, |x, _| image::Rgb([(x / 2) as u8, 0, 0])).save(input.path()).unwrap();
+        let output_dir = temp_dir.child("sizes");
+        fs::create_dir(output_dir.path()).unwrap();
+
+        // Cropped to a square before the widths are rendered
+        let config = ProcessConfig { aspect: Some("1:1".parse().unwrap()), ..Default::default() };
+        let stats = ImageProcessor::new(config)
+            .process_sizes(input.path(), output_dir.path(), &[240, 0, 60])
+            .unwrap();
+        assert_eq!(stats.processed_count, 2);
+        assert_eq!(image::image_dimensions(output_dir.child("photo_240w.png").path()).unwrap(), (240, 240));
+        assert_eq!(image::image_dimensions(output_dir.child("photo_60w.png").path()).unwrap(), (60, 60));
+
+        let stats = ImageProcessor::new(ProcessConfig::default())
+            .process_sizes(input.path(), output_dir.path(), &[100])
+            .unwrap();
+        assert_eq!(stats.processed_count, 1);
+        assert_eq!(image::image_dimensions(output_dir.child("photo_100w.png").path()).unwrap(), (100, 75));
+
+        let limited = ProcessConfig { max_file_size: Some(16), ..Default::default() };
+        assert!(ImageProcessor::new(limited).process_sizes(input.path(), output_dir.path(), &[100]).is_err());
+
+        // Auto quality picks a quality per size, as for single outputs
+        let detailed = temp_dir.child("detail.png");
+        image::RgbImage::from_fn(400, 300, |x, y| image::Rgb([(x * 7 + y * 3) as u8, (x * y) as u8, (x ^ y) as u8]))
+            .save(detailed.path())
+            .unwrap();
+        let mut sizes = Vec::new();
+        for auto_quality in [None, Some(0.8)] {
+            let config = ProcessConfig {
+                quality: 95,
+                auto_quality,
+                format: Some(pixie::OutputFormat::Jpeg),
+                ..Default::default()
+            };
+            ImageProcessor::new(config).process_sizes(detailed.path(), output_dir.path(), &[200]).unwrap();
+            sizes.push(fs::metadata(output_dir.child("detail_200w.jpg").path()).unwrap().len());
+        }
+        assert!(sizes[1] < sizes[0], "{:?}", sizes);
+
+        // Masked corners get the mask background when the format has no alpha
+        let config = ProcessConfig {
+            corner_radius: Some(16),
+            mask_background: pixie::Color::new(255, 0, 0),
+            format: Some(pixie::OutputFormat::Jpeg),
+            ..Default::default()
+        };
+        ImageProcessor::new(config).process_sizes(input.path(), output_dir.path(), &[200]).unwrap();
+        let output = image::open(output_dir.child("photo_200w.jpg").path()).unwrap().to_rgb8();
+        let corner = output.get_pixel(0, 0).0;
+        assert!(corner[0] > 200 && corner[1] < 50 && corner[2] < 50, "{:?}", corner);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_process_async() {
+        use image::GenericImage;
+        use pixie::{ConflictPolicy, Phase, Progress, ProcessingStage, StageContext};
+        use std::sync::{Arc, Mutex};
+
+        struct Stamp;
+        impl ProcessingStage for Stamp {
+            fn name(&self) -> &str {
+                "stamp"
+            }
+
+            fn apply(&self, mut image: image::DynamicImage, _ctx: &StageContext) -> pixie::Result<image::DynamicImage> {
+                image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+                Ok(image)
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.child("a.png");
+        image::RgbImage::new(16, 16).save(input.path()).unwrap();
+        let output = temp_dir.child("stamped.png");
+
+        let phases = Arc::new(Mutex::new(Vec::new()));
+        let seen = phases.clone();
+        let processor = ImageProcessor::new(ProcessConfig { width: 8, ..Default::default() })
+            .with_stage(Arc::new(Stamp))
+            .with_progress_hook(Arc::new(move |progress| {
+                if let Progress::Phase(phase) = progress {
+                    seen.lock().unwrap().push(phase);
+                }
+            }));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let stats = runtime.block_on(processor.process_async(input.path(), output.path())).unwrap();
+        assert_eq!(stats.processed_count, 1);
+
+        let stamped = image::open(output.path()).unwrap().to_rgb8();
+        assert_eq!(stamped.dimensions(), (8, 8));
+        assert_eq!(stamped.get_pixel(0, 0).0, [255, 0, 0]);
+        assert!(phases.lock().unwrap().contains(&Phase::Resize));
+
+        // Same output handling as the blocking path
+        let config = ProcessConfig {
+            create_dirs: true,
+            verify: true,
+            on_conflict: Some(ConflictPolicy::Skip),
+            ..Default::default()
+        };
+        let nested = temp_dir.child("new/dir/out.png");
+        let processor = ImageProcessor::new(config);
+        let stats = runtime.block_on(processor.process_async(input.path(), nested.path())).unwrap();
+        assert_eq!(stats.processed_count, 1);
+        assert_eq!(image::image_dimensions(nested.path()).unwrap(), (16, 16));
+        let stats = runtime.block_on(processor.process_async(input.path(), nested.path())).unwrap();
+        assert_eq!((stats.processed_count, stats.existing_count, stats.skipped_count), (0, 1, 0));
+
+        let config = ProcessConfig { create_dirs: false, ..Default::default() };
+        let missing = temp_dir.child("missing/out.png");
+        let result = runtime.block_on(ImageProcessor::new(config).process_async(input.path(), missing.path()));
+        assert!(result.is_err());
+        assert!(!temp_dir.child("missing").path().exists());
+
+        // Builder settings carry over into the blocking task
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        for name in ["a.png", "b.png"] {
+            image::RgbImage::new(16, 16).save(input_dir.child(name).path()).unwrap();
+        }
+        let batch = pixie::BatchProcessor::new(ProcessConfig::default(), 2)
+            .unwrap()
+            .with_progress(false)
+            .with_skip_duplicates(true);
+        let stats = runtime.block_on(batch.process_directory_async(input_dir.path(), output_dir.path(), false)).unwrap();
+        assert_eq!((stats.processed_count, stats.duplicates.len()), (1, 1));
+    }
+
+    #[test]
+    fn test_batch_recursive_folders() {
+        use pixie::BatchProcessor;
+
+        let input_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        for (folder, shade) in [("a", 50), ("b/c", 200)] {
+            fs::create_dir_all(input_dir.child(folder).path()).unwrap();
+            image::RgbImage::from_pixel(40, 20, image::Rgb([shade, shade, shade]))
+                .save(input_dir.child(folder).child("x.png").path())
+                .unwrap();
+        }
+
+        let stats = BatchProcessor::new(ProcessConfig { width: 20, ..Default::default() }, 2)
+            .unwrap()
+            .with_progress(false)
+            .process_directory(input_dir.path(), output_dir.path(), true)
+            .unwrap();
+
+        // Same-named inputs keep their folders rather than overwriting each other
+        assert_eq!(stats.processed_count, 2);
+        let a = image::open(output_dir.child("a/x.png").path()).unwrap().to_rgb8();
+        let c = image::open(output_dir.child("b/c/x.png").path()).unwrap().to_rgb8();
+        assert_eq!((a.dimensions(), a.get_pixel(0, 0).0), ((20, 10), [50, 50, 50]));
+        assert_eq!((c.dimensions(), c.get_pixel(0, 0).0), ((20, 10), [200, 200, 200]));
+        assert!(!output_dir.child("x.png").path().exists());
+
+        // The batch lays out its own folders, whatever create_dirs says,
+        // both in memory and when processing from the file
+        for measure_quality in [false, true] {
+            let output_dir = TempDir::new().unwrap();
+            let config = ProcessConfig { width: 20, create_dirs: false, measure_quality, ..Default::default() };
+            let stats = BatchProcessor::new(config, 2)
+                .unwrap()
+                .with_progress(false)
+                .process_directory(input_dir.path(), output_dir.path(), true)
+                .unwrap();
+            assert_eq!(stats.processed_count, 2, "{:?}", stats.errors);
+            assert!(output_dir.child("b/c/x.png").path().exists());
+        }
+    }
+}